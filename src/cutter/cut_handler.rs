@@ -1,14 +1,16 @@
-use super::cuts::Cut;
+use super::cuts::{Cut, RangeCut};
 use crate::histoer::histogrammer::Histogrammer;
 use polars::prelude::*;
 
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Write};
 use std::path::PathBuf;
 
 #[derive(Default, serde::Deserialize, serde::Serialize)]
 pub struct CutHandler {
     pub cuts: Vec<Cut>,
+    #[serde(default)]
+    pub range_cuts: Vec<RangeCut>,
 }
 
 impl CutHandler {
@@ -28,11 +30,72 @@ impl CutHandler {
         Ok(())
     }
 
+    // save all the cuts to one file
+    pub fn save_cuts_to_file(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(file_path) = rfd::FileDialog::new()
+            .set_file_name("cuts.json")
+            .add_filter("JSON Files", &["json"])
+            .save_file()
+        {
+            let serialized = serde_json::to_string_pretty(&self.cuts)?;
+            let mut file = File::create(file_path)?;
+            file.write_all(serialized.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // append the cuts from a file, cuts with a name that is already loaded are skipped
+    pub fn load_cuts_from_file(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(file_path) = rfd::FileDialog::new()
+            .set_file_name("cuts.json")
+            .add_filter("JSON Files", &["json"])
+            .pick_file()
+        {
+            let file = File::open(file_path)?;
+            let reader = BufReader::new(file);
+            let cuts: Vec<Cut> = serde_json::from_reader(reader)?;
+
+            self.add_cuts(cuts);
+        }
+        Ok(())
+    }
+
+    // Add the cuts whose names are not taken yet, so loading the same cuts twice keeps one copy
+    pub fn add_cuts(&mut self, cuts: Vec<Cut>) {
+        for cut in cuts {
+            if self
+                .cuts
+                .iter()
+                .any(|existing| existing.polygon.name == cut.polygon.name)
+            {
+                log::warn!(
+                    "Skipping cut '{}', a cut with that name is already loaded",
+                    cut.polygon.name
+                );
+                continue;
+            }
+
+            self.cuts.push(cut);
+        }
+    }
+
+    // The first name shared by two cuts, loading skips cuts with a name that is already taken
+    fn duplicate_cut_name(&self) -> Option<&str> {
+        self.cuts.iter().enumerate().find_map(|(index, cut)| {
+            self.cuts[..index]
+                .iter()
+                .any(|other| other.polygon.name == cut.polygon.name)
+                .then_some(cut.polygon.name.as_str())
+        })
+    }
+
     pub fn cuts_are_selected(&self) -> bool {
-        self.cuts.iter().any(|cut| cut.selected)
+        self.cuts.iter().any(|cut| cut.selected) || self.range_cuts.iter().any(|cut| cut.selected)
     }
 
     pub fn cut_ui(&mut self, ui: &mut egui::Ui, histogrammer: &mut Histogrammer) {
+        histogrammer.retrieve_range_cuts(self);
+
         ui.collapsing("Cuts", |ui| {
             ui.horizontal(|ui| {
                 if ui.button("Get Cut").clicked() {
@@ -47,24 +110,54 @@ impl CutHandler {
                 }
             });
 
+            ui.horizontal(|ui| {
+                if ui.button("Save Cuts").clicked() {
+                    if let Err(e) = self.save_cuts_to_file() {
+                        log::error!("Error saving cuts: {:?}", e);
+                    }
+                }
+
+                if ui.button("Load Cuts").clicked() {
+                    if let Err(e) = self.load_cuts_from_file() {
+                        log::error!("Error loading cuts: {:?}", e);
+                    }
+                }
+            });
+
             if self.cuts.is_empty() {
                 ui.label("No cuts loaded");
             } else {
+                ui.horizontal(|ui| {
+                    let active = self.cuts.iter().filter(|cut| cut.selected).count();
+                    ui.label(format!("{} of {} cuts active", active, self.cuts.len()))
+                        .on_hover_text(
+                            "Only the active cuts are applied when filling or filtering",
+                        );
+
+                    if ui.button("Enable All").clicked() {
+                        self.cuts.iter_mut().for_each(|cut| cut.selected = true);
+                    }
+
+                    if ui.button("Disable All").clicked() {
+                        self.cuts.iter_mut().for_each(|cut| cut.selected = false);
+                    }
+                });
+
                 egui::Grid::new("cuts")
                     .striped(true)
-                    .num_columns(6)
+                    .num_columns(8)
                     .show(ui, |ui| {
-                        ui.label("Cuts");
+                        ui.label("Name");
                         ui.label("X Column\t\t\t\t\t");
                         ui.label("Y Column\t\t\t\t\t");
-                        ui.label("Polygon");
+                        ui.label("Shape");
+                        ui.label("Vertices");
+                        ui.label("Invert");
                         ui.label("Active");
                         ui.end_row();
 
                         let mut index_to_remove = None;
                         for (index, cut) in self.cuts.iter_mut().enumerate() {
-                            ui.label(format!("Cut {}", index));
-
                             cut.ui(ui);
 
                             ui.horizontal(|ui| {
@@ -82,11 +175,52 @@ impl CutHandler {
                         }
                     });
 
+                if let Some(name) = self.duplicate_cut_name() {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!("More than one cut is named '{}'", name),
+                    );
+                }
+
                 // add button to remove all
                 if ui.button("Remove All").clicked() {
                     self.cuts.clear();
                 }
             }
+
+            if !self.range_cuts.is_empty() {
+                ui.separator();
+
+                egui::Grid::new("range_cuts")
+                    .striped(true)
+                    .num_columns(5)
+                    .show(ui, |ui| {
+                        ui.label("1D Gates");
+                        ui.label("Column\t\t\t\t\t");
+                        ui.label("Range");
+                        ui.label("");
+                        ui.label("Active");
+                        ui.end_row();
+
+                        let mut index_to_remove = None;
+                        for (index, cut) in self.range_cuts.iter_mut().enumerate() {
+                            cut.ui(ui);
+
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut cut.selected, "");
+                                if ui.button("🗙").clicked() {
+                                    index_to_remove = Some(index);
+                                }
+                            });
+
+                            ui.end_row();
+                        }
+
+                        if let Some(index) = index_to_remove {
+                            self.range_cuts.remove(index);
+                        }
+                    });
+            }
         });
     }
 
@@ -103,9 +237,26 @@ impl CutHandler {
             }
         }
 
+        for cut in self.range_cuts.iter().filter(|cut| cut.selected) {
+            filtered_lf = cut.filter_lf_with_cut(&filtered_lf);
+        }
+
         Ok(filtered_lf)
     }
 
+    // The number of rows that pass the selected cuts, counted without collecting the rows
+    pub fn preview_row_count(&self, lf: &LazyFrame) -> PolarsResult<usize> {
+        let mut filtered_lf = lf.clone();
+        for cut in self.cuts.iter().filter(|cut| cut.selected) {
+            filtered_lf = cut.filter_lf_with_cut(&filtered_lf)?;
+        }
+        for cut in self.range_cuts.iter().filter(|cut| cut.selected) {
+            filtered_lf = cut.filter_lf_with_cut(&filtered_lf);
+        }
+
+        row_count(&filtered_lf)
+    }
+
     pub fn filter_files_and_save_to_one_file(
         &mut self,
         file_paths: Vec<PathBuf>,
@@ -138,3 +289,12 @@ impl CutHandler {
         Ok(())
     }
 }
+
+pub fn row_count(lf: &LazyFrame) -> PolarsResult<usize> {
+    let df = lf
+        .clone()
+        .select([len().cast(DataType::UInt64).alias("rows")])
+        .collect()?;
+
+    Ok(df.column("rows")?.u64()?.get(0).unwrap_or(0) as usize)
+}
@@ -1,4 +1,4 @@
-use geo::Contains;
+use geo::{Contains, Intersects};
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
 use polars::prelude::*;
@@ -6,6 +6,38 @@ use std::fs::File;
 use std::io::{BufReader, Write};
 
 use crate::egui_plot_stuff::egui_polygon::EguiPolygon;
+use crate::histoer::histo2d::histogram2d::RangeEdges;
+
+// Number of points used to draw an ellipse cut
+const ELLIPSE_POINTS: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum CutShape {
+    #[default]
+    Polygon,
+    Rectangle, // the two vertices are opposite corners
+    Ellipse,   // the two vertices are opposite corners of the bounding box
+}
+
+impl CutShape {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CutShape::Polygon => "Polygon",
+            CutShape::Rectangle => "Rectangle",
+            CutShape::Ellipse => "Ellipse",
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for shape in [CutShape::Polygon, CutShape::Rectangle, CutShape::Ellipse] {
+                ui.radio_value(self, shape, shape.name());
+            }
+        })
+        .response
+        .on_hover_text("Rectangles and ellipses are dragged out from one corner to the other");
+    }
+}
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Cut {
@@ -14,16 +46,37 @@ pub struct Cut {
     pub y_column: String,
     #[serde(skip)]
     pub selected: bool,
+    #[serde(default)]
+    pub range_edges: RangeEdges, // whether points on the polygon boundary are inside the cut
+    #[serde(default)]
+    pub shape: CutShape,
+    #[serde(default)]
+    pub invert: bool, // veto, keep the rows outside the cut
 }
 
 impl Cut {
     pub fn ui(&mut self, ui: &mut egui::Ui) {
         // putting this in a grid
+        ui.text_edit_singleline(&mut self.polygon.name)
+            .on_hover_text("Rename the cut");
+
         ui.text_edit_singleline(&mut self.x_column);
 
         ui.text_edit_singleline(&mut self.y_column);
 
-        self.polygon.polygon_info_menu_button(ui);
+        ui.label(self.shape.name());
+
+        ui.menu_button(format!("{}", self.polygon.vertices.len()), |ui| {
+            ui.label("Vertices (X,Y)");
+            for (index, vertex) in self.polygon.vertices.iter().enumerate() {
+                ui.label(format!(
+                    "Vertex {}: ({:.2}, {:.2})",
+                    index, vertex[0], vertex[1]
+                ));
+            }
+        });
+
+        self.invert_checkbox(ui);
     }
 
     pub fn menu_button(&mut self, ui: &mut egui::Ui) {
@@ -39,9 +92,36 @@ impl Cut {
             }
         }
 
+        self.invert_checkbox(ui);
+
         self.polygon.menu_button(ui);
     }
 
+    // The bounding box on one axis as a 1D gate, the shape itself is dropped
+    pub fn to_range_cut(&self, y_axis: bool) -> RangeCut {
+        let (x_min, x_max, y_min, y_max) = self.bounds();
+        let (column, min, max, axis) = if y_axis {
+            (&self.y_column, y_min, y_max, "y")
+        } else {
+            (&self.x_column, x_min, x_max, "x")
+        };
+
+        RangeCut {
+            name: format!("{}_{}", self.polygon.name, axis),
+            column: column.clone(),
+            min,
+            max,
+            range_edges: self.range_edges,
+            selected: true,
+            invert: self.invert,
+        }
+    }
+
+    fn invert_checkbox(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(&mut self.invert, "Invert")
+            .on_hover_text("Veto: keep everything outside the cut, drawn dashed");
+    }
+
     pub fn save_cut_to_json(&self) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(file_path) = rfd::FileDialog::new()
             .add_filter("JSON Files", &["json"]) // Add a filter for json files
@@ -68,22 +148,223 @@ impl Cut {
         Ok(())
     }
 
-    fn to_geo_polygon(&self) -> geo::Polygon<f64> {
+    pub fn to_geo_polygon(&self) -> geo::Polygon<f64> {
         let exterior_coords: Vec<_> = self.polygon.vertices.iter().map(|&[x, y]| (x, y)).collect();
         let exterior_line_string = geo::LineString::from(exterior_coords);
         geo::Polygon::new(exterior_line_string, vec![])
     }
 
     pub fn is_inside(&self, x: f64, y: f64) -> bool {
+        self.inside_test()(x, y)
+    }
+
+    // Point-in-cut test for any shape, use when checking many points so the polygon is only built once
+    pub fn inside_test(&self) -> impl Fn(f64, f64) -> bool + '_ {
+        let polygon = (self.shape == CutShape::Polygon).then(|| self.to_geo_polygon());
+        move |x, y| match &polygon {
+            Some(polygon) => self.is_inside_polygon(polygon, x, y),
+            None => self.is_inside_shape(x, y),
+        }
+    }
+
+    fn is_inside_shape(&self, x: f64, y: f64) -> bool {
+        match self.shape {
+            CutShape::Ellipse => match self.ellipse() {
+                Some((x_center, y_center, x_radius, y_radius)) => {
+                    let r =
+                        ((x - x_center) / x_radius).powi(2) + ((y - y_center) / y_radius).powi(2);
+                    match self.range_edges {
+                        RangeEdges::Exclusive => r < 1.0,
+                        RangeEdges::Inclusive => r <= 1.0,
+                    }
+                }
+                None => false,
+            },
+            _ => {
+                let (x_min, x_max, y_min, y_max) = self.bounds();
+                self.range_edges.contains(x, x_min, x_max)
+                    && self.range_edges.contains(y, y_min, y_max)
+            }
+        }
+    }
+
+    // x min, x max, y min and y max of the vertices
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        self.polygon.vertices.iter().fold(
+            (
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                f64::NEG_INFINITY,
+            ),
+            |(x_min, x_max, y_min, y_max), &[x, y]| {
+                (x_min.min(x), x_max.max(x), y_min.min(y), y_max.max(y))
+            },
+        )
+    }
+
+    // Center and radii of the ellipse inside the bounds, None until it has an area
+    fn ellipse(&self) -> Option<(f64, f64, f64, f64)> {
+        let (x_min, x_max, y_min, y_max) = self.bounds();
+        let (x_radius, y_radius) = ((x_max - x_min) / 2.0, (y_max - y_min) / 2.0);
+
+        (x_radius > 0.0 && y_radius > 0.0).then_some((
+            x_min + x_radius,
+            y_min + y_radius,
+            x_radius,
+            y_radius,
+        ))
+    }
+
+    // The outline drawn on the plot, the vertices stay the drag handles
+    pub fn outline(&self) -> Vec<[f64; 2]> {
+        match self.shape {
+            CutShape::Polygon => self.polygon.vertices.clone(),
+            CutShape::Rectangle => {
+                if self.polygon.vertices.len() < 2 {
+                    return self.polygon.vertices.clone();
+                }
+                let (x_min, x_max, y_min, y_max) = self.bounds();
+                vec![
+                    [x_min, y_min],
+                    [x_max, y_min],
+                    [x_max, y_max],
+                    [x_min, y_max],
+                ]
+            }
+            CutShape::Ellipse => match self.ellipse() {
+                Some((x_center, y_center, x_radius, y_radius)) => (0..ELLIPSE_POINTS)
+                    .map(|i| {
+                        let angle = std::f64::consts::TAU * i as f64 / ELLIPSE_POINTS as f64;
+                        [
+                            x_center + x_radius * angle.cos(),
+                            y_center + y_radius * angle.sin(),
+                        ]
+                    })
+                    .collect(),
+                None => self.polygon.vertices.clone(),
+            },
+        }
+    }
+
+    pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
+        let outline = self.outline();
+
+        // vetoes are dashed so they stand out from gates
+        let style = self.polygon.style;
+        if self.invert {
+            self.polygon.style = Some(egui_plot::LineStyle::dashed_dense());
+        }
+        self.polygon.draw_outline(plot_ui, outline);
+        self.polygon.style = style;
+    }
+
+    // Rectangles and ellipses are dragged out from one corner to the opposite one instead of clicked
+    pub fn handle_interactions(&mut self, plot_response: &egui_plot::PlotResponse<()>) -> bool {
+        if self.shape != CutShape::Polygon && self.polygon.interactive_clicking {
+            let pointer = plot_response.response.ctx.input(|i| i.pointer.clone());
+            if self.polygon.draw
+                && plot_response.response.hovered()
+                && pointer.button_pressed(egui::PointerButton::Primary)
+            {
+                if let Some(position) = pointer.interact_pos() {
+                    let value = plot_response.transform.value_from_position(position);
+                    self.polygon.start_drag_out(value.x, value.y);
+                    return true;
+                }
+            }
+            return false;
+        }
+
+        self.polygon.handle_interactions(plot_response)
+    }
+
+    // ((x - x center) / x radius)^2 + ((y - y center) / y radius)^2 < 1
+    fn ellipse_filter_expr(&self) -> Expr {
+        let Some((x_center, y_center, x_radius, y_radius)) = self.ellipse() else {
+            return lit(false);
+        };
+
+        let dx = (col(&self.x_column).cast(DataType::Float64) - lit(x_center)) / lit(x_radius);
+        let dy = (col(&self.y_column).cast(DataType::Float64) - lit(y_center)) / lit(y_radius);
+        let r = dx.clone() * dx + dy.clone() * dy;
+
+        match self.range_edges {
+            RangeEdges::Exclusive => r.lt(lit(1.0)),
+            RangeEdges::Inclusive => r.lt_eq(lit(1.0)),
+        }
+    }
+
+    // The filter as a python polars expression, to reuse the cut outside of spectrix. Polygons
+    // become an even-odd ray casting test, so points exactly on an edge may land on either side.
+    pub fn polars_filter_string(&self) -> String {
+        let x = format!("pl.col({:?})", self.x_column);
+        let y = format!("pl.col({:?})", self.y_column);
+        let (x_min, x_max, y_min, y_max) = self.bounds();
+
+        let expr = match self.shape {
+            CutShape::Rectangle => format!(
+                "{} & {}",
+                self.range_edges.filter_string(&self.x_column, x_min, x_max),
+                self.range_edges.filter_string(&self.y_column, y_min, y_max)
+            ),
+            CutShape::Ellipse => match self.ellipse() {
+                Some((x_center, y_center, x_radius, y_radius)) => {
+                    let comparison = match self.range_edges {
+                        RangeEdges::Exclusive => "<",
+                        RangeEdges::Inclusive => "<=",
+                    };
+                    format!(
+                        "(((({x} - {x_center:?}) / {x_radius:?}) ** 2 + (({y} - {y_center:?}) / {y_radius:?}) ** 2) {comparison} 1.0)"
+                    )
+                }
+                None => "pl.lit(False)".to_string(),
+            },
+            CutShape::Polygon => {
+                let vertices = &self.polygon.vertices;
+                let crossings: Vec<String> = (0..vertices.len())
+                    .filter_map(|i| {
+                        let [x_i, y_i] = vertices[i];
+                        let [x_j, y_j] = vertices[(i + 1) % vertices.len()];
+                        // horizontal edges are never crossed by the ray
+                        if y_i == y_j {
+                            return None;
+                        }
+                        let slope = (x_j - x_i) / (y_j - y_i);
+                        Some(format!(
+                            "((({y} > {y_i:?}) != ({y} > {y_j:?})) & ({x} < {slope:?} * ({y} - {y_i:?}) + {x_i:?}))"
+                        ))
+                    })
+                    .collect();
+
+                if crossings.is_empty() {
+                    "pl.lit(False)".to_string()
+                } else {
+                    format!("({})", crossings.join("\n ^ "))
+                }
+            }
+        };
+
+        if self.invert {
+            format!("~({})", expr)
+        } else {
+            expr
+        }
+    }
+
+    // Points on the boundary are only inside the cut when the range edges are inclusive
+    // Use with to_geo_polygon when checking many points so the polygon is only built once
+    pub fn is_inside_polygon(&self, polygon: &geo::Polygon<f64>, x: f64, y: f64) -> bool {
         let point = geo::Point::new(x, y);
-        let polygon = self.to_geo_polygon();
-        polygon.contains(&point)
+        match self.range_edges {
+            RangeEdges::Exclusive => polygon.contains(&point),
+            RangeEdges::Inclusive => polygon.intersects(&point),
+        }
     }
 
     pub fn filter_lf_with_cut(&self, lf: &LazyFrame) -> Result<LazyFrame, PolarsError> {
         let x_column = self.x_column.clone(); // Clone the column names to avoid borrowing `self`
         let y_column = self.y_column.clone();
-        let polygon = self.polygon.clone(); // Clone the polygon or other data needed
 
         // Ensure the columns exist
         let check_lf = lf.clone().limit(1);
@@ -104,34 +385,34 @@ impl Cut {
             return Err(PolarsError::ColumnNotFound(y_column.into()));
         }
 
-        let x_min = polygon
-            .vertices
-            .iter()
-            .map(|&[x, _]| x)
-            .fold(f64::INFINITY, |a, b| a.min(b));
-        let x_max = polygon
-            .vertices
-            .iter()
-            .map(|&[x, _]| x)
-            .fold(f64::NEG_INFINITY, |a, b| a.max(b));
-        let y_min = polygon
-            .vertices
-            .iter()
-            .map(|&[_, y]| y)
-            .fold(f64::INFINITY, |a, b| a.min(b));
-        let y_max = polygon
-            .vertices
-            .iter()
-            .map(|&[_, y]| y)
-            .fold(f64::NEG_INFINITY, |a, b| a.max(b));
+        let (x_min, x_max, y_min, y_max) = self.bounds();
+
+        // rectangles and ellipses are plain expressions, only polygons need the point by point mask
+        let shape_expr = match self.shape {
+            CutShape::Rectangle => Some(
+                self.range_edges
+                    .filter_expr(&x_column, x_min, x_max)
+                    .and(self.range_edges.filter_expr(&y_column, y_min, y_max)),
+            ),
+            CutShape::Ellipse => Some(self.ellipse_filter_expr()),
+            CutShape::Polygon => None,
+        };
 
-        // Apply the basic range filters first
-        let filtered_lf = lf
-            .clone()
-            .filter(col(&x_column).gt_eq(lit(x_min)))
-            .filter(col(&x_column).lt_eq(lit(x_max)))
-            .filter(col(&y_column).gt_eq(lit(y_min)))
-            .filter(col(&y_column).lt_eq(lit(y_max)));
+        if let Some(expr) = shape_expr {
+            let expr = if self.invert { expr.not() } else { expr };
+            return Ok(lf.clone().filter(expr));
+        }
+
+        // Apply the basic range filters first, a veto keeps the rows outside of the range too
+        let filtered_lf = if self.invert {
+            lf.clone()
+        } else {
+            lf.clone()
+                .filter(col(&x_column).gt_eq(lit(x_min)))
+                .filter(col(&x_column).lt_eq(lit(x_max)))
+                .filter(col(&y_column).gt_eq(lit(y_min)))
+                .filter(col(&y_column).lt_eq(lit(y_max)))
+        };
 
         let filtered_df = filtered_lf
             .clone()
@@ -155,9 +436,10 @@ impl Cut {
                 .progress_chars("#>-"),
         );
 
+        let geo_polygon = self.to_geo_polygon();
         for (x_value, y_value) in x_values.into_iter().zip(y_values) {
             let inside = match (x_value, y_value) {
-                (Some(x), Some(y)) => polygon.is_inside(x, y),
+                (Some(x), Some(y)) => self.is_inside_polygon(&geo_polygon, x, y) != self.invert,
                 _ => false,
             };
             mask.push(inside);
@@ -184,11 +466,80 @@ impl Cut {
     }
 }
 
+// A gate on a single column, e.g. the x range of a 2D cut
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RangeCut {
+    pub name: String,
+    pub column: String,
+    pub min: f64,
+    pub max: f64,
+    pub range_edges: RangeEdges,
+    pub selected: bool,
+    pub invert: bool, // veto, keep the rows outside the range
+}
+
+impl RangeCut {
+    // putting this in a grid
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.text_edit_singleline(&mut self.name);
+        ui.text_edit_singleline(&mut self.column);
+
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut self.min)
+                    .speed(0.1)
+                    .prefix("min: "),
+            );
+            ui.add(
+                egui::DragValue::new(&mut self.max)
+                    .speed(0.1)
+                    .prefix("max: "),
+            );
+        });
+
+        ui.checkbox(&mut self.invert, "Invert")
+            .on_hover_text("Veto: keep everything outside the range");
+    }
+
+    pub fn filter_lf_with_cut(&self, lf: &LazyFrame) -> LazyFrame {
+        let expr = self
+            .range_edges
+            .filter_expr(&self.column, self.min, self.max);
+        lf.clone()
+            .filter(if self.invert { expr.not() } else { expr })
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct HistogramCuts {
     pub cuts: Vec<Cut>,
     pub x_column: String,
     pub y_column: String,
+    #[serde(default)]
+    pub range_edges: RangeEdges,
+    #[serde(default)]
+    pub new_cut_shape: CutShape,
+    #[serde(default)]
+    pub snap_to_grid: bool,
+    #[serde(default = "default_grid_step")]
+    pub grid_step: f64, // in data coordinates, the same on both axes
+    // The cut that Ctrl+Z and Ctrl+Y apply to
+    #[serde(skip)]
+    last_edited: Option<usize>,
+    #[serde(skip)]
+    pub pending_range_cuts: Vec<RangeCut>, // picked up by the cut handler
+    #[serde(skip)]
+    pub pending_propagation: Option<Cut>, // copied to the 2D histograms with the same columns by the histogrammer
+    #[serde(skip)]
+    pub propagation_message: Option<String>,
+}
+
+fn default_grid_step() -> f64 {
+    1.0
+}
+
+fn snap_to_step(value: f64, step: f64) -> f64 {
+    (value / step).round() * step
 }
 
 impl Default for HistogramCuts {
@@ -197,6 +548,14 @@ impl Default for HistogramCuts {
             cuts: vec![],
             x_column: "".to_string(),
             y_column: "".to_string(),
+            range_edges: RangeEdges::default(),
+            new_cut_shape: CutShape::default(),
+            snap_to_grid: false,
+            grid_step: default_grid_step(),
+            last_edited: None,
+            pending_range_cuts: Vec::new(),
+            pending_propagation: None,
+            propagation_message: None,
         }
     }
 }
@@ -218,6 +577,9 @@ impl HistogramCuts {
             x_column: "".to_string(),
             y_column: "".to_string(),
             selected: false,
+            range_edges: self.range_edges,
+            shape: self.new_cut_shape,
+            invert: false,
         };
         self.cuts.push(new_cut);
     }
@@ -235,18 +597,117 @@ impl HistogramCuts {
         for cut in &mut self.cuts {
             cut.x_column.clone_from(&self.x_column);
             cut.y_column.clone_from(&self.y_column);
+            cut.range_edges = self.range_edges;
         }
     }
 
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
         for cut in &mut self.cuts {
-            cut.polygon.draw(plot_ui);
+            cut.draw(plot_ui);
+        }
+
+        self.draw_vertex_readout(plot_ui);
+    }
+
+    // Vertex count and whether the cut is closed, next to the cursor while drawing or dragging
+    fn draw_vertex_readout(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let Some(cursor) = plot_ui.pointer_coordinate() else {
+            return;
+        };
+
+        let Some(cut) = self.cuts.iter().find(|cut| {
+            cut.polygon.draw && (cut.polygon.interactive_clicking || cut.polygon.is_dragging)
+        }) else {
+            return;
+        };
+
+        let vertices = cut.polygon.vertices.len();
+        let state = if cut.polygon.interactive_clicking {
+            "open, double-click to close"
+        } else {
+            "closed"
+        };
+        let mut text = format!("{}: {} vertices, {}", cut.polygon.name, vertices, state);
+        if self.snap_to_grid {
+            text.push_str(&format!(
+                "\n({}, {})",
+                snap_to_step(cursor.x, self.grid_step),
+                snap_to_step(cursor.y, self.grid_step)
+            ));
+        }
+
+        plot_ui.text(
+            egui_plot::Text::new(cursor, text)
+                .anchor(egui::Align2::LEFT_TOP)
+                .allow_hover(false),
+        );
+    }
+
+    // Round the vertex that was just placed and the one being dragged to the grid
+    fn snap_vertices(&mut self, cut_index: usize, vertices_before: usize) {
+        if !self.snap_to_grid || self.grid_step <= 0.0 {
+            return;
+        }
+
+        let step = self.grid_step;
+        let snap = |vertex: &mut [f64; 2]| {
+            vertex[0] = snap_to_step(vertex[0], step);
+            vertex[1] = snap_to_step(vertex[1], step);
+        };
+
+        let polygon = &mut self.cuts[cut_index].polygon;
+        if polygon.vertices.len() > vertices_before {
+            if let Some(vertex) = polygon.vertices.last_mut() {
+                snap(vertex);
+            }
+        }
+        if let Some(vertex) = polygon.dragged_vertex_mut() {
+            snap(vertex);
         }
     }
 
     pub fn interactive_response(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
-        for cuts in &mut self.cuts {
-            cuts.polygon.handle_interactions(plot_response);
+        for index in 0..self.cuts.len() {
+            let vertices_before = self.cuts[index].polygon.vertices.len();
+            if self.cuts[index].handle_interactions(plot_response) {
+                self.last_edited = Some(index);
+            }
+            self.snap_vertices(index, vertices_before);
+        }
+
+        if plot_response.response.hovered() {
+            self.undo_keybinds(&plot_response.response.ctx);
+        }
+    }
+
+    fn undo_keybinds(&mut self, ctx: &egui::Context) {
+        use egui::{Key, KeyboardShortcut, Modifiers};
+
+        // the cut being drawn, otherwise the last one edited
+        let index = self
+            .cuts
+            .iter()
+            .position(|cut| cut.polygon.interactive_clicking)
+            .or(self.last_edited);
+
+        let Some(cut) = index.and_then(|index| self.cuts.get_mut(index)) else {
+            return;
+        };
+
+        // Ctrl+Shift+Z is checked first since Ctrl+Z ignores the shift
+        let redo = ctx.input_mut(|i| {
+            i.consume_shortcut(&KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::Z,
+            )) || i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Y))
+        });
+        let undo = ctx
+            .input_mut(|i| i.consume_shortcut(&KeyboardShortcut::new(Modifiers::COMMAND, Key::Z)));
+
+        if redo {
+            cut.polygon.redo();
+        } else if undo {
+            cut.polygon.undo();
         }
     }
 
@@ -258,6 +719,20 @@ impl HistogramCuts {
             }
         });
 
+        self.new_cut_shape.ui(ui);
+
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.snap_to_grid, "Snap to Grid")
+                .on_hover_text("Round new and dragged vertices to a multiple of the step so cuts can share a boundary");
+            ui.add_enabled(
+                self.snap_to_grid,
+                egui::DragValue::new(&mut self.grid_step)
+                    .speed(0.1)
+                    .range(1e-9..=f64::INFINITY)
+                    .prefix("step: "),
+            );
+        });
+
         ui.horizontal(|ui| {
             ui.label("X Column");
             ui.text_edit_singleline(&mut self.x_column);
@@ -280,11 +755,141 @@ impl HistogramCuts {
                 ui.separator();
 
                 cut.menu_button(ui);
+
+                ui.menu_button("1D Gate", |ui| {
+                    for (y_axis, column) in [(false, &cut.x_column), (true, &cut.y_column)] {
+                        if ui
+                            .button(format!("{} Range", column))
+                            .on_hover_text(
+                                "Add the bounding box on this axis to the cuts used when filling",
+                            )
+                            .clicked()
+                        {
+                            self.pending_range_cuts.push(cut.to_range_cut(y_axis));
+                            ui.close_menu();
+                        }
+                    }
+                });
+
+                if ui
+                    .button("Apply to All")
+                    .on_hover_text("Copy this cut to every other 2D histogram with the same x and y columns, replacing a cut with the same name")
+                    .clicked()
+                {
+                    self.pending_propagation = Some(cut.clone());
+                }
+
+                if ui
+                    .button("Copy Filter")
+                    .on_hover_text("Copy the cut as a python polars filter expression")
+                    .clicked()
+                {
+                    let filter = cut.polars_filter_string();
+                    ui.output_mut(|o| o.copied_text = filter);
+                    log::info!(
+                        "Copied the filter of '{}' to the clipboard",
+                        cut.polygon.name
+                    );
+                }
             });
         }
 
         if let Some(index) = index_to_remove {
             self.cuts.remove(index);
+            self.last_edited = None;
+        }
+
+        if let Some(message) = &self.propagation_message {
+            let mut dismiss = false;
+            ui.horizontal(|ui| {
+                ui.label(message);
+                dismiss = ui.small_button("🗙").clicked();
+            });
+            if dismiss {
+                self.propagation_message = None;
+            }
+        }
+    }
+
+    // Add a copy of the cut, replacing one with the same name
+    pub fn insert_cut(&mut self, mut cut: Cut) {
+        cut.x_column.clone_from(&self.x_column);
+        cut.y_column.clone_from(&self.y_column);
+        cut.selected = false;
+        cut.polygon.interactive_clicking = false;
+        cut.polygon.is_dragging = false;
+
+        match self
+            .cuts
+            .iter_mut()
+            .find(|existing| existing.polygon.name == cut.polygon.name)
+        {
+            Some(existing) => *existing = cut,
+            None => self.cuts.push(cut),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cut(shape: CutShape, vertices: Vec<[f64; 2]>, range_edges: RangeEdges) -> Cut {
+        Cut {
+            polygon: EguiPolygon {
+                vertices,
+                ..Default::default()
+            },
+            x_column: "x".to_string(),
+            y_column: "y".to_string(),
+            selected: false,
+            range_edges,
+            shape,
+            invert: false,
+        }
+    }
+
+    // every shape spans 0..10 on both axes
+    fn cuts(range_edges: RangeEdges) -> Vec<Cut> {
+        vec![
+            cut(
+                CutShape::Polygon,
+                vec![[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]],
+                range_edges,
+            ),
+            cut(
+                CutShape::Rectangle,
+                vec![[0.0, 0.0], [10.0, 10.0]],
+                range_edges,
+            ),
+            cut(
+                CutShape::Ellipse,
+                vec![[0.0, 0.0], [10.0, 10.0]],
+                range_edges,
+            ),
+        ]
+    }
+
+    const EDGE_POINTS: [(f64, f64); 4] = [(0.0, 5.0), (10.0, 5.0), (5.0, 0.0), (5.0, 10.0)];
+
+    #[test]
+    fn exclusive_cuts_drop_points_on_the_boundary() {
+        for cut in cuts(RangeEdges::Exclusive) {
+            assert!(cut.is_inside(5.0, 5.0), "{:?} center", cut.shape);
+            for (x, y) in EDGE_POINTS {
+                assert!(!cut.is_inside(x, y), "{:?} at ({}, {})", cut.shape, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn inclusive_cuts_keep_points_on_the_boundary() {
+        for cut in cuts(RangeEdges::Inclusive) {
+            assert!(cut.is_inside(5.0, 5.0), "{:?} center", cut.shape);
+            for (x, y) in EDGE_POINTS {
+                assert!(cut.is_inside(x, y), "{:?} at ({}, {})", cut.shape, x, y);
+            }
+            assert!(!cut.is_inside(10.1, 5.0), "{:?} outside", cut.shape);
         }
     }
 }
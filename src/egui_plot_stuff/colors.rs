@@ -23,6 +23,23 @@ pub const COLOR_OPTIONS: &[(Color32, &str)] = &[
     (Color32::LIGHT_BLUE, "Light Blue"),
 ];
 
+// Colors of the fitted peaks, cycled by peak index. Blue is left out so the composition line
+// stays distinguishable.
+pub const PEAK_COLORS: &[Color32] = &[
+    Color32::from_rgb(255, 0, 255),
+    Color32::from_rgb(230, 25, 75),
+    Color32::from_rgb(60, 180, 75),
+    Color32::from_rgb(245, 130, 48),
+    Color32::from_rgb(145, 30, 180),
+    Color32::from_rgb(70, 240, 240),
+    Color32::from_rgb(210, 245, 60),
+    Color32::from_rgb(128, 128, 0),
+];
+
+pub fn peak_color(index: usize) -> Color32 {
+    PEAK_COLORS[index % PEAK_COLORS.len()]
+}
+
 #[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
 pub struct Rgb {
     pub r: u8,
@@ -3,12 +3,26 @@ use egui_plot::{Line, LineStyle, PlotPoint, PlotPoints, PlotUi};
 
 use crate::egui_plot_stuff::colors::{Rgb, COLOR_OPTIONS};
 
+// Smallest y value drawn on a log y axis, empty bins sit here instead of dropping out of the plot
+pub const LOG_Y_FLOOR: f64 = 0.1;
+
+// The floor of a line drawn with a y scale, 0.1 counts before the scaling
+pub fn log_y_floor(y_scale: f64) -> f64 {
+    if y_scale > 0.0 {
+        LOG_Y_FLOOR * y_scale
+    } else {
+        LOG_Y_FLOOR
+    }
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct EguiLine {
     pub draw: bool,
     pub name_in_legend: bool,
     pub log_y: bool,
     pub log_x: bool,
+    #[serde(skip, default = "default_y_scale")]
+    pub y_scale: f64, // applied when drawing, e.g. to follow the normalization of the histogram
     pub name: String,
     pub highlighted: bool,
     pub stroke: Stroke,
@@ -27,6 +41,10 @@ pub struct EguiLine {
     pub stroke_rgb: Rgb,
 }
 
+fn default_y_scale() -> f64 {
+    1.0
+}
+
 impl Default for EguiLine {
     fn default() -> Self {
         EguiLine {
@@ -34,6 +52,7 @@ impl Default for EguiLine {
             name_in_legend: false,
             log_y: false,
             log_x: false,
+            y_scale: 1.0,
             name: "Line".to_string(),
             highlighted: false,
             stroke: Stroke::new(1.0, Color32::from_rgb(120, 47, 64)),
@@ -79,8 +98,11 @@ impl EguiLine {
                     } else {
                         x
                     };
-                    let y = if self.log_y && y > 0.0 {
-                        y.log10().max(0.0001)
+                    let y = y * self.y_scale;
+                    // clamp values below the log floor, a background that dips below zero
+                    // would otherwise pull the composition line far below the plot
+                    let y = if self.log_y {
+                        y.max(log_y_floor(self.y_scale)).log10()
                     } else {
                         y
                     };
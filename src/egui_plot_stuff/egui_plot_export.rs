@@ -0,0 +1,124 @@
+use egui::{ColorImage, Rect, Ui};
+use std::path::PathBuf;
+
+// Exports a plot to a PNG by taking a screenshot of the viewport and cropping it to the plot
+#[derive(Debug, Clone, Default)]
+pub struct EguiPlotExport {
+    pub path: Option<PathBuf>,
+    pub result: Option<Result<PathBuf, String>>, // result of the last export
+    screenshot_requested: bool,
+}
+
+impl EguiPlotExport {
+    pub fn request(&mut self, path: PathBuf) {
+        self.path = Some(path);
+        self.result = None;
+        self.screenshot_requested = false;
+    }
+
+    pub fn cancel(&mut self) {
+        self.path = None;
+        self.screenshot_requested = false;
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.path.is_some()
+    }
+
+    pub fn menu_button(&mut self, ui: &mut Ui, name: &str) {
+        if ui
+            .button("Export PNG")
+            .on_hover_text("Save the plot as a PNG image")
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name(format!("{}.png", sanitize_file_name(name)))
+                .add_filter("PNG", &["png"])
+                .save_file()
+            {
+                self.request(path);
+            }
+
+            ui.close_menu();
+        }
+    }
+
+    // Call every frame with the area of the plot, the screenshot arrives a frame after the request
+    pub fn update(&mut self, ui: &Ui, rect: Rect) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+
+        let ctx = ui.ctx().clone();
+
+        if !self.screenshot_requested {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
+            self.screenshot_requested = true;
+            ctx.request_repaint();
+            return;
+        }
+
+        let screenshot = ctx.input(|i| {
+            i.raw.events.iter().find_map(|event| {
+                if let egui::Event::Screenshot { image, .. } = event {
+                    Some(image.clone())
+                } else {
+                    None
+                }
+            })
+        });
+
+        match screenshot {
+            Some(image) => {
+                let plot_image = image.region(&rect, Some(ctx.pixels_per_point()));
+                self.result = match save_png(&plot_image, &path) {
+                    Ok(()) => {
+                        log::info!("Saved plot to {:?}", path);
+                        Ok(path)
+                    }
+                    Err(e) => {
+                        log::error!("Failed to save plot to {:?}: {}", path, e);
+                        Err(e)
+                    }
+                };
+
+                self.path = None;
+                self.screenshot_requested = false;
+            }
+            None => ctx.request_repaint(),
+        }
+    }
+}
+
+fn save_png(image: &ColorImage, path: &PathBuf) -> Result<(), String> {
+    image::save_buffer_with_format(
+        path,
+        image.as_raw(),
+        image.width() as u32,
+        image.height() as u32,
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Replace characters that are not safe in file names
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if sanitized.is_empty() {
+        "plot".to_string()
+    } else {
+        sanitized
+    }
+}
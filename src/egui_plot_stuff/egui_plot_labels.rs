@@ -0,0 +1,61 @@
+// Axis titles and a display title for presentations, empty strings fall back to the defaults.
+// The histogram name is left alone since it is used to look the histogram up.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct PlotLabels {
+    pub x_label: String,
+    pub y_label: String,
+    pub display_title: String,
+}
+
+impl PlotLabels {
+    pub fn menu_button(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Labels", |ui| {
+            egui::Grid::new("plot_labels_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Title:");
+                    ui.text_edit_singleline(&mut self.display_title);
+                    ui.end_row();
+
+                    ui.label("X Axis:");
+                    ui.text_edit_singleline(&mut self.x_label);
+                    ui.end_row();
+
+                    ui.label("Y Axis:");
+                    ui.text_edit_singleline(&mut self.y_label);
+                    ui.end_row();
+                });
+
+            if ui.button("Clear").clicked() {
+                *self = PlotLabels::default();
+            }
+        });
+    }
+
+    // Call after the other settings so the labels replace the calibrated axis label
+    pub fn apply_to_plot<'a>(&self, plot: egui_plot::Plot<'a>) -> egui_plot::Plot<'a> {
+        let plot = if self.x_label.trim().is_empty() {
+            plot
+        } else {
+            plot.x_axis_label(self.x_label.trim())
+        };
+
+        if self.y_label.trim().is_empty() {
+            plot
+        } else {
+            plot.y_axis_label(self.y_label.trim())
+        }
+    }
+
+    // The title sits above the plot, egui_plot doesn't draw one itself
+    pub fn title_ui(&self, ui: &mut egui::Ui) {
+        let title = self.display_title.trim();
+        if title.is_empty() {
+            return;
+        }
+
+        ui.vertical_centered(|ui| {
+            ui.heading(title);
+        });
+    }
+}
@@ -4,6 +4,9 @@ use geo::Contains;
 
 use crate::egui_plot_stuff::colors::{Rgb, COLOR_OPTIONS};
 
+// Number of vertex edits kept for undo, the oldest are dropped first
+const MAX_UNDO_HISTORY: usize = 50;
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct EguiPolygon {
     pub draw: bool,
@@ -30,6 +33,12 @@ pub struct EguiPolygon {
     pub is_dragging: bool,
     #[serde(skip)]
     dragged_vertex_index: Option<usize>,
+
+    // Snapshots of the vertices before each edit
+    #[serde(skip)]
+    undo_stack: Vec<Vec<[f64; 2]>>,
+    #[serde(skip)]
+    redo_stack: Vec<Vec<[f64; 2]>>,
 }
 
 impl Default for EguiPolygon {
@@ -53,6 +62,8 @@ impl Default for EguiPolygon {
             temp_vertex: None,
             is_dragging: false,
             dragged_vertex_index: None,
+            undo_stack: vec![],
+            redo_stack: vec![],
         }
     }
 }
@@ -78,7 +89,10 @@ impl EguiPolygon {
         polygon.contains(&point)
     }
 
-    pub fn handle_interactions(&mut self, plot_response: &PlotResponse<()>) {
+    // Returns true if the vertices were edited
+    pub fn handle_interactions(&mut self, plot_response: &PlotResponse<()>) -> bool {
+        let history_length = self.undo_stack.len();
+
         let pointer_state = plot_response.response.ctx.input(|i| i.pointer.clone());
         if let Some(pointer_pos) = pointer_state.hover_pos() {
             let x_value = plot_response.transform.value_from_position(pointer_pos).x;
@@ -122,6 +136,7 @@ impl EguiPolygon {
                         );
 
                         if pointer_state.button_pressed(egui::PointerButton::Primary) {
+                            self.record_undo();
                             self.is_dragging = true;
                             self.dragged_vertex_index = closest_index;
                         }
@@ -135,25 +150,97 @@ impl EguiPolygon {
                         self.vertices[index] = [x_value, y_value];
                     }
                     if pointer_state.button_released(egui::PointerButton::Primary) {
-                        self.is_dragging = false;
-                        self.dragged_vertex_index = None;
+                        self.finish_drag();
                     }
                 }
             }
         } else if pointer_state.button_released(egui::PointerButton::Primary) {
-            self.is_dragging = false;
+            self.finish_drag();
+        }
+
+        self.undo_stack.len() != history_length
+    }
+
+    fn finish_drag(&mut self) {
+        if self.is_dragging {
+            // a click on a vertex without moving it is not an edit
+            if self.undo_stack.last() == Some(&self.vertices) {
+                self.undo_stack.pop();
+            }
+        }
+        self.is_dragging = false;
+        self.dragged_vertex_index = None;
+    }
+
+    fn record_undo(&mut self) {
+        if self.undo_stack.len() >= MAX_UNDO_HISTORY {
+            self.undo_stack.remove(0);
         }
+        self.undo_stack.push(self.vertices.clone());
+        self.redo_stack.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(vertices) = self.undo_stack.pop() {
+            self.redo_stack
+                .push(std::mem::replace(&mut self.vertices, vertices));
+        }
+    }
+
+    pub fn redo(&mut self) {
+        if let Some(vertices) = self.redo_stack.pop() {
+            self.undo_stack
+                .push(std::mem::replace(&mut self.vertices, vertices));
+        }
+    }
+
+    // Get the vertex that is currently being dragged
+    pub fn dragged_vertex_mut(&mut self) -> Option<&mut [f64; 2]> {
+        if !self.is_dragging {
+            return None;
+        }
+
+        self.dragged_vertex_index
+            .and_then(|index| self.vertices.get_mut(index))
+    }
+
+    // Start a shape defined by two corners, the second corner follows the pointer until released
+    pub fn start_drag_out(&mut self, x: f64, y: f64) {
+        self.record_undo();
+        self.vertices = vec![[x, y], [x, y]];
+        self.interactive_clicking = false;
+        self.temp_vertex = None;
+        self.is_dragging = true;
+        self.dragged_vertex_index = Some(1);
     }
 
     pub fn add_vertex(&mut self, x: f64, y: f64) {
+        self.record_undo();
         self.vertices.push([x, y]);
     }
 
     pub fn clear_vertices(&mut self) {
+        if !self.vertices.is_empty() {
+            self.record_undo();
+        }
         self.vertices.clear();
     }
 
     pub fn draw(&mut self, plot_ui: &mut PlotUi) {
+        let outline = self.vertices.clone();
+        self.draw_outline(plot_ui, outline);
+    }
+
+    // Draw a different outline than the vertices, the vertices are still the drag handles
+    pub fn draw_outline(&mut self, plot_ui: &mut PlotUi, outline: Vec<[f64; 2]>) {
         if self.draw {
             // draw the temp vertex
             if let Some(temp_vertex) = &self.temp_vertex {
@@ -164,7 +251,7 @@ impl EguiPolygon {
                 plot_ui.points(temp_vertex_points);
             }
 
-            let mut polygon = Polygon::new(self.vertices.clone())
+            let mut polygon = Polygon::new(outline)
                 .highlight(self.highlighted)
                 .stroke(self.stroke)
                 .width(self.width)
@@ -242,26 +329,28 @@ impl EguiPolygon {
             });
 
             ui.separator();
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(self.can_undo(), egui::Button::new("Undo"))
+                    .on_hover_text("Ctrl + Z")
+                    .clicked()
+                {
+                    self.undo();
+                }
+                if ui
+                    .add_enabled(self.can_redo(), egui::Button::new("Redo"))
+                    .on_hover_text("Ctrl + Y")
+                    .clicked()
+                {
+                    self.redo();
+                }
+            });
             if ui.button("Clear Vertices").clicked() {
                 self.clear_vertices();
             }
         });
     }
 
-    pub fn polygon_info_menu_button(&mut self, ui: &mut Ui) {
-        ui.menu_button(self.name.to_string(), |ui| {
-            ui.text_edit_singleline(&mut self.name);
-
-            ui.label("Vertices (X,Y)");
-            for (index, vertex) in self.vertices.iter().enumerate() {
-                ui.horizontal(|ui| {
-                    ui.label(format!("Vertex {}", index));
-                    ui.label(format!("({:.2}, {:.2})", vertex[0], vertex[1]));
-                });
-            }
-        });
-    }
-
     pub fn stroke_color_selection_buttons(&mut self, ui: &mut Ui) {
         ui.label("Color");
         ui.horizontal_wrapped(|ui| {
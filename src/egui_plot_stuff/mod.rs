@@ -2,6 +2,8 @@ pub mod colors;
 pub mod egui_horizontal_line;
 pub mod egui_image;
 pub mod egui_line;
+pub mod egui_plot_export;
+pub mod egui_plot_labels;
 pub mod egui_plot_settings;
 pub mod egui_polygon;
 pub mod egui_vertical_line;
@@ -27,10 +27,22 @@ impl BackgroundFitter {
 
     pub fn fit(&mut self) {
         match self.model {
-            FitModel::Gaussian(_, _, _, _) => {
+            FitModel::Gaussian(..) => {
                 log::error!("Gaussian background fitting not implemented");
             }
 
+            FitModel::Lorentzian(_) => {
+                log::error!("Lorentzian background fitting not implemented");
+            }
+
+            FitModel::Voigt(_) => {
+                log::error!("Voigt background fitting not implemented");
+            }
+
+            FitModel::GaussianWithTail(_) => {
+                log::error!("Gaussian with tail background fitting not implemented");
+            }
+
             FitModel::Polynomial(degree) => {
                 log::info!("Fitting polynomial of degree {}", degree);
                 let mut polynomial_fitter = PolynomialFitter::new(degree);
@@ -101,7 +113,10 @@ impl BackgroundFitter {
     pub fn fitter_stats(&self, ui: &mut egui::Ui) {
         if let Some(fit) = &self.result {
             match fit {
-                FitResult::Gaussian(fit) => fit.fit_params_ui(ui),
+                FitResult::Gaussian(fit) => fit.fit_params_ui(ui, None),
+                FitResult::Lorentzian(fit) => fit.fit_params_ui(ui, None),
+                FitResult::Voigt(fit) => fit.fit_params_ui(ui, None),
+                FitResult::GaussianWithTail(fit) => fit.fit_params_ui(ui, None),
                 FitResult::Polynomial(fit) => fit.fit_params_ui(ui),
                 FitResult::Exponential(fit) => fit.fit_params_ui(ui),
                 FitResult::DoubleExponential(fit) => fit.fit_params_ui(ui),
@@ -109,6 +124,24 @@ impl BackgroundFitter {
         }
     }
 
+    // Evaluate the fitted background at x, zero if there is no result
+    pub fn get_background(&self, x: f64) -> f64 {
+        match &self.result {
+            Some(FitResult::Polynomial(fitter)) => fitter.evaluate(x),
+            Some(FitResult::Exponential(fitter)) => fitter
+                .coefficients
+                .as_ref()
+                .map_or(0.0, |coef| coef.a.value * (-x / coef.b.value).exp()),
+            Some(FitResult::DoubleExponential(fitter)) => {
+                fitter.coefficients.as_ref().map_or(0.0, |coef| {
+                    coef.a.value * (-x / coef.b.value).exp()
+                        + coef.c.value * (-x / coef.d.value).exp()
+                })
+            }
+            _ => 0.0,
+        }
+    }
+
     pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
         self.fit_line.draw(plot_ui);
     }
@@ -120,7 +153,7 @@ impl BackgroundFitter {
                 FitResult::Exponential(fitter) => fitter.subtract_background(x_data, y_data),
                 FitResult::DoubleExponential(fitter) => fitter.subtract_background(x_data, y_data),
                 _ => {
-                    log::error!("Gaussian background fitting not implemented");
+                    log::error!("Peak background fitting not implemented");
                     vec![0.0; x_data.len()]
                 }
             }
@@ -6,6 +6,8 @@ use std::io::{Read, Write};
 use super::background_fitter::BackgroundFitter;
 use super::fit_settings::FitSettings;
 use super::main_fitter::Fitter;
+use crate::egui_plot_stuff::egui_line::EguiLine;
+use crate::histoer::histo1d::calibration::Calibration;
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Fits {
@@ -13,6 +15,26 @@ pub struct Fits {
     pub temp_background_fit: Option<BackgroundFitter>,
     pub stored_fits: Vec<Fitter>,
     pub settings: FitSettings,
+    #[serde(skip)]
+    pub calibration: Option<Calibration>, // set by the histogram while its calibration is active
+    #[serde(skip)]
+    pub pending_refit: Option<usize>, // stored fit to refit, picked up by the histogram
+    #[serde(skip)]
+    pub pending_calibration: Option<(Vec<f64>, Vec<f64>)>, // linear fit coefficients and uncertainties, picked up by the histogram
+    #[serde(skip)]
+    pub clone_target: String,
+    #[serde(skip)]
+    pub pending_clone: Option<(Option<usize>, String)>, // stored fit (None for the current fit) and target histogram, picked up by the histogrammer
+    #[serde(skip, default = "default_convoluted_line")]
+    pub convoluted_line: EguiLine, // the current gaussian fit plus its linear background
+}
+
+fn default_convoluted_line() -> EguiLine {
+    let mut line = EguiLine::new(egui::Color32::LIGHT_BLUE);
+    line.name = "Convoluted".to_string();
+    line.width = 1.0;
+    line.draw = false;
+    line
 }
 
 impl Default for Fits {
@@ -28,14 +50,29 @@ impl Fits {
             temp_background_fit: None,
             stored_fits: Vec::new(),
             settings: FitSettings::default(),
+            calibration: None,
+            pending_refit: None,
+            pending_calibration: None,
+            clone_target: String::new(),
+            pending_clone: None,
+            convoluted_line: default_convoluted_line(),
         }
     }
 
+    // Peak positions of the current and stored fits
+    pub fn peak_centroids(&self) -> Vec<f64> {
+        self.temp_fit
+            .iter()
+            .chain(self.stored_fits.iter())
+            .flat_map(|fit| fit.get_peak_markers())
+            .collect()
+    }
+
     pub fn store_temp_fit(&mut self) {
         if let Some(temp_fit) = &mut self.temp_fit.take() {
             temp_fit.set_background_color(egui::Color32::DARK_GREEN);
             temp_fit.set_composition_color(egui::Color32::DARK_BLUE);
-            temp_fit.set_decomposition_color(egui::Color32::from_rgb(150, 0, 255));
+            // the peaks keep their own colors so they can be told apart after storing
 
             temp_fit.set_name(format!("Fit {}", self.stored_fits.len()));
 
@@ -55,11 +92,30 @@ impl Fits {
             temp_background_fit.fit_line.log_x = log_x;
         }
 
+        self.convoluted_line.log_y = log_y;
+        self.convoluted_line.log_x = log_x;
+
         for fit in &mut self.stored_fits {
             fit.set_log(log_y, log_x);
         }
     }
 
+    pub fn set_y_scale(&mut self, y_scale: f64) {
+        if let Some(temp_fit) = &mut self.temp_fit {
+            temp_fit.set_y_scale(y_scale);
+        }
+
+        if let Some(temp_background_fit) = &mut self.temp_background_fit {
+            temp_background_fit.fit_line.y_scale = y_scale;
+        }
+
+        self.convoluted_line.y_scale = y_scale;
+
+        for fit in &mut self.stored_fits {
+            fit.set_y_scale(y_scale);
+        }
+    }
+
     pub fn set_stored_fits_background_color(&mut self, color: egui::Color32) {
         for fit in &mut self.stored_fits {
             if let Some(background) = &mut fit.background {
@@ -87,13 +143,17 @@ impl Fits {
             temp_fit.show_decomposition(self.settings.show_decomposition);
             temp_fit.show_composition(self.settings.show_composition);
             temp_fit.show_background(self.settings.show_background);
+            temp_fit.show_components(self.settings.show_components);
         }
 
         for fit in &mut self.stored_fits {
             fit.show_decomposition(self.settings.show_decomposition);
             fit.show_composition(self.settings.show_composition);
             fit.show_background(self.settings.show_background);
+            fit.show_components(self.settings.show_components);
         }
+
+        self.convoluted_line.draw = self.settings.show_convoluted;
     }
 
     pub fn apply_visibility_settings(&mut self) {
@@ -116,16 +176,77 @@ impl Fits {
         }
     }
 
+    // One row per peak of the stored fits, the mean and FWHM are calibrated like the stats grid
+    pub fn fit_table_csv(&self) -> String {
+        let unit = match &self.calibration {
+            Some(calibration) if !calibration.unit.is_empty() => {
+                format!(" [{}]", calibration.unit)
+            }
+            _ => String::new(),
+        };
+
+        let mut csv = format!(
+            "fit,peak,mean{unit},mean_uncertainty{unit},fwhm{unit},fwhm_uncertainty{unit},area,area_uncertainty\n"
+        );
+        for (fit_index, fit) in self.stored_fits.iter().enumerate() {
+            for (peak_index, (mean, fwhm, area)) in fit.peak_values().into_iter().enumerate() {
+                let (mean, fwhm) = match &self.calibration {
+                    Some(calibration) => calibration.calibrate_peak(&mean, &fwhm),
+                    None => (mean, fwhm),
+                };
+                csv.push_str(&format!(
+                    "{},{},{},{},{},{},{},{}\n",
+                    fit_index,
+                    peak_index,
+                    mean.value,
+                    mean.uncertainty,
+                    fwhm.value,
+                    fwhm.uncertainty,
+                    area.value,
+                    area.uncertainty
+                ));
+            }
+        }
+
+        csv
+    }
+
+    fn export_fit_table_csv(&self) {
+        let Some(path) = FileDialog::new()
+            .set_file_name("fits.csv")
+            .add_filter("CSV", &["csv"])
+            .save_file()
+        else {
+            return;
+        };
+
+        let csv = self.fit_table_csv();
+        match File::create(&path).and_then(|mut file| file.write_all(csv.as_bytes())) {
+            Ok(()) => log::info!("Exported the fit table to {:?}", path),
+            Err(e) => log::error!("Failed to export the fit table to {:?}: {:?}", path, e),
+        }
+    }
+
     fn load_from_file(&mut self) {
         if let Some(path) = FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
             let file = File::open(path);
             match file {
                 Ok(mut file) => {
                     let mut contents = String::new();
-                    file.read_to_string(&mut contents)
-                        .expect("Failed to read file");
-                    let loaded_fits: Fits =
-                        serde_json::from_str(&contents).expect("Failed to deserialize fits");
+                    if let Err(e) = file.read_to_string(&mut contents) {
+                        log::error!("Error reading fits: {:?}", e);
+                        return;
+                    }
+                    let loaded_fits: Fits = match serde_json::from_str(&contents) {
+                        Ok(fits) => fits,
+                        Err(e) => {
+                            log::error!(
+                                "The fits could not be read, they may be from an incompatible version: {}",
+                                e
+                            );
+                            return;
+                        }
+                    };
                     self.stored_fits.extend(loaded_fits.stored_fits); // Append loaded fits to current stored fits
                     self.temp_fit = loaded_fits.temp_fit; // override temp_fit
                     self.temp_background_fit = loaded_fits.temp_background_fit; // override temp_background_fit
@@ -170,6 +291,37 @@ impl Fits {
         for fit in &mut self.stored_fits.iter() {
             fit.draw(plot_ui);
         }
+
+        if self.convoluted_line.draw {
+            if let Some(points) = self.temp_fit.as_ref().and_then(Fitter::convoluted_points) {
+                self.convoluted_line.points = points;
+                self.convoluted_line.draw(plot_ui);
+            }
+        }
+
+        self.draw_peak_labels(plot_ui);
+    }
+
+    pub fn has_residuals(&self) -> bool {
+        self.temp_fit
+            .iter()
+            .chain(self.stored_fits.iter())
+            .any(|fit| fit.residuals().is_some())
+    }
+
+    // Drawn in the residual plot below the histogram, each fit in its composition color
+    pub fn draw_residuals(&self, plot_ui: &mut egui_plot::PlotUi) {
+        plot_ui.hline(egui_plot::HLine::new(0.0).color(egui::Color32::GRAY));
+
+        for fit in self.temp_fit.iter().chain(self.stored_fits.iter()) {
+            if let Some(residuals) = fit.residuals() {
+                let points = egui_plot::Points::new(residuals.to_vec())
+                    .radius(2.0)
+                    .color(fit.composition_line.color)
+                    .name(format!("{} Residuals", fit.name));
+                plot_ui.points(points);
+            }
+        }
     }
 
     pub fn fit_stats_grid_ui(&mut self, ui: &mut egui::Ui) {
@@ -185,23 +337,36 @@ impl Fits {
             .show(ui, |ui| {
                 ui.label("Fit");
                 ui.label("Peak");
-                ui.label("Mean");
-                ui.label("FWHM");
+                match &self.calibration {
+                    Some(calibration) if !calibration.unit.is_empty() => {
+                        ui.label(format!("Mean [{}]", calibration.unit));
+                        ui.label(format!("FWHM [{}]", calibration.unit));
+                    }
+                    _ => {
+                        ui.label("Mean");
+                        ui.label("FWHM");
+                    }
+                }
                 ui.label("Area");
+                ui.label("χ²/ndf");
                 ui.end_row();
 
-                if self.temp_fit.is_some() {
-                    ui.label("Current");
+                if let Some(temp_fit) = &self.temp_fit {
+                    ui.horizontal(|ui| {
+                        temp_fit.quality().label(ui, "Current");
+                        clone_fit_menu(ui, &mut self.clone_target, &mut self.pending_clone, None);
+                    });
 
-                    if let Some(temp_fit) = &self.temp_fit {
-                        temp_fit.fitter_stats(ui);
+                    if let Some(coefficients) = temp_fit.fitter_stats(ui, self.calibration.as_ref())
+                    {
+                        self.pending_calibration = Some(coefficients);
                     }
                 }
 
                 if !self.stored_fits.is_empty() {
                     for (i, fit) in self.stored_fits.iter().enumerate() {
                         ui.horizontal(|ui| {
-                            ui.label(format!("{}", i));
+                            fit.quality().label(ui, format!("{}", i));
 
                             ui.separator();
 
@@ -209,9 +374,29 @@ impl Fits {
                                 to_remove = Some(i);
                             }
 
+                            if ui
+                                .button("Refit")
+                                .on_hover_text(
+                                    "Fit again with the current bin contents in the same region",
+                                )
+                                .clicked()
+                            {
+                                self.pending_refit = Some(i);
+                            }
+
+                            clone_fit_menu(
+                                ui,
+                                &mut self.clone_target,
+                                &mut self.pending_clone,
+                                Some(i),
+                            );
+
                             ui.separator();
                         });
-                        fit.fitter_stats(ui);
+                        if let Some(coefficients) = fit.fitter_stats(ui, self.calibration.as_ref())
+                        {
+                            self.pending_calibration = Some(coefficients);
+                        }
                     }
                 }
             });
@@ -253,6 +438,19 @@ impl Fits {
         ui.menu_button("Fits", |ui| {
             self.save_and_load_ui(ui);
 
+            if ui
+                .add_enabled(
+                    !self.stored_fits.is_empty(),
+                    egui::Button::new("Export fit table (CSV)"),
+                )
+                .on_hover_text("Write the mean, FWHM and area of every peak in the stored fits")
+                .on_disabled_hover_text("No stored fits")
+                .clicked()
+            {
+                self.export_fit_table_csv();
+                ui.close_menu();
+            }
+
             ui.separator();
 
             self.settings.menu_ui(ui);
@@ -274,3 +472,32 @@ impl Fits {
         });
     }
 }
+
+// Copy the peak shape, region, and peak positions of a fit to another 1D histogram by name
+fn clone_fit_menu(
+    ui: &mut egui::Ui,
+    clone_target: &mut String,
+    pending_clone: &mut Option<(Option<usize>, String)>,
+    fit_index: Option<usize>,
+) {
+    ui.menu_button("Clone", |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Histogram:");
+            ui.text_edit_singleline(clone_target);
+        });
+
+        if ui
+            .add_enabled(
+                !clone_target.trim().is_empty(),
+                egui::Button::new("Clone Fit"),
+            )
+            .on_hover_text(
+                "Set up the same fit on the other histogram without its data, ready to fit",
+            )
+            .clicked()
+        {
+            *pending_clone = Some((fit_index, clone_target.trim().to_string()));
+            ui.close_menu();
+        }
+    });
+}
@@ -0,0 +1,122 @@
+use super::main_fitter::{FitResult, Fitter};
+
+// Reduced chi-square limits of the grid colors, above the last one a fit is flagged as bad
+const CHI_SQUARE_GOOD: f64 = 2.0;
+const CHI_SQUARE_WARNING: f64 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FitQualityLevel {
+    Good,
+    Warning,
+    Bad,
+}
+
+#[derive(Debug, Clone)]
+pub struct FitQuality {
+    pub level: FitQualityLevel,
+    pub reasons: Vec<String>,
+}
+
+impl FitQuality {
+    fn flag(&mut self, level: FitQualityLevel, reason: String) {
+        self.level = self.level.max(level);
+        self.reasons.push(reason);
+    }
+
+    pub fn color(&self) -> egui::Color32 {
+        match self.level {
+            FitQualityLevel::Good => egui::Color32::GREEN,
+            FitQualityLevel::Warning => egui::Color32::YELLOW,
+            FitQualityLevel::Bad => egui::Color32::RED,
+        }
+    }
+
+    pub fn hover_text(&self) -> String {
+        if self.reasons.is_empty() {
+            return "No problems found".to_string();
+        }
+        self.reasons.join("\n")
+    }
+
+    // Colored label for the fit column of the stats grid, the tooltip lists the reasons
+    pub fn label(&self, ui: &mut egui::Ui, text: impl Into<String>) {
+        ui.colored_label(self.color(), text.into())
+            .on_hover_text(self.hover_text());
+    }
+}
+
+impl Fitter {
+    // Flags a fit from the reduced chi-square, where the model computes it, and from peaks that
+    // aren't physical: negative or non-finite areas and widths larger than the fit range
+    pub fn quality(&self) -> FitQuality {
+        let mut quality = FitQuality {
+            level: FitQualityLevel::Good,
+            reasons: Vec::new(),
+        };
+
+        if let Some(FitResult::Gaussian(fit)) = &self.result {
+            match fit.reduced_chi_square {
+                Some(chi_square) if !chi_square.is_finite() || chi_square > CHI_SQUARE_WARNING => {
+                    quality.flag(
+                        FitQualityLevel::Bad,
+                        format!(
+                            "χ²/ndf of {:.2} is above {}",
+                            chi_square, CHI_SQUARE_WARNING
+                        ),
+                    );
+                }
+                Some(chi_square) if chi_square > CHI_SQUARE_GOOD => {
+                    quality.flag(
+                        FitQualityLevel::Warning,
+                        format!("χ²/ndf of {:.2} is above {}", chi_square, CHI_SQUARE_GOOD),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        let range_width = self.fit_region().map(|(start, end)| (end - start).abs());
+        for (index, (mean, fwhm, area)) in self.peak_values().iter().enumerate() {
+            if !mean.value.is_finite() || !fwhm.value.is_finite() || !area.value.is_finite() {
+                quality.flag(
+                    FitQualityLevel::Bad,
+                    format!("Peak {} has a value that is not finite", index),
+                );
+                continue;
+            }
+
+            if area.value < 0.0 {
+                quality.flag(
+                    FitQualityLevel::Bad,
+                    format!("Peak {} has a negative area ({:.2})", index, area.value),
+                );
+            }
+
+            if fwhm.value <= 0.0 {
+                quality.flag(
+                    FitQualityLevel::Bad,
+                    format!("Peak {} has a FWHM of {:.2}", index, fwhm.value),
+                );
+            } else if let Some(width) = range_width.filter(|&width| fwhm.value > width) {
+                quality.flag(
+                    FitQualityLevel::Bad,
+                    format!(
+                        "Peak {} is wider than the fit range (FWHM {:.2}, range {:.2})",
+                        index, fwhm.value, width
+                    ),
+                );
+            }
+
+            if let Some((start, end)) = self.fit_region() {
+                if mean.value < start.min(end) || mean.value > start.max(end) {
+                    quality.flag(
+                        FitQualityLevel::Warning,
+                        format!("Peak {} is centered outside the fit range", index),
+                    );
+                }
+            }
+        }
+
+        quality
+    }
+}
@@ -1,14 +1,43 @@
 use super::main_fitter::FitModel;
+use super::models::gaussian::default_fit_line_points;
+
+// The line shape used for the peaks in the region
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum PeakShape {
+    #[default]
+    Gaussian,
+    Lorentzian,
+    Voigt,
+    GaussianWithTail,
+}
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct FitSettings {
     pub show_decomposition: bool,
     pub show_composition: bool,
     pub show_background: bool,
+    #[serde(default)]
+    pub show_components: bool,
+    #[serde(default)]
+    pub show_convoluted: bool,
     pub show_fit_stats: bool,
     pub fit_stats_height: f32,
+    #[serde(default)]
+    pub show_residuals: bool,
+    #[serde(default)]
+    pub show_peak_labels: bool,
+    #[serde(default)]
+    pub refit_after_refill: bool,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize,
+    #[serde(default)]
+    pub peak_shape: PeakShape,
     pub free_stddev: bool,
     pub free_position: bool,
+    #[serde(default)]
+    pub fix_fwhm: bool,
+    #[serde(default)]
+    pub fixed_fwhm: f64,
     pub background_model: FitModel,
     pub background_poly_degree: usize,
     pub background_single_exp_initial_guess: f64,
@@ -21,10 +50,19 @@ impl Default for FitSettings {
             show_decomposition: true,
             show_composition: true,
             show_background: true,
+            show_components: false,
+            show_convoluted: false,
             show_fit_stats: false,
             fit_stats_height: 0.0,
+            show_residuals: false,
+            show_peak_labels: false,
+            refit_after_refill: false,
+            fit_line_points: default_fit_line_points(),
+            peak_shape: PeakShape::Gaussian,
             free_stddev: false,
             free_position: true,
+            fix_fwhm: false,
+            fixed_fwhm: 0.0,
             background_model: FitModel::Polynomial(1),
             background_poly_degree: 1,
             background_single_exp_initial_guess: 200.0,
@@ -34,6 +72,12 @@ impl Default for FitSettings {
 }
 
 impl FitSettings {
+    // The fixed width as a gaussian sigma
+    pub fn fixed_sigma(&self) -> Option<f64> {
+        (self.fix_fwhm && self.fixed_fwhm > 0.0)
+            .then(|| self.fixed_fwhm / (2.0 * (2.0 * 2.0_f64.ln()).sqrt()))
+    }
+
     pub fn menu_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
             ui.label("Fit Stats: ");
@@ -50,6 +94,19 @@ impl FitSettings {
             .on_hover_text("Set the height of the fit statistics grid to see more fits at once");
         });
 
+        ui.checkbox(&mut self.show_residuals, "Show Residuals")
+            .on_hover_text("Plot (data - fit) / error for each bin below the histogram");
+
+        ui.checkbox(&mut self.show_peak_labels, "Label Peaks")
+            .on_hover_text(
+            "Write the mean of each fitted peak above it, calibrated if a calibration is active",
+        );
+
+        ui.checkbox(&mut self.refit_after_refill, "Refit After Refill")
+            .on_hover_text(
+                "Keep the region and peak markers when the histogram is refilled and redo the fits once it is filled",
+            );
+
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -60,6 +117,23 @@ impl FitSettings {
                 .on_hover_text("Show the composition line");
             ui.checkbox(&mut self.show_background, "Background")
                 .on_hover_text("Show the background line");
+            ui.checkbox(&mut self.show_components, "Gaussian Components")
+                .on_hover_text("Show the gaussian part of peaks fitted with a tail");
+            ui.checkbox(&mut self.show_convoluted, "Convoluted")
+                .on_hover_text("Show the current gaussian fit summed with its linear background");
+        });
+
+        ui.separator();
+
+        ui.heading("Peak Shape");
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.peak_shape, PeakShape::Gaussian, "Gaussian");
+            ui.radio_value(&mut self.peak_shape, PeakShape::Lorentzian, "Lorentzian")
+                .on_hover_text("Each peak has its own position and width");
+            ui.radio_value(&mut self.peak_shape, PeakShape::Voigt, "Voigt")
+                .on_hover_text("Thompson-Cox-Hastings pseudo-Voigt: a Gaussian and a Lorentzian with the same FWHM\nThe Gaussian and Lorentzian widths are fitted, the FWHM and mixing follow from them");
+            ui.radio_value(&mut self.peak_shape, PeakShape::GaussianWithTail, "Gaussian + Tail")
+                .on_hover_text("Gaussian with an exponential tail on the low side, each peak fits a tail amplitude and decay length\nThe FWHM is the width of the combined shape");
         });
 
         ui.separator();
@@ -71,6 +145,27 @@ impl FitSettings {
             ui.checkbox(&mut self.free_position, "Free Position")
                 .on_hover_text("Allow the position of the Gaussian to be free");
         });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.fix_fwhm, "Fix FWHM")
+                .on_hover_text("Fit every peak with this FWHM, in the uncalibrated x units");
+            ui.add_enabled(
+                self.fix_fwhm,
+                egui::DragValue::new(&mut self.fixed_fwhm)
+                    .speed(0.1)
+                    .range(0.0..=f64::INFINITY),
+            );
+        });
+        ui.label("Lock single peak positions in the Markers menu")
+            .on_hover_text(
+                "A locked peak keeps its centroid at the marker while the other peaks are free",
+            );
+        ui.add(
+            egui::DragValue::new(&mut self.fit_line_points)
+                .speed(10)
+                .range(10..=100_000)
+                .prefix("Fit Line Points: "),
+        )
+        .on_hover_text("Number of points used to draw each peak, the composition line uses 3x as many.\nFewer points draw faster, more points look smoother");
 
         ui.separator();
 
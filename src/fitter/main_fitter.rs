@@ -1,15 +1,31 @@
 use super::models::double_exponential::DoubleExponentialFitter;
 use super::models::exponential::ExponentialFitter;
-use super::models::gaussian::GaussianFitter;
+use super::models::gaussian::{
+    default_fit_line_points, GaussianConstraints, GaussianFitter, Value,
+};
+use super::models::gaussian_tail::GaussianTailFitter;
+use super::models::lorentzian::LorentzianFitter;
 use super::models::polynomial::PolynomialFitter;
+use super::models::voigt::VoigtFitter;
 
+use crate::egui_plot_stuff::colors::peak_color;
 use crate::egui_plot_stuff::egui_line::EguiLine;
 
 use crate::fitter::background_fitter::BackgroundFitter;
+use crate::histoer::histo1d::calibration::Calibration;
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize, PartialEq)]
 pub enum FitModel {
-    Gaussian(Vec<f64>, bool, bool, f64), // put the initial peak locations in here, free sigma, free position
+    Gaussian(
+        Vec<f64>, // put the initial peak locations in here
+        bool,     // free sigma
+        bool,     // free position
+        f64,      // bin width
+        #[serde(default)] GaussianConstraints,
+    ),
+    Lorentzian(Vec<f64>),        // put the initial peak locations in here
+    Voigt(Vec<f64>),             // put the initial peak locations in here
+    GaussianWithTail(Vec<f64>),  // put the initial peak locations in here
     Polynomial(usize), // the degree of the polynomial: 1 for linear, 2 for quadratic, etc.
     Exponential(f64),  // the initial guess for the exponential decay constant
     DoubleExponential(f64, f64), // the initial guess for the exponential decay constants
@@ -18,6 +34,9 @@ pub enum FitModel {
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub enum FitResult {
     Gaussian(GaussianFitter),
+    Lorentzian(LorentzianFitter),
+    Voigt(VoigtFitter),
+    GaussianWithTail(GaussianTailFitter),
     Polynomial(PolynomialFitter),
     Exponential(ExponentialFitter),
     DoubleExponential(DoubleExponentialFitter),
@@ -33,6 +52,14 @@ pub struct Fitter {
     pub result: Option<FitResult>,
     pub decomposition_lines: Vec<EguiLine>,
     pub composition_line: EguiLine,
+    #[serde(default)]
+    pub component_lines: Vec<EguiLine>, // the gaussian part of tailed peaks
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize,
+    #[serde(default)]
+    pub region: Option<(f64, f64)>, // x range the data was taken from, used to refit
 }
 
 impl Fitter {
@@ -48,6 +75,84 @@ impl Fitter {
             result: None,
             decomposition_lines: Vec::new(),
             composition_line: EguiLine::default(),
+            component_lines: Vec::new(),
+            notes: String::new(),
+            fit_line_points: default_fit_line_points(),
+            region: None,
+        }
+    }
+
+    // The x range to take the data from when refitting, older fits fall back to their data
+    pub fn fit_region(&self) -> Option<(f64, f64)> {
+        self.region
+            .or_else(|| Some((*self.x_data.first()?, *self.x_data.last()?)))
+    }
+
+    // Fit again with new bin contents, starting from the fitted peak positions. The background
+    // is refitted at the same positions and the line colors and names are kept.
+    pub fn refit(
+        &mut self,
+        x_data: Vec<f64>,
+        y_data: Vec<f64>,
+        bin_width: f64,
+        background_data: Option<(Vec<f64>, Vec<f64>)>,
+    ) {
+        let peaks = self.get_peak_markers();
+        match &mut self.model {
+            FitModel::Gaussian(peak_markers, _, _, model_bin_width, _) => {
+                *peak_markers = peaks;
+                *model_bin_width = bin_width;
+            }
+            FitModel::Lorentzian(peak_markers)
+            | FitModel::Voigt(peak_markers)
+            | FitModel::GaussianWithTail(peak_markers) => *peak_markers = peaks,
+            _ => {}
+        }
+
+        if let (Some(background), Some((x, y))) = (&mut self.background, background_data) {
+            background.x_data = x;
+            background.y_data = y;
+            background.result = None;
+        }
+
+        let decomposition_colors: Vec<egui::Color32> = self
+            .decomposition_lines
+            .iter()
+            .map(|line| line.color)
+            .collect();
+        let composition_color = self.composition_line.color;
+        let background_color = self
+            .background
+            .as_ref()
+            .map(|background| background.fit_line.color);
+        let name = self
+            .composition_line
+            .name
+            .strip_suffix("-Composition")
+            .map(str::to_string);
+
+        self.x_data = x_data;
+        self.y_data = y_data;
+        self.result = None;
+        self.decomposition_lines.clear();
+        self.component_lines.clear();
+        self.composition_line.points.clear();
+
+        self.fit();
+
+        for (line, &color) in self
+            .decomposition_lines
+            .iter_mut()
+            .zip(&decomposition_colors)
+        {
+            line.color = color;
+        }
+        self.set_composition_color(composition_color);
+        if let Some(color) = background_color {
+            self.set_background_color(color);
+        }
+        if let Some(name) = name {
+            self.set_name(name);
         }
     }
 
@@ -70,13 +175,66 @@ impl Fitter {
         }
     }
 
-    pub fn get_peak_markers(&self) -> Vec<f64> {
-        if let Some(FitResult::Gaussian(fit)) = &self.result {
-            fit.peak_markers.clone()
-        } else if let FitModel::Gaussian(peak_markers, _, _, _) = &self.model {
-            peak_markers.clone()
+    // Evaluate the fitted background at x, zero if there is no background
+    fn background_value(&self, x: f64) -> f64 {
+        self.background
+            .as_ref()
+            .map_or(0.0, |background| background.get_background(x))
+    }
+
+    // The histogram bin width, the x data are the bin centers
+    fn bin_width(&self) -> f64 {
+        if self.x_data.len() > 1 {
+            self.x_data[1] - self.x_data[0]
         } else {
-            Vec::new()
+            1.0
+        }
+    }
+
+    fn set_decomposition_lines(&mut self, fit_lines: &[Vec<[f64; 2]>]) {
+        for (i, line) in fit_lines.iter().enumerate() {
+            let mut fit_line = EguiLine::new(peak_color(i));
+            fit_line.name = format!("Peak {}", i);
+
+            fit_line.points.clone_from(line);
+            fit_line.name_in_legend = false;
+            fit_line.width = 1.0;
+            self.decomposition_lines.push(fit_line);
+        }
+    }
+
+    fn set_component_lines(&mut self, component_lines: &[Vec<[f64; 2]>]) {
+        for (i, line) in component_lines.iter().enumerate() {
+            let mut component_line = EguiLine::new(egui::Color32::from_rgb(255, 165, 0));
+            component_line.name = format!("Peak {} Gaussian", i);
+
+            component_line.points.clone_from(line);
+            component_line.name_in_legend = false;
+            component_line.width = 1.0;
+            component_line.draw = false;
+            self.component_lines.push(component_line);
+        }
+    }
+
+    fn set_composition_line(&mut self, points: Vec<[f64; 2]>) {
+        let mut line = EguiLine::new(egui::Color32::BLUE);
+        line.name = "Composition".to_string();
+        line.points = points;
+        line.width = 1.0;
+        self.composition_line = line;
+    }
+
+    pub fn get_peak_markers(&self) -> Vec<f64> {
+        match (&self.result, &self.model) {
+            (Some(FitResult::Gaussian(fit)), _) => fit.peak_markers.clone(),
+            (Some(FitResult::Lorentzian(fit)), _) => fit.peak_markers.clone(),
+            (Some(FitResult::Voigt(fit)), _) => fit.peak_markers.clone(),
+            (Some(FitResult::GaussianWithTail(fit)), _) => fit.peak_markers.clone(),
+            (_, FitModel::Gaussian(peak_markers, _, _, _, _)) => peak_markers.clone(),
+            (_, FitModel::Lorentzian(peak_markers)) => peak_markers.clone(),
+            (_, FitModel::Voigt(peak_markers)) => peak_markers.clone(),
+            (_, FitModel::GaussianWithTail(peak_markers)) => peak_markers.clone(),
+            _ => Vec::new(),
         }
     }
 
@@ -93,7 +251,13 @@ impl Fitter {
 
         // Perform the fit based on the model
         match &self.model {
-            FitModel::Gaussian(peak_markers, free_stddev, free_position, bin_width) => {
+            FitModel::Gaussian(
+                peak_markers,
+                free_stddev,
+                free_position,
+                bin_width,
+                constraints,
+            ) => {
                 // Perform Gaussian fit
                 let mut fit = GaussianFitter::new(
                     self.x_data.clone(),
@@ -103,21 +267,21 @@ impl Fitter {
                     *free_position,
                     *bin_width,
                 );
+                fit.fit_line_points = self.fit_line_points;
+                fit.constraints = constraints.clone();
+                // the errors come from the raw counts, not the background subtracted ones
+                fit.y_err = Some(self.y_err.clone().unwrap_or_else(|| {
+                    self.y_data
+                        .iter()
+                        .map(|y| y.abs().sqrt().max(1.0))
+                        .collect()
+                }));
 
                 fit.multi_gauss_fit();
 
                 // get the fit_lines and store them in the decomposition_lines
-                let decomposition_default_color = egui::Color32::from_rgb(255, 0, 255);
                 if let Some(fit_lines) = &fit.fit_lines {
-                    for (i, line) in fit_lines.iter().enumerate() {
-                        let mut fit_line = EguiLine::new(decomposition_default_color);
-                        fit_line.name = format!("Peak {}", i);
-
-                        fit_line.points.clone_from(line);
-                        fit_line.name_in_legend = false;
-                        fit_line.width = 1.0;
-                        self.decomposition_lines.push(fit_line);
-                    }
+                    self.set_decomposition_lines(fit_lines);
                 }
 
                 // calculate the composition line
@@ -164,20 +328,87 @@ impl Fitter {
                         }
                         _ => {}
                     }
-                    // if let Some((slope, intercept)) = background.get_slope_intercept() {
-                    //     let composition_points =
-                    //         fit.composition_fit_points_linear_bg(slope, intercept);
-
-                    // let mut line = EguiLine::new(egui::Color32::BLUE);
-                    // line.name = "Composition".to_string();
-                    // line.points = composition_points;
-                    // self.composition_line = line;
-                    // }
                 }
 
                 self.result = Some(FitResult::Gaussian(fit));
             }
 
+            FitModel::Lorentzian(peak_markers) => {
+                let mut fit = LorentzianFitter::new(
+                    self.x_data.clone(),
+                    y_data_corrected,
+                    peak_markers.clone(),
+                    self.bin_width(),
+                );
+                fit.fit_line_points = self.fit_line_points;
+
+                fit.multi_lorentzian_fit();
+
+                if let Some(fit_lines) = &fit.fit_lines {
+                    self.set_decomposition_lines(fit_lines);
+                }
+
+                if self.background.is_some() {
+                    let composition_points =
+                        fit.composition_fit_points(|x| self.background_value(x));
+                    self.set_composition_line(composition_points);
+                }
+
+                self.result = Some(FitResult::Lorentzian(fit));
+            }
+
+            FitModel::Voigt(peak_markers) => {
+                let mut fit = VoigtFitter::new(
+                    self.x_data.clone(),
+                    y_data_corrected,
+                    peak_markers.clone(),
+                    self.bin_width(),
+                );
+                fit.fit_line_points = self.fit_line_points;
+
+                fit.multi_voigt_fit();
+
+                if let Some(fit_lines) = &fit.fit_lines {
+                    self.set_decomposition_lines(fit_lines);
+                }
+
+                if self.background.is_some() {
+                    let composition_points =
+                        fit.composition_fit_points(|x| self.background_value(x));
+                    self.set_composition_line(composition_points);
+                }
+
+                self.result = Some(FitResult::Voigt(fit));
+            }
+
+            FitModel::GaussianWithTail(peak_markers) => {
+                let mut fit = GaussianTailFitter::new(
+                    self.x_data.clone(),
+                    y_data_corrected,
+                    peak_markers.clone(),
+                    self.bin_width(),
+                );
+                fit.fit_line_points = self.fit_line_points;
+
+                fit.multi_gaussian_tail_fit();
+
+                if let Some(fit_lines) = &fit.fit_lines {
+                    self.set_decomposition_lines(fit_lines);
+                }
+
+                if let Some(gaussian_lines) = &fit.gaussian_lines {
+                    self.set_component_lines(gaussian_lines);
+                }
+
+                if self.background.is_some() {
+                    let composition_points =
+                        fit.composition_fit_points(|x| self.background_value(x));
+                    self.set_composition_line(composition_points);
+                }
+
+                self.result = Some(FitResult::GaussianWithTail(fit));
+            }
+
             FitModel::Polynomial(degree) => {
                 // Perform Polynomial fit
                 let mut fit = PolynomialFitter::new(*degree);
@@ -210,14 +441,65 @@ impl Fitter {
         }
     }
 
-    pub fn fitter_stats(&self, ui: &mut egui::Ui) {
-        if let Some(fit) = &self.result {
-            match fit {
-                FitResult::Gaussian(fit) => fit.fit_params_ui(ui),
-                FitResult::Polynomial(fit) => fit.fit_params_ui(ui),
-                FitResult::Exponential(fit) => fit.fit_params_ui(ui),
-                FitResult::DoubleExponential(fit) => fit.fit_params_ui(ui),
+    // Returns the calibration coefficients and their uncertainties if a linear fit is picked as
+    // the calibration
+    pub fn fitter_stats(
+        &self,
+        ui: &mut egui::Ui,
+        calibration: Option<&Calibration>,
+    ) -> Option<(Vec<f64>, Vec<f64>)> {
+        match self.result.as_ref()? {
+            FitResult::Gaussian(fit) => fit.fit_params_ui(ui, calibration),
+            FitResult::Lorentzian(fit) => fit.fit_params_ui(ui, calibration),
+            FitResult::Voigt(fit) => fit.fit_params_ui(ui, calibration),
+            FitResult::GaussianWithTail(fit) => fit.fit_params_ui(ui, calibration),
+            FitResult::Polynomial(fit) => {
+                fit.fit_params_ui(ui);
+                return fit.use_as_calibration_ui(ui);
             }
+            FitResult::Exponential(fit) => fit.fit_params_ui(ui),
+            FitResult::DoubleExponential(fit) => fit.fit_params_ui(ui),
+        }
+
+        None
+    }
+
+    // Mean, FWHM and area of every fitted peak, empty for the background models
+    pub fn peak_values(&self) -> Vec<(Value, Value, Value)> {
+        fn values<P>(
+            params: &Option<Vec<P>>,
+            get: impl Fn(&P) -> (&Value, &Value, &Value),
+        ) -> Vec<(Value, Value, Value)> {
+            params
+                .iter()
+                .flatten()
+                .map(|p| {
+                    let (mean, fwhm, area) = get(p);
+                    (mean.clone(), fwhm.clone(), area.clone())
+                })
+                .collect()
+        }
+
+        match &self.result {
+            Some(FitResult::Gaussian(fit)) => {
+                values(&fit.fit_params, |p| (&p.mean, &p.fwhm, &p.area))
+            }
+            Some(FitResult::Lorentzian(fit)) => {
+                values(&fit.fit_params, |p| (&p.mean, &p.fwhm, &p.area))
+            }
+            Some(FitResult::Voigt(fit)) => values(&fit.fit_params, |p| (&p.mean, &p.fwhm, &p.area)),
+            Some(FitResult::GaussianWithTail(fit)) => {
+                values(&fit.fit_params, |p| (&p.mean, &p.fwhm, &p.area))
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // Normalized residuals of the fit, only Gaussian fits compute them
+    pub fn residuals(&self) -> Option<&[[f64; 2]]> {
+        match &self.result {
+            Some(FitResult::Gaussian(fit)) if !fit.residuals.is_empty() => Some(&fit.residuals),
+            _ => None,
         }
     }
 
@@ -229,6 +511,11 @@ impl Fitter {
 
     pub fn set_composition_color(&mut self, color: egui::Color32) {
         self.composition_line.color = color;
+
+        // a polynomial fit is drawn in place of the composition line
+        if let Some(FitResult::Polynomial(fit)) = &mut self.result {
+            fit.fit_line.color = color;
+        }
     }
 
     pub fn set_decomposition_color(&mut self, color: egui::Color32) {
@@ -243,6 +530,12 @@ impl Fitter {
         }
     }
 
+    pub fn show_components(&mut self, show: bool) {
+        for line in &mut self.component_lines {
+            line.draw = show;
+        }
+    }
+
     pub fn show_composition(&mut self, show: bool) {
         self.composition_line.draw = show;
     }
@@ -260,22 +553,47 @@ impl Fitter {
             line.name = format!("{}-Peak {}", name, i);
         }
 
+        for (i, line) in self.component_lines.iter_mut().enumerate() {
+            line.name = format!("{}-Peak {} Gaussian", name, i);
+        }
+
         if let Some(background) = &mut self.background {
             background.fit_line.name = format!("{}-Background", name);
         }
+
+        if let Some(FitResult::Polynomial(fit)) = &mut self.result {
+            fit.fit_line.name = format!("{}-Polynomial", name);
+        }
     }
 
     pub fn lines_ui(&mut self, ui: &mut egui::Ui) {
         if let Some(background) = &mut self.background {
             background.fit_line.menu_button(ui);
+
+            ui.collapsing(format!("{} Background Parameters", self.name), |ui| {
+                background.fitter_stats(ui);
+            });
         }
 
         self.composition_line.menu_button(ui);
 
+        if let Some(FitResult::Polynomial(fit)) = &mut self.result {
+            fit.fit_line.menu_button(ui);
+        }
+
         for line in &mut self.decomposition_lines {
             line.menu_button(ui);
         }
 
+        for line in &mut self.component_lines {
+            line.menu_button(ui);
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Notes:");
+            ui.text_edit_singleline(&mut self.notes);
+        });
+
         ui.separator();
     }
 
@@ -286,15 +604,45 @@ impl Fitter {
             line.draw(plot_ui);
         }
 
+        for line in &self.component_lines {
+            line.draw(plot_ui);
+        }
+
         // Draw the background if it exists
         if let Some(background) = &self.background {
             background.draw(plot_ui);
         }
 
+        // a polynomial fit has no decomposition, its line is kept in the result
+        if let Some(FitResult::Polynomial(fit)) = &self.result {
+            fit.draw(plot_ui);
+        }
+
         // Draw the composition line
         self.composition_line.draw(plot_ui);
     }
 
+    // A gaussian fit summed with its linear background, None for other models and backgrounds
+    pub fn convoluted_points(&self) -> Option<Vec<[f64; 2]>> {
+        let Some(FitResult::Gaussian(fit)) = &self.result else {
+            return None;
+        };
+        let Some(FitResult::Polynomial(background)) = &self.background.as_ref()?.result else {
+            return None;
+        };
+        let &[intercept, slope] = background.coefficients.as_deref()? else {
+            return None;
+        };
+
+        // the line drawing the points applies the y scale before its own log clamp
+        let points = fit
+            .calculate_convoluted_fit_points_with_linear_background(slope, intercept, false)
+            .into_iter()
+            .map(|point| [point.x, point.y])
+            .collect();
+        Some(points)
+    }
+
     // Set the log_y flag for all lines
     pub fn set_log(&mut self, log_y: bool, log_x: bool) {
         for line in &mut self.decomposition_lines {
@@ -302,12 +650,43 @@ impl Fitter {
             line.log_x = log_x;
         }
 
+        for line in &mut self.component_lines {
+            line.log_y = log_y;
+            line.log_x = log_x;
+        }
+
         if let Some(background) = &mut self.background {
             background.fit_line.log_y = log_y;
             background.fit_line.log_x = log_x;
         }
 
+        if let Some(FitResult::Polynomial(fit)) = &mut self.result {
+            fit.fit_line.log_y = log_y;
+            fit.fit_line.log_x = log_x;
+        }
+
         self.composition_line.log_y = log_y;
         self.composition_line.log_x = log_x;
     }
+
+    // Scale the drawn lines like the histogram counts, the fit itself is in raw counts
+    pub fn set_y_scale(&mut self, y_scale: f64) {
+        for line in self
+            .decomposition_lines
+            .iter_mut()
+            .chain(self.component_lines.iter_mut())
+        {
+            line.y_scale = y_scale;
+        }
+
+        if let Some(background) = &mut self.background {
+            background.fit_line.y_scale = y_scale;
+        }
+
+        if let Some(FitResult::Polynomial(fit)) = &mut self.result {
+            fit.fit_line.y_scale = y_scale;
+        }
+
+        self.composition_line.y_scale = y_scale;
+    }
 }
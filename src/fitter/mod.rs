@@ -1,5 +1,7 @@
 pub mod background_fitter;
 pub mod fit_handler;
+pub mod fit_quality;
 pub mod fit_settings;
 pub mod main_fitter;
 pub mod models;
+pub mod peak_labels;
@@ -1,7 +1,13 @@
-use nalgebra::DVector;
+use egui_plot::PlotPoint;
+use find_peaks::PeakFinder;
+use nalgebra::{DMatrix, DVector};
 use varpro::model::builder::SeparableModelBuilder;
 use varpro::solvers::levmar::{LevMarProblemBuilder, LevMarSolver};
 
+use crate::egui_plot_stuff::egui_line::LOG_Y_FLOOR;
+use crate::histoer::histo1d::calibration::Calibration;
+use crate::histoer::histo1d::peak_finder::peak_centroid;
+
 #[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Value {
     pub value: f64,
@@ -32,7 +38,7 @@ impl GaussianParams {
             log::error!("Area is negative");
             return None;
         }
-        let area_uncertainty = Self::area_uncertainty(amplitude.clone(), sigma.clone());
+        let area_uncertainty = Self::area_uncertainty(&amplitude, &sigma, 0.0, bin_width);
 
         Some(GaussianParams {
             amplitude,
@@ -64,31 +70,38 @@ impl GaussianParams {
         amplitude * sigma * (2.0 * std::f64::consts::PI).sqrt() / bin_width
     }
 
-    // Method to calculate area uncertainty
-    fn area_uncertainty(amplitude: Value, sigma: Value) -> f64 {
-        let two_pi_sqrt = (2.0 * std::f64::consts::PI).sqrt();
-        ((sigma.value * two_pi_sqrt * amplitude.uncertainty).powi(2)
-            + (amplitude.value * two_pi_sqrt * sigma.uncertainty).powi(2))
-        .sqrt()
+    // Propagate the amplitude and sigma uncertainties to the area, including their covariance
+    fn area_uncertainty(amplitude: &Value, sigma: &Value, covariance: f64, bin_width: f64) -> f64 {
+        let scale = (2.0 * std::f64::consts::PI).sqrt() / bin_width;
+        let d_amplitude = scale * sigma.value;
+        let d_sigma = scale * amplitude.value;
+
+        ((d_amplitude * amplitude.uncertainty).powi(2)
+            + (d_sigma * sigma.uncertainty).powi(2)
+            + 2.0 * d_amplitude * d_sigma * covariance)
+            .max(0.0)
+            .sqrt()
     }
 
-    pub fn params_ui(&self, ui: &mut egui::Ui) {
-        ui.label(format!(
-            "{:.2} ± {:.2}",
-            self.mean.value, self.mean.uncertainty
-        ));
-        ui.label(format!(
-            "{:.2} ± {:.2}",
-            self.fwhm.value, self.fwhm.uncertainty
-        ));
+    pub fn set_amplitude_sigma_covariance(&mut self, covariance: f64, bin_width: f64) {
+        self.area.uncertainty =
+            Self::area_uncertainty(&self.amplitude, &self.sigma, covariance, bin_width);
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        let (mean, fwhm) = calibration.map_or((self.mean.clone(), self.fwhm.clone()), |c| {
+            c.calibrate_peak(&self.mean, &self.fwhm)
+        });
+
+        ui.label(format!("{:.2} ± {:.2}", mean.value, mean.uncertainty));
+        ui.label(format!("{:.2} ± {:.2}", fwhm.value, fwhm.uncertainty));
         ui.label(format!(
             "{:.2} ± {:.2}",
             self.area.value, self.area.uncertainty
         ));
     }
 
-    pub fn fit_line_points(&self) -> Vec<[f64; 2]> {
-        let num_points = 1000;
+    pub fn fit_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
         let start = self.mean.value - 5.0 * self.sigma.value; // Adjust start and end to be +/- 5 sigma from the mean
         let end = self.mean.value + 5.0 * self.sigma.value;
         let step = (end - start) / num_points as f64;
@@ -104,6 +117,29 @@ impl GaussianParams {
     }
 }
 
+// Parameters held fixed in the fit on top of the free sigma and free position settings
+#[derive(Default, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GaussianConstraints {
+    pub fixed_positions: Vec<bool>, // per peak, true keeps the centroid at the peak marker
+    pub fixed_sigma: Option<f64>,   // every peak uses this sigma
+}
+
+impl GaussianConstraints {
+    pub fn is_active(&self) -> bool {
+        self.fixed_sigma.is_some() || self.fixed_positions.iter().any(|&fixed| fixed)
+    }
+
+    pub fn is_position_fixed(&self, peak: usize) -> bool {
+        self.fixed_positions.get(peak).copied().unwrap_or(false)
+    }
+
+    fn remove_peak(&mut self, peak: usize) {
+        if peak < self.fixed_positions.len() {
+            self.fixed_positions.remove(peak);
+        }
+    }
+}
+
 #[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct GaussianFitter {
     x: Vec<f64>,
@@ -114,6 +150,20 @@ pub struct GaussianFitter {
     pub free_stddev: bool, // false = fit all the gaussians with the same sigma
     pub free_position: bool, // false = fix the position of the gaussians to the peak_markers
     pub bin_width: f64,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize, // number of points in each peak line, the composition line uses 3x
+    #[serde(default)]
+    pub y_err: Option<Vec<f64>>, // per bin errors, sqrt(N) is used when not given
+    #[serde(default)]
+    pub reduced_chi_square: Option<f64>,
+    #[serde(default)]
+    pub residuals: Vec<[f64; 2]>, // (x, (data - fit) / error) for each bin
+    #[serde(default)]
+    pub constraints: GaussianConstraints,
+}
+
+pub fn default_fit_line_points() -> usize {
+    1000
 }
 
 impl GaussianFitter {
@@ -134,9 +184,50 @@ impl GaussianFitter {
             free_stddev,
             free_position,
             bin_width,
+            fit_line_points: default_fit_line_points(),
+            y_err: None,
+            reduced_chi_square: None,
+            residuals: Vec::new(),
+            constraints: GaussianConstraints::default(),
         }
     }
 
+    // Centroids, in increasing x, of the peaks in the y data that rise at least min_prominence
+    // above their surroundings and are at least min_distance bins apart. The fitter is given
+    // background subtracted data, so the prominence is measured above the background.
+    pub fn auto_find_peaks(&self, min_prominence: f64, min_distance: usize) -> Vec<f64> {
+        if self.y.is_empty() {
+            return Vec::new();
+        }
+
+        let mut peak_finder = PeakFinder::new(&self.y);
+        peak_finder.with_min_prominence(min_prominence);
+        peak_finder.with_min_distance(min_distance.max(1));
+
+        // the peak finder orders the peaks by prominence
+        let mut centroids: Vec<f64> = peak_finder
+            .find_peaks()
+            .iter()
+            .map(|peak| peak_centroid(&self.x, &self.y, peak.middle_position()))
+            .collect();
+        centroids.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        centroids
+    }
+
+    // The covariance matrix lists the amplitudes first and then the nonlinear parameters,
+    // sigma_index is None when the width was fixed
+    fn amplitude_sigma_covariance(
+        covariance: &DMatrix<f64>,
+        peak: usize,
+        peaks: usize,
+        sigma_index: Option<usize>,
+    ) -> f64 {
+        sigma_index
+            .map(|index| (peak, peaks + index))
+            .filter(|&(row, column)| row < covariance.nrows() && column < covariance.ncols())
+            .map_or(0.0, |position| covariance[position])
+    }
+
     fn gaussian(x: &DVector<f64>, mean: f64, sigma: f64) -> DVector<f64> {
         x.map(|x_val| (-((x_val - mean).powi(2)) / (2.0 * sigma.powi(2))).exp())
     }
@@ -301,7 +392,7 @@ impl GaussianFitter {
                     let sigma_variance = nonlinear_variances[i * 2 + 1];
                     let amplitude_variance = linear_variances[i];
 
-                    if let Some(gaussian_params) = GaussianParams::new(
+                    if let Some(mut gaussian_params) = GaussianParams::new(
                         Value {
                             value: amplitude,
                             uncertainty: amplitude_variance.sqrt(),
@@ -316,6 +407,15 @@ impl GaussianFitter {
                         },
                         self.bin_width,
                     ) {
+                        gaussian_params.set_amplitude_sigma_covariance(
+                            Self::amplitude_sigma_covariance(
+                                fit_statistics.covariance_matrix(),
+                                i,
+                                linear_coefficients.len(),
+                                Some(i * 2 + 1),
+                            ),
+                            self.bin_width,
+                        );
                         params.push(gaussian_params);
                     } else {
                         // Remove the peak marker with the negative area and retry the fit
@@ -409,7 +509,7 @@ impl GaussianFitter {
                     let mean_variance = nonlinear_variances[i];
                     let amplitude_variance = linear_variances[i];
 
-                    if let Some(gaussian_params) = GaussianParams::new(
+                    if let Some(mut gaussian_params) = GaussianParams::new(
                         Value {
                             value: amplitude,
                             uncertainty: amplitude_variance.sqrt(),
@@ -424,6 +524,15 @@ impl GaussianFitter {
                         },
                         self.bin_width,
                     ) {
+                        gaussian_params.set_amplitude_sigma_covariance(
+                            Self::amplitude_sigma_covariance(
+                                fit_statistics.covariance_matrix(),
+                                i,
+                                linear_coefficients.len(),
+                                Some(nonlinear_parameters.len() - 1),
+                            ),
+                            self.bin_width,
+                        );
                         params.push(gaussian_params);
                     } else {
                         self.peak_markers.remove(i);
@@ -537,7 +646,7 @@ impl GaussianFitter {
                     let mean_uncertainty = 0.0;
                     let amplitude_variance = linear_variances[i];
 
-                    if let Some(gaussian_params) = GaussianParams::new(
+                    if let Some(mut gaussian_params) = GaussianParams::new(
                         Value {
                             value: amplitude,
                             uncertainty: amplitude_variance.sqrt(),
@@ -552,6 +661,15 @@ impl GaussianFitter {
                         },
                         self.bin_width,
                     ) {
+                        gaussian_params.set_amplitude_sigma_covariance(
+                            Self::amplitude_sigma_covariance(
+                                fit_statistics.covariance_matrix(),
+                                i,
+                                linear_coefficients.len(),
+                                Some(nonlinear_parameters.len() - 1),
+                            ),
+                            self.bin_width,
+                        );
                         params.push(gaussian_params);
                     } else {
                         self.peak_markers.remove(i);
@@ -679,7 +797,7 @@ impl GaussianFitter {
                     let mean_uncertainty = 0.0;
                     let amplitude_variance = linear_variances[i];
 
-                    if let Some(gaussian_params) = GaussianParams::new(
+                    if let Some(mut gaussian_params) = GaussianParams::new(
                         Value {
                             value: amplitude,
                             uncertainty: amplitude_variance.sqrt(),
@@ -694,6 +812,15 @@ impl GaussianFitter {
                         },
                         self.bin_width,
                     ) {
+                        gaussian_params.set_amplitude_sigma_covariance(
+                            Self::amplitude_sigma_covariance(
+                                fit_statistics.covariance_matrix(),
+                                i,
+                                linear_coefficients.len(),
+                                Some(i),
+                            ),
+                            self.bin_width,
+                        );
                         params.push(gaussian_params);
                     } else {
                         self.peak_markers.remove(i);
@@ -716,8 +843,205 @@ impl GaussianFitter {
         }
     }
 
+    // Fit with some centroids or the width held fixed. Each peak only adds its free parameters,
+    // a peak with a fixed centroid and width only fits its amplitude.
+    fn multi_gauss_fit_constrained(&mut self) {
+        self.fit_params = None;
+        self.fit_lines = None;
+
+        if self.x.len() != self.y.len() {
+            log::error!("x_data and y_data must have the same length");
+            return;
+        }
+
+        if self.peak_markers.is_empty() {
+            log::error!(
+                "Peak markers are empty. Must have at least 1 marker to fit with constraints"
+            );
+            return;
+        }
+
+        let shared_sigma = !self.free_stddev;
+        let fixed_sigma = self.constraints.fixed_sigma;
+        let fixed_positions: Vec<bool> = (0..self.peak_markers.len())
+            .map(|i| !self.free_position || self.constraints.is_position_fixed(i))
+            .collect();
+
+        let mean_name = |i: usize| format!("mean{}", i);
+        let sigma_name = |i: usize| {
+            if shared_sigma {
+                "sigma".to_string()
+            } else {
+                format!("sigma{}", i)
+            }
+        };
+
+        let average_sigma = self.average_sigma();
+        let mut parameter_names: Vec<String> = Vec::new();
+        let mut initial_guess: Vec<f64> = Vec::new();
+
+        for (i, &mean) in self.peak_markers.iter().enumerate() {
+            if !fixed_positions[i] {
+                parameter_names.push(mean_name(i));
+                initial_guess.push(mean);
+            }
+            if fixed_sigma.is_none() && !shared_sigma {
+                parameter_names.push(sigma_name(i));
+                initial_guess.push(average_sigma);
+            }
+        }
+        if fixed_sigma.is_none() && shared_sigma {
+            parameter_names.push(sigma_name(0));
+            initial_guess.push(average_sigma);
+        }
+
+        if parameter_names.is_empty() {
+            log::error!("Every centroid and the width are fixed, free at least one of them to fit");
+            return;
+        }
+
+        let x_data = DVector::from_vec(self.x.clone());
+        let y_data = DVector::from_vec(self.y.clone());
+
+        let mut builder_proxy = SeparableModelBuilder::<f64>::new(parameter_names.clone())
+            .initial_parameters(initial_guess)
+            .independent_variable(x_data);
+
+        for (i, &peak) in self.peak_markers.iter().enumerate() {
+            builder_proxy = match (fixed_positions[i], fixed_sigma) {
+                (false, None) => builder_proxy
+                    .function([mean_name(i), sigma_name(i)], Self::gaussian)
+                    .partial_deriv(mean_name(i), Self::gaussian_pd_mean)
+                    .partial_deriv(sigma_name(i), Self::gaussian_pd_std_dev),
+                (false, Some(sigma)) => builder_proxy
+                    .function([mean_name(i)], move |x: &DVector<f64>, mean: f64| {
+                        Self::gaussian(x, mean, sigma)
+                    })
+                    .partial_deriv(mean_name(i), move |x: &DVector<f64>, mean: f64| {
+                        Self::gaussian_pd_mean(x, mean, sigma)
+                    }),
+                (true, None) => builder_proxy
+                    .function([sigma_name(i)], move |x: &DVector<f64>, sigma: f64| {
+                        Self::gaussian(x, peak, sigma)
+                    })
+                    .partial_deriv(sigma_name(i), move |x: &DVector<f64>, sigma: f64| {
+                        Self::gaussian_pd_std_dev(x, peak, sigma)
+                    }),
+                (true, Some(sigma)) => builder_proxy
+                    .invariant_function(move |x: &DVector<f64>| Self::gaussian(x, peak, sigma)),
+            };
+        }
+
+        let model = match builder_proxy.build() {
+            Ok(model) => model,
+            Err(e) => {
+                log::error!("Failed to build model: {:?}", e);
+                return;
+            }
+        };
+
+        let problem = match LevMarProblemBuilder::new(model)
+            .observations(y_data)
+            .build()
+        {
+            Ok(problem) => problem,
+            Err(e) => {
+                log::error!("Failed to build problem: {:?}", e);
+                return;
+            }
+        };
+
+        match LevMarSolver::default().fit_with_statistics(problem) {
+            Ok((fit_result, fit_statistics)) => {
+                let nonlinear_parameters = fit_result.nonlinear_parameters();
+                let nonlinear_variances = fit_statistics.nonlinear_parameters_variance();
+                let linear_coefficients = match fit_result.linear_coefficients() {
+                    Some(coefficients) => coefficients,
+                    None => {
+                        log::error!("Failed to get linear coefficients");
+                        return;
+                    }
+                };
+                let linear_variances = fit_statistics.linear_coefficients_variance();
+
+                // a fixed parameter keeps its value with no uncertainty
+                let fitted_value = |name: &str, fixed: f64| match parameter_names
+                    .iter()
+                    .position(|parameter| parameter == name)
+                {
+                    Some(index) => Value {
+                        value: nonlinear_parameters[index],
+                        uncertainty: nonlinear_variances[index].sqrt(),
+                    },
+                    None => Value {
+                        value: fixed,
+                        uncertainty: 0.0,
+                    },
+                };
+
+                let sigma_index = |i: usize| match fixed_sigma {
+                    Some(_) => None,
+                    None => parameter_names
+                        .iter()
+                        .position(|parameter| *parameter == sigma_name(i)),
+                };
+
+                let mut params: Vec<GaussianParams> = Vec::new();
+                for (i, &amplitude) in linear_coefficients.iter().enumerate() {
+                    let mean = fitted_value(&mean_name(i), self.peak_markers[i]);
+                    let sigma = match fixed_sigma {
+                        Some(sigma) => Value {
+                            value: sigma,
+                            uncertainty: 0.0,
+                        },
+                        None => fitted_value(&sigma_name(i), average_sigma),
+                    };
+
+                    if let Some(mut gaussian_params) = GaussianParams::new(
+                        Value {
+                            value: amplitude,
+                            uncertainty: linear_variances[i].sqrt(),
+                        },
+                        mean,
+                        sigma,
+                        self.bin_width,
+                    ) {
+                        gaussian_params.set_amplitude_sigma_covariance(
+                            Self::amplitude_sigma_covariance(
+                                fit_statistics.covariance_matrix(),
+                                i,
+                                linear_coefficients.len(),
+                                sigma_index(i),
+                            ),
+                            self.bin_width,
+                        );
+                        params.push(gaussian_params);
+                    } else {
+                        self.peak_markers.remove(i);
+                        self.constraints.remove_peak(i);
+                        self.multi_gauss_fit_constrained();
+                        return;
+                    }
+                }
+
+                self.peak_markers.clear();
+                for mean in &params {
+                    self.peak_markers.push(mean.mean.value);
+                }
+
+                self.fit_params = Some(params);
+                self.get_fit_lines();
+            }
+            Err(e) => {
+                log::error!("Failed to fit model: {:?}", e);
+            }
+        }
+    }
+
     pub fn multi_gauss_fit(&mut self) {
-        if self.free_stddev && self.free_position {
+        if self.constraints.is_active() {
+            self.multi_gauss_fit_constrained();
+        } else if self.free_stddev && self.free_position {
             self.multi_gauss_fit_free_stddev_free_position();
         } else if !self.free_stddev && self.free_position {
             self.multi_gauss_fit_fixed_stdev_free_position();
@@ -726,6 +1050,60 @@ impl GaussianFitter {
         } else if self.free_stddev && !self.free_position {
             self.multi_gauss_fit_free_stdev_fixed_position();
         }
+
+        self.calculate_goodness_of_fit();
+    }
+
+    // Sum of the fitted gaussians at x
+    fn model_value(&self, x: f64) -> f64 {
+        self.fit_params.as_ref().map_or(0.0, |params| {
+            params.iter().fold(0.0, |sum, param| {
+                sum + param.amplitude.value
+                    * (-((x - param.mean.value).powi(2)) / (2.0 * param.sigma.value.powi(2))).exp()
+            })
+        })
+    }
+
+    fn number_of_parameters(&self) -> usize {
+        let peaks = self.fit_params.as_ref().map_or(0, |params| params.len());
+        let means = (0..peaks)
+            .filter(|&i| self.free_position && !self.constraints.is_position_fixed(i))
+            .count();
+        let sigmas = if self.constraints.fixed_sigma.is_some() {
+            0
+        } else if self.free_stddev {
+            peaks
+        } else {
+            1
+        };
+
+        peaks + means + sigmas // the amplitudes are fitted for every peak
+    }
+
+    fn calculate_goodness_of_fit(&mut self) {
+        self.reduced_chi_square = None;
+        self.residuals.clear();
+
+        if self.fit_params.is_none() {
+            return;
+        }
+
+        let mut chi_square = 0.0;
+        for (i, (&x, &y)) in self.x.iter().zip(&self.y).enumerate() {
+            let error = match self.y_err.as_ref().and_then(|y_err| y_err.get(i)) {
+                Some(&error) if error > 0.0 => error,
+                _ => y.abs().sqrt().max(1.0),
+            };
+
+            let residual = (y - self.model_value(x)) / error;
+            chi_square += residual.powi(2);
+            self.residuals.push([x, residual]);
+        }
+
+        let degrees_of_freedom = self.x.len() as i64 - self.number_of_parameters() as i64;
+        if degrees_of_freedom > 0 {
+            self.reduced_chi_square = Some(chi_square / degrees_of_freedom as f64);
+        }
     }
 
     pub fn get_fit_lines(&mut self) {
@@ -733,7 +1111,7 @@ impl GaussianFitter {
             let mut fit_lines = Vec::new();
 
             for params in fit_params.iter() {
-                let line = params.fit_line_points();
+                let line = params.fit_line_points(self.fit_line_points);
                 fit_lines.push(line);
             }
 
@@ -745,7 +1123,7 @@ impl GaussianFitter {
 
     pub fn composition_fit_points_polynomial(&self, coef: Vec<f64>) -> Vec<[f64; 2]> {
         // coef = [c0, c1, c2, ...] c0 + c1*x + c2*x^2 + ...
-        let num_points = 3000;
+        let num_points = 3 * self.fit_line_points;
         let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
         let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
         let step = (max_x - min_x) / num_points as f64;
@@ -771,8 +1149,28 @@ impl GaussianFitter {
             .collect()
     }
 
+    // The peaks plus a linear background, values below the log floor are clamped on a log y scale
+    pub fn calculate_convoluted_fit_points_with_linear_background(
+        &self,
+        slope: f64,
+        intercept: f64,
+        log_y_scale: bool,
+    ) -> Vec<PlotPoint> {
+        self.composition_fit_points_polynomial(vec![intercept, slope])
+            .into_iter()
+            .map(|[x, y]| {
+                let y = if log_y_scale {
+                    y.max(LOG_Y_FLOOR).log10()
+                } else {
+                    y
+                };
+                PlotPoint::new(x, y)
+            })
+            .collect()
+    }
+
     pub fn composition_fit_points_exponential(&self, a: f64, b: f64) -> Vec<[f64; 2]> {
-        let num_points = 3000;
+        let num_points = 3 * self.fit_line_points;
         let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
         let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
         let step = (max_x - min_x) / num_points as f64;
@@ -802,7 +1200,7 @@ impl GaussianFitter {
         c: f64,
         d: f64,
     ) -> Vec<[f64; 2]> {
-        let num_points = 3000;
+        let num_points = 3 * self.fit_line_points;
         let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
         let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
         let step = (max_x - min_x) / num_points as f64;
@@ -825,7 +1223,8 @@ impl GaussianFitter {
             .collect()
     }
 
-    pub fn fit_params_ui(&self, ui: &mut egui::Ui) {
+    // Centroids and widths are shown in calibrated units when a calibration is given
+    pub fn fit_params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
         if let Some(fit_params) = &self.fit_params {
             for (i, params) in fit_params.iter().enumerate() {
                 if i != 0 {
@@ -833,9 +1232,47 @@ impl GaussianFitter {
                 }
 
                 ui.label(format!("{}", i));
-                params.params_ui(ui);
+                params.params_ui(ui, calibration);
+
+                // one value for the whole fit, shown on the first peak
+                if i == 0 {
+                    if let Some(reduced_chi_square) = self.reduced_chi_square {
+                        ui.label(format!("{:.2}", reduced_chi_square));
+                    }
+                }
                 ui.end_row();
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_find_peaks_skips_peaks_below_the_prominence() {
+        let y = vec![0.0, 1.0, 10.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 20.0, 0.0];
+        let x = (0..y.len()).map(|i| i as f64).collect();
+        let fitter = GaussianFitter::new(x, y, Vec::new(), true, true, 1.0);
+
+        assert_eq!(fitter.auto_find_peaks(5.0, 1), vec![2.0, 9.0]);
+    }
+
+    #[test]
+    fn convoluted_points_clamp_a_negative_background_on_log_y() {
+        let x = (0..11).map(|i| i as f64).collect();
+        let fitter = GaussianFitter::new(x, vec![0.0; 11], Vec::new(), true, true, 1.0);
+
+        // no peaks were fitted, so the points are the background alone
+        let linear =
+            fitter.calculate_convoluted_fit_points_with_linear_background(-1.0, 5.0, false);
+        assert_eq!(linear.first().map(|point| point.y), Some(5.0));
+        let last = linear.last().map_or(0.0, |point| point.y);
+        assert!((last + 5.0).abs() < 1e-9);
+
+        let log = fitter.calculate_convoluted_fit_points_with_linear_background(-1.0, 5.0, true);
+        assert!(log.iter().all(|point| point.y >= LOG_Y_FLOOR.log10()));
+        assert_eq!(log.last().map(|point| point.y), Some(LOG_Y_FLOOR.log10()));
+    }
+}
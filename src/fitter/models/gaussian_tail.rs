@@ -0,0 +1,494 @@
+use nalgebra::DVector;
+use varpro::model::builder::SeparableModelBuilder;
+use varpro::solvers::levmar::{LevMarProblemBuilder, LevMarSolver};
+
+use super::gaussian::{default_fit_line_points, Value};
+use crate::histoer::histo1d::calibration::Calibration;
+
+// Scaled complementary error function exp(z^2) * erfc(z) for z >= 0,
+// Chebyshev fit from Numerical Recipes with a fractional error below 1.2e-7
+fn erfcx(z: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * z);
+    t * (-1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277)))))))))
+        .exp()
+}
+
+fn gaussian(dx: f64, sigma: f64) -> f64 {
+    (-(dx.powi(2)) / (2.0 * sigma.powi(2))).exp()
+}
+
+// Low energy tail: an exponential with decay length tau convolved with the gaussian resolution,
+// T(x) = exp(dx / tau) * erfc(dx / (sqrt(2) sigma) + sigma / (sqrt(2) tau))
+fn tail(dx: f64, sigma: f64, tau: f64) -> f64 {
+    let u = dx / (std::f64::consts::SQRT_2 * sigma) + sigma / (std::f64::consts::SQRT_2 * tau);
+
+    if u >= 0.0 {
+        // exp(dx / tau) can overflow here, fold it into the scaled erfc
+        tail_edge(dx, sigma, tau) * erfcx(u)
+    } else {
+        // dx is negative so exp(dx / tau) is at most 1
+        (dx / tau).exp() * (2.0 - erfcx(-u) * (-u * u).exp())
+    }
+}
+
+// exp(dx / tau) * exp(-u^2) written without the large exponentials
+fn tail_edge(dx: f64, sigma: f64, tau: f64) -> f64 {
+    (-(dx.powi(2)) / (2.0 * sigma.powi(2)) - sigma.powi(2) / (2.0 * tau.powi(2))).exp()
+}
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GaussianTailParams {
+    pub amplitude: Value, // height of the gaussian part
+    pub mean: Value,
+    pub sigma: Value,
+    pub tail_amplitude: Value,
+    pub tail_length: Value, // decay length of the tail in x units
+    pub fwhm: Value,        // fwhm of the gaussian plus the tail
+    pub area: Value,
+}
+
+impl GaussianTailParams {
+    pub fn new(
+        amplitude: Value,
+        mean: Value,
+        sigma: Value,
+        tail_amplitude: Value,
+        tail_length: Value,
+        bin_width: f64,
+    ) -> Option<Self> {
+        if sigma.value < 0.0 || tail_length.value < 0.0 {
+            log::error!("Gaussian tail widths must be positive");
+            return None;
+        }
+
+        let gaussian_area = sigma.value * (2.0 * std::f64::consts::PI).sqrt();
+        let tail_factor = (-(sigma.value.powi(2)) / (2.0 * tail_length.value.powi(2))).exp();
+        let tail_area = 2.0 * tail_length.value * tail_factor;
+
+        let area = (amplitude.value * gaussian_area + tail_amplitude.value * tail_area) / bin_width;
+        if area < 0.0 {
+            log::error!("Area is negative");
+            return None;
+        }
+
+        // uncorrelated propagation, the effect of sigma on the tail area is neglected
+        let d_tail_area =
+            2.0 * tail_factor * (1.0 + sigma.value.powi(2) / tail_length.value.powi(2));
+        let area_uncertainty = ((gaussian_area * amplitude.uncertainty).powi(2)
+            + (amplitude.value * (2.0 * std::f64::consts::PI).sqrt() * sigma.uncertainty).powi(2)
+            + (tail_area * tail_amplitude.uncertainty).powi(2)
+            + (tail_amplitude.value * d_tail_area * tail_length.uncertainty).powi(2))
+        .sqrt()
+            / bin_width;
+
+        let mut params = GaussianTailParams {
+            amplitude,
+            mean,
+            sigma,
+            tail_amplitude,
+            tail_length,
+            fwhm: Value::default(),
+            area: Value {
+                value: area,
+                uncertainty: area_uncertainty,
+            },
+        };
+
+        // the tail only broadens the peak, so scale the gaussian fwhm uncertainty with it
+        let gaussian_fwhm = 2.0 * (2.0 * f64::ln(2.0)).sqrt() * params.sigma.value;
+        let fwhm = params.numeric_fwhm().unwrap_or(gaussian_fwhm);
+        params.fwhm = Value {
+            value: fwhm,
+            uncertainty: if gaussian_fwhm > 0.0 {
+                2.0 * (2.0 * f64::ln(2.0)).sqrt() * params.sigma.uncertainty * fwhm / gaussian_fwhm
+            } else {
+                0.0
+            },
+        };
+
+        Some(params)
+    }
+
+    fn line_range(&self) -> (f64, f64) {
+        (
+            self.mean.value - 5.0 * self.sigma.value - 10.0 * self.tail_length.value,
+            self.mean.value + 5.0 * self.sigma.value,
+        )
+    }
+
+    // Width at half the maximum of the combined shape, found on a fine grid
+    fn numeric_fwhm(&self) -> Option<f64> {
+        let (start, end) = self.line_range();
+        let num_points = 10_000;
+        let step = (end - start) / num_points as f64;
+        if step <= 0.0 || !step.is_finite() {
+            return None;
+        }
+
+        let points: Vec<(f64, f64)> = (0..=num_points)
+            .map(|i| {
+                let x = start + step * i as f64;
+                (x, self.evaluate(x))
+            })
+            .collect();
+
+        let (max_index, &(_, max_y)) = points
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1 .1.total_cmp(&b.1 .1))?;
+        let half_max = max_y / 2.0;
+
+        let crossing =
+            |a: (f64, f64), b: (f64, f64)| a.0 + (half_max - a.1) * (b.0 - a.0) / (b.1 - a.1);
+
+        let left = (1..=max_index)
+            .rev()
+            .find(|&i| points[i - 1].1 < half_max)
+            .map(|i| crossing(points[i - 1], points[i]))?;
+        let right = (max_index..num_points)
+            .find(|&i| points[i + 1].1 < half_max)
+            .map(|i| crossing(points[i], points[i + 1]))?;
+
+        Some(right - left)
+    }
+
+    pub fn evaluate_gaussian(&self, x: f64) -> f64 {
+        self.amplitude.value * gaussian(x - self.mean.value, self.sigma.value)
+    }
+
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let dx = x - self.mean.value;
+        self.evaluate_gaussian(x)
+            + self.tail_amplitude.value * tail(dx, self.sigma.value, self.tail_length.value)
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        let (mean, fwhm) = calibration.map_or((self.mean.clone(), self.fwhm.clone()), |c| {
+            c.calibrate_peak(&self.mean, &self.fwhm)
+        });
+
+        ui.label(format!("{:.2} ± {:.2}", mean.value, mean.uncertainty));
+        ui.label(format!("{:.2} ± {:.2}", fwhm.value, fwhm.uncertainty))
+            .on_hover_text(format!(
+                "σ: {:.2} ± {:.2}\nTail amplitude: {:.2} ± {:.2}\nTail length: {:.2} ± {:.2}",
+                self.sigma.value,
+                self.sigma.uncertainty,
+                self.tail_amplitude.value,
+                self.tail_amplitude.uncertainty,
+                self.tail_length.value,
+                self.tail_length.uncertainty
+            ));
+        ui.label(format!(
+            "{:.2} ± {:.2}",
+            self.area.value, self.area.uncertainty
+        ));
+    }
+
+    fn line_points(&self, num_points: usize, evaluate: impl Fn(f64) -> f64) -> Vec<[f64; 2]> {
+        let (start, end) = self.line_range();
+        let step = (end - start) / num_points as f64;
+
+        (0..num_points)
+            .map(|i| {
+                let x = start + step * i as f64;
+                [x, evaluate(x)]
+            })
+            .collect()
+    }
+
+    pub fn fit_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
+        self.line_points(num_points, |x| self.evaluate(x))
+    }
+
+    pub fn gaussian_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
+        self.line_points(num_points, |x| self.evaluate_gaussian(x))
+    }
+}
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct GaussianTailFitter {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    pub peak_markers: Vec<f64>,
+    pub fit_params: Option<Vec<GaussianTailParams>>,
+    pub fit_lines: Option<Vec<Vec<[f64; 2]>>>,
+    pub gaussian_lines: Option<Vec<Vec<[f64; 2]>>>, // the gaussian part of each peak without the tail
+    pub bin_width: f64,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize, // number of points in each peak line, the composition line uses 3x
+}
+
+impl GaussianTailFitter {
+    pub fn new(x: Vec<f64>, y: Vec<f64>, peak_markers: Vec<f64>, bin_width: f64) -> Self {
+        Self {
+            x,
+            y,
+            peak_markers,
+            fit_params: None,
+            fit_lines: None,
+            gaussian_lines: None,
+            bin_width,
+            fit_line_points: default_fit_line_points(),
+        }
+    }
+
+    fn gaussian_basis(x: &DVector<f64>, mean: f64, sigma: f64) -> DVector<f64> {
+        x.map(|x_val| gaussian(x_val - mean, sigma))
+    }
+
+    fn gaussian_pd_mean(x: &DVector<f64>, mean: f64, sigma: f64) -> DVector<f64> {
+        x.map(|x_val| {
+            let dx = x_val - mean;
+            dx / sigma.powi(2) * gaussian(dx, sigma)
+        })
+    }
+
+    fn gaussian_pd_sigma(x: &DVector<f64>, mean: f64, sigma: f64) -> DVector<f64> {
+        x.map(|x_val| {
+            let dx = x_val - mean;
+            dx.powi(2) / sigma.powi(3) * gaussian(dx, sigma)
+        })
+    }
+
+    fn tail_basis(x: &DVector<f64>, mean: f64, sigma: f64, tau: f64) -> DVector<f64> {
+        x.map(|x_val| tail(x_val - mean, sigma, tau))
+    }
+
+    fn tail_pd_mean(x: &DVector<f64>, mean: f64, sigma: f64, tau: f64) -> DVector<f64> {
+        let norm = 2.0 / (std::f64::consts::PI.sqrt() * std::f64::consts::SQRT_2 * sigma);
+        x.map(|x_val| {
+            let dx = x_val - mean;
+            -tail(dx, sigma, tau) / tau + norm * tail_edge(dx, sigma, tau)
+        })
+    }
+
+    fn tail_pd_sigma(x: &DVector<f64>, mean: f64, sigma: f64, tau: f64) -> DVector<f64> {
+        let norm = 2.0 / std::f64::consts::PI.sqrt();
+        x.map(|x_val| {
+            let dx = x_val - mean;
+            let du_dsigma = (-dx / sigma.powi(2) + 1.0 / tau) / std::f64::consts::SQRT_2;
+            -norm * tail_edge(dx, sigma, tau) * du_dsigma
+        })
+    }
+
+    fn tail_pd_tau(x: &DVector<f64>, mean: f64, sigma: f64, tau: f64) -> DVector<f64> {
+        let norm = 2.0 / std::f64::consts::PI.sqrt();
+        x.map(|x_val| {
+            let dx = x_val - mean;
+            -dx / tau.powi(2) * tail(dx, sigma, tau)
+                + norm * tail_edge(dx, sigma, tau) * sigma
+                    / (std::f64::consts::SQRT_2 * tau.powi(2))
+        })
+    }
+
+    fn average_width(&self) -> f64 {
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = max_x - min_x;
+
+        range / (10.0 * self.peak_markers.len() as f64)
+    }
+
+    pub fn multi_gaussian_tail_fit(&mut self) {
+        self.fit_params = None;
+        self.fit_lines = None;
+        self.gaussian_lines = None;
+
+        if self.x.len() != self.y.len() {
+            log::error!("x_data and y_data must have the same length");
+            return;
+        }
+
+        // if peak_marks is empty, find the max of the y data and use that index of the x data as the initial guess
+        if self.peak_markers.is_empty() {
+            let max_y = self.y.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+            let max_y_index = match self.y.iter().position(|&r| r == max_y) {
+                Some(index) => index,
+                None => {
+                    log::error!("Max y value not found in y data");
+                    return;
+                }
+            };
+            self.peak_markers.push(self.x[max_y_index]);
+        }
+
+        let mut initial_guesses: Vec<f64> = Vec::new();
+        let mut parameter_names: Vec<String> = Vec::new();
+        let average_width = self.average_width();
+
+        for (index, &mean) in self.peak_markers.iter().enumerate() {
+            initial_guesses.extend([mean, average_width, average_width]);
+            parameter_names.push(format!("mean{}", index));
+            parameter_names.push(format!("sigma{}", index));
+            parameter_names.push(format!("tau{}", index));
+        }
+
+        let x_data = DVector::from_vec(self.x.clone());
+        let y_data = DVector::from_vec(self.y.clone());
+
+        // each peak has a gaussian and a tail basis function, their heights are the linear coefficients
+        let mut builder_proxy = SeparableModelBuilder::<f64>::new(parameter_names)
+            .initial_parameters(initial_guesses)
+            .independent_variable(x_data)
+            .function(&["mean0", "sigma0"], Self::gaussian_basis)
+            .partial_deriv("mean0", Self::gaussian_pd_mean)
+            .partial_deriv("sigma0", Self::gaussian_pd_sigma)
+            .function(&["mean0", "sigma0", "tau0"], Self::tail_basis)
+            .partial_deriv("mean0", Self::tail_pd_mean)
+            .partial_deriv("sigma0", Self::tail_pd_sigma)
+            .partial_deriv("tau0", Self::tail_pd_tau);
+
+        for i in 1..self.peak_markers.len() {
+            builder_proxy = builder_proxy
+                .function(
+                    &[format!("mean{}", i), format!("sigma{}", i)],
+                    Self::gaussian_basis,
+                )
+                .partial_deriv(format!("mean{}", i), Self::gaussian_pd_mean)
+                .partial_deriv(format!("sigma{}", i), Self::gaussian_pd_sigma)
+                .function(
+                    &[
+                        format!("mean{}", i),
+                        format!("sigma{}", i),
+                        format!("tau{}", i),
+                    ],
+                    Self::tail_basis,
+                )
+                .partial_deriv(format!("mean{}", i), Self::tail_pd_mean)
+                .partial_deriv(format!("sigma{}", i), Self::tail_pd_sigma)
+                .partial_deriv(format!("tau{}", i), Self::tail_pd_tau);
+        }
+
+        let model = match builder_proxy.build() {
+            Ok(model) => model,
+            Err(e) => {
+                log::error!("Failed to build model: {:?}", e);
+                return;
+            }
+        };
+
+        let problem = match LevMarProblemBuilder::new(model)
+            .observations(y_data)
+            .build()
+        {
+            Ok(problem) => problem,
+            Err(e) => {
+                log::error!("Failed to build problem: {:?}", e);
+                return;
+            }
+        };
+
+        match LevMarSolver::default().fit_with_statistics(problem) {
+            Ok((fit_result, fit_statistics)) => {
+                let nonlinear_parameters = fit_result.nonlinear_parameters();
+                let nonlinear_variances = fit_statistics.nonlinear_parameters_variance();
+                let linear_coefficients = match fit_result.linear_coefficients() {
+                    Some(coefficients) => coefficients,
+                    None => {
+                        log::error!("Failed to get linear coefficients");
+                        return;
+                    }
+                };
+                let linear_variances = fit_statistics.linear_coefficients_variance();
+                let mut params: Vec<GaussianTailParams> = Vec::new();
+
+                for i in 0..self.peak_markers.len() {
+                    let value = |j: usize| Value {
+                        value: nonlinear_parameters[i * 3 + j],
+                        uncertainty: nonlinear_variances[i * 3 + j].sqrt(),
+                    };
+                    let coefficient = |j: usize| Value {
+                        value: linear_coefficients[i * 2 + j],
+                        uncertainty: linear_variances[i * 2 + j].sqrt(),
+                    };
+
+                    // sigma only enters squared in the gaussian so the solver can return it negative
+                    let mut sigma = value(1);
+                    sigma.value = sigma.value.abs();
+
+                    if let Some(tail_params) = GaussianTailParams::new(
+                        coefficient(0),
+                        value(0),
+                        sigma,
+                        coefficient(1),
+                        value(2),
+                        self.bin_width,
+                    ) {
+                        params.push(tail_params);
+                    } else {
+                        // Remove the peak marker with the negative area and retry the fit
+                        self.peak_markers.remove(i);
+                        self.multi_gaussian_tail_fit();
+                        return;
+                    }
+                }
+
+                self.peak_markers.clear();
+                for param in &params {
+                    self.peak_markers.push(param.mean.value);
+                }
+
+                self.fit_params = Some(params);
+                self.get_fit_lines();
+            }
+            Err(e) => {
+                log::error!("Failed to fit model: {:?}", e);
+            }
+        }
+    }
+
+    pub fn get_fit_lines(&mut self) {
+        self.fit_lines = self.fit_params.as_ref().map(|fit_params| {
+            fit_params
+                .iter()
+                .map(|params| params.fit_line_points(self.fit_line_points))
+                .collect()
+        });
+
+        self.gaussian_lines = self.fit_params.as_ref().map(|fit_params| {
+            fit_params
+                .iter()
+                .map(|params| params.gaussian_line_points(self.fit_line_points))
+                .collect()
+        });
+    }
+
+    // Sum of the peaks plus the background over the fit region
+    pub fn composition_fit_points(&self, background: impl Fn(f64) -> f64) -> Vec<[f64; 2]> {
+        let num_points = 3 * self.fit_line_points;
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let step = (max_x - min_x) / num_points as f64;
+
+        (0..=num_points)
+            .map(|i| {
+                let x = min_x + step * i as f64;
+                let y_peaks = self.fit_params.as_ref().map_or(0.0, |params| {
+                    params.iter().map(|param| param.evaluate(x)).sum::<f64>()
+                });
+                [x, y_peaks + background(x)]
+            })
+            .collect()
+    }
+
+    pub fn fit_params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        if let Some(fit_params) = &self.fit_params {
+            for (i, params) in fit_params.iter().enumerate() {
+                if i != 0 {
+                    ui.label("");
+                }
+
+                ui.label(format!("{}", i));
+                params.params_ui(ui, calibration);
+                ui.end_row();
+            }
+        }
+    }
+}
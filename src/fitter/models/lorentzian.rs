@@ -0,0 +1,305 @@
+use nalgebra::DVector;
+use varpro::model::builder::SeparableModelBuilder;
+use varpro::solvers::levmar::{LevMarProblemBuilder, LevMarSolver};
+
+use super::gaussian::{default_fit_line_points, Value};
+use crate::histoer::histo1d::calibration::Calibration;
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct LorentzianParams {
+    pub amplitude: Value,
+    pub mean: Value,
+    pub gamma: Value, // half width at half maximum
+    pub fwhm: Value,
+    pub area: Value,
+}
+
+impl LorentzianParams {
+    pub fn new(amplitude: Value, mean: Value, gamma: Value, bin_width: f64) -> Option<Self> {
+        if gamma.value < 0.0 {
+            log::error!("Gamma value is negative");
+            return None;
+        }
+
+        let area = amplitude.value * std::f64::consts::PI * gamma.value / bin_width;
+        if area < 0.0 {
+            log::error!("Area is negative");
+            return None;
+        }
+        let area_uncertainty = std::f64::consts::PI / bin_width
+            * ((gamma.value * amplitude.uncertainty).powi(2)
+                + (amplitude.value * gamma.uncertainty).powi(2))
+            .sqrt();
+
+        Some(LorentzianParams {
+            fwhm: Value {
+                value: 2.0 * gamma.value,
+                uncertainty: 2.0 * gamma.uncertainty,
+            },
+            area: Value {
+                value: area,
+                uncertainty: area_uncertainty,
+            },
+            amplitude,
+            mean,
+            gamma,
+        })
+    }
+
+    pub fn evaluate(&self, x: f64) -> f64 {
+        let u = (x - self.mean.value) / self.gamma.value;
+        self.amplitude.value / (1.0 + u * u)
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        let (mean, fwhm) = calibration.map_or((self.mean.clone(), self.fwhm.clone()), |c| {
+            c.calibrate_peak(&self.mean, &self.fwhm)
+        });
+
+        ui.label(format!("{:.2} ± {:.2}", mean.value, mean.uncertainty));
+        ui.label(format!("{:.2} ± {:.2}", fwhm.value, fwhm.uncertainty));
+        ui.label(format!(
+            "{:.2} ± {:.2}",
+            self.area.value, self.area.uncertainty
+        ));
+    }
+
+    pub fn fit_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
+        // the tails are much wider than a gaussian so draw out to +/- 10 fwhm
+        let start = self.mean.value - 20.0 * self.gamma.value;
+        let end = self.mean.value + 20.0 * self.gamma.value;
+        let step = (end - start) / num_points as f64;
+
+        (0..num_points)
+            .map(|i| {
+                let x = start + step * i as f64;
+                [x, self.evaluate(x)]
+            })
+            .collect()
+    }
+}
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct LorentzianFitter {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    pub peak_markers: Vec<f64>,
+    pub fit_params: Option<Vec<LorentzianParams>>,
+    pub fit_lines: Option<Vec<Vec<[f64; 2]>>>,
+    pub bin_width: f64,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize, // number of points in each peak line, the composition line uses 3x
+}
+
+impl LorentzianFitter {
+    pub fn new(x: Vec<f64>, y: Vec<f64>, peak_markers: Vec<f64>, bin_width: f64) -> Self {
+        Self {
+            x,
+            y,
+            peak_markers,
+            fit_params: None,
+            fit_lines: None,
+            bin_width,
+            fit_line_points: default_fit_line_points(),
+        }
+    }
+
+    // L(x) = 1 / (1 + u^2) with u = (x - mean) / gamma, the amplitude is the linear coefficient
+    fn lorentzian(x: &DVector<f64>, mean: f64, gamma: f64) -> DVector<f64> {
+        x.map(|x_val| {
+            let u = (x_val - mean) / gamma;
+            1.0 / (1.0 + u * u)
+        })
+    }
+
+    fn lorentzian_pd_mean(x: &DVector<f64>, mean: f64, gamma: f64) -> DVector<f64> {
+        x.map(|x_val| {
+            let u = (x_val - mean) / gamma;
+            2.0 * u / (gamma * (1.0 + u * u).powi(2))
+        })
+    }
+
+    fn lorentzian_pd_gamma(x: &DVector<f64>, mean: f64, gamma: f64) -> DVector<f64> {
+        x.map(|x_val| {
+            let u = (x_val - mean) / gamma;
+            2.0 * u * u / (gamma * (1.0 + u * u).powi(2))
+        })
+    }
+
+    fn average_gamma(&self) -> f64 {
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = max_x - min_x;
+
+        range / (10.0 * self.peak_markers.len() as f64)
+    }
+
+    pub fn multi_lorentzian_fit(&mut self) {
+        self.fit_params = None;
+        self.fit_lines = None;
+
+        if self.x.len() != self.y.len() {
+            log::error!("x_data and y_data must have the same length");
+            return;
+        }
+
+        // if peak_marks is empty, find the max of the y data and use that index of the x data as the initial guess
+        if self.peak_markers.is_empty() {
+            let max_y = self.y.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+            let max_y_index = match self.y.iter().position(|&r| r == max_y) {
+                Some(index) => index,
+                None => {
+                    log::error!("Max y value not found in y data");
+                    return;
+                }
+            };
+            self.peak_markers.push(self.x[max_y_index]);
+        }
+
+        let mut initial_guesses: Vec<f64> = Vec::new();
+        let mut parameter_names: Vec<String> = Vec::new();
+        let average_gamma = self.average_gamma();
+
+        for (index, &mean) in self.peak_markers.iter().enumerate() {
+            initial_guesses.push(mean);
+            parameter_names.push(format!("mean{}", index));
+            initial_guesses.push(average_gamma);
+            parameter_names.push(format!("gamma{}", index));
+        }
+
+        let x_data = DVector::from_vec(self.x.clone());
+        let y_data = DVector::from_vec(self.y.clone());
+
+        let mut builder_proxy = SeparableModelBuilder::<f64>::new(parameter_names)
+            .initial_parameters(initial_guesses)
+            .independent_variable(x_data)
+            .function(&["mean0", "gamma0"], Self::lorentzian)
+            .partial_deriv("mean0", Self::lorentzian_pd_mean)
+            .partial_deriv("gamma0", Self::lorentzian_pd_gamma);
+
+        for i in 1..self.peak_markers.len() {
+            builder_proxy = builder_proxy
+                .function(
+                    &[format!("mean{}", i), format!("gamma{}", i)],
+                    Self::lorentzian,
+                )
+                .partial_deriv(format!("mean{}", i), Self::lorentzian_pd_mean)
+                .partial_deriv(format!("gamma{}", i), Self::lorentzian_pd_gamma);
+        }
+
+        let model = match builder_proxy.build() {
+            Ok(model) => model,
+            Err(e) => {
+                log::error!("Failed to build model: {:?}", e);
+                return;
+            }
+        };
+
+        let problem = match LevMarProblemBuilder::new(model)
+            .observations(y_data)
+            .build()
+        {
+            Ok(problem) => problem,
+            Err(e) => {
+                log::error!("Failed to build problem: {:?}", e);
+                return;
+            }
+        };
+
+        match LevMarSolver::default().fit_with_statistics(problem) {
+            Ok((fit_result, fit_statistics)) => {
+                let nonlinear_parameters = fit_result.nonlinear_parameters();
+                let nonlinear_variances = fit_statistics.nonlinear_parameters_variance();
+                let linear_coefficients = match fit_result.linear_coefficients() {
+                    Some(coefficients) => coefficients,
+                    None => {
+                        log::error!("Failed to get linear coefficients");
+                        return;
+                    }
+                };
+                let linear_variances = fit_statistics.linear_coefficients_variance();
+                let mut params: Vec<LorentzianParams> = Vec::new();
+
+                for (i, &amplitude) in linear_coefficients.iter().enumerate() {
+                    // gamma only enters squared so the solver can return it negative
+                    let gamma = nonlinear_parameters[i * 2 + 1].abs();
+
+                    if let Some(lorentzian_params) = LorentzianParams::new(
+                        Value {
+                            value: amplitude,
+                            uncertainty: linear_variances[i].sqrt(),
+                        },
+                        Value {
+                            value: nonlinear_parameters[i * 2],
+                            uncertainty: nonlinear_variances[i * 2].sqrt(),
+                        },
+                        Value {
+                            value: gamma,
+                            uncertainty: nonlinear_variances[i * 2 + 1].sqrt(),
+                        },
+                        self.bin_width,
+                    ) {
+                        params.push(lorentzian_params);
+                    } else {
+                        // Remove the peak marker with the negative area and retry the fit
+                        self.peak_markers.remove(i);
+                        self.multi_lorentzian_fit();
+                        return;
+                    }
+                }
+
+                self.peak_markers.clear();
+                for param in &params {
+                    self.peak_markers.push(param.mean.value);
+                }
+
+                self.fit_params = Some(params);
+                self.get_fit_lines();
+            }
+            Err(e) => {
+                log::error!("Failed to fit model: {:?}", e);
+            }
+        }
+    }
+
+    pub fn get_fit_lines(&mut self) {
+        self.fit_lines = self.fit_params.as_ref().map(|fit_params| {
+            fit_params
+                .iter()
+                .map(|params| params.fit_line_points(self.fit_line_points))
+                .collect()
+        });
+    }
+
+    // Sum of the peaks plus the background over the fit region
+    pub fn composition_fit_points(&self, background: impl Fn(f64) -> f64) -> Vec<[f64; 2]> {
+        let num_points = 3 * self.fit_line_points;
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let step = (max_x - min_x) / num_points as f64;
+
+        (0..=num_points)
+            .map(|i| {
+                let x = min_x + step * i as f64;
+                let y_peaks = self.fit_params.as_ref().map_or(0.0, |params| {
+                    params.iter().map(|param| param.evaluate(x)).sum::<f64>()
+                });
+                [x, y_peaks + background(x)]
+            })
+            .collect()
+    }
+
+    pub fn fit_params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        if let Some(fit_params) = &self.fit_params {
+            for (i, params) in fit_params.iter().enumerate() {
+                if i != 0 {
+                    ui.label("");
+                }
+
+                ui.label(format!("{}", i));
+                params.params_ui(ui, calibration);
+                ui.end_row();
+            }
+        }
+    }
+}
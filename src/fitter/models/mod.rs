@@ -1,4 +1,7 @@
 pub mod double_exponential;
 pub mod exponential;
 pub mod gaussian;
+pub mod gaussian_tail;
+pub mod lorentzian;
 pub mod polynomial;
+pub mod voigt;
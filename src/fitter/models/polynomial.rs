@@ -1,5 +1,6 @@
 use crate::egui_plot_stuff::egui_line::EguiLine;
 use compute::predict::PolynomialRegressor;
+use nalgebra::DMatrix;
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct PolynomialFitter {
@@ -7,6 +8,8 @@ pub struct PolynomialFitter {
     pub y_data: Vec<f64>,
     pub degree: usize,
     pub coefficients: Option<Vec<f64>>,
+    #[serde(default)]
+    pub uncertainties: Option<Vec<f64>>, // standard errors of the coefficients
     pub fit_line: EguiLine,
 }
 
@@ -21,6 +24,7 @@ impl PolynomialFitter {
             y_data: Vec::new(),
             degree,
             coefficients: None,
+            uncertainties: None,
             fit_line,
         }
     }
@@ -37,12 +41,41 @@ impl PolynomialFitter {
         }
         regressor.fit(&self.x_data, &self.y_data);
 
+        self.uncertainties = self.coefficient_uncertainties(&regressor.coef);
         self.coefficients = Some(regressor.coef.clone());
         self.compute_fit_points();
 
         log::info!("Polynomial fit coefficients: {:?}", regressor.coef);
     }
 
+    // Standard errors from the covariance s^2 (X^T X)^-1 of the unweighted least squares fit,
+    // where s^2 is the residual variance
+    fn coefficient_uncertainties(&self, coef: &[f64]) -> Option<Vec<f64>> {
+        let points = self.x_data.len();
+        let parameters = coef.len();
+        if points <= parameters {
+            return None;
+        }
+
+        let design = DMatrix::from_fn(points, parameters, |row, column| {
+            self.x_data[row].powi(column as i32)
+        });
+        let residual_sum: f64 = self
+            .x_data
+            .iter()
+            .zip(&self.y_data)
+            .map(|(&x, &y)| (y - Self::evaluate_coefficients(coef, x)).powi(2))
+            .sum();
+        let variance = residual_sum / (points - parameters) as f64;
+
+        let inverse = (design.transpose() * &design).try_inverse()?;
+        Some(
+            (0..parameters)
+                .map(|i| (variance * inverse[(i, i)]).max(0.0).sqrt())
+                .collect(),
+        )
+    }
+
     pub fn subtract_background(&self, x_data: Vec<f64>, y_data: Vec<f64>) -> Vec<f64> {
         if let Some(coef) = &self.coefficients {
             if coef.is_empty() {
@@ -53,11 +86,7 @@ impl PolynomialFitter {
             let mut y_data = y_data.clone();
 
             for (i, x) in x_data.iter().enumerate() {
-                let y = coef
-                    .iter()
-                    .enumerate()
-                    .fold(0.0, |acc, (j, c)| acc + c * x.powi(j as i32));
-                y_data[i] -= y;
+                y_data[i] -= self.evaluate(*x);
             }
 
             y_data
@@ -66,6 +95,18 @@ impl PolynomialFitter {
         }
     }
 
+    // c0 + c1*x + c2*x^2 + ... using Horner's method
+    fn evaluate_coefficients(coef: &[f64], x: f64) -> f64 {
+        coef.iter().rev().fold(0.0, |acc, c| acc * x + c)
+    }
+
+    // Evaluate the fitted polynomial at x, zero if the fit failed
+    pub fn evaluate(&self, x: f64) -> f64 {
+        self.coefficients
+            .as_ref()
+            .map_or(0.0, |coef| Self::evaluate_coefficients(coef, x))
+    }
+
     fn compute_fit_points(&mut self) {
         if let Some(coef) = &self.coefficients {
             if coef.is_empty() {
@@ -86,16 +127,13 @@ impl PolynomialFitter {
             let number_points = 1000;
             for i in 0..number_points {
                 let x = x_min + (x_max - x_min) / (number_points as f64) * (i as f64);
-                let y = coef
-                    .iter()
-                    .enumerate()
-                    .fold(0.0, |acc, (j, c)| acc + c * x.powi(j as i32));
+                let y = Self::evaluate_coefficients(coef, x);
                 self.fit_line.add_point(x, y);
             }
         }
     }
 
-    pub fn _draw(&self, plot_ui: &mut egui_plot::PlotUi) {
+    pub fn draw(&self, plot_ui: &mut egui_plot::PlotUi) {
         self.fit_line.draw(plot_ui);
     }
 
@@ -109,17 +147,64 @@ impl PolynomialFitter {
         //     self.fit();
         // }
 
-        ui.label("Coefficients:");
+        if self.degree == 1 {
+            self.linear_params_ui(ui);
+            return;
+        }
+
+        ui.label(format!("Degree {} coefficients:", self.degree));
         if let Some(coef) = &self.coefficients {
             if coef.is_empty() {
                 ui.label("No coefficients found");
             } else {
                 for (i, coef) in coef.iter().enumerate() {
-                    ui.label(format!("c{}: {}", i, coef));
+                    match self.uncertainties.as_ref().and_then(|u| u.get(i)) {
+                        Some(uncertainty) => {
+                            ui.label(format!("c{}: {:.6e} ± {:.2e}", i, coef, uncertainty))
+                        }
+                        None => ui.label(format!("c{}: {:.6e}", i, coef)),
+                    };
                 }
             }
         } else {
             ui.label("No coefficients found");
         }
     }
+
+    fn linear_params_ui(&self, ui: &mut egui::Ui) {
+        let Some(coef) = self.coefficients.as_ref().filter(|coef| coef.len() == 2) else {
+            ui.label("No coefficients found");
+            return;
+        };
+
+        let uncertainty = |i: usize| self.uncertainties.as_ref().and_then(|u| u.get(i));
+        for (i, name) in ["Intercept", "Slope"].iter().enumerate() {
+            match uncertainty(i) {
+                Some(uncertainty) => {
+                    ui.label(format!("{}: {:.6e} ± {:.2e}", name, coef[i], uncertainty))
+                }
+                None => ui.label(format!("{}: {:.6e}", name, coef[i])),
+            };
+        }
+    }
+
+    // Returns the intercept and slope with their uncertainties when a linear fit is picked as
+    // the calibration
+    pub fn use_as_calibration_ui(&self, ui: &mut egui::Ui) -> Option<(Vec<f64>, Vec<f64>)> {
+        let coef = self
+            .coefficients
+            .as_ref()
+            .filter(|coef| self.degree == 1 && coef.len() == 2)?;
+
+        let finite = coef.iter().all(|c| c.is_finite());
+        if ui
+            .add_enabled(finite, egui::Button::new("Use as Calibration"))
+            .on_hover_text("Calibrate the x axis with the intercept and slope of this fit")
+            .clicked()
+        {
+            return Some((coef.clone(), self.uncertainties.clone().unwrap_or_default()));
+        }
+
+        None
+    }
 }
@@ -0,0 +1,481 @@
+use nalgebra::DVector;
+use varpro::model::builder::SeparableModelBuilder;
+use varpro::solvers::levmar::{LevMarProblemBuilder, LevMarSolver};
+
+use super::gaussian::{default_fit_line_points, Value};
+use crate::histoer::histo1d::calibration::Calibration;
+
+// Thompson-Cox-Hastings pseudo-Voigt peak: eta * L(x; f) + (1 - eta) * G(x; f), both with unit
+// height and the same fwhm f. f and eta follow from the gaussian sigma and lorentzian gamma of
+// the Voigt being approximated, so only the two widths are fitted. The profile deviates from a
+// true Voigt by up to about 1-2% of the peak height.
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct VoigtParams {
+    pub amplitude: Value,
+    pub mean: Value,
+    pub sigma: Value, // gaussian width
+    pub gamma: Value, // lorentzian half width at half maximum
+    pub eta: Value, // lorentzian fraction from the widths, 0 = pure gaussian and 1 = pure lorentzian
+    pub fwhm: Value, // shared fwhm of the two components
+    pub area: Value,
+}
+
+// fwhm of a gaussian in units of sigma
+fn gaussian_fwhm_factor() -> f64 {
+    2.0 * (2.0 * f64::ln(2.0)).sqrt()
+}
+
+// The shared fwhm and mixing of the pseudo-Voigt for the gaussian sigma and lorentzian gamma,
+// with the derivatives needed to carry fit derivatives and uncertainties back to the widths
+#[derive(Debug, Clone, Copy)]
+struct TchProfile {
+    fwhm: f64,
+    eta: f64,
+    dfwhm_dsigma: f64,
+    dfwhm_dgamma: f64,
+    deta_dsigma: f64,
+    deta_dgamma: f64,
+}
+
+impl TchProfile {
+    fn new(sigma: f64, gamma: f64) -> Self {
+        let f_g = gaussian_fwhm_factor() * sigma.abs();
+        let f_l = 2.0 * gamma.abs();
+
+        let sum = f_g.powi(5)
+            + 2.69269 * f_g.powi(4) * f_l
+            + 2.42843 * f_g.powi(3) * f_l.powi(2)
+            + 4.47163 * f_g.powi(2) * f_l.powi(3)
+            + 0.07842 * f_g * f_l.powi(4)
+            + f_l.powi(5);
+        let fwhm = sum.powf(0.2).max(f64::EPSILON);
+
+        let dsum_df_g = 5.0 * f_g.powi(4)
+            + 4.0 * 2.69269 * f_g.powi(3) * f_l
+            + 3.0 * 2.42843 * f_g.powi(2) * f_l.powi(2)
+            + 2.0 * 4.47163 * f_g * f_l.powi(3)
+            + 0.07842 * f_l.powi(4);
+        let dsum_df_l = 2.69269 * f_g.powi(4)
+            + 2.0 * 2.42843 * f_g.powi(3) * f_l
+            + 3.0 * 4.47163 * f_g.powi(2) * f_l.powi(2)
+            + 4.0 * 0.07842 * f_g * f_l.powi(3)
+            + 5.0 * f_l.powi(4);
+        let dfwhm_dsum = 0.2 * fwhm / sum.max(f64::MIN_POSITIVE);
+        let dfwhm_df_g = dfwhm_dsum * dsum_df_g;
+        let dfwhm_df_l = dfwhm_dsum * dsum_df_l;
+
+        let ratio = f_l / fwhm;
+        let raw_eta = 1.36603 * ratio - 0.47719 * ratio.powi(2) + 0.11116 * ratio.powi(3);
+        let eta = raw_eta.clamp(0.0, 1.0);
+        let deta_dratio = if raw_eta == eta {
+            1.36603 - 2.0 * 0.47719 * ratio + 3.0 * 0.11116 * ratio.powi(2)
+        } else {
+            0.0
+        };
+        let dratio_df_g = -f_l / fwhm.powi(2) * dfwhm_df_g;
+        let dratio_df_l = 1.0 / fwhm - f_l / fwhm.powi(2) * dfwhm_df_l;
+
+        Self {
+            fwhm,
+            eta,
+            dfwhm_dsigma: dfwhm_df_g * gaussian_fwhm_factor(),
+            dfwhm_dgamma: dfwhm_df_l * 2.0,
+            deta_dsigma: deta_dratio * dratio_df_g * gaussian_fwhm_factor(),
+            deta_dgamma: deta_dratio * dratio_df_l * 2.0,
+        }
+    }
+
+    // Derivatives with respect to sigma and gamma of a quantity that depends on the fwhm and eta
+    fn chain(&self, dx_dfwhm: f64, dx_deta: f64) -> (f64, f64) {
+        (
+            dx_dfwhm * self.dfwhm_dsigma + dx_deta * self.deta_dsigma,
+            dx_dfwhm * self.dfwhm_dgamma + dx_deta * self.deta_dgamma,
+        )
+    }
+
+    // Area of the unit height profile
+    fn unit_area(&self) -> f64 {
+        let gaussian = self.fwhm / gaussian_fwhm_factor() * (2.0 * std::f64::consts::PI).sqrt();
+        let lorentzian = std::f64::consts::PI * self.fwhm / 2.0;
+        self.eta * lorentzian + (1.0 - self.eta) * gaussian
+    }
+
+    fn unit_area_derivatives(&self) -> (f64, f64) {
+        let gaussian_per_fwhm = (2.0 * std::f64::consts::PI).sqrt() / gaussian_fwhm_factor();
+        let lorentzian_per_fwhm = std::f64::consts::PI / 2.0;
+        self.chain(
+            self.eta * lorentzian_per_fwhm + (1.0 - self.eta) * gaussian_per_fwhm,
+            self.fwhm * (lorentzian_per_fwhm - gaussian_per_fwhm),
+        )
+    }
+}
+
+fn propagate(derivatives: (f64, f64), sigma: &Value, gamma: &Value) -> f64 {
+    ((derivatives.0 * sigma.uncertainty).powi(2) + (derivatives.1 * gamma.uncertainty).powi(2))
+        .sqrt()
+}
+
+impl VoigtParams {
+    pub fn new(
+        amplitude: Value,
+        mean: Value,
+        sigma: Value,
+        gamma: Value,
+        bin_width: f64,
+    ) -> Option<Self> {
+        if sigma.value < 0.0 || gamma.value < 0.0 {
+            log::error!("Voigt widths must be positive");
+            return None;
+        }
+
+        let profile = TchProfile::new(sigma.value, gamma.value);
+
+        let unit_area = profile.unit_area();
+        let area = amplitude.value * unit_area / bin_width;
+        if area < 0.0 {
+            log::error!("Area is negative");
+            return None;
+        }
+
+        // uncorrelated propagation of the amplitude and the two widths
+        let area_uncertainty = ((unit_area * amplitude.uncertainty).powi(2)
+            + (amplitude.value * propagate(profile.unit_area_derivatives(), &sigma, &gamma))
+                .powi(2))
+        .sqrt()
+            / bin_width;
+
+        let fwhm = Value {
+            value: profile.fwhm,
+            uncertainty: propagate(profile.chain(1.0, 0.0), &sigma, &gamma),
+        };
+        let eta = Value {
+            value: profile.eta,
+            uncertainty: propagate(profile.chain(0.0, 1.0), &sigma, &gamma),
+        };
+
+        Some(VoigtParams {
+            amplitude,
+            mean,
+            sigma,
+            gamma,
+            eta,
+            fwhm,
+            area: Value {
+                value: area,
+                uncertainty: area_uncertainty,
+            },
+        })
+    }
+
+    pub fn evaluate(&self, x: f64) -> f64 {
+        self.amplitude.value
+            * VoigtFitter::profile(
+                x - self.mean.value,
+                self.fwhm.value.max(f64::EPSILON),
+                self.eta.value,
+            )
+    }
+
+    pub fn params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        let (mean, fwhm) = calibration.map_or((self.mean.clone(), self.fwhm.clone()), |c| {
+            c.calibrate_peak(&self.mean, &self.fwhm)
+        });
+
+        ui.label(format!("{:.2} ± {:.2}", mean.value, mean.uncertainty));
+        ui.label(format!("{:.2} ± {:.2}", fwhm.value, fwhm.uncertainty))
+            .on_hover_text(format!(
+                "σ: {:.2} ± {:.2}\nγ: {:.2} ± {:.2}\nη: {:.3} ± {:.3}",
+                self.sigma.value,
+                self.sigma.uncertainty,
+                self.gamma.value,
+                self.gamma.uncertainty,
+                self.eta.value,
+                self.eta.uncertainty
+            ));
+        ui.label(format!(
+            "{:.2} ± {:.2}",
+            self.area.value, self.area.uncertainty
+        ));
+    }
+
+    pub fn fit_line_points(&self, num_points: usize) -> Vec<[f64; 2]> {
+        let half_width = 10.0 * self.fwhm.value;
+        let start = self.mean.value - half_width;
+        let end = self.mean.value + half_width;
+        let step = (end - start) / num_points as f64;
+
+        (0..num_points)
+            .map(|i| {
+                let x = start + step * i as f64;
+                [x, self.evaluate(x)]
+            })
+            .collect()
+    }
+}
+
+#[derive(Default, Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct VoigtFitter {
+    x: Vec<f64>,
+    y: Vec<f64>,
+    pub peak_markers: Vec<f64>,
+    pub fit_params: Option<Vec<VoigtParams>>,
+    pub fit_lines: Option<Vec<Vec<[f64; 2]>>>,
+    pub bin_width: f64,
+    #[serde(default = "default_fit_line_points")]
+    pub fit_line_points: usize, // number of points in each peak line, the composition line uses 3x
+}
+
+impl VoigtFitter {
+    pub fn new(x: Vec<f64>, y: Vec<f64>, peak_markers: Vec<f64>, bin_width: f64) -> Self {
+        Self {
+            x,
+            y,
+            peak_markers,
+            fit_params: None,
+            fit_lines: None,
+            bin_width,
+            fit_line_points: default_fit_line_points(),
+        }
+    }
+
+    // Unit height pseudo-Voigt with both components sharing the fwhm
+    fn profile(dx: f64, fwhm: f64, eta: f64) -> f64 {
+        let sigma = fwhm / gaussian_fwhm_factor();
+        let half_width = fwhm / 2.0;
+        let gaussian = (-(dx.powi(2)) / (2.0 * sigma.powi(2))).exp();
+        let lorentzian = 1.0 / (1.0 + (dx / half_width).powi(2));
+
+        eta * lorentzian + (1.0 - eta) * gaussian
+    }
+
+    // Derivatives of the unit height profile with respect to the mean, the fwhm, and eta
+    fn profile_derivatives(dx: f64, fwhm: f64, eta: f64) -> (f64, f64, f64) {
+        let sigma = fwhm / gaussian_fwhm_factor();
+        let half_width = fwhm / 2.0;
+        let gaussian = (-(dx.powi(2)) / (2.0 * sigma.powi(2))).exp();
+        let lorentzian = 1.0 / (1.0 + (dx / half_width).powi(2));
+
+        let d_mean = eta * 2.0 * dx / half_width.powi(2) * lorentzian.powi(2)
+            + (1.0 - eta) * dx / sigma.powi(2) * gaussian;
+        let d_fwhm = eta * dx.powi(2) / half_width.powi(3) * lorentzian.powi(2)
+            + (1.0 - eta) * dx.powi(2) / (sigma.powi(3) * gaussian_fwhm_factor()) * gaussian;
+        let d_eta = lorentzian - gaussian;
+
+        (d_mean, d_fwhm, d_eta)
+    }
+
+    fn pseudo_voigt(x: &DVector<f64>, mean: f64, sigma: f64, gamma: f64) -> DVector<f64> {
+        let profile = TchProfile::new(sigma, gamma);
+        x.map(|x_val| Self::profile(x_val - mean, profile.fwhm, profile.eta))
+    }
+
+    fn pseudo_voigt_pd_mean(x: &DVector<f64>, mean: f64, sigma: f64, gamma: f64) -> DVector<f64> {
+        let profile = TchProfile::new(sigma, gamma);
+        x.map(|x_val| Self::profile_derivatives(x_val - mean, profile.fwhm, profile.eta).0)
+    }
+
+    // the widths enter through the absolute value in the profile
+    fn pseudo_voigt_pd_sigma(x: &DVector<f64>, mean: f64, sigma: f64, gamma: f64) -> DVector<f64> {
+        let profile = TchProfile::new(sigma, gamma);
+        x.map(|x_val| {
+            let (_, d_fwhm, d_eta) =
+                Self::profile_derivatives(x_val - mean, profile.fwhm, profile.eta);
+            profile.chain(d_fwhm, d_eta).0 * sigma.signum()
+        })
+    }
+
+    fn pseudo_voigt_pd_gamma(x: &DVector<f64>, mean: f64, sigma: f64, gamma: f64) -> DVector<f64> {
+        let profile = TchProfile::new(sigma, gamma);
+        x.map(|x_val| {
+            let (_, d_fwhm, d_eta) =
+                Self::profile_derivatives(x_val - mean, profile.fwhm, profile.eta);
+            profile.chain(d_fwhm, d_eta).1 * gamma.signum()
+        })
+    }
+
+    fn average_width(&self) -> f64 {
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = max_x - min_x;
+
+        range / (10.0 * self.peak_markers.len() as f64)
+    }
+
+    pub fn multi_voigt_fit(&mut self) {
+        self.fit_params = None;
+        self.fit_lines = None;
+
+        if self.x.len() != self.y.len() {
+            log::error!("x_data and y_data must have the same length");
+            return;
+        }
+
+        // if peak_marks is empty, find the max of the y data and use that index of the x data as the initial guess
+        if self.peak_markers.is_empty() {
+            let max_y = self.y.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+            let max_y_index = match self.y.iter().position(|&r| r == max_y) {
+                Some(index) => index,
+                None => {
+                    log::error!("Max y value not found in y data");
+                    return;
+                }
+            };
+            self.peak_markers.push(self.x[max_y_index]);
+        }
+
+        let mut initial_guesses: Vec<f64> = Vec::new();
+        let mut parameter_names: Vec<String> = Vec::new();
+        let average_width = self.average_width();
+
+        for (index, &mean) in self.peak_markers.iter().enumerate() {
+            initial_guesses.extend([mean, average_width, average_width]);
+            parameter_names.push(format!("mean{}", index));
+            parameter_names.push(format!("sigma{}", index));
+            parameter_names.push(format!("gamma{}", index));
+        }
+
+        let x_data = DVector::from_vec(self.x.clone());
+        let y_data = DVector::from_vec(self.y.clone());
+
+        let mut builder_proxy = SeparableModelBuilder::<f64>::new(parameter_names)
+            .initial_parameters(initial_guesses)
+            .independent_variable(x_data)
+            .function(&["mean0", "sigma0", "gamma0"], Self::pseudo_voigt)
+            .partial_deriv("mean0", Self::pseudo_voigt_pd_mean)
+            .partial_deriv("sigma0", Self::pseudo_voigt_pd_sigma)
+            .partial_deriv("gamma0", Self::pseudo_voigt_pd_gamma);
+
+        for i in 1..self.peak_markers.len() {
+            builder_proxy = builder_proxy
+                .function(
+                    &[
+                        format!("mean{}", i),
+                        format!("sigma{}", i),
+                        format!("gamma{}", i),
+                    ],
+                    Self::pseudo_voigt,
+                )
+                .partial_deriv(format!("mean{}", i), Self::pseudo_voigt_pd_mean)
+                .partial_deriv(format!("sigma{}", i), Self::pseudo_voigt_pd_sigma)
+                .partial_deriv(format!("gamma{}", i), Self::pseudo_voigt_pd_gamma);
+        }
+
+        let model = match builder_proxy.build() {
+            Ok(model) => model,
+            Err(e) => {
+                log::error!("Failed to build model: {:?}", e);
+                return;
+            }
+        };
+
+        let problem = match LevMarProblemBuilder::new(model)
+            .observations(y_data)
+            .build()
+        {
+            Ok(problem) => problem,
+            Err(e) => {
+                log::error!("Failed to build problem: {:?}", e);
+                return;
+            }
+        };
+
+        match LevMarSolver::default().fit_with_statistics(problem) {
+            Ok((fit_result, fit_statistics)) => {
+                let nonlinear_parameters = fit_result.nonlinear_parameters();
+                let nonlinear_variances = fit_statistics.nonlinear_parameters_variance();
+                let linear_coefficients = match fit_result.linear_coefficients() {
+                    Some(coefficients) => coefficients,
+                    None => {
+                        log::error!("Failed to get linear coefficients");
+                        return;
+                    }
+                };
+                let linear_variances = fit_statistics.linear_coefficients_variance();
+                let mut params: Vec<VoigtParams> = Vec::new();
+
+                for (i, &amplitude) in linear_coefficients.iter().enumerate() {
+                    let value = |j: usize| Value {
+                        value: nonlinear_parameters[i * 3 + j],
+                        uncertainty: nonlinear_variances[i * 3 + j].sqrt(),
+                    };
+
+                    // the widths enter through their absolute value so the solver can return them negative
+                    let mut sigma = value(1);
+                    sigma.value = sigma.value.abs();
+                    let mut gamma = value(2);
+                    gamma.value = gamma.value.abs();
+
+                    if let Some(voigt_params) = VoigtParams::new(
+                        Value {
+                            value: amplitude,
+                            uncertainty: linear_variances[i].sqrt(),
+                        },
+                        value(0),
+                        sigma,
+                        gamma,
+                        self.bin_width,
+                    ) {
+                        params.push(voigt_params);
+                    } else {
+                        // Remove the peak marker with the negative area and retry the fit
+                        self.peak_markers.remove(i);
+                        self.multi_voigt_fit();
+                        return;
+                    }
+                }
+
+                self.peak_markers.clear();
+                for param in &params {
+                    self.peak_markers.push(param.mean.value);
+                }
+
+                self.fit_params = Some(params);
+                self.get_fit_lines();
+            }
+            Err(e) => {
+                log::error!("Failed to fit model: {:?}", e);
+            }
+        }
+    }
+
+    pub fn get_fit_lines(&mut self) {
+        self.fit_lines = self.fit_params.as_ref().map(|fit_params| {
+            fit_params
+                .iter()
+                .map(|params| params.fit_line_points(self.fit_line_points))
+                .collect()
+        });
+    }
+
+    // Sum of the peaks plus the background over the fit region
+    pub fn composition_fit_points(&self, background: impl Fn(f64) -> f64) -> Vec<[f64; 2]> {
+        let num_points = 3 * self.fit_line_points;
+        let min_x = self.x.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_x = self.x.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let step = (max_x - min_x) / num_points as f64;
+
+        (0..=num_points)
+            .map(|i| {
+                let x = min_x + step * i as f64;
+                let y_peaks = self.fit_params.as_ref().map_or(0.0, |params| {
+                    params.iter().map(|param| param.evaluate(x)).sum::<f64>()
+                });
+                [x, y_peaks + background(x)]
+            })
+            .collect()
+    }
+
+    pub fn fit_params_ui(&self, ui: &mut egui::Ui, calibration: Option<&Calibration>) {
+        if let Some(fit_params) = &self.fit_params {
+            for (i, params) in fit_params.iter().enumerate() {
+                if i != 0 {
+                    ui.label("");
+                }
+
+                ui.label(format!("{}", i));
+                params.params_ui(ui, calibration);
+                ui.end_row();
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use egui_plot::{PlotPoint, PlotUi, Text};
+
+use super::fit_handler::Fits;
+use super::main_fitter::Fitter;
+use crate::egui_plot_stuff::egui_line::log_y_floor;
+
+// Rough size of a label in screen pixels, used to stack labels that would overlap
+const LABEL_CHAR_WIDTH: f32 = 7.0;
+const LABEL_HEIGHT: f32 = 14.0;
+const LABEL_GAP: f32 = 4.0;
+
+struct PeakLabel {
+    x: f64, // plot coordinates, log x already applied
+    y: f64,
+    text: String,
+    color: egui::Color32,
+}
+
+impl Fits {
+    fn peak_labels(&self) -> Vec<PeakLabel> {
+        let unit = match &self.calibration {
+            Some(calibration) if !calibration.unit.is_empty() => format!(" {}", calibration.unit),
+            _ => String::new(),
+        };
+
+        let mut labels = Vec::new();
+        for fit in self.temp_fit.iter().chain(self.stored_fits.iter()) {
+            for (mean, fwhm, _area) in fit.peak_values() {
+                let Some((x, y)) = peak_top(fit, mean.value) else {
+                    continue;
+                };
+
+                let (mean, _fwhm) = match &self.calibration {
+                    Some(calibration) => calibration.calibrate_peak(&mean, &fwhm),
+                    None => (mean, fwhm),
+                };
+
+                labels.push(PeakLabel {
+                    x,
+                    y,
+                    text: format!("{:.2}{}", mean.value, unit),
+                    color: fit.composition_line.color,
+                });
+            }
+        }
+
+        labels.sort_by(|a, b| a.x.total_cmp(&b.x));
+        labels
+    }
+
+    // The mean of every fitted peak written above the fit line. A label that would overlap one
+    // to its left is moved up a row, so dense multiplets stay readable.
+    pub fn draw_peak_labels(&self, plot_ui: &mut PlotUi) {
+        if !self.settings.show_peak_labels {
+            return;
+        }
+
+        let mut row_ends: Vec<f32> = Vec::new(); // right edge of the last label in each row, in pixels
+
+        for label in self.peak_labels() {
+            let anchor = plot_ui.screen_from_plot(PlotPoint::new(label.x, label.y));
+            let half_width = label.text.chars().count() as f32 * LABEL_CHAR_WIDTH / 2.0;
+            let left = anchor.x - half_width;
+
+            let row = match row_ends.iter().position(|&end| end + LABEL_GAP <= left) {
+                Some(row) => row,
+                None => {
+                    row_ends.push(f32::NEG_INFINITY);
+                    row_ends.len() - 1
+                }
+            };
+            row_ends[row] = anchor.x + half_width;
+
+            let position = plot_ui
+                .plot_from_screen(anchor - egui::vec2(0.0, LABEL_GAP + row as f32 * LABEL_HEIGHT));
+
+            plot_ui.text(
+                Text::new(position, egui::RichText::new(label.text).color(label.color))
+                    .anchor(egui::Align2::CENTER_BOTTOM),
+            );
+        }
+    }
+}
+
+// Where the composition line is at the mean, in plot coordinates
+fn peak_top(fit: &Fitter, mean: f64) -> Option<(f64, f64)> {
+    let line = &fit.composition_line;
+    let &[_, y] = line
+        .points
+        .iter()
+        .min_by(|a, b| (a[0] - mean).abs().total_cmp(&(b[0] - mean).abs()))?;
+
+    let x = if line.log_x && mean > 0.0 {
+        mean.log10().max(0.0001)
+    } else {
+        mean
+    };
+    let y = y * line.y_scale;
+    let y = if line.log_y {
+        y.max(log_y_floor(line.y_scale)).log10()
+    } else {
+        y
+    };
+
+    Some((x, y))
+}
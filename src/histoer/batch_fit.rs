@@ -0,0 +1,236 @@
+use rfd::FileDialog;
+
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Write;
+
+use crate::fitter::models::gaussian::Value;
+
+// One fitted peak of the batch fit summary
+#[derive(Debug, Clone)]
+pub struct BatchFitRow {
+    pub histogram: String,
+    pub peak: usize,
+    pub mean: Value,
+    pub fwhm: Value,
+    pub area: Value,
+}
+
+impl BatchFitRow {
+    // FWHM / mean in percent, the uncertainties add in quadrature
+    pub fn resolution(&self) -> Value {
+        if self.mean.value == 0.0 {
+            return Value::default();
+        }
+
+        let resolution = 100.0 * self.fwhm.value / self.mean.value;
+        let relative_fwhm = if self.fwhm.value == 0.0 {
+            0.0
+        } else {
+            self.fwhm.uncertainty / self.fwhm.value
+        };
+        let relative_mean = self.mean.uncertainty / self.mean.value;
+
+        Value {
+            value: resolution,
+            uncertainty: resolution.abs() * relative_fwhm.hypot(relative_mean),
+        }
+    }
+}
+
+// The same peak region fitted on many 1D histograms, each fit is stored on its histogram
+pub struct BatchFit {
+    pub open: bool,
+    pub region: (f64, f64),
+    pub selected: BTreeSet<String>,
+    pub filter: String,
+    pub results: Vec<BatchFitRow>,
+    pub failures: Vec<String>,
+}
+
+impl Default for BatchFit {
+    fn default() -> Self {
+        Self {
+            open: false,
+            region: (0.0, 1.0),
+            selected: BTreeSet::new(),
+            filter: String::new(),
+            results: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl BatchFit {
+    pub fn summary_csv(&self) -> String {
+        let mut csv = "histogram,peak,mean,mean_uncertainty,fwhm,fwhm_uncertainty,resolution_percent,resolution_uncertainty,area,area_uncertainty\n".to_string();
+        for row in &self.results {
+            let resolution = row.resolution();
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{},{}\n",
+                row.histogram,
+                row.peak,
+                row.mean.value,
+                row.mean.uncertainty,
+                row.fwhm.value,
+                row.fwhm.uncertainty,
+                resolution.value,
+                resolution.uncertainty,
+                row.area.value,
+                row.area.uncertainty
+            ));
+        }
+
+        csv
+    }
+
+    fn export_summary_csv(&self) {
+        let Some(path) = FileDialog::new()
+            .set_file_name("batch_fit.csv")
+            .add_filter("CSV", &["csv"])
+            .save_file()
+        else {
+            return;
+        };
+
+        let csv = self.summary_csv();
+        match File::create(&path).and_then(|mut file| file.write_all(csv.as_bytes())) {
+            Ok(()) => log::info!("Exported the batch fit summary to {:?}", path),
+            Err(e) => log::error!(
+                "Failed to export the batch fit summary to {:?}: {:?}",
+                path,
+                e
+            ),
+        }
+    }
+
+    // Returns true when the fit was requested
+    pub fn ui(&mut self, ui: &mut egui::Ui, names: &[String]) -> bool {
+        let mut run = false;
+
+        ui.horizontal(|ui| {
+            ui.label("Region:");
+            ui.add(egui::DragValue::new(&mut self.region.0).speed(0.1));
+            ui.label("to");
+            ui.add(egui::DragValue::new(&mut self.region.1).speed(0.1));
+        })
+        .response
+        .on_hover_text("In the uncalibrated x units of the histograms");
+
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            ui.text_edit_singleline(&mut self.filter);
+
+            if ui.button("Select All").clicked() {
+                self.selected.extend(
+                    names
+                        .iter()
+                        .filter(|name| name.contains(self.filter.as_str()))
+                        .cloned(),
+                );
+            }
+            if ui.button("Clear").clicked() {
+                self.selected.clear();
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_salt("batch_fit_histograms")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for name in names
+                    .iter()
+                    .filter(|name| name.contains(self.filter.as_str()))
+                {
+                    let mut selected = self.selected.contains(name);
+                    if ui.checkbox(&mut selected, name).changed() {
+                        if selected {
+                            self.selected.insert(name.clone());
+                        } else {
+                            self.selected.remove(name);
+                        }
+                    }
+                }
+            });
+
+        let ready = !self.selected.is_empty() && self.region.0 < self.region.1;
+        if ui
+            .add_enabled(
+                ready,
+                egui::Button::new(format!("Fit {} Histograms", self.selected.len())),
+            )
+            .on_hover_text("Fit the region of every selected histogram with its own fit settings and store the fits.\nThe background is anchored to the region edges.")
+            .clicked()
+        {
+            run = true;
+        }
+
+        if !self.failures.is_empty() {
+            ui.collapsing(format!("{} Failed", self.failures.len()), |ui| {
+                for failure in &self.failures {
+                    ui.label(failure);
+                }
+            });
+        }
+
+        if !self.results.is_empty() {
+            ui.separator();
+            self.results_ui(ui);
+        }
+
+        run
+    }
+
+    fn results_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Export CSV").clicked() {
+                self.export_summary_csv();
+            }
+            if ui.button("Copy to Clipboard").clicked() {
+                let csv = self.summary_csv();
+                ui.output_mut(|o| o.copied_text = csv);
+            }
+            if ui.button("Clear Results").clicked() {
+                self.results.clear();
+                self.failures.clear();
+            }
+        });
+
+        egui::ScrollArea::both()
+            .id_salt("batch_fit_results")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("batch_fit_results_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in ["Histogram", "Peak", "Mean", "FWHM", "Resolution", "Area"] {
+                            ui.strong(header);
+                        }
+                        ui.end_row();
+
+                        for row in &self.results {
+                            let resolution = row.resolution();
+                            ui.label(&row.histogram);
+                            ui.label(format!("{}", row.peak));
+                            ui.label(format!(
+                                "{:.2} ± {:.2}",
+                                row.mean.value, row.mean.uncertainty
+                            ));
+                            ui.label(format!(
+                                "{:.2} ± {:.2}",
+                                row.fwhm.value, row.fwhm.uncertainty
+                            ));
+                            ui.label(format!(
+                                "{:.2} ± {:.2} %",
+                                resolution.value, resolution.uncertainty
+                            ));
+                            ui.label(format!(
+                                "{:.0} ± {:.0}",
+                                row.area.value, row.area.uncertainty
+                            ));
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
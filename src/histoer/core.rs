@@ -0,0 +1,637 @@
+use fnv::FnvHashMap;
+use polars::prelude::*;
+use rayon::prelude::*;
+
+use std::fs::File;
+use std::path::Path;
+
+// The binning of the histograms without any plotting state, so histograms can be filled and
+// exported from a script without the UI. Histogram keeps its counts in a Histogram1DCore and
+// rebins them for plotting, Histogram2D bins its fills with bin_index_2d like Histogram2DCore.
+
+// Number of values each rayon task bins before the partial counts are summed
+const PARALLEL_CHUNK_SIZE: usize = 100_000;
+
+// How values that land exactly on the histogram range edges are treated when filling.
+// Cuts made on the histogram use the same convention for points on the polygon boundary.
+#[derive(PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub enum RangeEdges {
+    // min < value < max, values on the edges (or cut boundary) are dropped
+    #[default]
+    Exclusive,
+    // min <= value <= max, values on the max edge go in the last bin
+    Inclusive,
+}
+
+impl RangeEdges {
+    pub fn contains(&self, value: f64, min: f64, max: f64) -> bool {
+        match self {
+            RangeEdges::Exclusive => value > min && value < max,
+            RangeEdges::Inclusive => value >= min && value <= max,
+        }
+    }
+
+    // Count a value left out of the range, values on an excluded edge count as overflow or
+    // underflow so every entry ends up in a bin or a flow counter. NaN is counted in neither.
+    pub fn count_flow(
+        &self,
+        value: f64,
+        min: f64,
+        max: f64,
+        overflow: &mut u64,
+        underflow: &mut u64,
+    ) {
+        if self.contains(value, min, max) {
+            return;
+        }
+
+        if value >= max {
+            *overflow += 1;
+        } else if value <= min {
+            *underflow += 1;
+        }
+    }
+
+    pub fn filter_expr(&self, column_name: &str, min: f64, max: f64) -> Expr {
+        match self {
+            RangeEdges::Exclusive => col(column_name)
+                .gt(lit(min))
+                .and(col(column_name).lt(lit(max))),
+            RangeEdges::Inclusive => col(column_name)
+                .gt_eq(lit(min))
+                .and(col(column_name).lt_eq(lit(max))),
+        }
+    }
+
+    // The same filter written for python polars
+    pub fn filter_string(&self, column_name: &str, min: f64, max: f64) -> String {
+        let (lower, upper) = match self {
+            RangeEdges::Exclusive => (">", "<"),
+            RangeEdges::Inclusive => (">=", "<="),
+        };
+        format!(
+            "(pl.col({column_name:?}) {lower} {min:?}) & (pl.col({column_name:?}) {upper} {max:?})"
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+        }
+    }
+
+    fn write(&self, df: &mut DataFrame, path: &Path) -> PolarsResult<()> {
+        let file = File::create(path)?;
+        match self {
+            ExportFormat::Parquet => {
+                ParquetWriter::new(file).finish(df)?;
+            }
+            ExportFormat::Csv => {
+                CsvWriter::new(file).include_header(true).finish(df)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Counts per bin plus the overflow and underflow for values in [min, max)
+pub fn bin_values(
+    range: (f64, f64),
+    number_of_bins: usize,
+    values: &[f64],
+) -> (Vec<u64>, u64, u64) {
+    let bin_width = (range.1 - range.0) / number_of_bins as f64;
+
+    bin_values_by(range, number_of_bins, values, |value| {
+        ((value - range.0) / bin_width) as usize
+    })
+}
+
+// Same as bin_values for increasing bin edges of any width, each value is found with a binary search
+pub fn bin_values_with_edges(edges: &[f64], values: &[f64]) -> (Vec<u64>, u64, u64) {
+    let range = (edges[0], edges[edges.len() - 1]);
+
+    bin_values_by(range, edges.len() - 1, values, |value| {
+        edge_index(edges, value)
+    })
+}
+
+// The bin of a value inside the edges, the bin whose lower edge is the last one not above the value
+pub fn edge_index(edges: &[f64], value: f64) -> usize {
+    edges
+        .partition_point(|&edge| edge <= value)
+        .saturating_sub(1)
+}
+
+fn bin_values_by(
+    range: (f64, f64),
+    number_of_bins: usize,
+    values: &[f64],
+    index_of: impl Fn(f64) -> usize + Sync,
+) -> (Vec<u64>, u64, u64) {
+    let (min, max) = range;
+
+    values
+        .par_chunks(PARALLEL_CHUNK_SIZE)
+        .map(|chunk| {
+            let mut counts = vec![0_u64; number_of_bins];
+            let mut overflow = 0;
+            let mut underflow = 0;
+
+            for &value in chunk {
+                // NaN is in no bin and counted in neither the overflow nor the underflow
+                if value.is_nan() {
+                    continue;
+                }
+
+                if value >= min && value < max {
+                    let index = index_of(value);
+                    if index < number_of_bins {
+                        counts[index] += 1;
+                    }
+                } else if value >= max {
+                    overflow += 1;
+                } else {
+                    underflow += 1;
+                }
+            }
+
+            (counts, overflow, underflow)
+        })
+        .reduce(
+            || (vec![0_u64; number_of_bins], 0, 0),
+            |mut a, b| {
+                for (total, count) in a.0.iter_mut().zip(b.0) {
+                    *total += count;
+                }
+                (a.0, a.1 + b.1, a.2 + b.2)
+            },
+        )
+}
+
+// The bin of a value pair inside the range, values on the max edge (inclusive edges) go in the last bin
+pub fn bin_index_2d(
+    range: ((f64, f64), (f64, f64)),
+    bins: (usize, usize),
+    bin_width: (f64, f64),
+    range_edges: RangeEdges,
+    x: f64,
+    y: f64,
+) -> Option<(usize, usize)> {
+    let ((x_min, x_max), (y_min, y_max)) = range;
+
+    if range_edges.contains(x, x_min, x_max) && range_edges.contains(y, y_min, y_max) {
+        let x_index = (((x - x_min) / bin_width.0) as usize).min(bins.0 - 1);
+        let y_index = (((y - y_min) / bin_width.1) as usize).min(bins.1 - 1);
+        Some((x_index, y_index))
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Histogram1DCore {
+    pub name: String,
+    pub bins: Vec<u64>,
+    pub range: (f64, f64),
+    pub overflow: u64,
+    pub underflow: u64,
+    pub bin_width: f64,
+    #[serde(default)]
+    pub edges: Option<Vec<f64>>, // increasing bin edges for variable binning, None for uniform bins
+}
+
+impl Histogram1DCore {
+    pub fn new(name: &str, number_of_bins: usize, range: (f64, f64)) -> Self {
+        Self {
+            name: name.to_string(),
+            bins: vec![0; number_of_bins],
+            range,
+            overflow: 0,
+            underflow: 0,
+            bin_width: (range.1 - range.0) / number_of_bins as f64,
+            edges: None,
+        }
+    }
+
+    // Variable bin widths from increasing edges, the bin width is the mean width
+    pub fn with_edges(name: &str, edges: Vec<f64>) -> Self {
+        let range = (edges[0], edges[edges.len() - 1]);
+        let number_of_bins = edges.len() - 1;
+        Self {
+            edges: Some(edges),
+            ..Self::new(name, number_of_bins, range)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.bins = vec![0; self.bins.len()];
+        self.overflow = 0;
+        self.underflow = 0;
+    }
+
+    // The bin of a value inside the range, found with the edges for variable binning
+    pub fn bin_index(&self, value: f64) -> Option<usize> {
+        if !(value >= self.range.0 && value < self.range.1) {
+            return None;
+        }
+
+        let index = match &self.edges {
+            Some(edges) => edge_index(edges, value),
+            None => ((value - self.range.0) / self.bin_width) as usize,
+        };
+        (index < self.bins.len()).then_some(index)
+    }
+
+    // Returns the bin the value was added to, None for values in the overflow or underflow and
+    // for NaN, which is counted in neither
+    pub fn fill(&mut self, value: f64) -> Option<usize> {
+        if value.is_nan() {
+            return None;
+        }
+
+        if value >= self.range.0 && value < self.range.1 {
+            let index = self.bin_index(value)?;
+            self.bins[index] += 1;
+            return Some(index);
+        }
+
+        if value >= self.range.1 {
+            self.overflow += 1;
+        } else {
+            self.underflow += 1;
+        }
+        None
+    }
+
+    // Returns the counts added to each bin
+    pub fn fill_batch(&mut self, values: &[f64]) -> Vec<u64> {
+        let (counts, overflow, underflow) = match &self.edges {
+            Some(edges) => bin_values_with_edges(edges, values),
+            None => bin_values(self.range, self.bins.len(), values),
+        };
+
+        for (total, count) in self.bins.iter_mut().zip(&counts) {
+            *total += count;
+        }
+
+        self.overflow += overflow;
+        self.underflow += underflow;
+        counts
+    }
+
+    pub fn counts(&self) -> &[u64] {
+        &self.bins
+    }
+
+    pub fn count(&self, index: usize) -> u64 {
+        self.bins.get(index).copied().unwrap_or(0)
+    }
+
+    pub fn integral(&self) -> u64 {
+        self.bins.iter().sum()
+    }
+
+    pub fn bin_edges(&self) -> Vec<f64> {
+        match &self.edges {
+            Some(edges) => edges.clone(),
+            None => (0..=self.bins.len())
+                .map(|i| self.range.0 + i as f64 * self.bin_width)
+                .collect(),
+        }
+    }
+
+    pub fn bin_centers(&self) -> Vec<f64> {
+        self.bin_edges()
+            .windows(2)
+            .map(|pair| (pair[0] + pair[1]) / 2.0)
+            .collect()
+    }
+
+    // One row per bin, the overflow and underflow are not included
+    pub fn to_dataframe(&self) -> PolarsResult<DataFrame> {
+        let edges = self.bin_edges();
+
+        df!(
+            "bin_low" => &edges[..self.bins.len()],
+            "bin_high" => &edges[1..],
+            "counts" => &self.bins,
+        )
+    }
+
+    // The bins with an underflow row first and an overflow row last, the open edges are infinite
+    pub fn to_dataframe_with_flow(&self) -> PolarsResult<DataFrame> {
+        let edges = self.bin_edges();
+
+        let mut bin_low = vec![f64::NEG_INFINITY];
+        bin_low.extend_from_slice(&edges);
+        let mut bin_high = edges;
+        bin_high.push(f64::INFINITY);
+
+        let mut counts = vec![self.underflow];
+        counts.extend_from_slice(&self.bins);
+        counts.push(self.overflow);
+
+        df!(
+            "bin_low" => &bin_low,
+            "bin_high" => &bin_high,
+            "counts" => &counts,
+        )
+    }
+
+    // CSV files keep the overflow and underflow rows
+    pub fn export(&self, path: &Path, format: ExportFormat) -> PolarsResult<()> {
+        let mut df = match format {
+            ExportFormat::Csv => self.to_dataframe_with_flow()?,
+            ExportFormat::Parquet => self.to_dataframe()?,
+        };
+        format.write(&mut df, path)
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Histogram2DCore {
+    pub name: String,
+    pub bins: (usize, usize),
+    pub range: ((f64, f64), (f64, f64)),
+    pub bin_width: (f64, f64),
+    pub counts: FnvHashMap<(usize, usize), u64>,
+    pub overflow: (u64, u64),
+    pub underflow: (u64, u64),
+    pub range_edges: RangeEdges,
+}
+
+impl Histogram2DCore {
+    pub fn new(name: &str, bins: (usize, usize), range: ((f64, f64), (f64, f64))) -> Self {
+        Self {
+            name: name.to_string(),
+            bins,
+            range,
+            bin_width: (
+                (range.0 .1 - range.0 .0) / bins.0 as f64,
+                (range.1 .1 - range.1 .0) / bins.1 as f64,
+            ),
+            counts: FnvHashMap::default(),
+            overflow: (0, 0),
+            underflow: (0, 0),
+            range_edges: RangeEdges::default(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.clear();
+        self.overflow = (0, 0);
+        self.underflow = (0, 0);
+    }
+
+    pub fn bin_index(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        bin_index_2d(
+            self.range,
+            self.bins,
+            self.bin_width,
+            self.range_edges,
+            x,
+            y,
+        )
+    }
+
+    fn count_flow(&self, x: f64, y: f64, overflow: &mut (u64, u64), underflow: &mut (u64, u64)) {
+        let ((x_min, x_max), (y_min, y_max)) = self.range;
+
+        self.range_edges
+            .count_flow(x, x_min, x_max, &mut overflow.0, &mut underflow.0);
+        self.range_edges
+            .count_flow(y, y_min, y_max, &mut overflow.1, &mut underflow.1);
+    }
+
+    pub fn fill(&mut self, x: f64, y: f64) {
+        if let Some(index) = self.bin_index(x, y) {
+            *self.counts.entry(index).or_insert(0) += 1;
+        } else {
+            let (mut overflow, mut underflow) = (self.overflow, self.underflow);
+            self.count_flow(x, y, &mut overflow, &mut underflow);
+            self.overflow = overflow;
+            self.underflow = underflow;
+        }
+    }
+
+    pub fn fill_batch(&mut self, x_values: &[f64], y_values: &[f64]) {
+        if x_values.len() != y_values.len() {
+            log::error!(
+                "Cannot fill '{}' with {} x values and {} y values",
+                self.name,
+                x_values.len(),
+                y_values.len()
+            );
+            return;
+        }
+
+        let (counts, overflow, underflow) = x_values
+            .par_chunks(PARALLEL_CHUNK_SIZE)
+            .zip(y_values.par_chunks(PARALLEL_CHUNK_SIZE))
+            .map(|(x_chunk, y_chunk)| {
+                let mut counts: FnvHashMap<(usize, usize), u64> = FnvHashMap::default();
+                let mut overflow = (0, 0);
+                let mut underflow = (0, 0);
+
+                for (&x, &y) in x_chunk.iter().zip(y_chunk) {
+                    if let Some(index) = self.bin_index(x, y) {
+                        *counts.entry(index).or_insert(0) += 1;
+                    } else {
+                        self.count_flow(x, y, &mut overflow, &mut underflow);
+                    }
+                }
+
+                (counts, overflow, underflow)
+            })
+            .reduce(
+                || (FnvHashMap::default(), (0, 0), (0, 0)),
+                |mut a, b| {
+                    for (index, count) in b.0 {
+                        *a.0.entry(index).or_insert(0) += count;
+                    }
+                    (
+                        a.0,
+                        (a.1 .0 + b.1 .0, a.1 .1 + b.1 .1),
+                        (a.2 .0 + b.2 .0, a.2 .1 + b.2 .1),
+                    )
+                },
+            );
+
+        for (index, count) in counts {
+            *self.counts.entry(index).or_insert(0) += count;
+        }
+
+        self.overflow = (self.overflow.0 + overflow.0, self.overflow.1 + overflow.1);
+        self.underflow = (
+            self.underflow.0 + underflow.0,
+            self.underflow.1 + underflow.1,
+        );
+    }
+
+    pub fn count(&self, x_index: usize, y_index: usize) -> u64 {
+        self.counts.get(&(x_index, y_index)).copied().unwrap_or(0)
+    }
+
+    pub fn integral(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    // One row per filled bin with the bin indices and centers
+    pub fn to_dataframe(&self) -> PolarsResult<DataFrame> {
+        let mut indices: Vec<&(usize, usize)> = self.counts.keys().collect();
+        indices.sort();
+
+        let ((x_min, _), (y_min, _)) = self.range;
+        let x_bin: Vec<u64> = indices.iter().map(|index| index.0 as u64).collect();
+        let y_bin: Vec<u64> = indices.iter().map(|index| index.1 as u64).collect();
+        let x_center: Vec<f64> = indices
+            .iter()
+            .map(|index| x_min + (index.0 as f64 + 0.5) * self.bin_width.0)
+            .collect();
+        let y_center: Vec<f64> = indices
+            .iter()
+            .map(|index| y_min + (index.1 as f64 + 0.5) * self.bin_width.1)
+            .collect();
+        let counts: Vec<u64> = indices.iter().map(|index| self.counts[index]).collect();
+
+        df!(
+            "x_bin" => x_bin,
+            "y_bin" => y_bin,
+            "x_center" => x_center,
+            "y_center" => y_center,
+            "counts" => counts,
+        )
+    }
+
+    pub fn export(&self, path: &Path, format: ExportFormat) -> PolarsResult<()> {
+        format.write(&mut self.to_dataframe()?, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_edges_contains_the_edges_only_when_inclusive() {
+        let (min, max) = (0.0, 10.0);
+
+        for value in [min, max] {
+            assert!(!RangeEdges::Exclusive.contains(value, min, max));
+            assert!(RangeEdges::Inclusive.contains(value, min, max));
+        }
+
+        for edges in [RangeEdges::Exclusive, RangeEdges::Inclusive] {
+            assert!(edges.contains(5.0, min, max));
+            assert!(!edges.contains(-0.1, min, max));
+            assert!(!edges.contains(10.1, min, max));
+        }
+    }
+
+    #[test]
+    fn range_edges_count_the_excluded_edges_as_flow() {
+        let (min, max) = (0.0, 10.0);
+        let values = [min, 5.0, max, -1.0, 11.0];
+
+        let mut flow = (0, 0);
+        for value in values {
+            RangeEdges::Exclusive.count_flow(value, min, max, &mut flow.0, &mut flow.1);
+        }
+        assert_eq!(flow, (2, 2));
+
+        let mut flow = (0, 0);
+        for value in values {
+            RangeEdges::Inclusive.count_flow(value, min, max, &mut flow.0, &mut flow.1);
+        }
+        assert_eq!(flow, (1, 1));
+    }
+
+    #[test]
+    fn range_edges_filter_expr_matches_contains() {
+        let (min, max) = (0.0, 10.0);
+        let values = [min, 5.0, max, -1.0, 11.0];
+        let df = df!("x" => &values).unwrap();
+
+        for edges in [RangeEdges::Exclusive, RangeEdges::Inclusive] {
+            let filtered = df
+                .clone()
+                .lazy()
+                .filter(edges.filter_expr("x", min, max))
+                .collect()
+                .unwrap();
+            let kept: Vec<f64> = filtered
+                .column("x")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect();
+
+            let expected: Vec<f64> = values
+                .into_iter()
+                .filter(|&value| edges.contains(value, min, max))
+                .collect();
+            assert_eq!(kept, expected);
+        }
+    }
+
+    #[test]
+    fn histogram_1d_core_fills_variable_bins() {
+        let mut hist = Histogram1DCore::with_edges("variable", vec![0.0, 1.0, 5.0, 10.0]);
+
+        assert_eq!(hist.fill(0.5), Some(0));
+        assert_eq!(hist.fill(4.0), Some(1));
+        assert_eq!(hist.fill(10.0), None);
+        assert_eq!(hist.fill_batch(&[7.0, 9.5, -1.0]), vec![0, 0, 2]);
+        assert_eq!(hist.bins, vec![1, 1, 2]);
+        assert_eq!((hist.overflow, hist.underflow), (1, 1));
+        assert_eq!(hist.bin_centers(), vec![0.5, 3.0, 7.5]);
+    }
+
+    #[test]
+    fn nan_is_counted_in_no_bin_and_neither_flow() {
+        let mut hist = Histogram1DCore::new("nan", 10, (0.0, 10.0));
+        hist.fill_batch(&[f64::NAN, 5.0, -1.0]);
+        assert_eq!(hist.fill(f64::NAN), None);
+        assert_eq!(hist.integral(), 1);
+        assert_eq!((hist.overflow, hist.underflow), (0, 1));
+
+        let mut hist = Histogram2DCore::new("nan", (10, 10), ((0.0, 10.0), (0.0, 10.0)));
+        hist.fill_batch(&[f64::NAN, 5.0], &[5.0, f64::NAN]);
+        hist.fill(f64::NAN, f64::NAN);
+        assert_eq!(hist.integral(), 0);
+        assert_eq!((hist.overflow, hist.underflow), ((0, 0), (0, 0)));
+    }
+
+    #[test]
+    fn histogram_2d_core_fills_the_range_edges() {
+        let range = ((0.0, 10.0), (0.0, 10.0));
+
+        let mut hist = Histogram2DCore::new("exclusive", (10, 10), range);
+        hist.fill_batch(&[0.0, 10.0, 5.0, 5.0], &[5.0, 5.0, 0.0, 10.0]);
+        assert_eq!(hist.integral(), 0);
+        assert_eq!(hist.overflow, (1, 1));
+        assert_eq!(hist.underflow, (1, 1));
+
+        let mut hist = Histogram2DCore::new("inclusive", (10, 10), range);
+        hist.range_edges = RangeEdges::Inclusive;
+        hist.fill_batch(&[0.0, 10.0, 5.0, 5.0], &[5.0, 5.0, 0.0, 10.0]);
+        assert_eq!(hist.integral(), 4);
+        assert_eq!(hist.count(0, 5), 1);
+        assert_eq!(hist.count(9, 5), 1);
+        assert_eq!(hist.count(5, 0), 1);
+        assert_eq!(hist.count(5, 9), 1);
+        assert_eq!(hist.overflow, (0, 0));
+        assert_eq!(hist.underflow, (0, 0));
+    }
+}
@@ -0,0 +1,176 @@
+use super::histogram1d::Histogram;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum HistogramOperation {
+    #[default]
+    Add,
+    Subtract,
+    Divide,
+}
+
+impl HistogramOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HistogramOperation::Add => "Add",
+            HistogramOperation::Subtract => "Subtract",
+            HistogramOperation::Divide => "Divide",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            HistogramOperation::Add => "+",
+            HistogramOperation::Subtract => "-",
+            HistogramOperation::Divide => "/",
+        }
+    }
+}
+
+impl Histogram {
+    // The counts of the original binning, weighted if the histogram was filled with weights
+    fn original_counts(&self) -> Vec<f64> {
+        if self.is_weighted() {
+            self.original_weights.clone()
+        } else {
+            self.core.bins.iter().map(|&count| count as f64).collect()
+        }
+    }
+
+    fn original_sumw2_or_counts(&self) -> Vec<f64> {
+        if self.original_sumw2.is_empty() {
+            self.original_counts()
+        } else {
+            self.original_sumw2.clone()
+        }
+    }
+
+    fn check_compatible(&self, other: &Histogram) -> Result<(), String> {
+        if self.core.bins.len() != other.core.bins.len() {
+            return Err(format!(
+                "'{}' has {} bins and '{}' has {}",
+                self.name,
+                self.core.bins.len(),
+                other.name,
+                other.core.bins.len()
+            ));
+        }
+
+        if self.range != other.range {
+            return Err(format!(
+                "'{}' covers {:?} and '{}' covers {:?}",
+                self.name, self.range, other.name, other.range
+            ));
+        }
+
+        if self.core.edges != other.core.edges {
+            return Err(format!(
+                "'{}' and '{}' have different bin edges",
+                self.name, other.name
+            ));
+        }
+
+        Ok(())
+    }
+
+    // Bin-by-bin combination of the original binning. The result keeps signed values in the
+    // weighted counts (plotted and fitted) while the entries are clamped at zero.
+    fn combine(
+        &self,
+        other: &Histogram,
+        operation: impl Fn(f64, f64, f64, f64) -> (f64, f64),
+    ) -> Result<Histogram, String> {
+        self.check_compatible(other)?;
+
+        let (counts, sumw2): (Vec<f64>, Vec<f64>) = self
+            .original_counts()
+            .into_iter()
+            .zip(other.original_counts())
+            .zip(
+                self.original_sumw2_or_counts()
+                    .into_iter()
+                    .zip(other.original_sumw2_or_counts()),
+            )
+            .map(|((a, b), (a_sumw2, b_sumw2))| operation(a, b, a_sumw2, b_sumw2))
+            .unzip();
+
+        let mut result = Histogram::new(&self.name, counts.len(), self.range);
+        result.core.edges.clone_from(&self.core.edges);
+        result.core.bins = counts
+            .iter()
+            .map(|&count| count.round().max(0.0) as u64)
+            .collect();
+        result.bins.clone_from(&result.core.bins);
+        result.original_weights = counts;
+        result.original_sumw2 = sumw2;
+        result.update_line_points();
+
+        Ok(result)
+    }
+
+    pub fn add(&self, other: &Histogram) -> Result<Histogram, String> {
+        let mut result =
+            self.combine(other, |a, b, a_sumw2, b_sumw2| (a + b, a_sumw2 + b_sumw2))?;
+        result.core.overflow = self.core.overflow + other.core.overflow;
+        result.core.underflow = self.core.underflow + other.core.underflow;
+        Ok(result)
+    }
+
+    // The difference keeps its sign, the uncertainties add in quadrature
+    pub fn subtract(&self, other: &Histogram) -> Result<Histogram, String> {
+        let mut result =
+            self.combine(other, |a, b, a_sumw2, b_sumw2| (a - b, a_sumw2 + b_sumw2))?;
+        result.core.overflow = self.core.overflow.saturating_sub(other.core.overflow);
+        result.core.underflow = self.core.underflow.saturating_sub(other.core.underflow);
+        Ok(result)
+    }
+
+    // Bins divided by zero are left empty
+    pub fn divide(&self, other: &Histogram) -> Result<Histogram, String> {
+        self.combine(other, |a, b, a_sumw2, b_sumw2| {
+            if b == 0.0 {
+                return (0.0, 0.0);
+            }
+            let ratio = a / b;
+            (ratio, a_sumw2 / (b * b) + ratio * ratio * b_sumw2 / (b * b))
+        })
+    }
+
+    // Add the counts of another histogram with the same binning, keeping this one's settings and fits
+    pub fn merge(&mut self, other: &Histogram) -> Result<(), String> {
+        self.check_compatible(other)?;
+
+        if self.is_weighted() || other.is_weighted() {
+            let add = |a: Vec<f64>, b: Vec<f64>| -> Vec<f64> {
+                a.into_iter().zip(b).map(|(a, b)| a + b).collect()
+            };
+            let weights = add(self.original_counts(), other.original_counts());
+            let sumw2 = add(
+                self.original_sumw2_or_counts(),
+                other.original_sumw2_or_counts(),
+            );
+            self.original_weights = weights;
+            self.original_sumw2 = sumw2;
+        }
+
+        for (count, other_count) in self.core.bins.iter_mut().zip(&other.core.bins) {
+            *count += other_count;
+        }
+        self.core.overflow += other.core.overflow;
+        self.core.underflow += other.core.underflow;
+
+        self.rebin();
+        Ok(())
+    }
+
+    pub fn apply_operation(
+        &self,
+        other: &Histogram,
+        operation: HistogramOperation,
+    ) -> Result<Histogram, String> {
+        match operation {
+            HistogramOperation::Add => self.add(other),
+            HistogramOperation::Subtract => self.subtract(other),
+            HistogramOperation::Divide => self.divide(other),
+        }
+    }
+}
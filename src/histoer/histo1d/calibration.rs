@@ -0,0 +1,326 @@
+use crate::fitter::models::gaussian::Value;
+use crate::fitter::models::polynomial::PolynomialFitter;
+
+// A peak centroid in channels and the known energy it should map to
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReferencePeak {
+    pub channel: f64,
+    pub energy: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Calibration {
+    pub enabled: bool,
+    pub coefficients: Vec<f64>, // c0 + c1*x + c2*x^2 + ...
+    pub unit: String,
+    #[serde(default)]
+    pub reference_peaks: Vec<ReferencePeak>,
+    #[serde(default = "default_degree")]
+    pub degree: usize, // degree of the polynomial fit to the reference peaks
+    #[serde(skip)]
+    pub coefficient_uncertainties: Vec<f64>, // from the last fit to the reference peaks
+}
+
+fn default_degree() -> usize {
+    1
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Calibration {
+            enabled: false,
+            coefficients: vec![0.0, 1.0],
+            unit: "keV".to_string(),
+            reference_peaks: Vec::new(),
+            degree: default_degree(),
+            coefficient_uncertainties: Vec::new(),
+        }
+    }
+}
+
+impl Calibration {
+    pub fn is_active(&self) -> bool {
+        self.enabled && !self.coefficients.is_empty()
+    }
+
+    // Evaluate the calibration polynomial at a channel value
+    pub fn calibrate(&self, channel: f64) -> f64 {
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |acc, &coef| acc * channel + coef)
+    }
+
+    // Slope of the calibration polynomial at a channel value
+    pub fn derivative(&self, channel: f64) -> f64 {
+        self.coefficients
+            .iter()
+            .enumerate()
+            .skip(1)
+            .rev()
+            .fold(0.0, |acc, (i, &coef)| acc * channel + i as f64 * coef)
+    }
+
+    // Calibrated centroid and fwhm of a fitted peak, the widths and uncertainties are scaled by
+    // the slope at the centroid
+    pub fn calibrate_peak(&self, mean: &Value, fwhm: &Value) -> (Value, Value) {
+        let slope = self.derivative(mean.value).abs();
+
+        (
+            Value {
+                value: self.calibrate(mean.value),
+                uncertainty: slope * mean.uncertainty,
+            },
+            Value {
+                value: slope * fwhm.value,
+                uncertainty: slope * fwhm.uncertainty,
+            },
+        )
+    }
+
+    // Take the intercept and slope of a linear fit done in the fit panel
+    pub fn use_coefficients(&mut self, coefficients: Vec<f64>, uncertainties: Vec<f64>) {
+        if coefficients.is_empty() || coefficients.iter().any(|coef| !coef.is_finite()) {
+            log::error!("Can't calibrate with the coefficients {:?}", coefficients);
+            return;
+        }
+
+        self.degree = coefficients.len() - 1;
+        self.coefficients = coefficients;
+        self.coefficient_uncertainties = uncertainties;
+        self.enabled = true;
+        log::info!("Calibration set to {:?}", self.coefficients);
+    }
+
+    // Straight line through the reference peaks, the intercept and slope become the calibration
+    pub fn fit_line_to_peaks(&mut self) {
+        self.degree = 1;
+        self.calibrate_from_peaks();
+    }
+
+    // Least squares polynomial through the reference peaks
+    pub fn calibrate_from_peaks(&mut self) {
+        if self.reference_peaks.len() < 2 {
+            log::error!("Need at least two reference peaks to calibrate");
+            return;
+        }
+
+        let degree = self.degree.clamp(1, self.reference_peaks.len() - 1);
+        let mut fitter = PolynomialFitter::new(degree);
+        fitter.x_data = self
+            .reference_peaks
+            .iter()
+            .map(|peak| peak.channel)
+            .collect();
+        fitter.y_data = self
+            .reference_peaks
+            .iter()
+            .map(|peak| peak.energy)
+            .collect();
+        fitter.fit();
+
+        match fitter.coefficients {
+            Some(coefficients) if coefficients.iter().all(|coef| coef.is_finite()) => {
+                self.coefficients = coefficients;
+                self.coefficient_uncertainties = fitter.uncertainties.unwrap_or_default();
+                self.degree = degree;
+                self.enabled = true;
+            }
+            _ => log::error!("Failed to fit the calibration to the reference peaks"),
+        }
+    }
+
+    // Format the tick labels in calibrated units, the bins and fits stay in channels
+    pub fn apply_to_plot<'a>(&self, plot: egui_plot::Plot<'a>) -> egui_plot::Plot<'a> {
+        if !self.is_active() {
+            return plot;
+        }
+
+        let calibration = self.clone();
+        let label = if self.unit.is_empty() {
+            "Calibrated".to_string()
+        } else {
+            format!("Calibrated [{}]", self.unit)
+        };
+
+        plot.x_axis_label(label)
+            .x_axis_formatter(move |gm, _bounds| {
+                let value = calibration.calibrate(gm.value);
+
+                // use the calibrated step size to pick the number of decimals
+                let step = (calibration.calibrate(gm.value + gm.step_size) - value).abs();
+                let decimals = if step > 0.0 {
+                    (-step.log10().floor()).clamp(0.0, 6.0) as usize
+                } else {
+                    2
+                };
+
+                format!("{value:.decimals$}")
+            })
+    }
+
+    // Fitted centroids can be added as reference peaks, their energies are entered by hand
+    fn peaks_ui(&mut self, ui: &mut egui::Ui, peak_centroids: &[f64]) {
+        ui.menu_button("Calibrate from Peaks", |ui| {
+            ui.label("Reference Peaks (channel → energy)");
+
+            let mut index_to_remove = None;
+            for (i, peak) in self.reference_peaks.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut peak.channel)
+                            .speed(0.1)
+                            .prefix("Channel: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut peak.energy)
+                            .speed(0.1)
+                            .prefix("Energy: ")
+                            .suffix(format!(" {}", self.unit)),
+                    );
+
+                    if ui.button("🗙").clicked() {
+                        index_to_remove = Some(i);
+                    }
+                });
+            }
+
+            if let Some(index) = index_to_remove {
+                self.reference_peaks.remove(index);
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Add Peak").clicked() {
+                    self.reference_peaks.push(ReferencePeak::default());
+                }
+
+                if ui
+                    .add_enabled(
+                        !peak_centroids.is_empty(),
+                        egui::Button::new("Add Fitted Peaks"),
+                    )
+                    .on_hover_text("Add the centroids of the current and stored fits")
+                    .clicked()
+                {
+                    for &channel in peak_centroids {
+                        if !self
+                            .reference_peaks
+                            .iter()
+                            .any(|peak| peak.channel == channel)
+                        {
+                            self.reference_peaks.push(ReferencePeak {
+                                channel,
+                                energy: 0.0,
+                            });
+                        }
+                    }
+                }
+
+                if ui.button("Clear").clicked() {
+                    self.reference_peaks.clear();
+                }
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::DragValue::new(&mut self.degree)
+                        .range(1..=self.reference_peaks.len().saturating_sub(1).max(1))
+                        .prefix("Degree: "),
+                );
+
+                if ui
+                    .add_enabled(
+                        self.reference_peaks.len() >= 2,
+                        egui::Button::new("Calibrate"),
+                    )
+                    .clicked()
+                {
+                    self.calibrate_from_peaks();
+                }
+
+                if ui
+                    .add_enabled(
+                        self.reference_peaks.len() >= 2,
+                        egui::Button::new("Linear Fit"),
+                    )
+                    .on_hover_text(
+                        "Fit a line to the (channel, energy) pairs and use its intercept and slope",
+                    )
+                    .clicked()
+                {
+                    self.fit_line_to_peaks();
+                }
+            });
+
+            if self.coefficients.len() == 2 && self.coefficient_uncertainties.len() == 2 {
+                for (i, name) in ["Intercept", "Slope"].iter().enumerate() {
+                    ui.label(format!(
+                        "{}: {:.6e} ± {:.2e}",
+                        name, self.coefficients[i], self.coefficient_uncertainties[i]
+                    ));
+                }
+            }
+        });
+    }
+
+    pub fn menu_button(&mut self, ui: &mut egui::Ui, peak_centroids: &[f64]) {
+        ui.menu_button("Calibration", |ui| {
+            ui.checkbox(&mut self.enabled, "Show Calibrated Axis")
+                .on_hover_text(
+                "Label the x-axis ticks with calibrated values\nThe bins and fits stay in channels",
+            );
+
+            ui.horizontal(|ui| {
+                ui.label("Unit:");
+                ui.text_edit_singleline(&mut self.unit);
+            });
+
+            ui.separator();
+
+            ui.label("Coefficients (c0 + c1*x + c2*x² + ...)");
+
+            let mut index_to_remove = None;
+            let mut edited = false;
+            for (i, coef) in self.coefficients.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    edited |= ui
+                        .add(
+                            egui::DragValue::new(coef)
+                                .speed(0.001)
+                                .prefix(format!("c{}: ", i)),
+                        )
+                        .changed();
+
+                    if ui.button("🗙").clicked() {
+                        index_to_remove = Some(i);
+                    }
+                });
+            }
+
+            if let Some(index) = index_to_remove {
+                self.coefficients.remove(index);
+                edited = true;
+            }
+            if edited {
+                // the uncertainties only belong to the fitted coefficients
+                self.coefficient_uncertainties.clear();
+            }
+
+            ui.horizontal(|ui| {
+                if ui.button("Add Term").clicked() {
+                    self.coefficients.push(0.0);
+                }
+
+                if ui.button("Reset").clicked() {
+                    *self = Calibration::default();
+                }
+            });
+
+            ui.separator();
+
+            self.peaks_ui(ui, peak_centroids);
+        });
+    }
+}
@@ -4,8 +4,20 @@ impl Histogram {
     // Handles the context menu for the histogram
     pub fn context_menu(&mut self, ui: &mut egui::Ui) {
         self.line.menu_button(ui);
-        self.plot_settings.settings_ui(ui);
+        let peak_centroids = self.fits.peak_centroids();
+        self.plot_settings.settings_ui(ui, &peak_centroids);
         self.keybinds_ui(ui);
+        self.plot_settings.export.menu_button(ui, &self.name);
+        self.export_root_button(ui);
+        self.export_csv_button(ui);
+        self.fit_report_menu(ui);
+
+        ui.menu_button("Notes", |ui| {
+            ui.text_edit_multiline(&mut self.notes);
+        });
+
+        self.overlays_ui(ui);
+        self.duplicate_ui(ui);
 
         self.fits.fit_context_menu_ui(ui);
 
@@ -16,6 +28,13 @@ impl Histogram {
             .on_hover_text("Takes the settings (adjust below) and finds peaks in the spectrum\nIf there are background markers, it will fit a background before it finds the peaks in between the min and max values. Likewise for region markers.\nKeybind: o").clicked() {
             self.find_peaks();
         }
+        if ui
+            .button("Detect Peaks for Fit")
+            .on_hover_text("Place peak markers at the peaks that rise above the background by the min prominence and min distance of the settings below, ready to fit")
+            .clicked()
+        {
+            self.detect_fit_peaks();
+        }
         self.plot_settings.find_peaks_settings.menu_button(ui);
 
         ui.separator();
@@ -0,0 +1,62 @@
+use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_plot_export::sanitize_file_name;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl Histogram {
+    // One row per bin with an underflow row first and an overflow row last, the open edges are
+    // infinite like the headless export. The current bins are written, so the rebin factor is
+    // already applied, and the counts are weighted if the histogram was filled with weights.
+    // The first line is a comment with the fingerprint, skipped with pandas' comment="#".
+    pub fn export_csv(&self, path: &Path) -> std::io::Result<()> {
+        let edges = self.get_bin_edges();
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# fingerprint: {}", self.fingerprint())?;
+        writeln!(writer, "bin_low,bin_high,counts")?;
+        writeln!(
+            writer,
+            "{},{},{}",
+            f64::NEG_INFINITY,
+            edges[0],
+            self.core.underflow
+        )?;
+        for (pair, count) in edges.windows(2).zip(self.counts()) {
+            writeln!(writer, "{},{},{}", pair[0], pair[1], count)?;
+        }
+        writeln!(
+            writer,
+            "{},{},{}",
+            edges[edges.len() - 1],
+            f64::INFINITY,
+            self.core.overflow
+        )?;
+        writer.flush()?;
+
+        log::info!("Exported '{}' to {:?}", self.name, path);
+
+        Ok(())
+    }
+
+    pub fn export_csv_button(&self, ui: &mut egui::Ui) {
+        if ui
+            .button("Export CSV")
+            .on_hover_text("Save the bin edges and counts with the underflow and overflow rows")
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name(format!("{}.csv", sanitize_file_name(&self.name)))
+                .add_filter("CSV", &["csv"])
+                .save_file()
+            {
+                if let Err(e) = self.export_csv(&path) {
+                    log::error!("Failed to export '{}' to {:?}: {:?}", self.name, path, e);
+                }
+            }
+
+            ui.close_menu();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_plot_export::{sanitize_file_name, EguiPlotExport};
+
+// The histogram with its fits and stats box, saved as a PNG or copied to the clipboard as text
+#[derive(Debug, Clone, Default)]
+pub struct FitReport {
+    pub export: EguiPlotExport,
+    restore_stats_info: Option<bool>, // the stats box is forced on while the screenshot is taken
+}
+
+impl Histogram {
+    // Text version of the report, egui can only put text on the clipboard
+    pub fn fit_report_text(&self) -> String {
+        let mut report = format!(
+            "{}\n\n{}\n",
+            self.name,
+            self.stats_text(self.range.0, self.range.1, "range")
+        );
+
+        if self.fits.stored_fits.is_empty() {
+            report.push_str("\nNo stored fits\n");
+        } else {
+            report.push_str(&format!("\n{}", self.fits.fit_table_csv()));
+        }
+
+        if !self.notes.trim().is_empty() {
+            report.push_str(&format!("\nNotes:\n{}\n", self.notes.trim()));
+        }
+
+        report
+    }
+
+    pub fn request_fit_report_png(&mut self, path: std::path::PathBuf) {
+        if self.fit_report.restore_stats_info.is_none() {
+            self.fit_report.restore_stats_info = Some(self.plot_settings.stats_info);
+        }
+        self.plot_settings.stats_info = true;
+        self.fit_report.export.request(path);
+    }
+
+    pub fn fit_report_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Fit Report", |ui| {
+            if ui
+                .button("Save PNG")
+                .on_hover_text("Save the histogram with the fit stats, the stats box, and the residuals as a PNG image")
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_file_name(format!("{}_fit_report.png", sanitize_file_name(&self.name)))
+                    .add_filter("PNG", &["png"])
+                    .save_file()
+                {
+                    self.request_fit_report_png(path);
+                }
+
+                ui.close_menu();
+            }
+
+            if ui
+                .button("Copy to Clipboard")
+                .on_hover_text("Copy the stats box and the stored fit table as text")
+                .clicked()
+            {
+                let report = self.fit_report_text();
+                ui.output_mut(|o| o.copied_text = report);
+                log::info!("Copied the fit report of '{}' to the clipboard", self.name);
+
+                ui.close_menu();
+            }
+        });
+    }
+
+    // Called at the end of render with the area covered by the fit stats, the plot and the residuals
+    pub fn update_fit_report(&mut self, ui: &egui::Ui, rect: egui::Rect) {
+        self.fit_report.export.update(ui, rect);
+
+        if !self.fit_report.export.is_pending() {
+            if let Some(stats_info) = self.fit_report.restore_stats_info.take() {
+                self.plot_settings.stats_info = stats_info;
+            }
+        }
+    }
+}
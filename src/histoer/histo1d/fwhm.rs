@@ -0,0 +1,161 @@
+use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_line::log_y_floor;
+
+// A quick peak width measured from the bin contents without fitting, it is only drawn until the
+// next click and never stored with the fits
+#[derive(Debug, Clone, Copy)]
+pub struct FwhmMeasurement {
+    pub centroid: f64,
+    pub fwhm: f64,
+    pub maximum: f64,
+    pub left: f64,  // half maximum crossing on the low side
+    pub right: f64, // half maximum crossing on the high side
+}
+
+// Position where the straight line between two bin centers reaches the target height
+fn interpolate_crossing(x0: f64, y0: f64, x1: f64, y1: f64, target: f64) -> f64 {
+    if y1 == y0 {
+        return 0.5 * (x0 + x1);
+    }
+    x0 + (target - y0) * (x1 - x0) / (y1 - y0)
+}
+
+impl Histogram {
+    // Find the local maximum near x, then walk out to the half maximum crossings on each side.
+    // The half maximum is measured from zero, there is no background subtraction.
+    pub fn measure_fwhm(&self, x: f64) -> Option<FwhmMeasurement> {
+        let counts = self.counts();
+        let centers = self.get_bin_centers();
+        let clicked_bin = self.get_bin_index(x)?.min(counts.len().checked_sub(1)?);
+
+        // the click only has to be close, take the largest bin in a small window
+        let window = (counts.len() / 100).max(3);
+        let start = clicked_bin.saturating_sub(window);
+        let end = (clicked_bin + window).min(counts.len() - 1);
+        let mut peak_bin = (start..=end).max_by(|&a, &b| counts[a].total_cmp(&counts[b]))?;
+
+        // then climb to the top in case the window stopped on the shoulder
+        while peak_bin > 0 && counts[peak_bin - 1] > counts[peak_bin] {
+            peak_bin -= 1;
+        }
+        while peak_bin + 1 < counts.len() && counts[peak_bin + 1] > counts[peak_bin] {
+            peak_bin += 1;
+        }
+
+        let maximum = counts[peak_bin];
+        if maximum <= 0.0 {
+            return None;
+        }
+        let half_maximum = 0.5 * maximum;
+
+        let left_bin = (0..peak_bin)
+            .rev()
+            .find(|&bin| counts[bin] < half_maximum)?;
+        let right_bin = (peak_bin + 1..counts.len()).find(|&bin| counts[bin] < half_maximum)?;
+
+        let left = interpolate_crossing(
+            centers[left_bin],
+            counts[left_bin],
+            centers[left_bin + 1],
+            counts[left_bin + 1],
+            half_maximum,
+        );
+        let right = interpolate_crossing(
+            centers[right_bin - 1],
+            counts[right_bin - 1],
+            centers[right_bin],
+            counts[right_bin],
+            half_maximum,
+        );
+
+        // centroid of the bins above half maximum
+        let (sum, total) = (left_bin + 1..right_bin).fold((0.0, 0.0), |(sum, total), bin| {
+            (sum + counts[bin] * centers[bin], total + counts[bin])
+        });
+
+        Some(FwhmMeasurement {
+            centroid: sum / total,
+            fwhm: right - left,
+            maximum,
+            left,
+            right,
+        })
+    }
+
+    pub fn update_fwhm_measurement(&mut self, x: f64) {
+        self.fwhm_measurement = self.measure_fwhm(x);
+        if self.fwhm_measurement.is_none() {
+            log::warn!(
+                "No peak with both half maximum crossings found near {:.2} in '{}'",
+                x,
+                self.name
+            );
+        }
+    }
+
+    pub fn draw_fwhm_measurement(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let Some(measurement) = self.fwhm_measurement else {
+            return;
+        };
+
+        let log_y = self.plot_settings.egui_settings.log_y;
+        let log_x = self.plot_settings.egui_settings.log_x;
+        let factor = self.plot_settings.normalization.factor(&self.counts());
+        let to_plot_y = |y: f64| {
+            let y = y * factor;
+            if log_y {
+                y.max(log_y_floor(factor)).log10()
+            } else {
+                y
+            }
+        };
+        let to_plot_x = |x: f64| {
+            if log_x {
+                x.max(f64::MIN_POSITIVE).log10()
+            } else {
+                x
+            }
+        };
+
+        let half_maximum = to_plot_y(0.5 * measurement.maximum);
+        plot_ui.line(
+            egui_plot::Line::new(vec![
+                [to_plot_x(measurement.left), half_maximum],
+                [to_plot_x(measurement.right), half_maximum],
+            ])
+            .color(egui::Color32::YELLOW)
+            .width(2.0)
+            .allow_hover(false),
+        );
+
+        let calibration = &self.plot_settings.calibration;
+        let text = if calibration.is_active() && !log_x {
+            let slope = calibration.derivative(measurement.centroid).abs();
+            format!(
+                "Centroid: {:.2} {unit}\nFWHM: {:.2} {unit}",
+                calibration.calibrate(measurement.centroid),
+                slope * measurement.fwhm,
+                unit = calibration.unit
+            )
+        } else {
+            format!(
+                "Centroid: {:.2}\nFWHM: {:.2}",
+                measurement.centroid, measurement.fwhm
+            )
+        };
+
+        plot_ui.text(
+            egui_plot::Text::new(
+                egui_plot::PlotPoint::new(
+                    to_plot_x(measurement.centroid),
+                    to_plot_y(measurement.maximum),
+                ),
+                text,
+            )
+            .anchor(egui::Align2::CENTER_BOTTOM)
+            .highlight(true)
+            .allow_hover(false)
+            .color(egui::Color32::YELLOW),
+        );
+    }
+}
@@ -1,23 +1,47 @@
 use egui::Vec2b;
 
-use super::plot_settings::PlotSettings;
-use crate::egui_plot_stuff::egui_line::EguiLine;
+use super::fit_report::FitReport;
+use super::fwhm::FwhmMeasurement;
+use super::plot_settings::{Normalization, PlotSettings};
+use crate::egui_plot_stuff::egui_line::{log_y_floor, EguiLine};
 use crate::fitter::background_fitter::BackgroundFitter;
 use crate::fitter::fit_handler::Fits;
+use crate::fitter::fit_settings::PeakShape;
 use crate::fitter::main_fitter::{FitModel, Fitter};
+use crate::fitter::models::gaussian::{GaussianConstraints, Value};
+use crate::histoer::core::Histogram1DCore;
+use crate::util::preset::FitTemplate;
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct Histogram {
     pub name: String,
-    pub bins: Vec<u64>,
+    pub bins: Vec<u64>, // the counts of the current binning, rebinned from the core
     pub range: (f64, f64),
-    pub overflow: u64,
-    pub underflow: u64,
     pub bin_width: f64,
     pub line: EguiLine,
     pub plot_settings: PlotSettings,
     pub fits: Fits,
-    pub original_bins: Vec<u64>,
+    pub core: Histogram1DCore, // the counts, overflow and underflow of the original binning
+    #[serde(default)]
+    pub original_weights: Vec<f64>, // weighted counts of the original bins, empty unless filled with weights
+    #[serde(default)]
+    pub original_sumw2: Vec<f64>, // summed squared weights of the original bins, empty unless filled with weights
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub overlays: Vec<Histogram>, // the same histogram filled from other datasets, named by dataset
+    #[serde(skip)]
+    pub duplicate_name: String,
+    #[serde(skip)]
+    pub pending_duplicate: Option<String>, // picked up by the histogrammer, the name of the copy
+    #[serde(skip)]
+    pub fwhm_measurement: Option<FwhmMeasurement>, // alt + click on a peak, cleared by the next click
+    #[serde(skip)]
+    pub fit_report: FitReport,
+    #[serde(skip)]
+    pub visible_range: Option<(f64, f64)>, // x bounds of the plot in data units, updated every draw
+    #[serde(skip)]
+    pub pending_refill_refit: bool, // set by reset, the histogrammer refits once the fill is done
 }
 
 impl Histogram {
@@ -27,8 +51,6 @@ impl Histogram {
             name: name.to_string(),
             bins: vec![0; number_of_bins],
             range,
-            overflow: 0,
-            underflow: 0,
             bin_width: (range.1 - range.0) / number_of_bins as f64,
             line: EguiLine {
                 name: name.to_string(),
@@ -36,34 +58,270 @@ impl Histogram {
             },
             plot_settings: PlotSettings::default(),
             fits: Fits::new(),
-            original_bins: vec![0; number_of_bins],
+            core: Histogram1DCore::new(name, number_of_bins, range),
+            original_weights: Vec::new(),
+            original_sumw2: Vec::new(),
+            notes: String::new(),
+            overlays: Vec::new(),
+            duplicate_name: String::new(),
+            pending_duplicate: None,
+            fwhm_measurement: None,
+            fit_report: FitReport::default(),
+            visible_range: None,
+            pending_refill_refit: false,
         }
     }
 
+    // Create a histogram with variable bin widths from increasing bin edges.
+    // The bin width is the mean width, used where a single width is needed like the fit initial guesses.
+    pub fn with_edges(name: &str, edges: Vec<f64>) -> Result<Self, String> {
+        if edges.len() < 2 {
+            return Err(format!(
+                "'{}' needs at least two bin edges, got {}",
+                name,
+                edges.len()
+            ));
+        }
+
+        if edges.iter().any(|edge| !edge.is_finite()) {
+            return Err(format!("'{}' has bin edges that are not finite", name));
+        }
+
+        if let Some(pair) = edges.windows(2).find(|pair| pair[1] <= pair[0]) {
+            return Err(format!(
+                "'{}' bin edges must be increasing, {} is followed by {}",
+                name, pair[0], pair[1]
+            ));
+        }
+
+        let range = (edges[0], edges[edges.len() - 1]);
+        let mut hist = Self::new(name, edges.len() - 1, range);
+        hist.core = Histogram1DCore::with_edges(name, edges);
+        hist.update_line_points();
+
+        Ok(hist)
+    }
+
+    pub fn is_variable_binning(&self) -> bool {
+        self.core.edges.is_some()
+    }
+
+    // The number of original bins merged into each bin of the current binning. Fills go to the
+    // core, and to the current bin holding the original one, so the counts stay right after a rebin.
+    fn rebin_factor(&self) -> usize {
+        (self.core.bins.len() / self.bins.len().max(1)).max(1)
+    }
+
+    // Draw another dataset's histogram over this one, replacing any overlay with the same name
+    pub fn set_overlay(&mut self, mut overlay: Histogram, color: egui::Color32) {
+        overlay.line.name.clone_from(&overlay.name);
+        overlay.line.color = color;
+        overlay.line.name_in_legend = true;
+        overlay.update_line_points();
+
+        self.line.name_in_legend = true;
+
+        if let Some(existing) = self.overlays.iter_mut().find(|o| o.name == overlay.name) {
+            *existing = overlay;
+        } else {
+            self.overlays.push(overlay);
+        }
+    }
+
+    pub fn overlays_ui(&mut self, ui: &mut egui::Ui) {
+        if self.overlays.is_empty() {
+            return;
+        }
+
+        ui.menu_button("Overlays", |ui| {
+            let mut index_to_remove = None;
+            for (i, overlay) in self.overlays.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    egui::color_picker::color_edit_button_srgba(
+                        ui,
+                        &mut overlay.line.color,
+                        egui::color_picker::Alpha::Opaque,
+                    );
+                    ui.checkbox(&mut overlay.line.draw, &overlay.name);
+
+                    // each run can have its own live time
+                    if let Normalization::Scale(factor) = self.plot_settings.normalization {
+                        let mut overlay_factor = match overlay.plot_settings.normalization {
+                            Normalization::Scale(overlay_factor) => overlay_factor,
+                            _ => factor,
+                        };
+                        if ui
+                            .add(
+                                egui::DragValue::new(&mut overlay_factor)
+                                    .speed(0.001)
+                                    .max_decimals(6)
+                                    .prefix("× "),
+                            )
+                            .changed()
+                        {
+                            overlay.plot_settings.normalization =
+                                Normalization::Scale(overlay_factor);
+                        }
+                    }
+
+                    if ui.button("🗙").clicked() {
+                        index_to_remove = Some(i);
+                    }
+                });
+            }
+
+            if let Some(index) = index_to_remove {
+                self.overlays.remove(index);
+            }
+
+            if ui.button("Clear Overlays").clicked() {
+                self.overlays.clear();
+            }
+        });
+    }
+
+    // The settings without the counts, fits, or overlays
+    pub fn duplicate(&self, new_name: &str) -> Self {
+        let mut copy = self.clone();
+        copy.name = new_name.to_string();
+        copy.line.name = new_name.to_string();
+        copy.reset();
+        copy.fits = Fits {
+            settings: self.fits.settings.clone(),
+            ..Fits::new()
+        };
+        copy.overlays.clear();
+        copy.plot_settings.progress = None;
+        copy.pending_duplicate = None;
+        copy.pending_refill_refit = false;
+        copy.fit_report = FitReport::default();
+        copy.update_line_points();
+        copy
+    }
+
+    pub fn duplicate_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Duplicate", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("New Name:");
+                ui.text_edit_singleline(&mut self.duplicate_name);
+            });
+
+            if ui
+                .add_enabled(
+                    !self.duplicate_name.trim().is_empty(),
+                    egui::Button::new("Duplicate"),
+                )
+                .on_hover_text("Add an empty copy with the same settings to this grid")
+                .clicked()
+            {
+                self.pending_duplicate = Some(self.duplicate_name.trim().to_string());
+                self.duplicate_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+
     pub fn reset(&mut self) {
         self.bins = vec![0; self.bins.len()];
-        self.original_bins = vec![0; self.original_bins.len()];
-        self.overflow = 0;
-        self.underflow = 0;
+        self.core.reset();
+        self.original_weights.clear();
+        self.original_sumw2.clear();
+        self.fwhm_measurement = None;
+        self.pending_refill_refit = self.fits.settings.refit_after_refill
+            && (self.fits.temp_fit.is_some() || !self.fits.stored_fits.is_empty());
     }
 
     // Add a value to the histogram
     pub fn fill(&mut self, value: f64, current_step: usize, total_steps: usize) {
-        if value >= self.range.0 && value < self.range.1 {
-            let index = ((value - self.range.0) / self.bin_width) as usize;
-            if index < self.bins.len() {
-                self.bins[index] += 1;
-                self.original_bins[index] += 1;
-            }
-        } else if value >= self.range.1 {
-            self.overflow += 1;
-        } else {
-            self.underflow += 1;
+        if let Some(index) = self.core.fill(value) {
+            self.bins[index / self.rebin_factor()] += 1;
         }
         // Update progress
         self.plot_settings.progress = Some(current_step as f32 / total_steps as f32);
     }
 
+    // Add many values at once, the chunks are binned in parallel and then summed
+    pub fn fill_batch(&mut self, values: &[f64]) {
+        let counts = self.core.fill_batch(values);
+
+        let rebin_factor = self.rebin_factor();
+        for (index, count) in counts.into_iter().enumerate() {
+            self.bins[index / rebin_factor] += count;
+            if let Some(weight) = self.original_weights.get_mut(index) {
+                *weight += count as f64;
+            }
+            if let Some(sumw2) = self.original_sumw2.get_mut(index) {
+                *sumw2 += count as f64;
+            }
+        }
+    }
+
+    pub fn is_weighted(&self) -> bool {
+        !self.original_weights.is_empty()
+    }
+
+    // Start the weighted counts from the unweighted ones so mixed fills add up
+    fn init_weights(&mut self) {
+        if self.original_weights.is_empty() {
+            self.original_weights = self.core.bins.iter().map(|&count| count as f64).collect();
+        }
+        if self.original_sumw2.is_empty() {
+            self.original_sumw2.clone_from(&self.original_weights);
+        }
+    }
+
+    // Add a value that counts as `weight` entries, the overflow and underflow count entries
+    pub fn fill_weighted(&mut self, value: f64, weight: f64) {
+        self.init_weights();
+
+        if let Some(index) = self.core.fill(value) {
+            self.bins[index / self.rebin_factor()] += 1;
+            self.original_weights[index] += weight;
+            self.original_sumw2[index] += weight * weight;
+        }
+    }
+
+    pub fn fill_batch_weighted(&mut self, values: &[f64], weights: &[f64]) {
+        for (&value, &weight) in values.iter().zip(weights) {
+            self.fill_weighted(value, weight);
+        }
+    }
+
+    // The counts of the current binning, weighted if the histogram was filled with weights
+    pub fn counts(&self) -> Vec<f64> {
+        if self.is_weighted() {
+            let rebin_factor = self.rebin_factor();
+            self.original_weights
+                .chunks(rebin_factor)
+                .map(|chunk| chunk.iter().sum())
+                .collect()
+        } else {
+            self.bins.iter().map(|&count| count as f64).collect()
+        }
+    }
+
+    // The summed squared weights of the current binning, the counts for unweighted fills
+    pub fn sumw2(&self) -> Vec<f64> {
+        if self.original_sumw2.is_empty() {
+            return self.counts();
+        }
+
+        let rebin_factor = (self.original_sumw2.len() / self.bins.len().max(1)).max(1);
+        self.original_sumw2
+            .chunks(rebin_factor)
+            .map(|chunk| chunk.iter().sum())
+            .collect()
+    }
+
+    // Wrap headless counts for plotting
+    pub fn from_core(core: &Histogram1DCore) -> Self {
+        let mut hist = Self::new(&core.name, core.bins.len(), core.range);
+        hist.bins.clone_from(&core.bins);
+        hist.core = core.clone();
+        hist.update_line_points();
+        hist
+    }
+
     pub fn auto_axis_lims(&mut self, plot_ui: &mut egui_plot::PlotUi) {
         plot_ui.set_auto_bounds(Vec2b::new(true, true));
     }
@@ -72,25 +330,67 @@ impl Histogram {
         self.bins = counts;
     }
 
-    // Get the bin edges
+    // Get the bin edges of the current binning
     pub fn get_bin_edges(&self) -> Vec<f64> {
-        (0..=self.bins.len())
-            .map(|i| self.range.0 + i as f64 * self.bin_width)
-            .collect()
+        match &self.core.edges {
+            Some(edges) => {
+                let rebin_factor = self.rebin_factor();
+                edges.iter().step_by(rebin_factor).copied().collect()
+            }
+            None => (0..=self.bins.len())
+                .map(|i| self.range.0 + i as f64 * self.bin_width)
+                .collect(),
+        }
+    }
+
+    pub fn get_bin_centers(&self) -> Vec<f64> {
+        match &self.core.edges {
+            Some(_) => self
+                .get_bin_edges()
+                .windows(2)
+                .map(|pair| 0.5 * (pair[0] + pair[1]))
+                .collect(),
+            None => (0..self.bins.len())
+                .map(|i| self.range.0 + (i as f64 + 0.5) * self.bin_width)
+                .collect(),
+        }
+    }
+
+    fn get_bin_center(&self, bin: usize) -> f64 {
+        match &self.core.edges {
+            Some(_) => {
+                let edges = self.get_bin_edges();
+                0.5 * (edges[bin] + edges[bin + 1])
+            }
+            None => self.range.0 + (bin as f64 * self.bin_width) + self.bin_width * 0.5,
+        }
+    }
+
+    // The counts as drawn, scaled by the normalization
+    pub fn display_counts(&self) -> Vec<f64> {
+        self.normalized_counts(self.plot_settings.normalization)
+    }
+
+    fn normalized_counts(&self, normalization: Normalization) -> Vec<f64> {
+        let counts = self.counts();
+        let factor = normalization.factor(&counts);
+        counts.into_iter().map(|count| count * factor).collect()
     }
 
     // Convert histogram bins to line points
     pub fn update_line_points(&mut self) {
-        self.line.points = self
-            .bins
-            .iter()
-            .enumerate()
-            .flat_map(|(index, &count)| {
-                let start = self.range.0 + index as f64 * self.bin_width;
-                let end = start + self.bin_width;
-                let y_value = count as f64;
-                vec![[start, y_value], [end, y_value]]
-            })
+        self.update_line_points_normalized(self.plot_settings.normalization);
+    }
+
+    // The points are the raw counts, the line scales them so its log floor follows the normalization
+    fn update_line_points_normalized(&mut self, normalization: Normalization) {
+        let edges = self.get_bin_edges();
+        let counts = self.counts();
+        self.line.y_scale = normalization.factor(&counts);
+        self.line.points = counts
+            .into_iter()
+            .zip(edges.windows(2))
+            .flat_map(|(y_value, pair)| vec![[pair[0], y_value], [pair[1], y_value]])
             .collect();
     }
 
@@ -100,54 +400,97 @@ impl Histogram {
             return None;
         }
 
+        if self.is_variable_binning() {
+            // x on the max edge belongs to the last bin
+            let index = self.core.bin_index(x).unwrap_or(self.core.bins.len() - 1);
+            return Some((index / self.rebin_factor()).min(self.bins.len() - 1));
+        }
+
+        // x on the max edge belongs to the last bin
         let bin_index: usize = ((x - self.range.0) / self.bin_width).floor() as usize;
 
-        Some(bin_index)
+        Some(bin_index.min(self.bins.len() - 1))
+    }
+
+    // The bins between the start and end x values (inclusive), values outside the range are
+    // clamped to the first and last bin. None if the interval misses the histogram.
+    pub fn bin_range(&self, start_x: f64, end_x: f64) -> Option<std::ops::RangeInclusive<usize>> {
+        let last_bin = self.bins.len().checked_sub(1)?;
+        let (start_x, end_x) = (start_x.min(end_x), start_x.max(end_x));
+        if end_x < self.range.0 || start_x > self.range.1 {
+            return None;
+        }
+
+        let start_bin = self.get_bin_index(start_x).unwrap_or(0).min(last_bin);
+        let end_bin = self.get_bin_index(end_x).unwrap_or(last_bin).min(last_bin);
+        Some(start_bin..=end_bin)
+    }
+
+    // The x bounds of the last drawn plot inside the histogram range, used by the stats box, the
+    // integral and the fit without region markers. None before the first draw.
+    pub fn visible_x_range(&self) -> Option<(f64, f64)> {
+        let (start_x, end_x) = self.visible_range?;
+        let (start_x, end_x) = (start_x.max(self.range.0), end_x.min(self.range.1));
+        (start_x < end_x).then_some((start_x, end_x))
     }
 
     // Get the bin centers between the start and end x values (inclusive)
     pub fn get_bin_centers_between(&self, start_x: f64, end_x: f64) -> Vec<f64> {
-        let start_bin = self.get_bin_index(start_x).unwrap_or(0);
-        let end_bin = self.get_bin_index(end_x).unwrap_or(self.bins.len() - 1);
-
-        (start_bin..=end_bin)
-            .map(|bin| self.range.0 + (bin as f64 * self.bin_width) + self.bin_width * 0.5)
-            .collect()
+        self.bin_range(start_x, end_x)
+            .map_or_else(Vec::new, |bins| self.get_bin_centers()[bins].to_vec())
     }
 
     // Get the bin counts between the start and end x values (inclusive)
     pub fn get_bin_counts_between(&self, start_x: f64, end_x: f64) -> Vec<f64> {
-        let start_bin = self.get_bin_index(start_x).unwrap_or(0);
-        let end_bin = self.get_bin_index(end_x).unwrap_or(self.bins.len() - 1);
-
-        (start_bin..=end_bin)
-            .map(|bin| self.bins[bin] as f64)
-            .collect()
+        self.bin_range(start_x, end_x)
+            .map_or_else(Vec::new, |bins| self.counts()[bins].to_vec())
     }
 
     // Get bin counts and bin center at x value
     pub fn get_bin_count_and_center(&self, x: f64) -> Option<(f64, f64)> {
         self.get_bin_index(x).map(|bin| {
-            let bin_center = self.range.0 + (bin as f64 * self.bin_width) + self.bin_width * 0.5;
-            let bin_count = self.bins[bin] as f64;
+            let bin_center = self.get_bin_center(bin);
+            let bin_count = self.counts()[bin];
             (bin_center, bin_count)
         })
     }
 
+    // The bins at the background markers and every bin inside a background region, sorted by x
+    fn background_data(&self) -> (Vec<f64>, Vec<f64>) {
+        let markers = &self.plot_settings.markers;
+        let counts = self.counts();
+
+        let mut points: Vec<(f64, f64)> = self
+            .get_bin_centers()
+            .into_iter()
+            .zip(counts)
+            .filter(|&(center, _)| markers.in_background_region(center))
+            .collect();
+
+        // markers inside a region would count their bin twice
+        points.extend(
+            markers
+                .get_background_marker_positions()
+                .into_iter()
+                .filter_map(|position| self.get_bin_count_and_center(position))
+                .filter(|&(center, _)| !markers.in_background_region(center)),
+        );
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+        points.into_iter().unzip()
+    }
+
     pub fn fit_background(&mut self) {
         self.fits.remove_temp_fits();
 
-        let marker_positions = self.plot_settings.markers.get_background_marker_positions();
-        if marker_positions.len() < 2 {
-            log::error!("Need to set at least two background markers to fit the histogram");
+        let (x_data, y_data) = self.background_data();
+        if x_data.len() < 2 {
+            log::error!(
+                "Need at least two background markers or a background region to fit the histogram"
+            );
             return;
         }
 
-        let (x_data, y_data): (Vec<f64>, Vec<f64>) = marker_positions
-            .iter()
-            .filter_map(|&pos| self.get_bin_count_and_center(pos))
-            .unzip();
-
         // let mut background_fitter = BackgroundFitter::new(x_data, y_data, FitModel::Linear);
         let mut background_fitter =
             BackgroundFitter::new(x_data, y_data, self.fits.settings.background_model.clone());
@@ -158,6 +501,20 @@ impl Histogram {
     }
 
     pub fn fit_gaussians(&mut self) {
+        // without region markers the fit covers the visible part of the plot, the markers are
+        // kept so the fit can be adjusted and refit
+        if self.plot_settings.markers.region_markers.is_empty() {
+            if let Some((start_x, end_x)) = self.visible_x_range() {
+                log::info!(
+                    "No region markers, added region markers at the visible range {:.2} to {:.2}",
+                    start_x,
+                    end_x
+                );
+                self.plot_settings.markers.add_region_marker(start_x);
+                self.plot_settings.markers.add_region_marker(end_x);
+            }
+        }
+
         let region_marker_positions = self.plot_settings.markers.get_region_marker_positions();
         if region_marker_positions.len() != 2 {
             log::error!("Need to set two region markers to fit the histogram");
@@ -168,9 +525,15 @@ impl Histogram {
             .markers
             .remove_peak_markers_outside_region();
         let peak_positions = self.plot_settings.markers.get_peak_marker_positions();
+        let constraints = GaussianConstraints {
+            fixed_positions: self.plot_settings.markers.get_locked_peak_flags(),
+            fixed_sigma: self.fits.settings.fixed_sigma(),
+        };
 
         if self.fits.temp_background_fit.is_none() {
-            if self.plot_settings.markers.background_markers.len() <= 1 {
+            if self.plot_settings.markers.background_markers.len() <= 1
+                && self.plot_settings.markers.background_regions.is_empty()
+            {
                 for position in region_marker_positions.iter() {
                     self.plot_settings.markers.add_background_marker(*position);
                 }
@@ -178,20 +541,28 @@ impl Histogram {
             self.fit_background();
         }
 
-        let mut fitter = Fitter::new(
-            FitModel::Gaussian(
+        let model = match self.fits.settings.peak_shape {
+            PeakShape::Gaussian => FitModel::Gaussian(
                 peak_positions,
                 self.fits.settings.free_stddev,
                 self.fits.settings.free_position,
                 self.bin_width,
+                constraints,
             ),
-            self.fits.temp_background_fit.clone(),
-        );
+            PeakShape::Lorentzian => FitModel::Lorentzian(peak_positions),
+            PeakShape::Voigt => FitModel::Voigt(peak_positions),
+            PeakShape::GaussianWithTail => FitModel::GaussianWithTail(peak_positions),
+        };
+
+        let mut fitter = Fitter::new(model, self.fits.temp_background_fit.clone());
+
+        fitter.fit_line_points = self.fits.settings.fit_line_points;
 
         let (start_x, end_x) = (region_marker_positions[0], region_marker_positions[1]);
 
         fitter.x_data = self.get_bin_centers_between(start_x, end_x);
         fitter.y_data = self.get_bin_counts_between(start_x, end_x);
+        fitter.region = Some((start_x, end_x));
 
         fitter.fit();
 
@@ -208,6 +579,194 @@ impl Histogram {
         self.fits.temp_fit = Some(fitter);
     }
 
+    // Refit a stored fit with the current bins, used after the histogram was refilled or rebinned
+    pub fn refit_stored_fit(&mut self, index: usize) {
+        let Some(fit) = self.fits.stored_fits.get(index) else {
+            return;
+        };
+
+        let Some((start_x, end_x)) = fit.fit_region() else {
+            log::error!("Stored fit {} has no data to refit", index);
+            return;
+        };
+
+        let x_data = self.get_bin_centers_between(start_x, end_x);
+        let y_data = self.get_bin_counts_between(start_x, end_x);
+        let background_data = fit.background.as_ref().map(|background| {
+            background
+                .x_data
+                .iter()
+                .filter_map(|&x| self.get_bin_count_and_center(x))
+                .unzip()
+        });
+        let bin_width = self.bin_width;
+
+        self.fits.stored_fits[index].refit(x_data, y_data, bin_width, background_data);
+        log::info!("Refit stored fit {} of '{}'", index, self.name);
+    }
+
+    // The peak shape, region, and fitted peak positions of the current (None) or a stored fit,
+    // the background markers are the ones currently set on this histogram
+    pub fn fit_clone(&self, fit_index: Option<usize>) -> Option<FitTemplate> {
+        let fit = match fit_index {
+            Some(index) => self.fits.stored_fits.get(index),
+            None => self.fits.temp_fit.as_ref(),
+        }?;
+
+        let mut settings = self.fits.settings.clone();
+        match &fit.model {
+            FitModel::Gaussian(_, free_stddev, free_position, _, _) => {
+                settings.peak_shape = PeakShape::Gaussian;
+                settings.free_stddev = *free_stddev;
+                settings.free_position = *free_position;
+            }
+            FitModel::Lorentzian(_) => settings.peak_shape = PeakShape::Lorentzian,
+            FitModel::Voigt(_) => settings.peak_shape = PeakShape::Voigt,
+            FitModel::GaussianWithTail(_) => settings.peak_shape = PeakShape::GaussianWithTail,
+            _ => {}
+        }
+
+        let mut markers = self.plot_settings.markers.clone();
+        markers.clear_region_markers();
+        if let Some((start_x, end_x)) = fit.fit_region() {
+            markers.add_region_marker(start_x);
+            markers.add_region_marker(end_x);
+        }
+        markers.clear_peak_markers();
+        for peak in fit.get_peak_markers() {
+            markers.add_peak_marker(peak);
+        }
+
+        Some(FitTemplate { settings, markers })
+    }
+
+    // Use a fit cloned from another histogram, the old fit and background are cleared so the
+    // next fit starts from the cloned markers
+    pub fn apply_fit_clone(&mut self, template: FitTemplate) {
+        self.fits.settings = template.settings;
+        self.plot_settings.markers = template.markers;
+        self.fits.temp_fit = None;
+        self.fits.temp_background_fit = None;
+    }
+
+    // Redo the fits with the refilled bins. The stored fits keep their regions and the current
+    // fit is redone from the region and peak markers, which reset leaves alone.
+    pub fn refit_after_refill(&mut self) {
+        self.pending_refill_refit = false;
+
+        for index in 0..self.fits.stored_fits.len() {
+            self.refit_stored_fit(index);
+        }
+
+        if self.fits.temp_fit.is_some()
+            && self
+                .plot_settings
+                .markers
+                .get_region_marker_positions()
+                .len()
+                == 2
+        {
+            self.fit_gaussians();
+        }
+    }
+
+    // Fit a peak at every maximum the peak finder detects between start_x and end_x, with the
+    // background anchored to the region edges
+    pub fn fit_all_peaks_in_region(&mut self, start_x: f64, end_x: f64) {
+        let markers = &mut self.plot_settings.markers;
+        markers.clear_region_markers();
+        markers.add_region_marker(start_x);
+        markers.add_region_marker(end_x);
+
+        markers.clear_background_markers();
+        markers.add_background_marker(start_x);
+        markers.add_background_marker(end_x);
+
+        // the peak finder works on the background subtracted counts in the region
+        self.find_peaks();
+        if self.plot_settings.markers.peak_markers.is_empty() {
+            log::info!(
+                "No peaks found between {:.2} and {:.2}, fitting the maximum",
+                start_x,
+                end_x
+            );
+        }
+
+        self.fit_gaussians();
+    }
+
+    // Fit start_x to end_x with the background anchored to the region edges and store the fit.
+    // Returns the mean, FWHM, and area of each peak, calibrated if the calibration is active.
+    // The markers and the current fit are put back afterwards so the pane is left as it was.
+    pub fn fit_region_and_store(
+        &mut self,
+        start_x: f64,
+        end_x: f64,
+    ) -> Result<Vec<(Value, Value, Value)>, String> {
+        if start_x >= end_x {
+            return Err(format!("the region {} to {} is empty", start_x, end_x));
+        }
+
+        let markers = self.plot_settings.markers.clone();
+        let temp_fit = self.fits.temp_fit.take();
+        let temp_background_fit = self.fits.temp_background_fit.take();
+
+        let peaks = self.fit_region(start_x, end_x);
+        if peaks.is_ok() {
+            self.fits.store_temp_fit();
+        }
+
+        self.plot_settings.markers = markers;
+        self.fits.temp_fit = temp_fit;
+        self.fits.temp_background_fit = temp_background_fit;
+
+        // the calibration is read here since fits.calibration is only synced when the pane is drawn
+        let calibration = &self.plot_settings.calibration;
+        Ok(peaks?
+            .into_iter()
+            .map(|(mean, fwhm, area)| {
+                let (mean, fwhm) = match calibration.is_active() {
+                    true => calibration.calibrate_peak(&mean, &fwhm),
+                    false => (mean, fwhm),
+                };
+                (mean, fwhm, area)
+            })
+            .collect())
+    }
+
+    // The temporary fit of start_x to end_x from the region edges alone, without peak markers
+    // the fit starts from the maximum of the region
+    fn fit_region(
+        &mut self,
+        start_x: f64,
+        end_x: f64,
+    ) -> Result<Vec<(Value, Value, Value)>, String> {
+        let markers = &mut self.plot_settings.markers;
+        markers.clear_region_markers();
+        markers.add_region_marker(start_x);
+        markers.add_region_marker(end_x);
+
+        markers.clear_background_markers();
+        markers.add_background_marker(start_x);
+        markers.add_background_marker(end_x);
+        markers.clear_background_regions();
+
+        markers.clear_peak_markers();
+
+        self.fit_gaussians();
+
+        let peaks = match &self.fits.temp_fit {
+            Some(fit) => fit.peak_values(),
+            None => return Err("the fit could not be set up".to_string()),
+        };
+        if peaks.is_empty() {
+            self.fits.remove_temp_fits();
+            return Err("no peaks were fitted".to_string());
+        }
+
+        Ok(peaks)
+    }
+
     // Draw the histogram, fit lines, markers, and stats
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
         // update the histogram and fit lines with the log setting and draw
@@ -218,14 +777,35 @@ impl Histogram {
         self.line.log_x = log_x;
         self.line.draw(plot_ui);
 
+        for overlay in &mut self.overlays {
+            overlay.line.log_y = log_y;
+            overlay.line.log_x = log_x;
+            overlay.line.draw(plot_ui);
+        }
+
+        self.draw_smoothed(plot_ui);
+
         self.fits.set_log(log_y, log_x);
+        self.fits
+            .set_y_scale(self.plot_settings.normalization.factor(&self.counts()));
         self.fits.draw(plot_ui);
 
+        let bounds = plot_ui.plot_bounds();
+        let (min_x, max_x) = (bounds.min()[0], bounds.max()[0]);
+        self.visible_range = Some(if log_x {
+            (10.0_f64.powf(min_x), 10.0_f64.powf(max_x))
+        } else {
+            (min_x, max_x)
+        });
+
         self.show_stats(plot_ui);
+        self.draw_fwhm_measurement(plot_ui);
+        self.draw_integral(plot_ui);
 
         self.plot_settings.markers.draw_all_markers(plot_ui);
-        // Check if markers are being dragged
-        if self.plot_settings.markers.is_dragging() {
+        // Check if markers are being dragged or a region is being selected with shift
+        let shift = plot_ui.ctx().input(|i| i.modifiers.shift);
+        if self.plot_settings.markers.is_dragging() || shift {
             // Disable dragging if a marker is being dragged
             self.plot_settings.egui_settings.allow_drag = false;
         } else {
@@ -263,7 +843,24 @@ impl Histogram {
         let current_y_min = plot_bounds.min()[1];
         let current_y_max = plot_bounds.max()[1];
 
-        let y_max = self.bins.iter().max().cloned().unwrap_or(0) as f64;
+        // on a log axis the bounds are in log10 units and start at the floor for empty bins
+        let normalization = self.plot_settings.normalization;
+        let max_count = self
+            .overlays
+            .iter()
+            .flat_map(|overlay| {
+                overlay.normalized_counts(
+                    normalization.for_overlay(overlay.plot_settings.normalization),
+                )
+            })
+            .chain(self.display_counts())
+            .fold(0.0, f64::max);
+        let floor = log_y_floor(normalization.factor(&self.counts()));
+        let (y_min, y_max) = if self.plot_settings.egui_settings.log_y {
+            (floor.log10(), max_count.max(floor).log10())
+        } else {
+            (0.0, max_count)
+        };
 
         if current_x_min == -1.0
             && current_x_max == 1.0
@@ -271,7 +868,7 @@ impl Histogram {
             && current_y_max == 1.0
         {
             let default_bounds =
-                egui_plot::PlotBounds::from_min_max([self.range.0, 0.0], [self.range.1, y_max]);
+                egui_plot::PlotBounds::from_min_max([self.range.0, y_min], [self.range.1, y_max]);
 
             plot_ui.set_plot_bounds(default_bounds);
             return;
@@ -280,7 +877,7 @@ impl Histogram {
         // Clamping bounds only for scrolling
         let new_x_min = current_x_min.max(self.range.0);
         let new_x_max = current_x_max.min(self.range.1);
-        let new_y_min = current_y_min.max(0.0);
+        let new_y_min = current_y_min.max(y_min);
         let new_y_max = current_y_max.min(y_max);
 
         if new_x_min != current_x_min
@@ -300,13 +897,49 @@ impl Histogram {
         self.plot_settings.progress_ui(ui);
 
         self.update_line_points(); // Ensure line points are updated for projections
+        let normalization = self.plot_settings.normalization;
+        for overlay in &mut self.overlays {
+            let overlay_normalization =
+                normalization.for_overlay(overlay.plot_settings.normalization);
+            overlay.update_line_points_normalized(overlay_normalization);
+        }
         self.keybinds(ui); // Handle interactive elements
 
         let mut plot = egui_plot::Plot::new(self.name.clone());
         plot = self.plot_settings.egui_settings.apply_to_plot(plot);
+        if !self.plot_settings.egui_settings.log_x {
+            plot = self.plot_settings.calibration.apply_to_plot(plot);
+        }
+        plot = self.plot_settings.labels.apply_to_plot(plot);
 
+        if let Some((coefficients, uncertainties)) = self.fits.pending_calibration.take() {
+            self.plot_settings
+                .calibration
+                .use_coefficients(coefficients, uncertainties);
+        }
+        self.fits.calibration = self
+            .plot_settings
+            .calibration
+            .is_active()
+            .then(|| self.plot_settings.calibration.clone());
+        if let Some(index) = self.fits.pending_refit.take() {
+            self.refit_stored_fit(index);
+        }
+        let report_top = ui.cursor().min.y;
+        self.plot_settings.labels.title_ui(ui);
         self.fits.fit_stats_ui(ui);
 
+        // the residual plot shares the x axis and takes the bottom quarter
+        let residuals_id = egui::Id::new(format!("{} Residuals", self.name));
+        let show_residuals = self.fits.settings.show_residuals
+            && self.fits.has_residuals()
+            && !self.plot_settings.egui_settings.log_x;
+        if show_residuals {
+            plot = plot
+                .height(ui.available_height() * 0.75)
+                .link_axis(residuals_id, true, false);
+        }
+
         let plot_response = plot.show(ui, |plot_ui| {
             self.draw(plot_ui);
 
@@ -316,10 +949,69 @@ impl Histogram {
             }
         });
 
+        if show_residuals {
+            let residual_plot = egui_plot::Plot::new(residuals_id)
+                .link_axis(residuals_id, true, false)
+                .y_axis_label("(Data - Fit) / σ");
+            self.plot_settings
+                .calibration
+                .apply_to_plot(residual_plot)
+                .show(ui, |plot_ui| {
+                    self.fits.draw_residuals(plot_ui);
+                });
+        }
+
         plot_response.response.context_menu(|ui| {
             self.context_menu(ui);
         });
 
-        self.plot_settings.interactive_response(&plot_response);
+        // alt + click measures the peak under the cursor, any other click clears it
+        if plot_response.response.clicked() {
+            let x = self.plot_settings.cursor_position.map(|cursor| {
+                match self.plot_settings.egui_settings.log_x {
+                    true => 10.0_f64.powf(cursor.x),
+                    false => cursor.x,
+                }
+            });
+            match x {
+                Some(x) if ui.input(|i| i.modifiers.alt) => self.update_fwhm_measurement(x),
+                _ => self.fwhm_measurement = None,
+            }
+        }
+
+        if let Some((start_x, end_x)) = self.plot_settings.interactive_response(&plot_response) {
+            self.fit_all_peaks_in_region(start_x, end_x);
+        }
+        self.plot_settings
+            .export
+            .update(ui, plot_response.response.rect);
+
+        // the report covers the fit stats grid, the plot and the residuals
+        let plot_rect = plot_response.response.rect;
+        let report_rect = egui::Rect::from_min_max(
+            egui::pos2(plot_rect.left(), report_top),
+            egui::pos2(plot_rect.right(), ui.min_rect().bottom()),
+        );
+        self.update_fit_report(ui, report_rect);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_after_a_rebin_land_in_the_original_bins() {
+        let mut hist = Histogram::new("rebinned", 8, (0.0, 8.0));
+        hist.plot_settings.rebin_factor = 4;
+        hist.rebin();
+
+        hist.fill_batch(&[6.5]);
+        hist.fill(7.5, 1, 1);
+        hist.fill_weighted(0.5, 2.0);
+
+        assert_eq!(hist.core.bins, vec![1, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(hist.bins, vec![1, 2]);
+        assert_eq!(hist.counts(), vec![2.0, 2.0]);
     }
 }
@@ -0,0 +1,165 @@
+use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_line::log_y_floor;
+
+// Summed counts between the two region markers, or over the visible range without markers,
+// drawn while the integral is turned on
+#[derive(Debug, Clone, Copy, Default, serde::Serialize, serde::Deserialize)]
+pub struct IntegralSettings {
+    pub show: bool,
+    pub subtract_background: bool, // straight line between the counts of the two endpoint bins
+}
+
+impl IntegralSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show, "Show Integral")
+                .on_hover_text("Sum the counts between the two region markers, or the visible range without markers\nKeybind: c");
+            ui.add_enabled(
+                self.show,
+                egui::Checkbox::new(&mut self.subtract_background, "Linear Background"),
+            )
+            .on_hover_text("Subtract a line drawn between the counts of the two endpoint bins");
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Integral {
+    pub gross: f64,
+    pub gross_uncertainty: f64,
+    pub background: f64,
+    pub background_uncertainty: f64,
+    pub start: (f64, f64), // center and counts of the first bin
+    pub end: (f64, f64),   // center and counts of the last bin
+}
+
+impl Integral {
+    pub fn net(&self) -> f64 {
+        self.gross - self.background
+    }
+
+    pub fn net_uncertainty(&self) -> f64 {
+        self.gross_uncertainty.hypot(self.background_uncertainty)
+    }
+}
+
+impl Histogram {
+    // Sum the bins between start_x and end_x (inclusive). The background is the trapezoid under
+    // the line through the endpoint bins, so its uncertainty comes from those two bins only.
+    pub fn integral(
+        &self,
+        start_x: f64,
+        end_x: f64,
+        subtract_background: bool,
+    ) -> Option<Integral> {
+        let counts = self.counts();
+        let sumw2 = self.sumw2();
+        let centers = self.get_bin_centers();
+
+        let bins = self.bin_range(start_x, end_x)?;
+        let (start_bin, end_bin) = (*bins.start(), *bins.end());
+
+        let gross: f64 = counts[start_bin..=end_bin].iter().sum();
+        let gross_uncertainty = sumw2[start_bin..=end_bin].iter().sum::<f64>().sqrt();
+
+        let (background, background_uncertainty) = if subtract_background {
+            let half_bins = 0.5 * (end_bin - start_bin + 1) as f64;
+            (
+                half_bins * (counts[start_bin] + counts[end_bin]),
+                half_bins * (sumw2[start_bin] + sumw2[end_bin]).sqrt(),
+            )
+        } else {
+            (0.0, 0.0)
+        };
+
+        Some(Integral {
+            gross,
+            gross_uncertainty,
+            background,
+            background_uncertainty,
+            start: (centers[start_bin], counts[start_bin]),
+            end: (centers[end_bin], counts[end_bin]),
+        })
+    }
+
+    pub fn draw_integral(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let settings = self.plot_settings.integral;
+        if !settings.show {
+            return;
+        }
+
+        // without region markers the visible part of the plot is summed
+        let region = self.plot_settings.markers.get_region_marker_positions();
+        let (start_x, end_x) = match region[..] {
+            [start_x, end_x] => (start_x, end_x),
+            [] => match self.visible_x_range() {
+                Some(range) => range,
+                None => return,
+            },
+            _ => return,
+        };
+
+        let Some(integral) = self.integral(start_x, end_x, settings.subtract_background) else {
+            return;
+        };
+
+        let log_y = self.plot_settings.egui_settings.log_y;
+        let log_x = self.plot_settings.egui_settings.log_x;
+        let factor = self.plot_settings.normalization.factor(&self.counts());
+        let to_plot = |(x, y): (f64, f64)| {
+            let y = y * factor;
+            [
+                if log_x {
+                    x.max(f64::MIN_POSITIVE).log10()
+                } else {
+                    x
+                },
+                if log_y {
+                    y.max(log_y_floor(factor)).log10()
+                } else {
+                    y
+                },
+            ]
+        };
+
+        if settings.subtract_background {
+            plot_ui.line(
+                egui_plot::Line::new(vec![to_plot(integral.start), to_plot(integral.end)])
+                    .color(egui::Color32::LIGHT_GREEN)
+                    .width(1.5)
+                    .allow_hover(false),
+            );
+        }
+
+        // the sums are scaled like the drawn counts
+        let mut text = format!(
+            "Integral [{:.2}, {:.2}]\nGross: {:.2} ± {:.2}",
+            start_x,
+            end_x,
+            integral.gross * factor,
+            integral.gross_uncertainty * factor
+        );
+        if settings.subtract_background {
+            text.push_str(&format!(
+                "\nBackground: {:.2} ± {:.2}\nNet: {:.2} ± {:.2}",
+                integral.background * factor,
+                integral.background_uncertainty * factor,
+                integral.net() * factor,
+                integral.net_uncertainty() * factor
+            ));
+        }
+
+        // anchored at the top left of the view so it doesn't cover the stats box
+        let bounds = plot_ui.plot_bounds();
+        plot_ui.text(
+            egui_plot::Text::new(
+                egui_plot::PlotPoint::new(bounds.min()[0], bounds.max()[1]),
+                text,
+            )
+            .anchor(egui::Align2::LEFT_TOP)
+            .highlight(true)
+            .allow_hover(false)
+            .color(egui::Color32::LIGHT_GREEN),
+        );
+    }
+}
@@ -33,6 +33,7 @@ impl Histogram {
 
             if ui.input(|i| i.key_pressed(egui::Key::Delete)) {
                 self.plot_settings.markers.clear_background_markers();
+                self.plot_settings.markers.clear_background_regions();
                 self.plot_settings.markers.clear_peak_markers();
                 self.plot_settings.markers.clear_region_markers();
             }
@@ -57,6 +58,10 @@ impl Histogram {
                 self.plot_settings.stats_info = !self.plot_settings.stats_info;
             }
 
+            if ui.input(|i| i.key_pressed(egui::Key::C)) {
+                self.plot_settings.integral.show = !self.plot_settings.integral.show;
+            }
+
             if ui.input(|i| i.key_pressed(egui::Key::L)) {
                 self.plot_settings.egui_settings.log_y = !self.plot_settings.egui_settings.log_y;
             }
@@ -86,12 +91,16 @@ impl Histogram {
                 ui.separator();
                 ui.label("Fitting");
                 ui.label("G: Fit Background").on_hover_text("Fit a linear background using the background markers");
-                ui.label("F: Fit Gaussians").on_hover_text("Fit gaussians at the peak markers give some region with a linear background");
+                ui.label("F: Fit Gaussians").on_hover_text("Fit gaussians at the peak markers give some region with a linear background. Without region markers the visible range is used");
+                ui.label("Shift + Drag: Fit All Peaks in Region").on_hover_text("Fit a peak at every maximum the peak finder detects in the selected region with a background anchored to the region edges");
+                ui.label("Shift + Ctrl + Drag: Add Background Region").on_hover_text("Only the bins inside the background regions and the background markers feed the background fit, keep the regions free of peaks");
+                ui.label("Alt + Click: Measure FWHM").on_hover_text("Find the maximum near the cursor and the half maximum crossings on each side without fitting. Click again to clear it");
                 ui.label("S: Store Fit").on_hover_text("Store the current fit as a permanent fit which can be saved and loaded later");
                 ui.separator();
                 ui.label("Plot");
                 ui.label("I: Toggle Stats");
                 ui.label("L: Toggle Log Y");
+                ui.label("C: Toggle Integral").on_hover_text("Sum the counts between the two region markers, with an optional linear background from the endpoint bins");
                 ui.separator();
                 ui.label("Peak Finder");
                 ui.label("O: Detect Peaks").on_hover_text("Detect peaks in the spectrum using the peak finding parameters");
@@ -1,17 +1,27 @@
 use crate::egui_plot_stuff::egui_vertical_line::EguiVerticalLine;
-use egui_plot::{PlotPoint, PlotUi};
+use egui_plot::{PlotPoint, PlotPoints, PlotUi, Polygon};
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FitMarkers {
     pub region_markers: Vec<EguiVerticalLine>,
     pub peak_markers: Vec<EguiVerticalLine>,
     pub background_markers: Vec<EguiVerticalLine>,
+    #[serde(default)]
+    pub locked_peaks: Vec<f64>, // positions of the peak markers whose centroid is fixed in gaussian fits
+    #[serde(default)]
+    pub background_regions: Vec<(f64, f64)>, // peak free x ranges whose bins feed the background fit
 
     #[serde(skip)]
     pub cursor_position: Option<PlotPoint>,
 
     #[serde(skip)]
     pub manual_marker_position: f64,
+
+    #[serde(skip)]
+    pub region_selection: Option<(f64, f64)>, // start and current x of a shift + drag selection
+
+    #[serde(skip)]
+    pub selecting_background_region: bool, // ctrl was held when the selection started
 }
 
 impl FitMarkers {
@@ -45,6 +55,7 @@ impl FitMarkers {
 
         marker.width = 0.5;
         marker.name = format!("Peak Marker (x={:.2})", x);
+        marker.interactive_dragging = !self.locked_peaks.contains(&x);
 
         self.peak_markers.push(marker);
         self.peak_markers
@@ -74,6 +85,23 @@ impl FitMarkers {
         self.background_markers.clear();
     }
 
+    pub fn add_background_region(&mut self, start: f64, end: f64) {
+        self.background_regions
+            .push((start.min(end), start.max(end)));
+        self.background_regions
+            .sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    pub fn clear_background_regions(&mut self) {
+        self.background_regions.clear();
+    }
+
+    pub fn in_background_region(&self, x: f64) -> bool {
+        self.background_regions
+            .iter()
+            .any(|&(start, end)| x >= start && x <= end)
+    }
+
     fn delete_marker(markers: &mut Vec<EguiVerticalLine>, marker_to_delete: f64) {
         if let Some(index) = markers.iter().position(|x| x.x_value == marker_to_delete) {
             markers.remove(index);
@@ -123,6 +151,26 @@ impl FitMarkers {
         Self::get_marker_positions(&self.peak_markers)
     }
 
+    // Locked markers can't be dragged, so the position stays the same until it is unlocked
+    pub fn set_peak_locked(&mut self, index: usize, locked: bool) {
+        let Some(marker) = self.peak_markers.get_mut(index) else {
+            return;
+        };
+
+        marker.interactive_dragging = !locked;
+        self.locked_peaks.retain(|&x| x != marker.x_value);
+        if locked {
+            self.locked_peaks.push(marker.x_value);
+        }
+    }
+
+    pub fn get_locked_peak_flags(&self) -> Vec<bool> {
+        self.peak_markers
+            .iter()
+            .map(|marker| self.locked_peaks.contains(&marker.x_value))
+            .collect()
+    }
+
     pub fn get_background_marker_positions(&self) -> Vec<f64> {
         Self::get_marker_positions(&self.background_markers)
     }
@@ -139,7 +187,41 @@ impl FitMarkers {
         });
     }
 
+    fn draw_x_band(plot_ui: &mut PlotUi, start: f64, end: f64, color: egui::Color32) {
+        let bounds = plot_ui.plot_bounds();
+        let (y_min, y_max) = (bounds.min()[1], bounds.max()[1]);
+        let band = Polygon::new(PlotPoints::new(vec![
+            [start, y_min],
+            [end, y_min],
+            [end, y_max],
+            [start, y_max],
+        ]))
+        .fill_color(egui::Color32::from_rgba_unmultiplied(
+            color.r(),
+            color.g(),
+            color.b(),
+            30,
+        ))
+        .stroke(egui::Stroke::new(0.5, color))
+        .allow_hover(false);
+
+        plot_ui.polygon(band);
+    }
+
     pub fn draw_all_markers(&mut self, plot_ui: &mut PlotUi) {
+        for &(start, end) in &self.background_regions {
+            Self::draw_x_band(plot_ui, start, end, egui::Color32::GREEN);
+        }
+
+        if let Some((start, end)) = self.region_selection {
+            let color = if self.selecting_background_region {
+                egui::Color32::GREEN
+            } else {
+                egui::Color32::BLUE
+            };
+            Self::draw_x_band(plot_ui, start, end, color);
+        }
+
         for marker in &mut self.background_markers {
             marker.draw(plot_ui);
         }
@@ -153,7 +235,66 @@ impl FitMarkers {
         }
     }
 
-    pub fn interactive_dragging(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
+    // Shift + drag selects an x region, which is returned once the button is released
+    fn region_selection_dragging(
+        &mut self,
+        plot_response: &egui_plot::PlotResponse<()>,
+    ) -> Option<(f64, f64)> {
+        let (pointer_state, shift, command) = plot_response
+            .response
+            .ctx
+            .input(|i| (i.pointer.clone(), i.modifiers.shift, i.modifiers.command));
+        let pointer_x = pointer_state
+            .hover_pos()
+            .map(|pos| plot_response.transform.value_from_position(pos).x);
+
+        match &mut self.region_selection {
+            None => {
+                if shift
+                    && plot_response.response.hovered()
+                    && pointer_state.button_pressed(egui::PointerButton::Primary)
+                    && !self.is_dragging()
+                {
+                    self.region_selection = pointer_x.map(|x| (x, x));
+                    self.selecting_background_region = command;
+                }
+                None
+            }
+            Some(selection) => {
+                if let Some(x) = pointer_x {
+                    selection.1 = x;
+                }
+
+                if pointer_state.primary_down() {
+                    return None;
+                }
+
+                let (start, end) = self.region_selection.take()?;
+                if start == end {
+                    return None;
+                }
+                Some((start.min(end), start.max(end)))
+            }
+        }
+    }
+
+    // Returns the region of a finished shift + drag selection, with ctrl held it is kept as a
+    // background region instead
+    pub fn interactive_dragging(
+        &mut self,
+        plot_response: &egui_plot::PlotResponse<()>,
+    ) -> Option<(f64, f64)> {
+        let selected_region = self.region_selection_dragging(plot_response);
+        if let Some((start, end)) = selected_region {
+            if self.selecting_background_region {
+                self.add_background_region(start, end);
+                return None;
+            }
+        }
+        if selected_region.is_some() || self.region_selection.is_some() {
+            return selected_region;
+        }
+
         for marker in &mut self.background_markers {
             marker.interactive_dragging(plot_response);
         }
@@ -165,6 +306,8 @@ impl FitMarkers {
         for marker in &mut self.peak_markers {
             marker.interactive_dragging(plot_response);
         }
+
+        None
     }
 
     pub fn menu_button(&mut self, ui: &mut egui::Ui) {
@@ -204,6 +347,7 @@ impl FitMarkers {
 
                     if ui.button("All").clicked() {
                         self.clear_background_markers();
+                        self.clear_background_regions();
                         self.clear_peak_markers();
                         self.clear_region_markers();
                     }
@@ -219,9 +363,15 @@ impl FitMarkers {
                     if ui.button("Background").clicked() {
                         self.clear_background_markers();
                     }
+
+                    if ui.button("Background Regions").clicked() {
+                        self.clear_background_regions();
+                    }
                 });
             });
 
+            self.background_regions_ui(ui);
+
             ui.separator();
 
             egui::ScrollArea::vertical().show(ui, |ui| {
@@ -229,8 +379,23 @@ impl FitMarkers {
                     marker.menu_button(ui);
                 }
 
-                for marker in &mut self.peak_markers {
-                    marker.menu_button(ui);
+                // forget the locks of markers that were removed
+                let peak_positions = Self::get_marker_positions(&self.peak_markers);
+                self.locked_peaks.retain(|x| peak_positions.contains(x));
+
+                for index in 0..self.peak_markers.len() {
+                    ui.horizontal(|ui| {
+                        self.peak_markers[index].menu_button(ui);
+
+                        let mut locked = self.get_locked_peak_flags()[index];
+                        if ui
+                            .checkbox(&mut locked, "Lock")
+                            .on_hover_text("Fix the centroid at the marker in gaussian fits")
+                            .changed()
+                        {
+                            self.set_peak_locked(index, locked);
+                        }
+                    });
                 }
 
                 for marker in &mut self.background_markers {
@@ -239,4 +404,29 @@ impl FitMarkers {
             });
         });
     }
+
+    fn background_regions_ui(&mut self, ui: &mut egui::Ui) {
+        if self.background_regions.is_empty() {
+            return;
+        }
+
+        ui.separator();
+        ui.label("Background Regions")
+            .on_hover_text("Only the bins inside these regions and the background markers are used in the background fit\nShift + Ctrl + Drag to add a region");
+
+        let mut to_remove = None;
+        for (index, (start, end)) in self.background_regions.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(start).speed(1.0).prefix("Start: "));
+                ui.add(egui::DragValue::new(end).speed(1.0).prefix("End: "));
+                if ui.button("X").clicked() {
+                    to_remove = Some(index);
+                }
+            });
+        }
+
+        if let Some(index) = to_remove {
+            self.background_regions.remove(index);
+        }
+    }
 }
@@ -1,8 +1,16 @@
+pub mod arithmetic;
+pub mod background_subtraction;
+pub mod calibration;
 pub mod context_menu;
+pub mod csv_export;
+pub mod fit_report;
+pub mod fwhm;
 pub mod histogram1d;
+pub mod integral;
 pub mod keybinds;
 pub mod markers;
 pub mod peak_finder;
 pub mod plot_settings;
 pub mod rebinning;
+pub mod smoothing;
 pub mod statistics;
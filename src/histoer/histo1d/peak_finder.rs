@@ -2,13 +2,13 @@ use find_peaks::Peak;
 use find_peaks::PeakFinder;
 
 use super::histogram1d::Histogram;
+use crate::fitter::models::gaussian::GaussianFitter;
 
 impl Histogram {
-    // Add a function to find peaks
-    pub fn find_peaks(&mut self) {
-        // Clear the peak markers
-        self.plot_settings.markers.clear_peak_markers();
-
+    // The data the peaks are searched in. With two background markers the background is fit and
+    // subtracted first, and the region markers (or else the outer background markers) limit the
+    // range. None if the background fit failed.
+    fn peak_search_data(&mut self) -> Option<(Vec<f64>, Vec<f64>)> {
         let region_marker_positions = self.plot_settings.markers.get_region_marker_positions();
         let mut background_marker_positions =
             self.plot_settings.markers.get_background_marker_positions();
@@ -16,72 +16,110 @@ impl Histogram {
         // Sort background markers
         background_marker_positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-        let mut peaks_found_with_background = false;
-        let mut peaks_found_with_region = false;
-
-        let y_data: Vec<f64> = if background_marker_positions.len() >= 2 {
+        if background_marker_positions.len() >= 2 {
             self.fit_background();
 
             // Extract the y data from the temp background fit
-            if let Some(temp_background) = &self.fits.temp_background_fit {
-                // if there are region markers, use the data between them
-                let (start_x, end_x) = if region_marker_positions.len() == 2 {
-                    peaks_found_with_region = true;
-                    (region_marker_positions[0], region_marker_positions[1])
-                } else {
-                    peaks_found_with_background = true;
-                    (
-                        background_marker_positions[0],
-                        background_marker_positions[background_marker_positions.len() - 1],
-                    )
-                };
-
-                let x_data = self.get_bin_centers_between(start_x, end_x);
-                let y_data = self.get_bin_counts_between(start_x, end_x);
-
-                // Put the data in the background fitter to subtract the background
-                temp_background.subtract_background(x_data, y_data)
-            } else {
+            let Some(temp_background) = &self.fits.temp_background_fit else {
                 log::error!("Failed to fit background");
-                return;
-            }
+                return None;
+            };
+
+            // if there are region markers, use the data between them
+            let (start_x, end_x) = if region_marker_positions.len() == 2 {
+                (region_marker_positions[0], region_marker_positions[1])
+            } else {
+                (
+                    background_marker_positions[0],
+                    background_marker_positions[background_marker_positions.len() - 1],
+                )
+            };
+
+            let x_data = self.get_bin_centers_between(start_x, end_x);
+            let y_data = self.get_bin_counts_between(start_x, end_x);
+
+            // Put the data in the background fitter to subtract the background
+            let y_data = temp_background.subtract_background(x_data.clone(), y_data);
+            Some((x_data, y_data))
         } else if region_marker_positions.len() == 2 {
             let (start_x, end_x) = (region_marker_positions[0], region_marker_positions[1]);
-            peaks_found_with_region = true;
-            self.get_bin_counts_between(start_x, end_x)
+            Some((
+                self.get_bin_centers_between(start_x, end_x),
+                self.get_bin_counts_between(start_x, end_x),
+            ))
         } else {
-            self.bins.iter().map(|&count| count as f64).collect()
+            Some((
+                self.get_bin_centers_between(self.range.0, self.range.1),
+                self.bins.iter().map(|&count| count as f64).collect(),
+            ))
+        }
+    }
+
+    // Add a function to find peaks
+    pub fn find_peaks(&mut self) {
+        // Clear the peak markers
+        self.plot_settings.markers.clear_peak_markers();
+
+        let Some((x_data, y_data)) = self.peak_search_data() else {
+            return;
         };
 
-        let peaks = self.plot_settings.find_peaks_settings.find_peaks(y_data);
+        let peaks = self
+            .plot_settings
+            .find_peaks_settings
+            .find_peaks(y_data.clone());
 
-        // Add peak markers at detected peaks
+        // Add peak markers at the centroid of each detected peak
         for peak in &peaks {
-            let peak_position = peak.middle_position();
+            let peak_position = peak_centroid(&x_data, &y_data, peak.middle_position());
             log::info!("Peak at position: {}", peak_position);
-            // Adjust peak position relative to the first background marker
-            if peaks_found_with_background {
-                let adjusted_peak_position =
-                    self.bin_width * peak_position as f64 + background_marker_positions[0];
-                self.plot_settings
-                    .markers
-                    .add_peak_marker(adjusted_peak_position);
-            } else if peaks_found_with_region {
-                let adjusted_peak_position =
-                    self.bin_width * peak_position as f64 + region_marker_positions[0];
-                self.plot_settings
-                    .markers
-                    .add_peak_marker(adjusted_peak_position);
-            } else {
-                let adjusted_peak_position = self.bin_width * peak_position as f64 + self.range.0;
-                self.plot_settings
-                    .markers
-                    .add_peak_marker(adjusted_peak_position);
-            }
+            self.plot_settings.markers.add_peak_marker(peak_position);
+        }
+    }
+
+    // Seed the Gaussian fit with the peaks the fitter finds in the same data, only the min
+    // prominence and min distance of the peak finder settings are used
+    pub fn detect_fit_peaks(&mut self) {
+        self.plot_settings.markers.clear_peak_markers();
+
+        let Some((x_data, y_data)) = self.peak_search_data() else {
+            return;
+        };
+
+        let (min_prominence, min_distance) =
+            self.plot_settings.find_peaks_settings.seed_thresholds();
+        let fitter = GaussianFitter::new(
+            x_data,
+            y_data,
+            Vec::new(),
+            self.fits.settings.free_stddev,
+            self.fits.settings.free_position,
+            self.bin_width,
+        );
+
+        for peak_position in fitter.auto_find_peaks(min_prominence, min_distance) {
+            self.plot_settings.markers.add_peak_marker(peak_position);
         }
     }
 }
 
+// Count weighted mean of the bin centers around the peak, the negative counts left after the
+// background subtraction are ignored
+pub fn peak_centroid(x_data: &[f64], y_data: &[f64], index: usize) -> f64 {
+    let start = index.saturating_sub(1);
+    let end = (index + 1).min(x_data.len() - 1);
+
+    let (weighted_sum, total) = (start..=end)
+        .map(|i| (x_data[i], y_data[i].max(0.0)))
+        .fold((0.0, 0.0), |(sum, total), (x, y)| (sum + x * y, total + y));
+
+    if total > 0.0 {
+        weighted_sum / total
+    } else {
+        x_data[index]
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PeakFindingSettings {
     min_height: f64,
@@ -260,6 +298,22 @@ impl PeakFindingSettings {
         });
     }
 
+    // The min prominence and min distance used to seed fits, no limit for the disabled ones
+    pub fn seed_thresholds(&self) -> (f64, usize) {
+        (
+            if self.enable_min_prominence {
+                self.min_prominence
+            } else {
+                0.0
+            },
+            if self.enable_min_distance {
+                self.min_distance
+            } else {
+                1
+            },
+        )
+    }
+
     pub fn find_peaks(&self, y_data: Vec<f64>) -> Vec<Peak<f64>> {
         let mut peak_finder = PeakFinder::new(&y_data);
 
@@ -1,7 +1,80 @@
+use super::calibration::Calibration;
+use super::integral::IntegralSettings;
 use super::markers::FitMarkers;
 use super::peak_finder::PeakFindingSettings;
+use super::smoothing::SmoothingSettings;
+use crate::egui_plot_stuff::egui_plot_export::EguiPlotExport;
+use crate::egui_plot_stuff::egui_plot_labels::PlotLabels;
 use crate::egui_plot_stuff::egui_plot_settings::EguiPlotSettings;
 
+// Scales the drawn counts, the stored counts and fits are unchanged
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum Normalization {
+    #[default]
+    None,
+    UnitArea,
+    Scale(f64), // e.g. the inverse live time
+}
+
+impl Normalization {
+    pub fn factor(&self, counts: &[f64]) -> f64 {
+        match self {
+            Normalization::None => 1.0,
+            Normalization::UnitArea => {
+                let area: f64 = counts.iter().sum();
+                if area > 0.0 {
+                    1.0 / area
+                } else {
+                    1.0
+                }
+            }
+            Normalization::Scale(factor) => *factor,
+        }
+    }
+
+    // Overlays are normalized the same way, except a scaled overlay can keep its own factor
+    pub fn for_overlay(&self, overlay: Normalization) -> Normalization {
+        match (self, overlay) {
+            (Normalization::Scale(_), Normalization::Scale(factor)) => Normalization::Scale(factor),
+            _ => *self,
+        }
+    }
+
+    pub fn label(&self) -> Option<String> {
+        match self {
+            Normalization::None => None,
+            Normalization::UnitArea => Some("Normalized to unit area".to_string()),
+            Normalization::Scale(factor) => Some(format!("Scaled by {}", factor)),
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Normalize:");
+            ui.radio_value(self, Normalization::None, "None");
+            ui.radio_value(self, Normalization::UnitArea, "Unit Area");
+            if ui
+                .radio(matches!(self, Normalization::Scale(_)), "Scale")
+                .clicked()
+                && !matches!(self, Normalization::Scale(_))
+            {
+                *self = Normalization::Scale(1.0);
+            }
+
+            if let Normalization::Scale(factor) = self {
+                ui.add(
+                    egui::DragValue::new(factor)
+                        .speed(0.001)
+                        .max_decimals(6)
+                        .prefix("× "),
+                );
+            }
+        })
+        .response
+        .on_hover_text("Only changes how the counts are drawn, fits stay in counts");
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlotSettings {
     #[serde(skip)]
@@ -11,6 +84,19 @@ pub struct PlotSettings {
     pub markers: FitMarkers,
     pub rebin_factor: usize,
     pub find_peaks_settings: PeakFindingSettings,
+    #[serde(default)]
+    pub calibration: Calibration,
+    #[serde(default)]
+    pub normalization: Normalization,
+    #[serde(default)]
+    pub integral: IntegralSettings,
+    #[serde(default)]
+    pub labels: PlotLabels,
+    #[serde(default)]
+    pub smoothing: SmoothingSettings,
+
+    #[serde(skip)]
+    pub export: EguiPlotExport,
 
     #[serde(skip)] // Skip serialization for progress
     pub progress: Option<f32>, // Optional progress tracking
@@ -24,19 +110,38 @@ impl Default for PlotSettings {
             markers: FitMarkers::new(),
             rebin_factor: 1,
             find_peaks_settings: PeakFindingSettings::default(),
+            calibration: Calibration::default(),
+            normalization: Normalization::default(),
+            integral: IntegralSettings::default(),
+            labels: PlotLabels::default(),
+            smoothing: SmoothingSettings::default(),
+            export: EguiPlotExport::default(),
             progress: None,
         }
     }
 }
 impl PlotSettings {
-    pub fn settings_ui(&mut self, ui: &mut egui::Ui) {
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui, peak_centroids: &[f64]) {
         self.egui_settings.menu_button(ui);
+        self.labels.menu_button(ui);
         ui.checkbox(&mut self.stats_info, "Show Statistics");
+        ui.checkbox(&mut self.egui_settings.log_y, "Log Y")
+            .on_hover_text(
+                "Empty bins and fit tails are drawn at 0.1 counts, scaled by the normalization\nKeybind: l",
+            );
+        self.normalization.ui(ui);
+        self.integral.ui(ui);
+        self.smoothing.ui(ui);
         self.markers.menu_button(ui);
+        self.calibration.menu_button(ui, peak_centroids);
     }
 
-    pub fn interactive_response(&mut self, response: &egui_plot::PlotResponse<()>) {
-        self.markers.interactive_dragging(response);
+    // Returns the x region selected with shift + drag
+    pub fn interactive_response(
+        &mut self,
+        response: &egui_plot::PlotResponse<()>,
+    ) -> Option<(f64, f64)> {
+        self.markers.interactive_dragging(response)
     }
 
     pub fn progress_ui(&mut self, ui: &mut egui::Ui) {
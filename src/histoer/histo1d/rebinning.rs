@@ -1,36 +1,51 @@
 use super::histogram1d::Histogram;
 
 impl Histogram {
-    // Compute the possible rebin factors based on the initial number of bins
+    // Compute the possible rebin factors, every divisor of the initial number of bins
     pub fn possible_rebin_factors(&self) -> Vec<usize> {
-        let mut factors = vec![];
-        factors.push(1);
-        let mut factor = 1;
-        while self.original_bins.len() % (factor * 2) == 0 {
-            factor *= 2;
-            factors.push(factor);
-        }
+        let number_of_bins = self.core.bins.len();
 
-        // remove the last factor if it is the same as the number of bins
-        if factors.last() == Some(&self.original_bins.len()) {
-            factors.pop();
-        }
-        factors
+        // the number of bins itself is excluded since it would leave a single bin
+        (1..number_of_bins.max(2))
+            .filter(|factor| number_of_bins % factor == 0)
+            .collect()
     }
 
-    // Rebin the histogram according to the rebin factor
-    pub fn rebin(&mut self) {
-        let rebin_factor = self.plot_settings.rebin_factor;
-        let new_bin_count = self.original_bins.len() / rebin_factor;
-        let mut new_bins = vec![0; new_bin_count];
+    // The valid rebin factor closest to the requested one
+    fn nearest_rebin_factor(&self, factor: usize) -> usize {
+        self.possible_rebin_factors()
+            .into_iter()
+            .min_by_key(|&valid| valid.abs_diff(factor))
+            .unwrap_or(1)
+    }
 
-        for (i, &count) in self.original_bins.iter().enumerate() {
-            let new_index = i / rebin_factor;
-            new_bins[new_index] += count;
+    // Merge adjacent bins of the original counts by the rebin factor
+    pub fn rebinned_counts(&self) -> Vec<u64> {
+        let mut rebin_factor = self.plot_settings.rebin_factor.max(1);
+
+        if self.core.bins.len() % rebin_factor != 0 {
+            let nearest = self.nearest_rebin_factor(rebin_factor);
+            log::warn!(
+                "Rebin factor {} does not divide {} bins, using {} instead",
+                rebin_factor,
+                self.core.bins.len(),
+                nearest
+            );
+            rebin_factor = nearest;
         }
 
-        self.bins = new_bins;
-        self.bin_width = (self.range.1 - self.range.0) / new_bin_count as f64;
+        self.core
+            .bins
+            .chunks(rebin_factor)
+            .map(|chunk| chunk.iter().sum())
+            .collect()
+    }
+
+    // Rebin the histogram according to the rebin factor
+    pub fn rebin(&mut self) {
+        self.bins = self.rebinned_counts();
+        self.plot_settings.rebin_factor = (self.core.bins.len() / self.bins.len().max(1)).max(1);
+        self.bin_width = (self.range.1 - self.range.0) / self.bins.len() as f64;
         self.update_line_points();
     }
 }
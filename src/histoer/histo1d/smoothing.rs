@@ -0,0 +1,143 @@
+use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_line::log_y_floor;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum SmoothingMethod {
+    #[default]
+    MovingAverage,
+    SavitzkyGolay, // quadratic
+}
+
+// A smoothed line drawn over the counts, the stored counts are unchanged
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct SmoothingSettings {
+    pub show: bool,
+    pub method: SmoothingMethod,
+    pub window: usize, // bins, odd so the window is centered
+}
+
+impl Default for SmoothingSettings {
+    fn default() -> Self {
+        Self {
+            show: false,
+            method: SmoothingMethod::MovingAverage,
+            window: 5,
+        }
+    }
+}
+
+impl SmoothingSettings {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.show, "Smooth").on_hover_text(
+                "Draw a smoothed line over the counts, the counts and fits are unchanged",
+            );
+
+            ui.add_enabled_ui(self.show, |ui| {
+                ui.radio_value(
+                    &mut self.method,
+                    SmoothingMethod::MovingAverage,
+                    "Moving Average",
+                );
+                ui.radio_value(
+                    &mut self.method,
+                    SmoothingMethod::SavitzkyGolay,
+                    "Savitzky-Golay",
+                )
+                .on_hover_text(
+                    "Quadratic fit in each window, keeps peak heights better than the average",
+                );
+
+                // edited as the half width so the window stays odd and centered on the bin
+                let mut half = self.window / 2;
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut half)
+                            .range(1..=500)
+                            .custom_formatter(|half, _| format!("{}", 2 * half as usize + 1))
+                            .custom_parser(|text| {
+                                text.parse::<f64>()
+                                    .ok()
+                                    .map(|window| ((window - 1.0) / 2.0).round())
+                            })
+                            .prefix("Window: ")
+                            .suffix(" bins"),
+                    )
+                    .changed()
+                {
+                    self.window = 2 * half + 1;
+                }
+            });
+        });
+    }
+
+    // Smoothed values at each bin, the window shrinks to a centered moving average at the edges
+    pub fn smooth(&self, counts: &[f64]) -> Vec<f64> {
+        let half = self.window.max(3) / 2;
+        let n = counts.len();
+        let weights = match self.method {
+            SmoothingMethod::MovingAverage => vec![1.0 / (2 * half + 1) as f64; 2 * half + 1],
+            SmoothingMethod::SavitzkyGolay => savitzky_golay_weights(half),
+        };
+
+        (0..n)
+            .map(|i| {
+                if i >= half && i + half < n {
+                    counts[i - half..=i + half]
+                        .iter()
+                        .zip(&weights)
+                        .map(|(count, weight)| count * weight)
+                        .sum()
+                } else {
+                    let edge_half = i.min(n - 1 - i);
+                    let window = &counts[i - edge_half..=i + edge_half];
+                    window.iter().sum::<f64>() / window.len() as f64
+                }
+            })
+            .collect()
+    }
+}
+
+// Convolution weights of a quadratic (or cubic) Savitzky-Golay filter over 2 * half + 1 points
+fn savitzky_golay_weights(half: usize) -> Vec<f64> {
+    let m = half as f64;
+    let norm = (2.0 * m + 3.0) * (2.0 * m + 1.0) * (2.0 * m - 1.0);
+    (-(half as i64)..=half as i64)
+        .map(|i| (3.0 * (3.0 * m * m + 3.0 * m - 1.0) - 15.0 * (i * i) as f64) / norm)
+        .collect()
+}
+
+impl Histogram {
+    pub fn draw_smoothed(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let smoothing = &self.plot_settings.smoothing;
+        if !smoothing.show || self.bins.is_empty() {
+            return;
+        }
+
+        let log_x = self.plot_settings.egui_settings.log_x;
+        let log_y = self.plot_settings.egui_settings.log_y;
+        let floor = log_y_floor(self.plot_settings.normalization.factor(&self.counts()));
+
+        let points: Vec<[f64; 2]> = self
+            .get_bin_centers()
+            .into_iter()
+            .zip(smoothing.smooth(&self.display_counts()))
+            .map(|(x, y)| {
+                let x = if log_x && x > 0.0 {
+                    x.log10().max(0.0001)
+                } else {
+                    x
+                };
+                let y = if log_y { y.max(floor).log10() } else { y };
+                [x, y]
+            })
+            .collect();
+
+        plot_ui.line(
+            egui_plot::Line::new(points)
+                .color(self.line.color.gamma_multiply(0.6))
+                .width(2.0)
+                .name(format!("{} (smoothed)", self.name)),
+        );
+    }
+}
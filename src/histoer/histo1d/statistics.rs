@@ -1,72 +1,137 @@
+use std::hash::Hasher;
+
 use super::histogram1d::Histogram;
+use crate::egui_plot_stuff::egui_line::log_y_floor;
 
 impl Histogram {
-    // Calculate the statistics for the histogram within the specified x range.
-    pub fn get_statistics(&self, start_x: f64, end_x: f64) -> (u64, f64, f64) {
-        let start_bin = self.get_bin_index(start_x).unwrap_or(0);
-        let end_bin = self.get_bin_index(end_x).unwrap_or(self.bins.len() - 1);
+    // Calculate the statistics for the histogram within the specified x range, weighted if filled with weights.
+    pub fn get_statistics(&self, start_x: f64, end_x: f64) -> (f64, f64, f64) {
+        let counts = self.counts();
+        let Some(bins) = self.bin_range(start_x, end_x) else {
+            return (0.0, 0.0, 0.0);
+        };
+        let (start_bin, end_bin) = (*bins.start(), *bins.end());
+
+        let bin_centers = self.get_bin_centers();
+        let bin_center = |bin: usize| bin_centers[bin];
 
         let mut sum_product = 0.0;
-        let mut total_count = 0;
-
-        for bin in start_bin..=end_bin {
-            if bin < self.bins.len() {
-                let bin_center =
-                    self.range.0 + (bin as f64 * self.bin_width) + self.bin_width * 0.5;
-                sum_product += self.bins[bin] as f64 * bin_center;
-                total_count += self.bins[bin];
-            } else {
-                break;
-            }
+        let mut total_count = 0.0;
+
+        for (bin, &count) in counts.iter().enumerate().take(end_bin + 1).skip(start_bin) {
+            sum_product += count * bin_center(bin);
+            total_count += count;
         }
 
-        if total_count == 0 {
-            (0, 0.0, 0.0)
+        if total_count == 0.0 {
+            (0.0, 0.0, 0.0)
         } else {
-            let mean = sum_product / total_count as f64;
+            let mean = sum_product / total_count;
 
             let mut sum_squared_diff = 0.0;
 
-            for bin in start_bin..=end_bin {
-                if bin < self.bins.len() {
-                    let bin_center =
-                        self.range.0 + (bin as f64 * self.bin_width) + (self.bin_width * 0.5);
-                    let diff = bin_center - mean;
-                    sum_squared_diff += self.bins[bin] as f64 * diff * diff;
-                } else {
-                    break;
-                }
+            for (bin, &count) in counts.iter().enumerate().take(end_bin + 1).skip(start_bin) {
+                let diff = bin_center(bin) - mean;
+                sum_squared_diff += count * diff * diff;
             }
 
-            let stdev = (sum_squared_diff / total_count as f64).sqrt();
+            let stdev = (sum_squared_diff / total_count).sqrt();
 
             (total_count, mean, stdev)
         }
     }
 
-    // Get the legend stat entries for the histogram
+    // Stable hash of the binning and contents, used to check if two histograms are identical.
+    // Bin counts don't depend on the order the values were filled in, so neither does the hash.
+    // The original bins are hashed so the display rebin factor doesn't change it, and sizes are
+    // hashed as u64 so it is the same on 32-bit targets like wasm.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = fnv::FnvHasher::default();
+
+        hasher.write_u64(self.range.0.to_bits());
+        hasher.write_u64(self.range.1.to_bits());
+        hasher.write_u64(self.core.bins.len() as u64);
+
+        for &count in &self.core.bins {
+            hasher.write_u64(count);
+        }
+
+        for &weight in &self.original_weights {
+            hasher.write_u64(weight.to_bits());
+        }
+
+        // uniform histograms keep the same hash as before variable binning existed
+        for &edge in self.core.edges.iter().flatten() {
+            hasher.write_u64(edge.to_bits());
+        }
+
+        hasher.write_u64(self.core.overflow);
+        hasher.write_u64(self.core.underflow);
+
+        format!("{:016x}", hasher.finish())
+    }
+
+    // Lines of the stats box, the counts, mean and stdev are taken between start_x and end_x
+    pub fn stats_text(&self, start_x: f64, end_x: f64, range_label: &str) -> String {
+        // the counts follow the normalization, the mean and stdev don't depend on it
+        let counts = self.counts();
+        let factor = self.plot_settings.normalization.factor(&counts);
+
+        let (counts_in_range, mean, stdev) = self.get_statistics(start_x, end_x);
+        let counts_in_range = counts_in_range * factor;
+        let entries: u64 = self.bins.iter().sum::<u64>() + self.core.underflow + self.core.overflow;
+        let total: f64 = counts.iter().sum::<f64>() * factor;
+
+        let mut stats_text = [
+            format!("Entries: {}", entries),
+            format!(
+                "Integral: {} (underflow {}, overflow {})",
+                total, self.core.underflow, self.core.overflow
+            ),
+            format!("Counts in {}: {}", range_label, counts_in_range),
+            format!("Mean: {:.2}", mean),
+            format!("Stdev: {:.2}", stdev),
+            format!("FWHM: {:.2}", 2.0 * (2.0 * 2.0_f64.ln()).sqrt() * stdev),
+            format!("Fingerprint: {}", self.fingerprint()),
+        ]
+        .join("\n");
+        if let Some(label) = self.plot_settings.normalization.label() {
+            stats_text.push_str(&format!("\n{}", label));
+        }
+
+        stats_text
+    }
+
+    // Draw a stats box in the top right corner of the plot, the view numbers follow the x-axis bounds
     pub fn show_stats(&self, plot_ui: &mut egui_plot::PlotUi) {
-        if self.plot_settings.stats_info {
-            let plot_min_x = plot_ui.plot_bounds().min()[0];
-            let plot_max_x = plot_ui.plot_bounds().max()[0];
-
-            let (integral, mean, stdev) = self.get_statistics(plot_min_x, plot_max_x);
-            let stats_entries = [
-                format!("Integral: {}", integral),
-                format!("Mean: {:.2}", mean),
-                format!("Stdev: {:.2}", stdev),
-                format!("Overflow: {:}", self.overflow),
-                format!("Underflow: {:}", self.underflow),
-            ];
-
-            for entry in stats_entries.iter() {
-                plot_ui.text(
-                    egui_plot::Text::new(egui_plot::PlotPoint::new(0, 0), " ") // Placeholder for positioning; adjust as needed
-                        .highlight(false)
-                        .color(self.line.color)
-                        .name(entry),
-                );
-            }
+        if !self.plot_settings.stats_info {
+            return;
         }
+
+        let plot_max_x = plot_ui.plot_bounds().max()[0];
+        let plot_max_y = plot_ui.plot_bounds().max()[1];
+
+        let (view_min_x, view_max_x) = self.visible_x_range().unwrap_or(self.range);
+        let stats_text = self.stats_text(view_min_x, view_max_x, "view");
+
+        // Keep the anchor inside the data so the text doesn't grow the auto bounds every frame
+        let counts = self.counts();
+        let factor = self.plot_settings.normalization.factor(&counts);
+        let max_count = counts.into_iter().fold(0.0, f64::max) * factor;
+        let max_y = if self.plot_settings.egui_settings.log_y {
+            max_count.max(log_y_floor(factor)).log10()
+        } else {
+            max_count
+        };
+        let x = plot_max_x.min(self.range.1);
+        let y = plot_max_y.min(max_y);
+
+        plot_ui.text(
+            egui_plot::Text::new(egui_plot::PlotPoint::new(x, y), stats_text)
+                .anchor(egui::Align2::RIGHT_TOP)
+                .highlight(true)
+                .allow_hover(false)
+                .color(self.line.color),
+        );
     }
 }
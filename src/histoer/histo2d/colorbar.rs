@@ -0,0 +1,113 @@
+use super::colormaps::{ColormapOptions, ZScale};
+use super::histogram2d::Histogram2D;
+
+const COLORBAR_WIDTH: f32 = 70.0; // gradient and tick labels
+const GRADIENT_WIDTH: f32 = 14.0;
+const GRADIENT_STEPS: usize = 128;
+const LINEAR_TICKS: usize = 5;
+
+impl Histogram2D {
+    pub fn colorbar_width(&self) -> f32 {
+        if self.plot_settings.show_colorbar {
+            COLORBAR_WIDTH
+        } else {
+            0.0
+        }
+    }
+
+    // The colormap gradient beside the plot, labelled with the counts the colors stand for
+    pub fn draw_colorbar(&self, ui: &mut egui::Ui, height: f32) {
+        let (rect, _response) =
+            ui.allocate_exact_size(egui::vec2(COLORBAR_WIDTH, height), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+
+        let options = self.plot_settings.colormap_options;
+        let colormap = &self.plot_settings.colormap;
+        let (min, max) = options.display_range(self.bins.min_count, self.bins.max_count);
+
+        // leave room for the top and bottom tick labels
+        let text_height = ui.text_style_height(&egui::TextStyle::Small);
+        let gradient = egui::Rect::from_min_max(
+            egui::pos2(rect.left() + 4.0, rect.top() + text_height),
+            egui::pos2(
+                rect.left() + 4.0 + GRADIENT_WIDTH,
+                rect.bottom() - text_height,
+            ),
+        );
+        if gradient.height() <= 0.0 {
+            return;
+        }
+
+        let step_height = gradient.height() / GRADIENT_STEPS as f32;
+        for step in 0..GRADIENT_STEPS {
+            let normalized = (step as f64 + 0.5) / GRADIENT_STEPS as f64;
+            let bottom = gradient.bottom() - step as f32 * step_height;
+            let step_rect = egui::Rect::from_min_max(
+                egui::pos2(gradient.left(), bottom - step_height),
+                egui::pos2(gradient.right(), bottom),
+            );
+            painter.rect_filled(
+                step_rect.expand2(egui::vec2(0.0, 0.5)),
+                0.0,
+                colormap.gradient_color(normalized, options),
+            );
+        }
+
+        let stroke = ui.visuals().widgets.noninteractive.fg_stroke;
+        painter.rect_stroke(gradient, 0.0, stroke);
+
+        for (normalized, count) in colorbar_ticks(&options, min, max) {
+            let y = gradient.bottom() - normalized as f32 * gradient.height();
+            painter.line_segment(
+                [
+                    egui::pos2(gradient.right(), y),
+                    egui::pos2(gradient.right() + 4.0, y),
+                ],
+                stroke,
+            );
+            painter.text(
+                egui::pos2(gradient.right() + 6.0, y),
+                egui::Align2::LEFT_CENTER,
+                format_count(count),
+                egui::TextStyle::Small.resolve(ui.style()),
+                stroke.color,
+            );
+        }
+    }
+}
+
+// Powers of ten for the log scale, evenly spaced positions otherwise
+fn colorbar_ticks(options: &ColormapOptions, min: u64, max: u64) -> Vec<(f64, f64)> {
+    if max <= min {
+        return vec![(0.0, min as f64)];
+    }
+
+    if options.z_scale() == ZScale::Log {
+        let ticks: Vec<(f64, f64)> = (0..=max.ilog10())
+            .map(|power| 10f64.powi(power as i32))
+            .filter(|&count| count >= min as f64 && count <= max as f64)
+            .map(|count| (options.normalize(count, min, max), count))
+            .collect();
+
+        if ticks.len() >= 2 {
+            return ticks;
+        }
+    }
+
+    (0..LINEAR_TICKS)
+        .map(|tick| {
+            let normalized = tick as f64 / (LINEAR_TICKS - 1) as f64;
+            (normalized, options.denormalize(normalized, min, max))
+        })
+        .collect()
+}
+
+fn format_count(count: f64) -> String {
+    if count >= 1e4 {
+        format!("{:.1e}", count)
+    } else if count >= 10.0 || count.fract() < 1e-6 {
+        format!("{:.0}", count)
+    } else {
+        format!("{:.1}", count)
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default)]
+use rfd::FileDialog;
+
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize, Default)]
 pub enum ColorMap {
     #[default]
     Viridis,
@@ -10,11 +12,159 @@ pub enum ColorMap {
     Inferno,
     Kindlmann,
     ExtendedKindlmann,
+    Custom(CustomColormap),
+}
+
+// A colormap loaded from a file, the stops are interpolated like the built in maps
+#[derive(PartialEq, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CustomColormap {
+    pub name: String,
+    pub stops: Vec<(f32, i32, i32, i32)>, // scalar, r, g, b
+}
+
+impl CustomColormap {
+    // The scalars must increase from 0 to 1 and the colors must be 0-255
+    pub fn new(name: &str, stops: Vec<(f32, i32, i32, i32)>) -> Result<Self, String> {
+        if stops.len() < 2 {
+            return Err(format!("'{}' needs at least two color stops", name));
+        }
+
+        if let Some(pair) = stops.windows(2).find(|pair| pair[1].0 <= pair[0].0) {
+            return Err(format!(
+                "'{}' stops must be sorted, {} is followed by {}",
+                name, pair[0].0, pair[1].0
+            ));
+        }
+
+        let (first, last) = (stops[0].0, stops[stops.len() - 1].0);
+        if first != 0.0 || last != 1.0 {
+            return Err(format!(
+                "'{}' stops must go from 0 to 1, got {} to {}",
+                name, first, last
+            ));
+        }
+
+        if let Some(stop) = stops.iter().find(|(_, r, g, b)| {
+            [r, g, b]
+                .iter()
+                .any(|component| !(0..=255).contains(*component))
+        }) {
+            return Err(format!(
+                "'{}' has a color outside 0-255 at {}",
+                name, stop.0
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            stops,
+        })
+    }
+
+    // One "scalar,r,g,b" stop per line, the colors can be 0-255 or 0-1. A header line and
+    // lines starting with # are skipped.
+    pub fn from_csv(name: &str, contents: &str) -> Result<Self, String> {
+        let mut rows: Vec<[f64; 4]> = Vec::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let values: Result<Vec<f64>, _> = line
+                .split([',', ';', '\t'])
+                .map(|value| value.trim().parse::<f64>())
+                .collect();
+            match values {
+                Ok(values) if values.len() == 4 => {
+                    rows.push([values[0], values[1], values[2], values[3]]);
+                }
+                Ok(values) => {
+                    return Err(format!(
+                        "Line {} has {} values, expected scalar,r,g,b",
+                        line_number + 1,
+                        values.len()
+                    ));
+                }
+                // a header
+                Err(_) if rows.is_empty() => continue,
+                Err(e) => return Err(format!("Line {}: {}", line_number + 1, e)),
+            }
+        }
+
+        let fractions = rows
+            .iter()
+            .all(|row| row[1..].iter().all(|component| *component <= 1.0));
+        let scale = if fractions { 255.0 } else { 1.0 };
+
+        let stops = rows
+            .iter()
+            .map(|row| {
+                (
+                    row[0] as f32,
+                    (row[1] * scale).round() as i32,
+                    (row[2] * scale).round() as i32,
+                    (row[3] * scale).round() as i32,
+                )
+            })
+            .collect();
+
+        Self::new(name, stops)
+    }
+
+    // Reads a CSV of stops or a JSON CustomColormap, named after the file for CSVs
+    pub fn load_from_file() -> Option<Self> {
+        let path = FileDialog::new()
+            .add_filter("Colormap", &["csv", "txt", "json"])
+            .pick_file()?;
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                log::error!("Error reading colormap: {:?}", e);
+                return None;
+            }
+        };
+
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Custom".to_string());
+
+        let colormap = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            serde_json::from_str::<CustomColormap>(&contents)
+                .map_err(|e| format!("Error deserializing colormap: {:?}", e))
+                .and_then(|colormap| Self::new(&colormap.name, colormap.stops))
+        } else {
+            Self::from_csv(&name, &contents)
+        };
+
+        match colormap {
+            Ok(colormap) => Some(colormap),
+            Err(e) => {
+                log::error!("{}", e);
+                None
+            }
+        }
+    }
+}
+
+// How the counts are mapped to the colormap, empty bins stay transparent for Sqrt and Log
+#[derive(PartialEq, Debug, Copy, Clone, serde::Serialize, serde::Deserialize, Default)]
+pub enum ZScale {
+    Linear,
+    Sqrt,
+    #[default]
+    Log,
 }
 
 #[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ColormapOptions {
-    log_norm: bool,
+    #[serde(default)]
+    z_scale: ZScale,
     reverse: bool,
     custom_display_range: bool,
     remove: bool,
@@ -25,7 +175,7 @@ pub struct ColormapOptions {
 impl Default for ColormapOptions {
     fn default() -> Self {
         ColormapOptions {
-            log_norm: true,
+            z_scale: ZScale::Log,
             reverse: false,
             custom_display_range: false,
             remove: false,
@@ -36,14 +186,74 @@ impl Default for ColormapOptions {
 }
 
 impl ColormapOptions {
-    pub fn ui(&mut self, ui: &mut egui::Ui, recalculate_image: &mut bool, max_z_range: u64) {
-        if ui
-            .checkbox(&mut self.log_norm, "Log Z")
-            .on_hover_text("Log the Z values. 0 bins will become transparent")
-            .changed()
-        {
-            *recalculate_image = true;
+    pub fn z_scale(&self) -> ZScale {
+        self.z_scale
+    }
+
+    // The counts at the ends of the colormap
+    pub fn display_range(&self, min: u64, max: u64) -> (u64, u64) {
+        if self.custom_display_range {
+            (self.display_min, self.display_max)
+        } else {
+            (min, max)
+        }
+    }
+
+    // Position of a count along the colormap from 0 to 1
+    pub fn normalize(&self, value: f64, min: u64, max: u64) -> f64 {
+        let (min, max) = (min as f64, max as f64);
+
+        // Handle case where min == max to avoid division by zero
+        if max <= min {
+            return 0.0;
+        }
+
+        match self.z_scale {
+            ZScale::Linear => (value - min) / (max - min),
+            ZScale::Sqrt => (value.sqrt() - min.sqrt()) / (max.sqrt() - min.sqrt()),
+            ZScale::Log => {
+                ((value + 1.0).log10() - (min + 1.0).log10())
+                    / ((max + 1.0).log10() - (min + 1.0).log10())
+            }
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    // The count at a position along the colormap, the inverse of normalize
+    pub fn denormalize(&self, normalized: f64, min: u64, max: u64) -> f64 {
+        let (min, max) = (min as f64, max as f64);
+        match self.z_scale {
+            ZScale::Linear => min + normalized * (max - min),
+            ZScale::Sqrt => (min.sqrt() + normalized * (max.sqrt() - min.sqrt())).powi(2),
+            ZScale::Log => {
+                let log_min = (min + 1.0).log10();
+                let log_max = (max + 1.0).log10();
+                10f64.powf(log_min + normalized * (log_max - log_min)) - 1.0
+            }
+        }
+    }
+
+    pub fn toggle_log_norm(&mut self) {
+        self.z_scale = if self.z_scale == ZScale::Log {
+            ZScale::Linear
+        } else {
+            ZScale::Log
         };
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, recalculate_image: &mut bool, max_z_range: u64) {
+        ui.horizontal(|ui| {
+            ui.label("Z Scale:");
+            let previous_scale = self.z_scale;
+            ui.radio_value(&mut self.z_scale, ZScale::Linear, "Linear");
+            ui.radio_value(&mut self.z_scale, ZScale::Sqrt, "Sqrt")
+                .on_hover_text("Square root of the counts. 0 bins will become transparent");
+            ui.radio_value(&mut self.z_scale, ZScale::Log, "Log")
+                .on_hover_text("log10(counts + 1). 0 bins will become transparent");
+            if self.z_scale != previous_scale {
+                *recalculate_image = true;
+            }
+        });
         if ui
             .checkbox(&mut self.reverse, "Reverse")
             .on_hover_text("Reverse the color map intensity values")
@@ -64,7 +274,7 @@ impl ColormapOptions {
         if self.custom_display_range {
             ui.horizontal(|ui| {
                 ui.label("Z ");
-                let min_z_range = if self.log_norm { 1 } else { 0 };
+                let min_z_range = if self.z_scale == ZScale::Log { 1 } else { 0 };
                 if ui
                     .add(
                         egui::widgets::DragValue::new(&mut self.display_min)
@@ -108,32 +318,37 @@ impl ColorMap {
         max_count: u64,
         options: ColormapOptions,
     ) -> egui::Color32 {
+        Self::colormap(self.color_data(), count, min_count, max_count, options)
+    }
+
+    // The color at a position from 0 to 1 along the colormap, used to draw the colorbar
+    pub fn gradient_color(&self, normalized: f64, options: ColormapOptions) -> egui::Color32 {
+        Self::interpolate(self.color_data(), normalized, options.reverse)
+    }
+
+    fn color_data(&self) -> Vec<(f32, i32, i32, i32)> {
         match self {
-            ColorMap::Viridis => Self::colormap(viridis(), count, min_count, max_count, options),
-            ColorMap::Fast => Self::colormap(fast(), count, min_count, max_count, options),
-            ColorMap::SmoothCoolWarm => {
-                Self::colormap(smooth_cool_warm(), count, min_count, max_count, options)
-            }
-            ColorMap::BentCoolWarm => {
-                Self::colormap(bent_cool_warm(), count, min_count, max_count, options)
-            }
-            ColorMap::Plasma => Self::colormap(plasma(), count, min_count, max_count, options),
-            ColorMap::Blackbody => {
-                Self::colormap(blackbody(), count, min_count, max_count, options)
-            }
-            ColorMap::Inferno => Self::colormap(inferno(), count, min_count, max_count, options),
-            ColorMap::Kindlmann => {
-                Self::colormap(kindlmann(), count, min_count, max_count, options)
-            }
-            ColorMap::ExtendedKindlmann => {
-                Self::colormap(extended_kindlmann(), count, min_count, max_count, options)
-            }
+            ColorMap::Viridis => viridis(),
+            ColorMap::Fast => fast(),
+            ColorMap::SmoothCoolWarm => smooth_cool_warm(),
+            ColorMap::BentCoolWarm => bent_cool_warm(),
+            ColorMap::Plasma => plasma(),
+            ColorMap::Blackbody => blackbody(),
+            ColorMap::Inferno => inferno(),
+            ColorMap::Kindlmann => kindlmann(),
+            ColorMap::ExtendedKindlmann => extended_kindlmann(),
+            ColorMap::Custom(custom) => custom.stops.clone(),
         }
     }
 
-    pub fn color_maps_ui(&mut self, ui: &mut egui::Ui, recalculate_image: &mut bool) {
+    pub fn color_maps_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        recalculate_image: &mut bool,
+        custom_colormaps: &[CustomColormap],
+    ) {
         // check to see if the colormap has changed
-        let new_colormap = *self;
+        let new_colormap = self.clone();
 
         ui.vertical(|ui| {
             ui.radio_value(self, ColorMap::Viridis, "Viridis");
@@ -145,6 +360,13 @@ impl ColorMap {
             ui.radio_value(self, ColorMap::Inferno, "Inferno");
             ui.radio_value(self, ColorMap::Kindlmann, "Kindlmann");
             ui.radio_value(self, ColorMap::ExtendedKindlmann, "Extended Kindlmann");
+
+            if !custom_colormaps.is_empty() {
+                ui.separator();
+            }
+            for custom in custom_colormaps {
+                ui.radio_value(self, ColorMap::Custom(custom.clone()), &custom.name);
+            }
         });
 
         if new_colormap != *self {
@@ -159,17 +381,12 @@ impl ColorMap {
         max: u64,
         options: ColormapOptions,
     ) -> egui::Color32 {
-        if value == 0 && options.log_norm {
+        if value == 0 && options.z_scale != ZScale::Linear {
             // Return transparent color for zero values
             return egui::Color32::from_rgba_unmultiplied(0, 0, 0, 0);
         }
 
-        // Handle display range options
-        let (display_min, display_max) = if options.custom_display_range {
-            (options.display_min, options.display_max)
-        } else {
-            (min, max)
-        };
+        let (display_min, display_max) = options.display_range(min, max);
 
         if options.custom_display_range {
             if options.remove && value < display_min {
@@ -183,8 +400,18 @@ impl ColorMap {
             }
         }
 
+        let normalized = options.normalize(value as f64, display_min, display_max);
+
+        Self::interpolate(color_data, normalized, options.reverse)
+    }
+
+    fn interpolate(
+        color_data: Vec<(f32, i32, i32, i32)>,
+        normalized: f64,
+        reverse: bool,
+    ) -> egui::Color32 {
         // Reverse RGB values while keeping scalar values the same
-        let color_data = if options.reverse {
+        let color_data = if reverse {
             let mut reversed_rgb = color_data
                 .iter()
                 .map(|&(_s, r, g, b)| (r, g, b))
@@ -201,24 +428,6 @@ impl ColorMap {
             color_data
         };
 
-        // Convert min and max to f64 for calculations
-        let (min_f64, max_f64) = (display_min as f64, display_max as f64);
-
-        // Handle case where min == max to avoid division by zero
-        let normalized: f64 = if max_f64 > min_f64 {
-            let value_f64 = value as f64;
-            if options.log_norm {
-                // Use logarithmic scale
-                (value_f64.log10() - min_f64.log10()) / (max_f64.log10() - min_f64.log10())
-            } else {
-                // Use linear scale
-                (value_f64 - min_f64) / (max_f64 - min_f64)
-            }
-        } else {
-            0.0
-        }
-        .clamp(0.0, 1.0);
-
         // Interpolate between colors in the colormap based on the scale value
         let mut lower = color_data[0];
         let mut upper = color_data[0];
@@ -5,46 +5,35 @@ impl Histogram2D {
     pub fn context_menu(&mut self, ui: &mut egui::Ui) {
         self.image.menu_button(ui);
         self.plot_settings.settings_ui(ui, self.bins.max_count);
+        self.plot_settings.export.menu_button(ui, &self.name);
+        self.export_npy_button(ui);
+        self.export_root_button(ui);
+
+        ui.menu_button("Notes", |ui| {
+            ui.text_edit_multiline(&mut self.notes);
+        });
+
+        self.range_edges.ui(ui);
+
+        if self.bins.is_weighted()
+            && ui
+                .checkbox(&mut self.bins.average_weights, "Average Weights")
+                .on_hover_text(
+                    "Show the mean weight of the entries in each bin instead of the summed weights",
+                )
+                .changed()
+        {
+            self.bins.update_min_max();
+            self.plot_settings.recalculate_image = true;
+        }
+
+        self.cut_projection_menu(ui);
+        self.duplicate_ui(ui);
 
         ui.separator();
 
         ui.heading("Rebin");
 
-        let possible_x_factors = self.possible_x_rebin_factors();
-        let possible_y_factors = self.possible_y_rebin_factors();
-
-        ui.label("Rebin Factor");
-
-        ui.horizontal_wrapped(|ui| {
-            ui.label("X: ");
-            for &factor in &possible_x_factors {
-                if ui
-                    .selectable_label(
-                        self.plot_settings.rebin_x_factor == factor,
-                        format!("{}", factor),
-                    )
-                    .clicked()
-                {
-                    self.plot_settings.rebin_x_factor = factor;
-                    self.rebin();
-                }
-            }
-        });
-
-        ui.horizontal_wrapped(|ui| {
-            ui.label("Y: ");
-            for &factor in &possible_y_factors {
-                if ui
-                    .selectable_label(
-                        self.plot_settings.rebin_y_factor == factor,
-                        format!("{}", factor),
-                    )
-                    .clicked()
-                {
-                    self.plot_settings.rebin_y_factor = factor;
-                    self.rebin();
-                }
-            }
-        });
+        self.rebin_ui(ui);
     }
 }
@@ -1,7 +1,12 @@
 use fnv::FnvHashMap;
+use std::thread::JoinHandle;
 
 use crate::egui_plot_stuff::egui_image::EguiImage;
+use crate::histoer::core::bin_index_2d;
+pub use crate::histoer::core::RangeEdges;
+use crate::histoer::histo1d::histogram1d::Histogram;
 
+use super::colormaps::{ColorMap, ColormapOptions};
 use super::plot_settings::PlotSettings;
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -14,6 +19,49 @@ pub struct Histogram2D {
     pub plot_settings: PlotSettings,
     pub image: EguiImage,
     pub backup_bins: Option<Bins>,
+    #[serde(default)]
+    pub notes: String,
+    #[serde(default)]
+    pub range_edges: RangeEdges,
+    #[serde(skip)]
+    pub pending_cut_projections: Vec<Histogram>, // picked up by the histogrammer and added to the "Other" grid
+    #[serde(skip)]
+    pub duplicate_name: String,
+    #[serde(skip)]
+    pub pending_duplicate: Option<String>, // picked up by the histogrammer, the name of the copy
+    #[serde(skip)]
+    pub image_job: ImageJob, // at most one image worker per histogram
+}
+
+// The worker thread building the heatmap image, a clone of the histogram starts its own
+#[derive(Default)]
+pub struct ImageJob(Option<JoinHandle<egui::ColorImage>>);
+
+impl Clone for ImageJob {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl ImageJob {
+    pub fn is_running(&self) -> bool {
+        self.0.is_some()
+    }
+
+    // Join the worker once it is done, like the fill threads in the histogrammer
+    fn take_finished(&mut self) -> Option<egui::ColorImage> {
+        if !self.0.as_ref()?.is_finished() {
+            return None;
+        }
+
+        match self.0.take()?.join() {
+            Ok(image) => Some(image),
+            Err(e) => {
+                log::error!("The image thread encountered an error: {:?}", e);
+                None
+            }
+        }
+    }
 }
 
 impl Histogram2D {
@@ -29,6 +77,9 @@ impl Histogram2D {
                 counts: FnvHashMap::default(),
                 min_count: u64::MAX,
                 max_count: u64::MIN,
+                weights: FnvHashMap::default(),
+                sumw2: FnvHashMap::default(),
+                average_weights: false,
             },
             range: Range {
                 x: Value {
@@ -49,36 +100,193 @@ impl Histogram2D {
                 [range.1 .0, range.1 .1],
             ),
             backup_bins: None,
+            notes: String::new(),
+            range_edges: RangeEdges::default(),
+            pending_cut_projections: Vec::new(),
+            duplicate_name: String::new(),
+            pending_duplicate: None,
+            image_job: ImageJob::default(),
         }
     }
 
     pub fn reset(&mut self) {
         self.bins.counts.clear();
+        self.bins.weights.clear();
+        self.bins.sumw2.clear();
         self.bins.min_count = u64::MAX;
         self.bins.max_count = u64::MIN;
         self.plot_settings.recalculate_image = true;
     }
 
+    // The settings and cuts without the counts
+    pub fn duplicate(&self, new_name: &str) -> Self {
+        let mut copy = self.clone();
+        copy.name = new_name.to_string();
+        copy.image.name = new_name.to_string();
+        copy.reset();
+        if let Some(backup_bins) = &mut copy.backup_bins {
+            backup_bins.counts.clear();
+            backup_bins.weights.clear();
+            backup_bins.sumw2.clear();
+            backup_bins.min_count = u64::MAX;
+            backup_bins.max_count = u64::MIN;
+        }
+        copy.overflow = (0, 0);
+        copy.underflow = (0, 0);
+        copy.plot_settings.progress = None;
+        copy.pending_cut_projections.clear();
+        copy.pending_duplicate = None;
+        copy
+    }
+
+    // Add the counts of another histogram with the same binning, keeping this one's settings and cuts
+    pub fn merge(&mut self, other: &Histogram2D) -> Result<(), String> {
+        // the original binning of both, before any rebinning
+        let other_bins = other.backup_bins.as_ref().unwrap_or(&other.bins);
+        let bins = self.backup_bins.as_ref().unwrap_or(&self.bins);
+
+        if (bins.x, bins.y) != (other_bins.x, other_bins.y) {
+            return Err(format!(
+                "'{}' has {}x{} bins and '{}' has {}x{}",
+                self.name, bins.x, bins.y, other.name, other_bins.x, other_bins.y
+            ));
+        }
+
+        let range = |hist: &Histogram2D| {
+            (
+                hist.range.x.min,
+                hist.range.x.max,
+                hist.range.y.min,
+                hist.range.y.max,
+            )
+        };
+        if range(self) != range(other) {
+            return Err(format!(
+                "'{}' covers {:?} and '{}' covers {:?}",
+                self.name,
+                range(self),
+                other.name,
+                range(other)
+            ));
+        }
+
+        match &mut self.backup_bins {
+            Some(backup_bins) => {
+                backup_bins.merge(other_bins);
+                self.rebin();
+            }
+            None => {
+                self.bins.merge(other_bins);
+                self.bins.update_min_max();
+            }
+        }
+
+        self.overflow.0 += other.overflow.0;
+        self.overflow.1 += other.overflow.1;
+        self.underflow.0 += other.underflow.0;
+        self.underflow.1 += other.underflow.1;
+        self.plot_settings.recalculate_image = true;
+
+        Ok(())
+    }
+
+    pub fn duplicate_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Duplicate", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("New Name:");
+                ui.text_edit_singleline(&mut self.duplicate_name);
+            });
+
+            if ui
+                .add_enabled(
+                    !self.duplicate_name.trim().is_empty(),
+                    egui::Button::new("Duplicate"),
+                )
+                .on_hover_text("Add an empty copy with the same settings and cuts to this grid")
+                .clicked()
+            {
+                self.pending_duplicate = Some(self.duplicate_name.trim().to_string());
+                self.duplicate_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+
+    // The bin of a value pair in the current binning, binned like Histogram2DCore
+    fn bin_index(&self, x_value: f64, y_value: f64) -> Option<(usize, usize)> {
+        bin_index_2d(
+            (
+                (self.range.x.min, self.range.x.max),
+                (self.range.y.min, self.range.y.max),
+            ),
+            (self.bins.x, self.bins.y),
+            (self.bins.x_width, self.bins.y_width),
+            self.range_edges,
+            x_value,
+            y_value,
+        )
+    }
+
     // Add a value to the histogram with progress tracking
     pub fn fill(&mut self, x_value: f64, y_value: f64, current_step: usize, total_steps: usize) {
-        if x_value >= self.range.x.min
-            && x_value < self.range.x.max
-            && y_value >= self.range.y.min
-            && y_value < self.range.y.max
-        {
-            let x_index = ((x_value - self.range.x.min) / self.bins.x_width) as usize;
-            let y_index = ((y_value - self.range.y.min) / self.bins.y_width) as usize;
+        if let Some((x_index, y_index)) = self.bin_index(x_value, y_value) {
             let count = self.bins.counts.entry((x_index, y_index)).or_insert(0);
             *count += 1;
 
             self.bins.min_count = self.bins.min_count.min(*count);
             self.bins.max_count = self.bins.max_count.max(*count);
+        } else {
+            self.count_flow(x_value, y_value);
         }
 
         // Update progress if it's being tracked
         self.plot_settings.progress = Some(current_step as f32 / total_steps as f32);
     }
 
+    // Add a value pair that counts as `weight` entries
+    pub fn fill_weighted(
+        &mut self,
+        x_value: f64,
+        y_value: f64,
+        weight: f64,
+        current_step: usize,
+        total_steps: usize,
+    ) {
+        if let Some((x_index, y_index)) = self.bin_index(x_value, y_value) {
+            self.bins.init_weights();
+            *self.bins.counts.entry((x_index, y_index)).or_insert(0) += 1;
+            *self.bins.weights.entry((x_index, y_index)).or_insert(0.0) += weight;
+            *self.bins.sumw2.entry((x_index, y_index)).or_insert(0.0) += weight * weight;
+
+            // the image shows the weighted counts, rounded to whole counts
+            let count = self.bins.image_count(&(x_index, y_index));
+            self.bins.min_count = self.bins.min_count.min(count);
+            self.bins.max_count = self.bins.max_count.max(count);
+        } else {
+            self.count_flow(x_value, y_value);
+        }
+
+        self.plot_settings.progress = Some(current_step as f32 / total_steps as f32);
+    }
+
+    // Values outside the range, or on an excluded edge, count as overflow or underflow per axis
+    fn count_flow(&mut self, x_value: f64, y_value: f64) {
+        self.range_edges.count_flow(
+            x_value,
+            self.range.x.min,
+            self.range.x.max,
+            &mut self.overflow.0,
+            &mut self.underflow.0,
+        );
+        self.range_edges.count_flow(
+            y_value,
+            self.range.y.min,
+            self.range.y.max,
+            &mut self.overflow.1,
+            &mut self.underflow.1,
+        );
+    }
+
     // get the bin index for a given x value
     pub fn get_bin_index_x(&self, x: f64) -> Option<usize> {
         if x < self.range.x.min || x > self.range.x.max {
@@ -101,45 +309,85 @@ impl Histogram2D {
         Some(bin_index)
     }
 
-    // Convert histogram data to a ColorImage
-    fn data_2_image(&self) -> egui::ColorImage {
-        let width = ((self.range.x.max - self.range.x.min) / self.bins.x_width) as usize; // number of pixels in x direction
-        let height = ((self.range.y.max - self.range.y.min) / self.bins.y_width) as usize; // number of pixels in y direction
+    // Snap the dragged cut vertices and projection lines to the closest bin edges
+    fn snap_to_bin_edges(&mut self) {
+        self.plot_settings.snapped_position = None;
 
-        // The pixels, row by row, from top to bottom. Each pixel is a Color32.
-        let mut pixels = Vec::with_capacity(width * height);
+        if !self.plot_settings.snap_to_bins {
+            return;
+        }
 
-        let colormap_options = self.plot_settings.colormap_options;
+        let snap = |value: f64, min: f64, max: f64, width: f64| -> f64 {
+            (min + ((value - min) / width).round() * width).clamp(min, max)
+        };
+
+        let (x_min, x_max, x_width) = (self.range.x.min, self.range.x.max, self.bins.x_width);
+        let (y_min, y_max, y_width) = (self.range.y.min, self.range.y.max, self.bins.y_width);
 
-        for y in 0..height {
-            for x in 0..width {
-                let count = self
-                    .bins
-                    .counts
-                    .get(&(x, height - y - 1))
-                    .cloned()
-                    .unwrap_or(0);
-                let color = self.plot_settings.colormap.color(
-                    count,
-                    self.bins.min_count,
-                    self.bins.max_count,
-                    colormap_options,
-                );
-                pixels.push(color);
+        for cut in &mut self.plot_settings.cuts.cuts {
+            if let Some(vertex) = cut.polygon.dragged_vertex_mut() {
+                vertex[0] = snap(vertex[0], x_min, x_max, x_width);
+                vertex[1] = snap(vertex[1], y_min, y_max, y_width);
+                self.plot_settings.snapped_position =
+                    Some((*vertex, format!("({:.2}, {:.2})", vertex[0], vertex[1])));
             }
         }
 
-        // Create the ColorImage with the specified width and height and pixel data
-        egui::ColorImage {
-            size: [width, height],
-            pixels,
+        let projections = &mut self.plot_settings.projections;
+        let cursor_y = self
+            .plot_settings
+            .cursor_position
+            .map_or(y_min, |position| position.y);
+        let cursor_x = self
+            .plot_settings
+            .cursor_position
+            .map_or(x_min, |position| position.x);
+
+        for line in [
+            &mut projections.y_projection_line_1,
+            &mut projections.y_projection_line_2,
+        ] {
+            if projections.add_y_projection && line.is_dragging {
+                line.x_value = snap(line.x_value, x_min, x_max, x_width);
+                self.plot_settings.snapped_position =
+                    Some(([line.x_value, cursor_y], format!("x = {:.2}", line.x_value)));
+            }
         }
+
+        for line in [
+            &mut projections.x_projection_line_1,
+            &mut projections.x_projection_line_2,
+        ] {
+            if projections.add_x_projection && line.is_dragging {
+                line.y_value = snap(line.y_value, y_min, y_max, y_width);
+                self.plot_settings.snapped_position =
+                    Some(([cursor_x, line.y_value], format!("y = {:.2}", line.y_value)));
+            }
+        }
+    }
+
+    // Build the image on a worker thread from a copy of the bins, the current texture stays on
+    // screen until the new one is swapped in by poll_image_job. Only called with no job running.
+    fn start_image_job(&mut self) {
+        let width = ((self.range.x.max - self.range.x.min) / self.bins.x_width) as usize; // number of pixels in x direction
+        let height = ((self.range.y.max - self.range.y.min) / self.bins.y_width) as usize; // number of pixels in y direction
+
+        let bins = self.bins.clone();
+        let colormap = self.plot_settings.colormap.clone();
+        let colormap_options = self.plot_settings.colormap_options;
+
+        self.image_job = ImageJob(Some(std::thread::spawn(move || {
+            bins.color_image(width, height, colormap, colormap_options)
+        })));
     }
 
-    // Recalculate the image and replace the existing texture
-    fn calculate_image(&mut self, ui: &mut egui::Ui) {
+    // Replace the texture once the worker has finished the image
+    fn poll_image_job(&mut self, ui: &mut egui::Ui) {
+        let Some(color_image) = self.image_job.take_finished() else {
+            return;
+        };
+
         self.image.texture = None;
-        let color_image = self.data_2_image();
         self.image.get_texture(ui, color_image);
     }
 
@@ -215,31 +463,59 @@ impl Histogram2D {
         // add the progress bar if it's being tracked
         self.plot_settings.progress_ui(ui);
 
-        // Recalculate the image if the settings have changed, like the colormap
-        if self.plot_settings.recalculate_image {
-            self.calculate_image(ui);
+        // Recalculate the image if the settings have changed, like the colormap. Changes made
+        // while a job runs stay flagged and start one more job once it is done.
+        self.poll_image_job(ui);
+        if !self.image_job.is_running()
+            && (self.plot_settings.recalculate_image || self.image.texture.is_none())
+        {
+            self.start_image_job();
             self.plot_settings.recalculate_image = false;
         }
+        if self.image_job.is_running() {
+            // keep polling, the thread can only be joined after it has returned
+            ui.ctx().request_repaint();
+        }
 
         let mut plot = egui_plot::Plot::new(self.name.clone());
         plot = self.plot_settings.egui_settings.apply_to_plot(plot);
-
-        if self.image.texture.is_none() {
-            self.calculate_image(ui);
+        plot = self.plot_settings.labels.apply_to_plot(plot);
+        if let Some(aspect) = self.plot_settings.view_aspect {
+            plot = plot.view_aspect(aspect);
         }
 
         self.check_projections();
         self.plot_settings.projections.show(ui);
+        self.plot_settings.labels.title_ui(ui);
 
-        let plot_response = plot.show(ui, |plot_ui| {
-            self.draw(plot_ui);
-        });
+        let colorbar_width = self.colorbar_width();
+        if colorbar_width > 0.0 {
+            plot = plot.width((ui.available_width() - colorbar_width).max(0.0));
+        }
+
+        let plot_response = ui
+            .with_layout(egui::Layout::left_to_right(egui::Align::Min), |ui| {
+                let plot_response = plot.show(ui, |plot_ui| {
+                    self.draw(plot_ui);
+                });
+
+                if colorbar_width > 0.0 {
+                    self.draw_colorbar(ui, plot_response.response.rect.height());
+                }
+
+                plot_response
+            })
+            .inner;
 
         plot_response.response.context_menu(|ui| {
             self.context_menu(ui);
         });
 
         self.plot_settings.interactive_response(&plot_response);
+        self.snap_to_bin_edges();
+        self.plot_settings
+            .export
+            .update(ui, plot_response.response.rect);
 
         self.keybinds(ui);
     }
@@ -251,9 +527,127 @@ pub struct Bins {
     pub x_width: f64,
     pub y: usize,
     pub y_width: f64,
-    pub counts: FnvHashMap<(usize, usize), u64>, // uses a hash map to store the histogram data (zero overhead for empty bins)
+    // The bins are sparse for any size: only filled bins are stored, so a mostly empty 4096x4096
+    // histogram never allocates its 16M cells. The image is the only dense buffer.
+    pub counts: FnvHashMap<(usize, usize), u64>, // every filled bin, the weights and sumw2 use the same keys
     pub min_count: u64,
     pub max_count: u64,
+    #[serde(default)]
+    pub weights: FnvHashMap<(usize, usize), f64>, // weighted counts, empty unless filled with weights
+    #[serde(default)]
+    pub sumw2: FnvHashMap<(usize, usize), f64>, // summed squared weights, empty unless filled with weights
+    #[serde(default)]
+    pub average_weights: bool, // the image shows the mean weight per entry instead of the summed weights
+}
+
+impl Bins {
+    pub fn is_weighted(&self) -> bool {
+        !self.weights.is_empty()
+    }
+
+    // Start the weighted counts from the unweighted ones so mixed fills add up
+    fn init_weights(&mut self) {
+        if self.weights.is_empty() {
+            self.weights = self
+                .counts
+                .iter()
+                .map(|(&index, &count)| (index, count as f64))
+                .collect();
+        }
+        if self.sumw2.is_empty() {
+            self.sumw2.clone_from(&self.weights);
+        }
+    }
+
+    // The weighted count of a bin, or the number of entries if not filled with weights
+    pub fn count(&self, index: &(usize, usize)) -> f64 {
+        if self.is_weighted() {
+            self.weights.get(index).copied().unwrap_or(0.0)
+        } else {
+            self.counts.get(index).copied().unwrap_or(0) as f64
+        }
+    }
+
+    // The summed squared weights of a bin, the number of entries for unweighted fills
+    pub fn sumw2(&self, index: &(usize, usize)) -> f64 {
+        if self.sumw2.is_empty() {
+            self.count(index)
+        } else {
+            self.sumw2.get(index).copied().unwrap_or(0.0)
+        }
+    }
+
+    pub fn image_count(&self, index: &(usize, usize)) -> u64 {
+        if self.is_weighted() {
+            let mut value = self.count(index);
+            if self.average_weights {
+                let entries = self.counts.get(index).copied().unwrap_or(0);
+                value = if entries > 0 {
+                    value / entries as f64
+                } else {
+                    0.0
+                };
+            }
+            value.round().max(0.0) as u64
+        } else {
+            self.counts.get(index).copied().unwrap_or(0)
+        }
+    }
+
+    // Add the bins of another histogram with the same number of bins
+    fn merge(&mut self, other: &Bins) {
+        if self.is_weighted() || other.is_weighted() {
+            self.init_weights();
+            for index in other.counts.keys() {
+                *self.weights.entry(*index).or_insert(0.0) += other.count(index);
+                *self.sumw2.entry(*index).or_insert(0.0) += other.sumw2(index);
+            }
+        }
+
+        for (index, count) in &other.counts {
+            *self.counts.entry(*index).or_insert(0) += count;
+        }
+    }
+
+    // The colormap limits from the values shown in the image
+    pub fn update_min_max(&mut self) {
+        let image_counts: Vec<u64> = self
+            .counts
+            .keys()
+            .map(|index| self.image_count(index))
+            .collect();
+        self.min_count = image_counts.iter().copied().min().unwrap_or(u64::MAX);
+        self.max_count = image_counts.iter().copied().max().unwrap_or(u64::MIN);
+    }
+
+    // Convert the bins to a ColorImage, runs on the image worker thread
+    pub fn color_image(
+        &self,
+        width: usize,
+        height: usize,
+        colormap: ColorMap,
+        colormap_options: ColormapOptions,
+    ) -> egui::ColorImage {
+        let color =
+            |count: u64| colormap.color(count, self.min_count, self.max_count, colormap_options);
+
+        // The pixels, row by row, from top to bottom. Each pixel is a Color32.
+        // Start from the empty bin color and only visit the filled bins, so a sparse 4096x4096
+        // histogram costs its filled bins instead of 16M hash lookups.
+        let mut pixels = vec![color(0); width * height];
+
+        for &(x, y) in self.counts.keys() {
+            if x < width && y < height {
+                pixels[(height - y - 1) * width + x] = color(self.image_count(&(x, y)));
+            }
+        }
+
+        // Create the ColorImage with the specified width and height and pixel data
+        egui::ColorImage {
+            size: [width, height],
+            pixels,
+        }
+    }
 }
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
@@ -267,3 +661,55 @@ pub struct Range {
     pub x: Value,
     pub y: Value,
 }
+
+impl RangeEdges {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Range Edges:");
+            ui.radio_value(self, RangeEdges::Exclusive, "Exclusive")
+                .on_hover_text("Drop values on the range edges and cut boundaries");
+            ui.radio_value(self, RangeEdges::Inclusive, "Inclusive")
+                .on_hover_text("Keep values on the range edges and cut boundaries");
+        })
+        .response
+        .on_hover_text("Takes effect the next time the histogram is filled");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one event on each edge of a 10x10 histogram over 0..10 on both axes
+    fn fill_edges(range_edges: RangeEdges) -> Histogram2D {
+        let mut hist = Histogram2D::new("edges", (10, 10), ((0.0, 10.0), (0.0, 10.0)));
+        hist.range_edges = range_edges;
+
+        let events = [(0.0, 5.0), (10.0, 5.0), (5.0, 0.0), (5.0, 10.0)];
+        for (i, &(x, y)) in events.iter().enumerate() {
+            hist.fill(x, y, i, events.len());
+        }
+        hist
+    }
+
+    #[test]
+    fn exclusive_edges_count_as_flow() {
+        let hist = fill_edges(RangeEdges::Exclusive);
+
+        assert!(hist.bins.counts.is_empty());
+        assert_eq!(hist.overflow, (1, 1));
+        assert_eq!(hist.underflow, (1, 1));
+    }
+
+    #[test]
+    fn inclusive_edges_fill_the_first_and_last_bins() {
+        let hist = fill_edges(RangeEdges::Inclusive);
+
+        for index in [(0, 5), (9, 5), (5, 0), (5, 9)] {
+            assert_eq!(hist.bins.counts.get(&index), Some(&1), "bin {:?}", index);
+        }
+        assert_eq!(hist.bins.counts.len(), 4);
+        assert_eq!(hist.overflow, (0, 0));
+        assert_eq!(hist.underflow, (0, 0));
+    }
+}
@@ -4,10 +4,30 @@ impl Histogram2D {
     // Handles the interactive elements of the histogram
     pub fn keybinds(&mut self, ui: &mut egui::Ui) {
         if let Some(_cursor_position) = self.plot_settings.cursor_position {
-            if ui.input(|i| i.key_pressed(egui::Key::I)) {
+            if ui.input(|i| i.key_pressed(egui::Key::I) || i.key_pressed(egui::Key::S)) {
                 self.plot_settings.stats_info = !self.plot_settings.stats_info;
             }
 
+            if ui.input(|i| i.key_pressed(egui::Key::L)) {
+                self.plot_settings.colormap_options.toggle_log_norm();
+                self.plot_settings.recalculate_image = true;
+            }
+
+            if ui.input(|i| i.key_pressed(egui::Key::R)) {
+                self.toggle_rebin();
+            }
+
+            // start an x projection, or stop both if one is already showing
+            if ui.input(|i| i.key_pressed(egui::Key::P)) {
+                let projections = &mut self.plot_settings.projections;
+                if projections.add_x_projection || projections.add_y_projection {
+                    projections.add_x_projection = false;
+                    projections.add_y_projection = false;
+                } else {
+                    projections.add_x_projection = true;
+                }
+            }
+
             if ui.input(|i| i.key_pressed(egui::Key::C)) {
                 self.plot_settings.cuts.new_cut();
             }
@@ -23,4 +43,21 @@ impl Histogram2D {
             }
         }
     }
+
+    // Switch between the original bins and a rebin factor of 2 on each axis that allows it
+    fn toggle_rebin(&mut self) {
+        if self.plot_settings.rebin_x_factor == 1 && self.plot_settings.rebin_y_factor == 1 {
+            if self.possible_x_rebin_factors().contains(&2) {
+                self.plot_settings.rebin_x_factor = 2;
+            }
+            if self.possible_y_rebin_factors().contains(&2) {
+                self.plot_settings.rebin_y_factor = 2;
+            }
+        } else {
+            self.plot_settings.rebin_x_factor = 1;
+            self.plot_settings.rebin_y_factor = 1;
+        }
+
+        self.rebin();
+    }
 }
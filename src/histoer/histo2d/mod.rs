@@ -1,7 +1,9 @@
+pub mod colorbar;
 pub mod colormaps;
 pub mod context_menu;
 pub mod histogram2d;
 pub mod keybinds;
+pub mod npy;
 pub mod plot_settings;
 pub mod projections;
 pub mod rebinning;
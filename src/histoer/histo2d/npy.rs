@@ -0,0 +1,95 @@
+use super::histogram2d::Histogram2D;
+use crate::egui_plot_stuff::egui_plot_export::sanitize_file_name;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl Histogram2D {
+    // Write the counts as a (ny, nx) little-endian u64 .npy array (version 1.0), or as f64 with
+    // the summed weights if the histogram was filled with weights.
+    // The current bins are written, so the rebin factors are already applied.
+    // Row 0 is the lowest y bin and column 0 is the lowest x bin.
+    pub fn export_npy(&self, path: &Path) -> std::io::Result<()> {
+        let (nx, ny) = (self.bins.x, self.bins.y);
+        let weighted = self.bins.is_weighted();
+
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+            if weighted { "<f8" } else { "<u8" },
+            ny,
+            nx
+        );
+
+        // magic (6) + version (2) + header length (2) + header must be a multiple of 64, ending in a newline
+        let unpadded_length = 10 + header.len() + 1;
+        let padding = (64 - unpadded_length % 64) % 64;
+        header.push_str(&" ".repeat(padding));
+        header.push('\n');
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(b"\x93NUMPY")?;
+        writer.write_all(&[1, 0])?;
+        writer.write_all(&(header.len() as u16).to_le_bytes())?;
+        writer.write_all(header.as_bytes())?;
+
+        for y in 0..ny {
+            for x in 0..nx {
+                if weighted {
+                    writer.write_all(&self.bins.count(&(x, y)).to_le_bytes())?;
+                } else {
+                    let count = self.bins.counts.get(&(x, y)).copied().unwrap_or(0);
+                    writer.write_all(&count.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()?;
+
+        // sidecar with the axes so the bin edges can be reconstructed
+        let metadata = serde_json::json!({
+            "name": self.name,
+            "shape": [ny, nx],
+            "x_range": [self.range.x.min, self.range.x.max],
+            "y_range": [self.range.y.min, self.range.y.max],
+            "x_bins": nx,
+            "y_bins": ny,
+            "x_bin_width": self.bins.x_width,
+            "y_bin_width": self.bins.y_width,
+            "rebin_x_factor": self.plot_settings.rebin_x_factor,
+            "rebin_y_factor": self.plot_settings.rebin_y_factor,
+            "weighted": weighted,
+            "overflow": [self.overflow.0, self.overflow.1],
+            "underflow": [self.underflow.0, self.underflow.1],
+            "fingerprint": self.fingerprint(),
+        });
+
+        let json = serde_json::to_string_pretty(&metadata)?;
+        std::fs::write(path.with_extension("json"), json)?;
+
+        log::info!("Exported '{}' to {:?}", self.name, path);
+
+        Ok(())
+    }
+
+    pub fn export_npy_button(&self, ui: &mut egui::Ui) {
+        if ui
+            .button("Export .npy")
+            .on_hover_text(
+                "Save the bin counts as a numpy array with a JSON file describing the axes",
+            )
+            .clicked()
+        {
+            if let Some(path) = rfd::FileDialog::new()
+                .set_file_name(format!("{}.npy", sanitize_file_name(&self.name)))
+                .add_filter("NumPy", &["npy"])
+                .save_file()
+            {
+                if let Err(e) = self.export_npy(&path) {
+                    log::error!("Failed to export '{}' to {:?}: {:?}", self.name, path, e);
+                }
+            }
+
+            ui.close_menu();
+        }
+    }
+}
@@ -1,9 +1,13 @@
 use crate::cutter::cuts::HistogramCuts;
+use crate::egui_plot_stuff::egui_plot_export::EguiPlotExport;
+use crate::egui_plot_stuff::egui_plot_labels::PlotLabels;
 use crate::egui_plot_stuff::egui_plot_settings::EguiPlotSettings;
 
-use super::colormaps::{ColorMap, ColormapOptions};
+use super::colormaps::{ColorMap, ColormapOptions, CustomColormap};
 use super::projections::Projections;
 
+use std::sync::Arc;
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlotSettings {
     #[serde(skip)]
@@ -16,8 +20,23 @@ pub struct PlotSettings {
     pub projections: Projections,
     pub rebin_x_factor: usize,
     pub rebin_y_factor: usize,
+    #[serde(default)]
+    pub snap_to_bins: bool,
+    #[serde(default)]
+    pub labels: PlotLabels,
+    #[serde(default)]
+    pub show_colorbar: bool,
+    #[serde(default)]
+    pub view_aspect: Option<f32>, // width / height of the plot, set from the grid layout
+    #[serde(skip)]
+    pub snapped_position: Option<([f64; 2], String)>, // position and label of the last snapped drag
     #[serde(skip)]
     pub recalculate_image: bool,
+    #[serde(skip)]
+    pub custom_colormaps: Arc<Vec<CustomColormap>>, // shared from the histogrammer for the colormap menu
+
+    #[serde(skip)]
+    pub export: EguiPlotExport,
 
     #[serde(skip)] // Skip serialization for progress
     pub progress: Option<f32>, // Optional progress tracking
@@ -34,7 +53,14 @@ impl Default for PlotSettings {
             projections: Projections::new(),
             rebin_x_factor: 1,
             rebin_y_factor: 1,
+            snap_to_bins: false,
+            labels: PlotLabels::default(),
+            show_colorbar: false,
+            view_aspect: None,
+            snapped_position: None,
             recalculate_image: false,
+            custom_colormaps: Arc::default(),
+            export: EguiPlotExport::default(),
             progress: None,
         }
     }
@@ -45,13 +71,20 @@ impl PlotSettings {
             self.colormap_options
                 .ui(ui, &mut self.recalculate_image, max_z_range);
             ui.separator();
-            self.colormap.color_maps_ui(ui, &mut self.recalculate_image);
+            self.colormap
+                .color_maps_ui(ui, &mut self.recalculate_image, &self.custom_colormaps);
         });
 
         ui.separator();
 
         ui.checkbox(&mut self.stats_info, "Show Statitics");
+        ui.checkbox(&mut self.show_colorbar, "Show Colorbar")
+            .on_hover_text("Show the counts of the colormap beside the plot");
+        ui.checkbox(&mut self.snap_to_bins, "Snap to Bin Edges")
+            .on_hover_text("Snap dragged cut vertices and projection lines to the bin edges");
         self.egui_settings.menu_button(ui);
+        self.labels.menu_button(ui);
+        self.keybinds_ui(ui);
 
         ui.separator();
 
@@ -72,6 +105,17 @@ impl PlotSettings {
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
         self.cuts.draw(plot_ui);
         self.projections.draw(plot_ui);
+
+        // show the snapped coordinate next to the item being dragged
+        if let Some((position, label)) = &self.snapped_position {
+            plot_ui.text(
+                egui_plot::Text::new(
+                    egui_plot::PlotPoint::new(position[0], position[1]),
+                    label.clone(),
+                )
+                .anchor(egui::Align2::LEFT_BOTTOM),
+            );
+        }
     }
 
     pub fn interactive_response(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
@@ -90,5 +134,24 @@ impl PlotSettings {
         }
     }
 
-    // pub fn keybinds(&mut self, ui: &mut egui::Ui) {}
+    pub fn keybinds_ui(&self, ui: &mut egui::Ui) {
+        ui.menu_button("Keybind Help", |ui| {
+            ui.heading("Keybinds");
+            ui.label("Keys only apply while the cursor is over the histogram");
+            ui.separator();
+            ui.label("I or S: Toggle Stats");
+            ui.label("L: Toggle Log Z");
+            ui.label("R: Toggle Rebin")
+                .on_hover_text("Switch between the original bins and a rebin factor of 2");
+            ui.separator();
+            ui.label("P: Start/Stop Projection")
+                .on_hover_text("Start an X projection, or remove the projections if any are shown");
+            ui.label("X: Toggle X Projection");
+            ui.label("Y: Toggle Y Projection");
+            ui.separator();
+            ui.label("C: New Cut");
+            ui.label("Ctrl + Z: Undo Cut Edit");
+            ui.label("Ctrl + Y: Redo Cut Edit");
+        });
+    }
 }
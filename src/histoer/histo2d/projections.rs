@@ -1,4 +1,6 @@
+use crate::cutter::cuts::Cut;
 use crate::egui_plot_stuff::egui_horizontal_line::EguiHorizontalLine;
+use crate::egui_plot_stuff::egui_polygon::EguiPolygon;
 use crate::egui_plot_stuff::egui_vertical_line::EguiVerticalLine;
 use crate::histoer::histo1d::histogram1d::Histogram;
 
@@ -33,6 +35,183 @@ impl Histogram2D {
         x_bins
     }
 
+    // Counts projected onto the line from start to end, the distance along the line is the x axis.
+    // Only bins whose centers are within half the width of the line are summed.
+    pub fn rotated_projection(&self, start: [f64; 2], end: [f64; 2], width: f64) -> Histogram {
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let length = dx.hypot(dy);
+        let (ux, uy) = if length > 0.0 {
+            (dx / length, dy / length)
+        } else {
+            (1.0, 0.0)
+        };
+
+        // about one 2D bin per projected bin along the line
+        let step = (self.bins.x_width * ux).hypot(self.bins.y_width * uy);
+        let number_of_bins = ((length / step).ceil() as usize).max(1);
+        let mut counts = vec![0; number_of_bins];
+
+        for ((x_index, y_index), &count) in &self.bins.counts {
+            let x_center = self.range.x.min + (*x_index as f64 + 0.5) * self.bins.x_width;
+            let y_center = self.range.y.min + (*y_index as f64 + 0.5) * self.bins.y_width;
+            let (px, py) = (x_center - start[0], y_center - start[1]);
+
+            let along = px * ux + py * uy;
+            let across = (py * ux - px * uy).abs();
+            if along >= 0.0 && along < length && across <= width / 2.0 {
+                let index = ((along / length) * number_of_bins as f64) as usize;
+                counts[index.min(number_of_bins - 1)] += count;
+            }
+        }
+
+        let name = format!(
+            "Rotated Projection of {}: ({:.2}, {:.2})-({:.2}, {:.2}) width={:.2}",
+            self.name, start[0], start[1], end[0], end[1], width
+        );
+        let mut histogram = Histogram::new(&name, number_of_bins, (0.0, length.max(f64::EPSILON)));
+        histogram.core.bins.clone_from(&counts);
+        histogram.bins = counts;
+        histogram.update_line_points();
+
+        histogram
+    }
+
+    // Project the bins whose centers are inside the cut onto the x (or y) axis
+    pub fn projection_inside_cut(&self, cut: &Cut, project_x: bool) -> Histogram {
+        let (axis, number_of_bins, range) = if project_x {
+            ("X", self.bins.x, (self.range.x.min, self.range.x.max))
+        } else {
+            ("Y", self.bins.y, (self.range.y.min, self.range.y.max))
+        };
+
+        let mut counts = vec![0; number_of_bins];
+        let inside = cut.inside_test();
+
+        for ((x_index, y_index), &count) in &self.bins.counts {
+            let x_center = self.range.x.min + (*x_index as f64 + 0.5) * self.bins.x_width;
+            let y_center = self.range.y.min + (*y_index as f64 + 0.5) * self.bins.y_width;
+
+            if inside(x_center, y_center) {
+                let index = if project_x { *x_index } else { *y_index };
+                if index < counts.len() {
+                    counts[index] += count;
+                }
+            }
+        }
+
+        let name = format!(
+            "{}-Projection of {} inside {}",
+            axis, self.name, cut.polygon.name
+        );
+        let mut histogram = Histogram::new(&name, number_of_bins, range);
+        histogram.core.bins.clone_from(&counts);
+        histogram.bins = counts;
+        histogram.update_line_points();
+
+        histogram
+    }
+
+    // First and last bin of the band of `width` bins centered on the bin at `position`.
+    // The band is along y for an x slice and along x for a y slice.
+    fn slice_bins(&self, position: f64, width: usize, slice_x: bool) -> (usize, usize) {
+        let (min, bin_width, number_of_bins) = if slice_x {
+            (self.range.y.min, self.bins.y_width, self.bins.y)
+        } else {
+            (self.range.x.min, self.bins.x_width, self.bins.x)
+        };
+        let last_bin = number_of_bins.saturating_sub(1);
+
+        let center = ((position - min) / bin_width).floor().max(0.0) as usize;
+        let first = center
+            .min(last_bin)
+            .saturating_sub(width.max(1).saturating_sub(1) / 2);
+        let last = (first + width.max(1) - 1).min(last_bin);
+
+        (first, last)
+    }
+
+    fn slice_name(&self, first: usize, last: usize, slice_x: bool) -> String {
+        let (axis, other_axis, min, bin_width) = if slice_x {
+            ("X", "y", self.range.y.min, self.bins.y_width)
+        } else {
+            ("Y", "x", self.range.x.min, self.bins.x_width)
+        };
+        format!(
+            "{}-Slice of {}: {}={:.2}-{:.2}",
+            axis,
+            self.name,
+            other_axis,
+            min + first as f64 * bin_width,
+            min + (last + 1) as f64 * bin_width
+        )
+    }
+
+    // Counts along x (or y) in the bins first..=last of the other axis
+    fn slice_between_bins(&self, first: usize, last: usize, slice_x: bool) -> Histogram {
+        let (number_of_bins, range) = if slice_x {
+            (self.bins.x, (self.range.x.min, self.range.x.max))
+        } else {
+            (self.bins.y, (self.range.y.min, self.range.y.max))
+        };
+
+        let mut counts = vec![0; number_of_bins];
+        for ((x_index, y_index), &count) in &self.bins.counts {
+            let (index, band_index) = if slice_x {
+                (*x_index, *y_index)
+            } else {
+                (*y_index, *x_index)
+            };
+            if band_index >= first && band_index <= last && index < counts.len() {
+                counts[index] += count;
+            }
+        }
+
+        let name = self.slice_name(first, last, slice_x);
+        let mut histogram = Histogram::new(&name, number_of_bins, range);
+        histogram.core.bins.clone_from(&counts);
+        histogram.bins = counts;
+        histogram.update_line_points();
+
+        histogram
+    }
+
+    // Slice of `width` bins centered on the bin at `position`
+    pub fn slice(&self, position: f64, width: usize, slice_x: bool) -> Histogram {
+        let (first, last) = self.slice_bins(position, width, slice_x);
+        self.slice_between_bins(first, last, slice_x)
+    }
+
+    pub fn cut_projection_menu(&mut self, ui: &mut egui::Ui) {
+        if self.plot_settings.cuts.cuts.is_empty() {
+            return;
+        }
+
+        ui.menu_button("Project inside cut", |ui| {
+            let mut projections = Vec::new();
+
+            for cut in &self.plot_settings.cuts.cuts {
+                ui.horizontal(|ui| {
+                    ui.label(&cut.polygon.name);
+
+                    if ui.button("X").clicked() {
+                        projections.push(self.projection_inside_cut(cut, true));
+                    }
+
+                    if ui.button("Y").clicked() {
+                        projections.push(self.projection_inside_cut(cut, false));
+                    }
+                });
+            }
+
+            if !projections.is_empty() {
+                self.pending_cut_projections.extend(projections);
+                ui.close_menu();
+            }
+        })
+        .response
+        .on_hover_text("Project the counts inside a cut to a new histogram in the Other grid");
+    }
+
     pub fn check_projections(&mut self) {
         // check to see if the x/y values are the same as the current projection else add a new projection based off the naming scheme
         // then you dont have to recalculate the bins if the projection is already calculated
@@ -135,6 +314,122 @@ impl Histogram2D {
                 self.plot_settings.projections.x_projection_line_2.y_value = self.range.y.max;
             }
         }
+
+        if self.plot_settings.projections.add_rotated_projection {
+            let projections = &mut self.plot_settings.projections;
+
+            // start along the diagonal of the histogram
+            if projections.rotated_projection_line.vertices.len() != 2 {
+                let start = [self.range.x.min, self.range.y.min];
+                let end = [self.range.x.max, self.range.y.max];
+                projections.rotated_projection_line.vertices = vec![start, end];
+                projections.rotated_projection_width =
+                    (end[0] - start[0]).hypot(end[1] - start[1]) / 10.0;
+            }
+
+            let [start, end] = [
+                projections.rotated_projection_line.vertices[0],
+                projections.rotated_projection_line.vertices[1],
+            ];
+            let width = projections.rotated_projection_width;
+            let name = format!(
+                "Rotated Projection of {}: ({:.2}, {:.2})-({:.2}, {:.2}) width={:.2}",
+                self.name, start[0], start[1], end[0], end[1], width
+            );
+
+            // only recalculate when the line or width changed
+            if projections
+                .rotated_projection
+                .as_ref()
+                .map_or(true, |projection| projection.name != name)
+            {
+                let histogram = self.rotated_projection(start, end, width);
+                let projections = &mut self.plot_settings.projections;
+
+                match &mut projections.rotated_projection {
+                    // keep the window's settings when the number of bins is the same
+                    Some(projection) if projection.bins.len() == histogram.bins.len() => {
+                        projection.name = histogram.name;
+                        projection.range = histogram.range;
+                        projection.bin_width = histogram.bin_width;
+                        projection.bins = histogram.bins;
+                        projection.core = histogram.core;
+                    }
+                    _ => projections.rotated_projection = Some(histogram),
+                }
+            }
+        }
+
+        if self.plot_settings.projections.add_slice {
+            self.check_slice();
+        }
+    }
+
+    fn check_slice(&mut self) {
+        let slice_x = !self.plot_settings.projections.slice_along_y;
+        let (min, max) = if slice_x {
+            (self.range.y.min, self.range.y.max)
+        } else {
+            (self.range.x.min, self.range.x.max)
+        };
+
+        let projections = &mut self.plot_settings.projections;
+        let position = if slice_x {
+            &mut projections.slice_x_line.y_value
+        } else {
+            &mut projections.slice_y_line.x_value
+        };
+        // start in the middle of the histogram
+        if *position < min || *position > max {
+            *position = (min + max) / 2.0;
+        }
+        let position = *position;
+        let width = projections.slice_width.max(1);
+
+        let (first, last) = self.slice_bins(position, width, slice_x);
+        let name = self.slice_name(first, last, slice_x);
+
+        let bin_width = if slice_x {
+            self.bins.y_width
+        } else {
+            self.bins.x_width
+        };
+        self.plot_settings.projections.slice_band = Some((
+            min + first as f64 * bin_width,
+            min + (last + 1) as f64 * bin_width,
+        ));
+
+        let projections = &self.plot_settings.projections;
+        // only recalculate when the band or axis changed
+        if projections
+            .slice
+            .as_ref()
+            .map_or(true, |slice| slice.name != name)
+        {
+            let histogram = self.slice_between_bins(first, last, slice_x);
+            let projections = &mut self.plot_settings.projections;
+
+            match &mut projections.slice {
+                // keep the window's settings when the axis is the same
+                Some(slice) if slice.bins.len() == histogram.bins.len() => {
+                    slice.name = histogram.name;
+                    slice.range = histogram.range;
+                    slice.bin_width = histogram.bin_width;
+                    slice.bins = histogram.bins;
+                    slice.core = histogram.core;
+                }
+                _ => projections.slice = Some(histogram),
+            }
+        }
+
+        let projections = &mut self.plot_settings.projections;
+        if std::mem::take(&mut projections.add_slice_to_histogrammer) {
+            if let Some(slice) = &projections.slice {
+                let mut histogram = slice.clone();
+                histogram.update_line_points();
+                self.pending_cut_projections.push(histogram);
+            }
+        }
     }
 }
 
@@ -149,6 +444,59 @@ pub struct Projections {
     pub x_projection: Option<Histogram>,
     pub x_projection_line_1: EguiHorizontalLine,
     pub x_projection_line_2: EguiHorizontalLine,
+
+    #[serde(default)]
+    pub add_rotated_projection: bool,
+    #[serde(skip)]
+    pub rotated_projection: Option<Histogram>,
+    #[serde(default = "default_rotated_projection_line")]
+    pub rotated_projection_line: EguiPolygon, // the two ends of the line are dragged
+    #[serde(default)]
+    pub rotated_projection_width: f64, // full width of the band summed across the line
+
+    #[serde(default)]
+    pub add_slice: bool,
+    #[serde(default)]
+    pub slice_along_y: bool, // false: x counts at a y position, true: y counts at an x position
+    #[serde(default = "default_slice_x_line")]
+    pub slice_x_line: EguiHorizontalLine,
+    #[serde(default = "default_slice_y_line")]
+    pub slice_y_line: EguiVerticalLine,
+    #[serde(default = "default_slice_width")]
+    pub slice_width: usize, // number of bins summed across the slice
+    #[serde(skip)]
+    pub slice: Option<Histogram>,
+    #[serde(skip)]
+    pub slice_band: Option<(f64, f64)>, // edges of the bins summed in the current slice
+    #[serde(skip)]
+    pub add_slice_to_histogrammer: bool,
+}
+
+fn default_slice_x_line() -> EguiHorizontalLine {
+    EguiHorizontalLine {
+        name: "X Slice Line".to_string(),
+        ..EguiHorizontalLine::default()
+    }
+}
+
+fn default_slice_y_line() -> EguiVerticalLine {
+    EguiVerticalLine {
+        name: "Y Slice Line".to_string(),
+        ..EguiVerticalLine::default()
+    }
+}
+
+fn default_slice_width() -> usize {
+    1
+}
+
+fn default_rotated_projection_line() -> EguiPolygon {
+    EguiPolygon {
+        name: "Rotated Projection Line".to_string(),
+        interactive_clicking: false,
+        interactive_dragging: true,
+        ..EguiPolygon::default()
+    }
 }
 impl Projections {
     pub fn new() -> Self {
@@ -174,6 +522,20 @@ impl Projections {
                 name: "X Projection Line 2".to_string(),
                 ..EguiHorizontalLine::default()
             },
+
+            add_rotated_projection: false,
+            rotated_projection: None,
+            rotated_projection_line: default_rotated_projection_line(),
+            rotated_projection_width: 0.0,
+
+            add_slice: false,
+            slice_along_y: false,
+            slice_x_line: default_slice_x_line(),
+            slice_y_line: default_slice_y_line(),
+            slice_width: default_slice_width(),
+            slice: None,
+            slice_band: None,
+            add_slice_to_histogrammer: false,
         }
     }
 
@@ -242,6 +604,100 @@ impl Projections {
         }
     }
 
+    fn show_rotated_projection(&mut self, ui: &mut egui::Ui) {
+        if self.add_rotated_projection {
+            if let Some(histogram) = &mut self.rotated_projection {
+                let name = histogram
+                    .name
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let ctx = ui.ctx().clone();
+                egui::Window::new(name).show(&ctx, |ui| {
+                    histogram.render(ui);
+                });
+            }
+        }
+    }
+
+    fn show_slice(&mut self, ui: &mut egui::Ui) {
+        if self.add_slice {
+            if let Some(histogram) = &mut self.slice {
+                let name = histogram
+                    .name
+                    .split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string();
+                let ctx = ui.ctx().clone();
+                egui::Window::new(name).show(&ctx, |ui| {
+                    if ui
+                        .button("Add to Histogrammer")
+                        .on_hover_text("Add a copy of the current slice to the Other grid")
+                        .clicked()
+                    {
+                        self.add_slice_to_histogrammer = true;
+                    }
+                    histogram.render(ui);
+                });
+            }
+        }
+    }
+
+    // Edges of the bins summed in the slice
+    fn draw_slice_band(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let Some((low, high)) = self.slice_band else {
+            return;
+        };
+
+        for edge in [low, high] {
+            if self.slice_along_y {
+                plot_ui.vline(
+                    egui_plot::VLine::new(edge)
+                        .color(self.slice_y_line.color)
+                        .style(egui_plot::LineStyle::dashed_dense()),
+                );
+            } else {
+                plot_ui.hline(
+                    egui_plot::HLine::new(edge)
+                        .color(self.slice_x_line.color)
+                        .style(egui_plot::LineStyle::dashed_dense()),
+                );
+            }
+        }
+    }
+
+    // The edges of the band that is summed, parallel to the projection line
+    fn draw_rotated_projection_band(&self, plot_ui: &mut egui_plot::PlotUi) {
+        let vertices = &self.rotated_projection_line.vertices;
+        if vertices.len() != 2 {
+            return;
+        }
+
+        let (start, end) = (vertices[0], vertices[1]);
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let length = dx.hypot(dy);
+        if length == 0.0 {
+            return;
+        }
+
+        let half_width = self.rotated_projection_width / 2.0;
+        let (nx, ny) = (-dy / length * half_width, dx / length * half_width);
+
+        for sign in [-1.0, 1.0] {
+            let edge = vec![
+                [start[0] + sign * nx, start[1] + sign * ny],
+                [end[0] + sign * nx, end[1] + sign * ny],
+            ];
+            plot_ui.line(
+                egui_plot::Line::new(edge)
+                    .color(self.rotated_projection_line.stroke.color)
+                    .style(egui_plot::LineStyle::dashed_dense()),
+            );
+        }
+    }
+
     pub fn is_dragging(&self) -> bool {
         if self.add_y_projection
             && (self.y_projection_line_1.is_dragging || self.y_projection_line_2.is_dragging)
@@ -255,12 +711,22 @@ impl Projections {
             return true;
         }
 
+        if self.add_rotated_projection && self.rotated_projection_line.is_dragging {
+            return true;
+        }
+
+        if self.add_slice && (self.slice_x_line.is_dragging || self.slice_y_line.is_dragging) {
+            return true;
+        }
+
         false
     }
 
     pub fn show(&mut self, ui: &mut egui::Ui) {
         self.show_y_projection(ui);
         self.show_x_projection(ui);
+        self.show_rotated_projection(ui);
+        self.show_slice(ui);
     }
 
     pub fn draw(&mut self, plot_ui: &mut egui_plot::PlotUi) {
@@ -273,6 +739,20 @@ impl Projections {
             self.x_projection_line_1.draw(plot_ui);
             self.x_projection_line_2.draw(plot_ui);
         }
+
+        if self.add_rotated_projection {
+            self.rotated_projection_line.draw(plot_ui);
+            self.draw_rotated_projection_band(plot_ui);
+        }
+
+        if self.add_slice {
+            if self.slice_along_y {
+                self.slice_y_line.draw(plot_ui);
+            } else {
+                self.slice_x_line.draw(plot_ui);
+            }
+            self.draw_slice_band(plot_ui);
+        }
     }
 
     pub fn interactive_dragging(&mut self, plot_response: &egui_plot::PlotResponse<()>) {
@@ -285,6 +765,19 @@ impl Projections {
             self.x_projection_line_1.interactive_dragging(plot_response);
             self.x_projection_line_2.interactive_dragging(plot_response);
         }
+
+        if self.add_rotated_projection {
+            self.rotated_projection_line
+                .handle_interactions(plot_response);
+        }
+
+        if self.add_slice {
+            if self.slice_along_y {
+                self.slice_y_line.interactive_dragging(plot_response);
+            } else {
+                self.slice_x_line.interactive_dragging(plot_response);
+            }
+        }
     }
 
     pub fn menu_button(&mut self, ui: &mut egui::Ui) {
@@ -323,5 +816,54 @@ impl Projections {
                 );
             });
         }
+
+        ui.checkbox(&mut self.add_rotated_projection, "Add Rotated Projection")
+            .on_hover_text("Project the counts onto a line at any angle\nDrag the ends of the line to place it, bins within the dashed band are summed");
+
+        if self.add_rotated_projection {
+            ui.add(
+                egui::DragValue::new(&mut self.rotated_projection_width)
+                    .speed(0.1)
+                    .range(0.0..=f64::INFINITY)
+                    .prefix("Width: "),
+            )
+            .on_hover_text("Full width of the band summed perpendicular to the line");
+        }
+
+        ui.checkbox(&mut self.add_slice, "Add Slice").on_hover_text(
+            "Counts in a band of bins at one position, updated live while dragging the line",
+        );
+
+        if self.add_slice {
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut self.slice_along_y, false, "X at Y");
+                ui.radio_value(&mut self.slice_along_y, true, "Y at X");
+            });
+
+            ui.horizontal(|ui| {
+                if self.slice_along_y {
+                    ui.add(
+                        egui::DragValue::new(&mut self.slice_y_line.x_value)
+                            .speed(1.0)
+                            .prefix("X: "),
+                    );
+                } else {
+                    ui.add(
+                        egui::DragValue::new(&mut self.slice_x_line.y_value)
+                            .speed(1.0)
+                            .prefix("Y: "),
+                    );
+                }
+
+                ui.add(
+                    egui::DragValue::new(&mut self.slice_width)
+                        .speed(0.1)
+                        .range(1..=usize::MAX)
+                        .prefix("Width: ")
+                        .suffix(" bins"),
+                )
+                .on_hover_text("Number of bins summed across the slice");
+            });
+        }
     }
 }
@@ -2,7 +2,91 @@ use super::histogram2d::{Bins, Histogram2D};
 
 use fnv::FnvHashMap;
 
+// Factors that divide the bins evenly, rebinning into a single bin is left out
+fn rebin_divisors(bins: usize) -> Vec<usize> {
+    (1..bins.max(2))
+        .filter(|factor| bins % factor == 0)
+        .collect()
+}
+
+// The divisor closest to the requested factor, the smaller one on a tie
+fn snap_rebin_factor(bins: usize, factor: usize) -> usize {
+    rebin_divisors(bins)
+        .into_iter()
+        .min_by_key(|&divisor| (divisor.abs_diff(factor), divisor))
+        .unwrap_or(1)
+}
+
+// "-" and "+" step to the neighbouring divisors, returns the new factor when one is clicked
+fn rebin_stepper(ui: &mut egui::Ui, axis: &str, factor: usize, bins: usize) -> Option<usize> {
+    let divisors = rebin_divisors(bins);
+    let smaller = divisors.iter().rev().find(|&&divisor| divisor < factor);
+    let larger = divisors.iter().find(|&&divisor| divisor > factor);
+    let mut new_factor = None;
+
+    ui.horizontal(|ui| {
+        ui.label(format!("{}:", axis));
+        if ui
+            .add_enabled(smaller.is_some(), egui::Button::new("-"))
+            .clicked()
+        {
+            new_factor = smaller.copied();
+        }
+        ui.label(format!("{}x", factor));
+        if ui
+            .add_enabled(larger.is_some(), egui::Button::new("+"))
+            .clicked()
+        {
+            new_factor = larger.copied();
+        }
+        ui.weak(format!("{} → {} bins", bins, bins.div_ceil(factor.max(1))));
+    });
+
+    new_factor
+}
+
 impl Histogram2D {
+    // The number of bins before rebinning
+    pub fn original_bins(&self) -> (usize, usize) {
+        let bins = self.backup_bins.as_ref().unwrap_or(&self.bins);
+        (bins.x, bins.y)
+    }
+
+    // Factors that don't divide the original bins are snapped to the nearest divisor
+    pub fn set_rebin_factors(&mut self, x_factor: usize, y_factor: usize) {
+        let (x_bins, y_bins) = self.original_bins();
+        self.plot_settings.rebin_x_factor = snap_rebin_factor(x_bins, x_factor);
+        self.plot_settings.rebin_y_factor = snap_rebin_factor(y_bins, y_factor);
+        self.rebin();
+    }
+
+    pub fn rebin_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Reset (1x1)").clicked() {
+                self.set_rebin_factors(1, 1);
+            }
+            if ui.button("2x2").clicked() {
+                self.set_rebin_factors(2, 2);
+            }
+            if ui.button("4x4").clicked() {
+                self.set_rebin_factors(4, 4);
+            }
+        });
+
+        let (x_bins, y_bins) = self.original_bins();
+        let (x_factor, y_factor) = (
+            self.plot_settings.rebin_x_factor,
+            self.plot_settings.rebin_y_factor,
+        );
+
+        if let Some(x_factor) = rebin_stepper(ui, "X", x_factor, x_bins) {
+            self.set_rebin_factors(x_factor, y_factor);
+        }
+        if let Some(y_factor) = rebin_stepper(ui, "Y", y_factor, y_bins) {
+            self.set_rebin_factors(x_factor, y_factor);
+        }
+    }
+
     // Compute the possible rebin factors based on the initial number of bins
     pub fn possible_x_rebin_factors(&self) -> Vec<usize> {
         let mut factors = vec![];
@@ -87,6 +171,9 @@ impl Histogram2D {
                 counts: FnvHashMap::default(),
                 min_count: u64::MAX,
                 max_count: u64::MIN,
+                weights: FnvHashMap::default(),
+                sumw2: FnvHashMap::default(),
+                average_weights: self.bins.average_weights,
             };
 
             // Transfer counts to new bins
@@ -99,16 +186,28 @@ impl Histogram2D {
                 let new_y_index =
                     ((old_y_value - self.range.y.min) / new_bins.y_width).floor() as usize;
 
-                let new_count = new_bins
+                *new_bins
                     .counts
                     .entry((new_x_index, new_y_index))
-                    .or_insert(0);
-                *new_count += count;
-
-                new_bins.min_count = new_bins.min_count.min(*new_count);
-                new_bins.max_count = new_bins.max_count.max(*new_count);
+                    .or_insert(0) += count;
+
+                if let Some(&weight) = backup_bins.weights.get(&(*old_x_index, *old_y_index)) {
+                    *new_bins
+                        .weights
+                        .entry((new_x_index, new_y_index))
+                        .or_insert(0.0) += weight;
+                }
+
+                if let Some(&sumw2) = backup_bins.sumw2.get(&(*old_x_index, *old_y_index)) {
+                    *new_bins
+                        .sumw2
+                        .entry((new_x_index, new_y_index))
+                        .or_insert(0.0) += sumw2;
+                }
             }
 
+            new_bins.update_min_max();
+
             self.bins = new_bins;
             self.plot_settings.recalculate_image = true;
 
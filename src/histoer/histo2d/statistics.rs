@@ -1,3 +1,5 @@
+use std::hash::Hasher;
+
 use super::histogram2d::Histogram2D;
 
 impl Histogram2D {
@@ -8,7 +10,7 @@ impl Histogram2D {
         end_x: f64,
         start_y: f64,
         end_y: f64,
-    ) -> (u64, f64, f64, f64, f64) {
+    ) -> (f64, f64, f64, f64, f64) {
         let start_x_index = self.get_bin_index_x(start_x).unwrap_or(0);
         let end_x_index = self.get_bin_index_x(end_x).unwrap_or_else(|| {
             self.bins
@@ -27,12 +29,12 @@ impl Histogram2D {
                 .map_or(0, |k| k.1)
         });
 
-        let mut total_count = 0;
+        let mut total_count = 0.0;
 
         let mut sum_product_x = 0.0;
         let mut sum_product_y = 0.0;
 
-        for (&(x_index, y_index), &count) in self.bins.counts.iter() {
+        for &(x_index, y_index) in self.bins.counts.keys() {
             if x_index >= start_x_index
                 && x_index <= end_x_index
                 && y_index >= start_y_index
@@ -45,23 +47,24 @@ impl Histogram2D {
                     + (y_index as f64 * self.bins.y_width)
                     + self.bins.y_width * 0.5;
 
+                let count = self.bins.count(&(x_index, y_index));
                 total_count += count;
 
-                sum_product_x += count as f64 * bin_center_x;
-                sum_product_y += count as f64 * bin_center_y;
+                sum_product_x += count * bin_center_x;
+                sum_product_y += count * bin_center_y;
             }
         }
 
-        if total_count == 0 {
-            (0, 0.0, 0.0, 0.0, 0.0)
+        if total_count == 0.0 {
+            (0.0, 0.0, 0.0, 0.0, 0.0)
         } else {
-            let mean_x = sum_product_x / total_count as f64;
-            let mean_y = sum_product_y / total_count as f64;
+            let mean_x = sum_product_x / total_count;
+            let mean_y = sum_product_y / total_count;
 
             let mut sum_squared_diff_x = 0.0;
             let mut sum_squared_diff_y = 0.0;
 
-            for (&(x_index, y_index), &count) in self.bins.counts.iter() {
+            for &(x_index, y_index) in self.bins.counts.keys() {
                 if x_index >= start_x_index
                     && x_index <= end_x_index
                     && y_index >= start_y_index
@@ -77,18 +80,64 @@ impl Histogram2D {
                     let diff_x = bin_center_x - mean_x;
                     let diff_y = bin_center_y - mean_y;
 
-                    sum_squared_diff_x += count as f64 * diff_x * diff_x;
-                    sum_squared_diff_y += count as f64 * diff_y * diff_y;
+                    let count = self.bins.count(&(x_index, y_index));
+                    sum_squared_diff_x += count * diff_x * diff_x;
+                    sum_squared_diff_y += count * diff_y * diff_y;
                 }
             }
 
-            let stdev_x = (sum_squared_diff_x / total_count as f64).sqrt();
-            let stdev_y = (sum_squared_diff_y / total_count as f64).sqrt();
+            let stdev_x = (sum_squared_diff_x / total_count).sqrt();
+            let stdev_y = (sum_squared_diff_y / total_count).sqrt();
 
             (total_count, mean_x, stdev_x, mean_y, stdev_y)
         }
     }
 
+    // Stable hash of the binning and contents, used to check if two histograms are identical.
+    // The counts are stored in a hash map, so the bins are sorted before hashing to keep it deterministic.
+    // The bins from before any rebinning are hashed so the display rebin factors don't change it,
+    // and indices are hashed as u64 so it is the same on 32-bit targets like wasm.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = fnv::FnvHasher::default();
+        let bins = self.backup_bins.as_ref().unwrap_or(&self.bins);
+
+        hasher.write_u64(self.range.x.min.to_bits());
+        hasher.write_u64(self.range.x.max.to_bits());
+        hasher.write_u64(self.range.y.min.to_bits());
+        hasher.write_u64(self.range.y.max.to_bits());
+        hasher.write_u64(bins.x as u64);
+        hasher.write_u64(bins.y as u64);
+
+        let mut counts: Vec<(&(usize, usize), &u64)> = bins
+            .counts
+            .iter()
+            .filter(|(_, &count)| count != 0)
+            .collect();
+        counts.sort_unstable_by_key(|(&index, _)| index);
+
+        for (&(x_index, y_index), &count) in counts {
+            hasher.write_u64(x_index as u64);
+            hasher.write_u64(y_index as u64);
+            hasher.write_u64(count);
+        }
+
+        let mut weights: Vec<(&(usize, usize), &f64)> = bins.weights.iter().collect();
+        weights.sort_unstable_by_key(|(&index, _)| index);
+
+        for (&(x_index, y_index), &weight) in weights {
+            hasher.write_u64(x_index as u64);
+            hasher.write_u64(y_index as u64);
+            hasher.write_u64(weight.to_bits());
+        }
+
+        hasher.write_u64(self.overflow.0);
+        hasher.write_u64(self.overflow.1);
+        hasher.write_u64(self.underflow.0);
+        hasher.write_u64(self.underflow.1);
+
+        format!("{:016x}", hasher.finish())
+    }
+
     // Show statistics on the plot
     pub fn show_stats(&self, plot_ui: &mut egui_plot::PlotUi) {
         if !self.plot_settings.stats_info {
@@ -108,6 +157,7 @@ impl Histogram2D {
             format!("Stdev: ({:.2}, {:.2})", stats.2, stats.4),
             format!("Overflow: ({:}, {:})", self.overflow.0, self.overflow.1),
             format!("Underflow: ({:}, {:})", self.underflow.0, self.underflow.1),
+            format!("Fingerprint: {}", self.fingerprint()),
         ];
 
         for entry in stats_entries.iter() {
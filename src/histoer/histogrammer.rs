@@ -1,32 +1,447 @@
+use super::batch_fit::{BatchFit, BatchFitRow};
+use super::histo1d::arithmetic::HistogramOperation;
 use super::histo1d::histogram1d::Histogram;
+use super::histo2d::colormaps::{ColorMap, ColormapOptions, CustomColormap};
 use super::histo2d::histogram2d::Histogram2D;
 use super::pane::Pane;
+use super::template::{
+    AnalysisTemplate, ColumnBinding, TemplateBinning, TemplateGrid, TemplateHistogram,
+};
 use super::tree::TreeBehavior;
 use crate::cutter::cut_handler::CutHandler;
+use crate::egui_plot_stuff::egui_plot_export::sanitize_file_name;
+use crate::util::lazyframer::LazyFramer;
+use crate::util::preset::FitTemplate;
 use egui_tiles::TileId;
 use fnv::FnvHashMap;
 use polars::prelude::*;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use std::path::PathBuf;
 use std::thread::JoinHandle;
 
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
+// Number of values given to each fill_batch call, the progress is updated after every batch
+const FILL_BATCH_SIZE: usize = 1_000_000;
+
+// Margin on each side of a range taken from the data, as a fraction of the data span
+const AUTO_RANGE_MARGIN: f64 = 0.01;
+
+// Bins and ranges used when a histogram is added without giving them, set in the Settings menu
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HistogramDefaults {
+    pub bins_1d: usize,
+    pub range_1d: (f64, f64),
+    pub bins_2d: (usize, usize),
+    pub range_2d: ((f64, f64), (f64, f64)),
+    pub auto_range_margin: f64,
+}
+
+impl Default for HistogramDefaults {
+    fn default() -> Self {
+        Self {
+            bins_1d: 512,
+            range_1d: (0.0, 4096.0),
+            bins_2d: (512, 512),
+            range_2d: ((0.0, 4096.0), (0.0, 4096.0)),
+            auto_range_margin: AUTO_RANGE_MARGIN,
+        }
+    }
+}
+
+impl HistogramDefaults {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.heading("New Histogram Defaults");
+
+        egui::Grid::new("histogram_defaults_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("1D Bins");
+                ui.add(egui::DragValue::new(&mut self.bins_1d).range(1..=usize::MAX));
+                ui.end_row();
+
+                ui.label("1D Range");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.range_1d.0).speed(1.0));
+                    ui.add(egui::DragValue::new(&mut self.range_1d.1).speed(1.0));
+                });
+                ui.end_row();
+
+                ui.label("2D Bins");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut self.bins_2d.0)
+                            .range(1..=usize::MAX)
+                            .prefix("x: "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut self.bins_2d.1)
+                            .range(1..=usize::MAX)
+                            .prefix("y: "),
+                    );
+                });
+                ui.end_row();
+
+                ui.label("2D X Range");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.range_2d.0 .0).speed(1.0));
+                    ui.add(egui::DragValue::new(&mut self.range_2d.0 .1).speed(1.0));
+                });
+                ui.end_row();
+
+                ui.label("2D Y Range");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.range_2d.1 .0).speed(1.0));
+                    ui.add(egui::DragValue::new(&mut self.range_2d.1 .1).speed(1.0));
+                });
+                ui.end_row();
+
+                ui.label("Auto Range Margin");
+                ui.add(
+                    egui::DragValue::new(&mut self.auto_range_margin)
+                        .speed(0.001)
+                        .range(0.0..=1.0),
+                )
+                .on_hover_text(
+                    "Added to each side of a range taken from the data, as a fraction of its span",
+                );
+                ui.end_row();
+            });
+
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+    }
+}
+
+// How the panes of a grid are laid out, kept by grid name so it is restored with the grid
+#[derive(Clone, Copy, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct GridConfig {
+    #[serde(default)]
+    pub columns: Option<usize>, // None lets egui_tiles pick the columns from the available size
+    #[serde(default)]
+    pub aspect_2d: Option<f32>, // width / height of the 2D plots, None fills the cell
+}
+
+impl GridConfig {
+    // Returns true when the config was changed
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = *self;
+
+        ui.horizontal(|ui| {
+            let mut fixed_columns = self.columns.is_some();
+            ui.checkbox(&mut fixed_columns, "Columns");
+            match (fixed_columns, &mut self.columns) {
+                (true, Some(columns)) => {
+                    ui.add(egui::DragValue::new(columns).range(1..=64));
+                }
+                (true, None) => self.columns = Some(2),
+                (false, _) => self.columns = None,
+            }
+        })
+        .response
+        .on_hover_text("Number of columns, unchecked picks them from the available size");
+
+        ui.horizontal(|ui| {
+            let mut fixed_aspect = self.aspect_2d.is_some();
+            ui.checkbox(&mut fixed_aspect, "2D Aspect");
+            match (fixed_aspect, &mut self.aspect_2d) {
+                (true, Some(aspect)) => {
+                    ui.add(
+                        egui::DragValue::new(aspect)
+                            .range(0.1..=10.0)
+                            .speed(0.01)
+                            .max_decimals(2),
+                    );
+                }
+                (true, None) => self.aspect_2d = Some(1.0),
+                (false, _) => self.aspect_2d = None,
+            }
+        })
+        .response
+        .on_hover_text("Width / height of the 2D plots, unchecked fills the cell");
+
+        *self != before
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ContainerType {
-    Grid,
+    Grid(GridConfig),
     Tabs,
     Vertical,
     Horizontal,
 }
 
+impl ContainerType {
+    fn kind(&self) -> egui_tiles::ContainerKind {
+        match self {
+            ContainerType::Grid(_) => egui_tiles::ContainerKind::Grid,
+            ContainerType::Tabs => egui_tiles::ContainerKind::Tabs,
+            ContainerType::Vertical => egui_tiles::ContainerKind::Vertical,
+            ContainerType::Horizontal => egui_tiles::ContainerKind::Horizontal,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ContainerType::Grid(_) => "Grid",
+            ContainerType::Tabs => "Tabs",
+            ContainerType::Vertical => "Vertical",
+            ContainerType::Horizontal => "Horizontal",
+        }
+    }
+
+    fn from_container(container: &egui_tiles::Container, config: GridConfig) -> Self {
+        match container.kind() {
+            egui_tiles::ContainerKind::Grid => ContainerType::Grid(config),
+            egui_tiles::ContainerKind::Tabs => ContainerType::Tabs,
+            egui_tiles::ContainerKind::Vertical => ContainerType::Vertical,
+            egui_tiles::ContainerKind::Horizontal => ContainerType::Horizontal,
+        }
+    }
+
+    // Returns true when the layout was changed
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let before = *self;
+        let config = match self {
+            ContainerType::Grid(config) => *config,
+            _ => GridConfig::default(),
+        };
+
+        ui.horizontal(|ui| {
+            for option in [
+                ContainerType::Grid(config),
+                ContainerType::Tabs,
+                ContainerType::Vertical,
+                ContainerType::Horizontal,
+            ] {
+                if ui
+                    .selectable_label(self.kind() == option.kind(), option.label())
+                    .clicked()
+                {
+                    *self = option;
+                }
+            }
+        });
+
+        let mut config_changed = false;
+        if let ContainerType::Grid(config) = self {
+            config_changed = config.ui(ui);
+        }
+
+        config_changed || *self != before
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Histogrammer {
     pub name: String,
     pub tree: egui_tiles::Tree<Pane>,
     pub behavior: TreeBehavior,
     #[serde(skip)]
-    pub handles: Vec<JoinHandle<()>>, // Multiple thread handles
+    pub handles: Vec<FillThread>,
     pub grid_histogram_map: HashMap<String, (TileId, Vec<TileId>)>, // Map grid names to a tuple of grid ID and histogram IDs
+    #[serde(skip)]
+    pub tree_filter: String,                      // Filter the tree by histogram name or notes
+    #[serde(skip)]
+    pub batch_export: BatchExport,
+    #[serde(skip)]
+    pub renaming: Option<(TileId, String)>, // tile being renamed in the tree and the edited name
+    #[serde(skip)]
+    pub confirm_clear_all: bool, // Clear All was clicked and waits for a confirmation
+    #[serde(skip)]
+    pub fill_errors: Arc<Mutex<Vec<String>>>, // pushed by the fill threads, shown until dismissed
+    #[serde(default)]
+    pub global_colormap: ColorMap,
+    #[serde(default)]
+    pub global_colormap_options: ColormapOptions,
+    #[serde(default)]
+    pub global_colormap_applied: bool, // new 2D histograms use the global colormap once it is applied
+    #[serde(skip)]
+    pub arithmetic: HistogramArithmetic,
+    #[serde(skip)]
+    pub background_subtraction: BackgroundSubtraction,
+    #[serde(skip)]
+    pub batch_fit: BatchFit,
+    #[serde(skip)]
+    pub queued_fills: VecDeque<QueuedFill>, // waiting for a free slot, started in check_and_join_finished_threads
+    #[serde(default = "default_max_concurrent_fills")]
+    pub max_concurrent_fills: usize,
+    #[serde(default)]
+    pub defaults: HistogramDefaults,
+    #[serde(default)]
+    pub column_bindings: HashMap<String, ColumnBinding>, // histogram name -> columns it was last filled from
+    #[serde(default)]
+    pub grid_configs: HashMap<String, GridConfig>, // grid name -> layout of its panes
+    #[serde(default)]
+    pub custom_colormaps: Vec<CustomColormap>, // loaded from files, offered in every 2D colormap menu
+}
+
+fn default_max_concurrent_fills() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+// The selections of the histogram arithmetic menu
+#[derive(Default)]
+pub struct HistogramArithmetic {
+    pub first: String,
+    pub second: String,
+    pub operation: HistogramOperation,
+    pub result_name: String,
+}
+
+// The selections of the background subtraction menu
+pub struct BackgroundSubtraction {
+    pub source: String,
+    pub iterations: usize,
+    pub result_name: String,
+}
+
+impl Default for BackgroundSubtraction {
+    fn default() -> Self {
+        Self {
+            source: String::new(),
+            iterations: 20,
+            result_name: String::new(),
+        }
+    }
+}
+
+// A running fill thread, the progress is read from the histogram it fills
+pub struct FillThread {
+    pub name: String,
+    pub handle: JoinHandle<()>,
+    pub cancel: Arc<AtomicBool>,
+    pub panes: Vec<Pane>, // empty for threads that do not fill panes directly
+}
+
+impl FillThread {
+    pub fn new(name: &str, handle: JoinHandle<()>, cancel: Arc<AtomicBool>) -> Self {
+        Self {
+            name: name.to_string(),
+            handle,
+            cancel,
+            panes: Vec::new(),
+        }
+    }
+
+    pub fn with_pane(mut self, pane: Pane) -> Self {
+        self.panes.push(pane);
+        self
+    }
+
+    pub fn with_panes(mut self, panes: Vec<Pane>) -> Self {
+        self.panes.extend(panes);
+        self
+    }
+}
+
+// A fill waiting for one of the max_concurrent_fills slots
+pub struct QueuedFill {
+    pub name: String,
+    pub job: Box<dyn FnOnce() + Send>,
+    pub cancel: Arc<AtomicBool>,
+    pub panes: Vec<Pane>,
+}
+
+// A histogram filled by Histogrammer::fill_hists_batched
+pub enum FillRequest {
+    Hist1d {
+        name: String,
+        column: String,
+        weight_column: Option<String>,
+        extra_filter: Option<Expr>, // ANDed with the range filter, can use any column of the lazyframe
+    },
+    Hist2d {
+        name: String,
+        x_column: String,
+        y_column: String,
+        weight_column: Option<String>,
+        extra_filter: Option<Expr>,
+    },
+}
+
+impl FillRequest {
+    pub fn name(&self) -> &str {
+        match self {
+            FillRequest::Hist1d { name, .. } | FillRequest::Hist2d { name, .. } => name,
+        }
+    }
+
+    pub fn extra_filter(&self) -> Option<&Expr> {
+        match self {
+            FillRequest::Hist1d { extra_filter, .. } | FillRequest::Hist2d { extra_filter, .. } => {
+                extra_filter.as_ref()
+            }
+        }
+    }
+
+    pub fn binding(&self) -> ColumnBinding {
+        match self {
+            FillRequest::Hist1d {
+                column,
+                weight_column,
+                ..
+            } => ColumnBinding {
+                columns: vec![column.clone()],
+                weight_column: weight_column.clone(),
+            },
+            FillRequest::Hist2d {
+                x_column,
+                y_column,
+                weight_column,
+                ..
+            } => ColumnBinding {
+                columns: vec![x_column.clone(), y_column.clone()],
+                weight_column: weight_column.clone(),
+            },
+        }
+    }
+
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            FillRequest::Hist1d {
+                column,
+                weight_column,
+                ..
+            } => std::iter::once(column.as_str())
+                .chain(weight_column.as_deref())
+                .collect(),
+            FillRequest::Hist2d {
+                x_column,
+                y_column,
+                weight_column,
+                ..
+            } => [x_column.as_str(), y_column.as_str()]
+                .into_iter()
+                .chain(weight_column.as_deref())
+                .collect(),
+        }
+    }
+}
+
+// Exports the panes one at a time, each pane is made active so it is drawn before the screenshot
+#[derive(Default)]
+pub struct BatchExport {
+    pub queue: Vec<(TileId, PathBuf)>,
+    pub current: Option<(TileId, PathBuf)>,
+    pub frames_waited: usize,
+    pub total: usize,
+    pub exported: usize,
+    pub failures: Vec<String>,
+}
+
+impl BatchExport {
+    pub fn is_running(&self) -> bool {
+        self.current.is_some() || !self.queue.is_empty()
+    }
 }
 
 impl Default for Histogrammer {
@@ -37,6 +452,23 @@ impl Default for Histogrammer {
             behavior: Default::default(),
             handles: vec![],
             grid_histogram_map: HashMap::new(),
+            tree_filter: String::new(),
+            batch_export: BatchExport::default(),
+            renaming: None,
+            confirm_clear_all: false,
+            fill_errors: Arc::new(Mutex::new(Vec::new())),
+            global_colormap: ColorMap::default(),
+            global_colormap_options: ColormapOptions::default(),
+            global_colormap_applied: false,
+            arithmetic: HistogramArithmetic::default(),
+            background_subtraction: BackgroundSubtraction::default(),
+            batch_fit: BatchFit::default(),
+            queued_fills: VecDeque::new(),
+            max_concurrent_fills: default_max_concurrent_fills(),
+            defaults: HistogramDefaults::default(),
+            column_bindings: HashMap::new(),
+            grid_configs: HashMap::new(),
+            custom_colormaps: Vec::new(),
         }
     }
 }
@@ -63,16 +495,11 @@ impl Histogrammer {
             let pane_id = self.tree.tiles.insert_pane(pane);
 
             let grid_name = grid.unwrap_or(name);
-            let grid_id = if let Some((grid_id, _)) = self.grid_histogram_map.get(grid_name) {
-                *grid_id
-            } else {
-                self.create_grid(grid_name.to_string())
-            };
+            let grid_id = self.get_or_create_grid(grid_name);
 
-            if let Some(egui_tiles::Tile::Container(egui_tiles::Container::Grid(grid))) =
-                self.tree.tiles.get_mut(grid_id)
-            {
-                grid.add_child(pane_id);
+            // the grid may have been switched to tabs or a row/column of panes
+            if let Some(egui_tiles::Tile::Container(container)) = self.tree.tiles.get_mut(grid_id) {
+                container.add_child(pane_id);
                 self.grid_histogram_map
                     .entry(grid_name.to_string())
                     .or_insert((grid_id, Vec::new()))
@@ -84,7 +511,579 @@ impl Histogrammer {
         }
     }
 
-    pub fn fill_hist1d(&mut self, name: &str, lf: &LazyFrame, column_name: &str) -> bool {
+    // The 1D histogram panes by name, so other threads can fill or overlay them
+    pub fn hist1d_panes(&self) -> HashMap<String, Arc<Mutex<Box<Histogram>>>> {
+        self.tree
+            .tiles
+            .iter()
+            .filter_map(|(_id, tile)| match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                    Some((hist.lock().unwrap().name.clone(), Arc::clone(hist)))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Add values on top of the current counts of a 1D histogram, for live data pushed in small
+    // batches. The progress is left alone since the total is unknown, and the plot picks up the
+    // new counts on the next repaint.
+    pub fn push_values(&self, name: &str, values: &[f64]) {
+        let hist = self.tree.tiles.iter().find_map(|(_id, tile)| match tile {
+            egui_tiles::Tile::Pane(Pane::Histogram(hist)) if hist.lock().unwrap().name == name => {
+                Some(Arc::clone(hist))
+            }
+            _ => None,
+        });
+
+        match hist {
+            Some(hist) => hist.lock().unwrap().fill_batch(values),
+            None => log::error!("No 1D histogram named '{}' to push values to", name),
+        }
+    }
+
+    // Add the projections requested from the 2D histograms to the "Other" grid
+    pub fn add_pending_cut_projections(&mut self) {
+        let mut projections = Vec::new();
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                projections.append(&mut hist.lock().unwrap().pending_cut_projections);
+            }
+        }
+
+        for projection in projections {
+            self.add_hist1d(
+                &projection.name,
+                projection.bins.len(),
+                projection.range,
+                Some("Other"),
+            );
+
+            for (_id, tile) in self.tree.tiles.iter_mut() {
+                if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                    let mut hist = hist.lock().unwrap();
+                    if hist.name == projection.name {
+                        hist.bins.clone_from(&projection.bins);
+                        hist.core.bins.clone_from(&projection.core.bins);
+                        hist.update_line_points();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    // Combine two 1D histograms bin by bin into a new histogram in the "Other" grid
+    pub fn combine_hists(
+        &mut self,
+        first: &str,
+        second: &str,
+        operation: HistogramOperation,
+        result_name: &str,
+    ) {
+        if self.tree.tiles.iter().any(
+            |(_id, tile)| matches!(tile, egui_tiles::Tile::Pane(pane) if pane.name() == result_name),
+        ) {
+            log::error!("A histogram named '{}' already exists", result_name);
+            return;
+        }
+
+        let panes = self.hist1d_panes();
+        let (Some(first_hist), Some(second_hist)) = (panes.get(first), panes.get(second)) else {
+            log::error!("Histograms '{}' and '{}' must both exist", first, second);
+            return;
+        };
+
+        // copied first so the same histogram can be used twice without locking it twice
+        let first_hist = first_hist.lock().unwrap().clone();
+        let result = first_hist.apply_operation(&second_hist.lock().unwrap(), operation);
+
+        match result {
+            Ok(mut result) => {
+                result.name = result_name.to_string();
+                result.line.name = result_name.to_string();
+
+                self.add_hist1d(result_name, result.bins.len(), result.range, Some("Other"));
+                if let Some(hist) = self.hist1d_panes().get(result_name) {
+                    **hist.lock().unwrap() = result;
+                }
+            }
+            Err(e) => log::error!(
+                "Cannot {} '{}' and '{}': {}",
+                operation.name().to_lowercase(),
+                first,
+                second,
+                e
+            ),
+        }
+    }
+
+    // Subtract the SNIP background of a 1D histogram into a new histogram in the "Other" grid
+    pub fn subtract_background_hist(&mut self, source: &str, iterations: usize, result_name: &str) {
+        if self.tree.tiles.iter().any(
+            |(_id, tile)| matches!(tile, egui_tiles::Tile::Pane(pane) if pane.name() == result_name),
+        ) {
+            log::error!("A histogram named '{}' already exists", result_name);
+            return;
+        }
+
+        let Some(source_hist) = self.hist1d_panes().get(source).cloned() else {
+            log::error!("No 1D histogram named '{}'", source);
+            return;
+        };
+
+        let result = source_hist
+            .lock()
+            .unwrap()
+            .background_subtracted(result_name, iterations);
+
+        self.add_hist1d(result_name, result.bins.len(), result.range, Some("Other"));
+        if let Some(hist) = self.hist1d_panes().get(result_name) {
+            **hist.lock().unwrap() = result;
+        }
+    }
+
+    // Add the counts of the sources to the target, e.g. the same histogram filled from several runs.
+    // Sources with a different binning are skipped. Returns the number of sources merged.
+    pub fn merge_into(
+        &mut self,
+        target_name: &str,
+        source_names: &[&str],
+        remove_sources: bool,
+    ) -> usize {
+        let find = |name: &str| {
+            self.tree.tiles.iter().find_map(|(id, tile)| match tile {
+                egui_tiles::Tile::Pane(pane) if pane.name() == name => Some((*id, pane.clone())),
+                _ => None,
+            })
+        };
+
+        let Some((_target_id, target)) = find(target_name) else {
+            log::error!("No histogram named '{}' to merge into", target_name);
+            return 0;
+        };
+
+        let mut merged = Vec::new();
+        for &source_name in source_names {
+            if source_name == target_name {
+                continue;
+            }
+            let Some((source_id, source)) = find(source_name) else {
+                log::error!("No histogram named '{}' to merge", source_name);
+                continue;
+            };
+
+            // the source is copied first so its lock is never held with the target's
+            let result = match (&target, &source) {
+                (Pane::Histogram(target), Pane::Histogram(source)) => {
+                    let source = source.lock().unwrap().clone();
+                    target.lock().unwrap().merge(&source)
+                }
+                (Pane::Histogram2D(target), Pane::Histogram2D(source)) => {
+                    let source = source.lock().unwrap().clone();
+                    target.lock().unwrap().merge(&source)
+                }
+                _ => Err("a 1D and a 2D histogram can not be merged".to_string()),
+            };
+
+            match result {
+                Ok(()) => merged.push(source_id),
+                Err(e) => log::error!(
+                    "Cannot merge '{}' into '{}': {}",
+                    source_name,
+                    target_name,
+                    e
+                ),
+            }
+        }
+
+        log::info!(
+            "Merged {} of {} histograms into '{}'",
+            merged.len(),
+            source_names.len(),
+            target_name
+        );
+
+        if remove_sources {
+            let removed_panes: Vec<Pane> = merged
+                .iter()
+                .flat_map(|&source_id| self.tree.remove_recursively(source_id))
+                .filter_map(|tile| match tile {
+                    egui_tiles::Tile::Pane(pane) => Some(pane),
+                    egui_tiles::Tile::Container(_) => None,
+                })
+                .collect();
+            self.cancel_fills_of(&removed_panes);
+
+            let tiles = &self.tree.tiles;
+            self.behavior
+                .tile_map
+                .retain(|tile_id, _| tiles.get(*tile_id).is_some());
+            for (_grid_id, histogram_ids) in self.grid_histogram_map.values_mut() {
+                histogram_ids.retain(|id| tiles.get(*id).is_some());
+            }
+        }
+
+        merged.len()
+    }
+
+    fn arithmetic_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Histogram Arithmetic", |ui| {
+            let mut names: Vec<String> = self.hist1d_panes().into_keys().collect();
+            names.sort();
+
+            let arithmetic = &mut self.arithmetic;
+            for (label, selection) in [("A", &mut arithmetic.first), ("B", &mut arithmetic.second)]
+            {
+                egui::ComboBox::from_label(label)
+                    .selected_text(selection.as_str())
+                    .show_ui(ui, |ui| {
+                        for name in &names {
+                            ui.selectable_value(selection, name.clone(), name);
+                        }
+                    });
+            }
+
+            ui.horizontal(|ui| {
+                for operation in [
+                    HistogramOperation::Add,
+                    HistogramOperation::Subtract,
+                    HistogramOperation::Divide,
+                ] {
+                    ui.radio_value(&mut arithmetic.operation, operation, operation.name());
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut arithmetic.result_name).hint_text(format!(
+                        "{} {} {}",
+                        arithmetic.first,
+                        arithmetic.operation.symbol(),
+                        arithmetic.second
+                    )),
+                );
+            });
+
+            let ready = !arithmetic.first.is_empty() && !arithmetic.second.is_empty();
+            if ui
+                .add_enabled(ready, egui::Button::new("Create"))
+                .on_hover_text("A and B need the same bins and range")
+                .clicked()
+            {
+                let result_name = if arithmetic.result_name.trim().is_empty() {
+                    format!(
+                        "{} {} {}",
+                        arithmetic.first,
+                        arithmetic.operation.symbol(),
+                        arithmetic.second
+                    )
+                } else {
+                    arithmetic.result_name.trim().to_string()
+                };
+                let (first, second, operation) = (
+                    arithmetic.first.clone(),
+                    arithmetic.second.clone(),
+                    arithmetic.operation,
+                );
+
+                self.combine_hists(&first, &second, operation, &result_name);
+                self.arithmetic.result_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+
+    fn background_subtraction_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("Background Subtraction", |ui| {
+            let mut names: Vec<String> = self.hist1d_panes().into_keys().collect();
+            names.sort();
+
+            let subtraction = &mut self.background_subtraction;
+            egui::ComboBox::from_label("Histogram")
+                .selected_text(subtraction.source.as_str())
+                .show_ui(ui, |ui| {
+                    for name in &names {
+                        ui.selectable_value(&mut subtraction.source, name.clone(), name);
+                    }
+                });
+
+            ui.add(
+                egui::DragValue::new(&mut subtraction.iterations)
+                    .range(1..=1000)
+                    .prefix("Iterations: "),
+            )
+            .on_hover_text(
+                "SNIP clipping window in bins, about half the width of the widest peak to remove",
+            );
+
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.add(
+                    egui::TextEdit::singleline(&mut subtraction.result_name)
+                        .hint_text(format!("{} - Background", subtraction.source)),
+                );
+            });
+
+            if ui
+                .add_enabled(!subtraction.source.is_empty(), egui::Button::new("Create"))
+                .on_hover_text("A new histogram of the counts minus the SNIP background, in the current binning")
+                .clicked()
+            {
+                let result_name = if subtraction.result_name.trim().is_empty() {
+                    format!("{} - Background", subtraction.source)
+                } else {
+                    subtraction.result_name.trim().to_string()
+                };
+                let (source, iterations) = (subtraction.source.clone(), subtraction.iterations);
+
+                self.subtract_background_hist(&source, iterations, &result_name);
+                self.background_subtraction.result_name.clear();
+                ui.close_menu();
+            }
+        });
+    }
+
+    // Fit the batch fit region on every selected 1D histogram and collect the peaks in the summary
+    pub fn run_batch_fit(&mut self) {
+        let (start_x, end_x) = self.batch_fit.region;
+        let panes = self.hist1d_panes();
+
+        self.batch_fit.results.clear();
+        self.batch_fit.failures.clear();
+
+        for name in &self.batch_fit.selected {
+            let Some(hist) = panes.get(name) else {
+                self.batch_fit
+                    .failures
+                    .push(format!("{}: no 1D histogram with this name", name));
+                continue;
+            };
+
+            match hist.lock().unwrap().fit_region_and_store(start_x, end_x) {
+                Ok(peaks) => {
+                    for (peak, (mean, fwhm, area)) in peaks.into_iter().enumerate() {
+                        self.batch_fit.results.push(BatchFitRow {
+                            histogram: name.clone(),
+                            peak,
+                            mean,
+                            fwhm,
+                            area,
+                        });
+                    }
+                }
+                Err(e) => self.batch_fit.failures.push(format!("{}: {}", name, e)),
+            }
+        }
+
+        log::info!(
+            "Batch fit {} of {} histograms between {} and {}",
+            self.batch_fit.selected.len() - self.batch_fit.failures.len(),
+            self.batch_fit.selected.len(),
+            start_x,
+            end_x
+        );
+    }
+
+    fn batch_fit_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Batch Fit")
+            .on_hover_text("Fit the same peak region on many 1D histograms")
+            .clicked()
+        {
+            self.batch_fit.open = !self.batch_fit.open;
+        }
+
+        if !self.batch_fit.open {
+            return;
+        }
+
+        let mut names: Vec<String> = self.hist1d_panes().into_keys().collect();
+        names.sort();
+
+        let mut open = self.batch_fit.open;
+        let mut run = false;
+        let ctx = ui.ctx().clone();
+        egui::Window::new("Batch Fit")
+            .open(&mut open)
+            .show(&ctx, |ui| {
+                run = self.batch_fit.ui(ui, &names);
+            });
+        self.batch_fit.open = open;
+
+        if run {
+            self.run_batch_fit();
+        }
+    }
+
+    // Copy a histogram's settings into a new empty histogram in the same grid
+    pub fn duplicate_hist(&mut self, source_name: &str, new_name: &str) {
+        if self.tree.tiles.iter().any(
+            |(_id, tile)| matches!(tile, egui_tiles::Tile::Pane(pane) if pane.name() == new_name),
+        ) {
+            log::error!("A histogram named '{}' already exists", new_name);
+            return;
+        }
+
+        let Some((source_id, copy)) = self.tree.tiles.iter().find_map(|(id, tile)| match tile {
+            egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                let hist = hist.lock().unwrap();
+                (hist.name == source_name).then(|| {
+                    let copy = hist.duplicate(new_name);
+                    (*id, Pane::Histogram(Arc::new(Mutex::new(Box::new(copy)))))
+                })
+            }
+            egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => {
+                let hist = hist.lock().unwrap();
+                (hist.name == source_name).then(|| {
+                    let copy = hist.duplicate(new_name);
+                    (*id, Pane::Histogram2D(Arc::new(Mutex::new(Box::new(copy)))))
+                })
+            }
+            _ => None,
+        }) else {
+            log::error!("Histogram '{}' not found in the tree", source_name);
+            return;
+        };
+
+        let pane_id = self.tree.tiles.insert_pane(copy);
+
+        let parent_id = self.tree.tiles.parent_of(source_id);
+        match parent_id.and_then(|parent_id| self.tree.tiles.get_mut(parent_id)) {
+            Some(egui_tiles::Tile::Container(container)) => container.add_child(pane_id),
+            _ => {
+                let grid_id = self.get_or_create_other_grid();
+                if let Some(egui_tiles::Tile::Container(container)) =
+                    self.tree.tiles.get_mut(grid_id)
+                {
+                    container.add_child(pane_id);
+                }
+            }
+        }
+
+        if let Some((_grid_id, panes)) = self
+            .grid_histogram_map
+            .values_mut()
+            .find(|(grid_id, _)| Some(*grid_id) == parent_id)
+        {
+            panes.push(pane_id);
+        }
+    }
+
+    // Add the copies requested from the histogram context menus
+    pub fn add_pending_duplicates(&mut self) {
+        let mut duplicates = Vec::new();
+        for (_id, tile) in self.tree.tiles.iter() {
+            match tile {
+                egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                    let mut hist = hist.lock().unwrap();
+                    if let Some(new_name) = hist.pending_duplicate.take() {
+                        duplicates.push((hist.name.clone(), new_name));
+                    }
+                }
+                egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => {
+                    let mut hist = hist.lock().unwrap();
+                    if let Some(new_name) = hist.pending_duplicate.take() {
+                        duplicates.push((hist.name.clone(), new_name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for (source_name, new_name) in duplicates {
+            self.duplicate_hist(&source_name, &new_name);
+        }
+    }
+
+    // Set up the fits cloned from the fit panels on their target histograms
+    pub fn clone_pending_fits(&mut self) {
+        let mut clones = Vec::new();
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                if let Some((fit_index, target)) = hist.fits.pending_clone.take() {
+                    if target == hist.name {
+                        log::error!("Cannot clone a fit of {} onto itself", hist.name);
+                        continue;
+                    }
+                    if let Some(template) = hist.fit_clone(fit_index) {
+                        clones.push((template, target));
+                    }
+                }
+            }
+        }
+
+        if clones.is_empty() {
+            return;
+        }
+
+        let hists = self.hist1d_panes();
+        for (template, target) in clones {
+            match hists.get(&target) {
+                Some(hist) => hist.lock().unwrap().apply_fit_clone(template),
+                None => log::error!("No 1D histogram named {} to clone the fit to", target),
+            }
+        }
+    }
+
+    // Copy the cuts sent with "Apply to All" to the other 2D histograms with the same columns
+    pub fn propagate_pending_cuts(&mut self) {
+        let mut requests = Vec::new();
+        for (id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                let cuts = &mut hist.plot_settings.cuts;
+                if let Some(cut) = cuts.pending_propagation.take() {
+                    requests.push((*id, cuts.x_column.clone(), cuts.y_column.clone(), cut));
+                }
+            }
+        }
+
+        for (source_id, x_column, y_column, cut) in requests {
+            let message = if x_column.is_empty() || y_column.is_empty() {
+                "Set the X and Y columns before applying the cut to other histograms".to_string()
+            } else {
+                let (mut received, mut skipped) = (0, 0);
+                for (id, tile) in self.tree.tiles.iter() {
+                    if *id == source_id {
+                        continue;
+                    }
+                    if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                        let cuts = &mut hist.lock().unwrap().plot_settings.cuts;
+                        if cuts.x_column == x_column && cuts.y_column == y_column {
+                            cuts.insert_cut(cut.clone());
+                            received += 1;
+                        } else {
+                            skipped += 1;
+                        }
+                    }
+                }
+                format!(
+                    "Applied '{}' to {} histograms, skipped {} with other columns",
+                    cut.polygon.name, received, skipped
+                )
+            };
+            log::info!("{}", message);
+
+            if let Some(egui_tiles::Tile::Pane(Pane::Histogram2D(hist))) =
+                self.tree.tiles.get(source_id)
+            {
+                hist.lock().unwrap().plot_settings.cuts.propagation_message = Some(message);
+            }
+        }
+    }
+
+    // Each value counts once, or by the value of the weight column when it is given
+    pub fn fill_hist1d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        column_name: &str,
+        weight_column: Option<&str>,
+        extra_filter: Option<Expr>,
+    ) -> bool {
         if let Some((_id, egui_tiles::Tile::Pane(Pane::Histogram(hist)))) =
             self.tree.tiles.iter_mut().find(|(_id, tile)| {
                 if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
@@ -95,53 +1094,27 @@ impl Histogrammer {
             })
         {
             let hist = Arc::clone(hist); // Clone the Arc to share ownership
-            let hist_range = hist.lock().unwrap().range; // Access the range safely
-            let filter_expr = col(column_name)
-                .gt(lit(hist_range.0))
-                .and(col(column_name).lt(lit(hist_range.1)));
-
-            let overflow_filter_expr = col(column_name).gt(lit(hist_range.1));
-            // get the overflow values
-            let overflow_df = lf
-                .clone()
-                .select([col(column_name)])
-                .filter(overflow_filter_expr)
-                .sum()
-                .collect()
-                .unwrap();
-
-            let overflow_value = overflow_df.column(column_name).unwrap().get(0).unwrap(); // Now you can access the first value safely
-
-            let overflow_as_u64 = match overflow_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
-            };
 
-            let underflow_filter_expr = col(column_name).lt(lit(hist_range.0));
-            // get the underflow values
-            let underflow_df = lf
-                .clone()
-                .select([col(column_name)])
-                .filter(underflow_filter_expr)
-                .sum()
-                .collect()
-                .unwrap();
-
-            let underflow_value = underflow_df.column(column_name).unwrap().get(0).unwrap(); // Now you can access the first value safely
-
-            let underflow_as_u64 = match underflow_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
+            let filter_expr = match prepare_hist1d_fill(lf, column_name, weight_column) {
+                Ok(filter_expr) => with_extra_filter(filter_expr, extra_filter),
+                Err(e) => {
+                    self.report_fill_error(name, &e);
+                    return false;
+                }
             };
 
-            hist.lock().unwrap().overflow = overflow_as_u64;
-            hist.lock().unwrap().underflow = underflow_as_u64;
+            self.column_bindings.insert(
+                name.to_string(),
+                ColumnBinding {
+                    columns: vec![column_name.to_string()],
+                    weight_column: weight_column.map(|weight_column| weight_column.to_string()),
+                },
+            );
 
             let lf = lf.clone();
             let name = name.to_string();
             let column_name = column_name.to_string();
+            let weight_column = weight_column.map(|weight_column| weight_column.to_string());
 
             log::info!(
                 "Starting to fill histogram '{}' with data from column '{}'",
@@ -149,46 +1122,39 @@ impl Histogrammer {
                 column_name
             );
 
-            // Spawn a new thread for the filling operation
-            let handle = std::thread::spawn(move || {
-                log::info!("Thread started for filling histogram '{}'", name);
+            let cancel = Arc::new(AtomicBool::new(false));
+            let thread_cancel = Arc::clone(&cancel);
 
-                if let Ok(df) = lf
-                    .select([col(&column_name)])
-                    .filter(filter_expr.clone()) // Clone for logging purposes
-                    .collect()
-                {
-                    log::info!("Data collected for histogram '{}'", name);
+            let fill_errors = Arc::clone(&self.fill_errors);
 
-                    let series = df.column(&column_name).unwrap();
-                    let values = series.f64().unwrap();
-                    let total_steps = values.len();
+            let fill_thread_name = name.clone();
+            let pane = Pane::Histogram(Arc::clone(&hist));
 
-                    log::info!(
-                        "Histogram '{}' will be filled with {} values from column '{}'",
+            // Fill on a new thread once a slot is free
+            let job = move || {
+                log::info!("Thread started for filling histogram '{}'", name);
+
+                if let Err(e) = fill_hist1d_values(
+                    &hist,
+                    lf,
+                    filter_expr,
+                    &column_name,
+                    weight_column.as_deref(),
+                    &thread_cancel,
+                ) {
+                    log::error!(
+                        "Failed to collect LazyFrame for histogram '{}': {}",
                         name,
-                        total_steps,
-                        column_name
+                        e
                     );
-
-                    for (i, value) in values.iter().enumerate() {
-                        if let Some(v) = value {
-                            let mut hist = hist.lock().unwrap(); // Lock the mutex to access the correct Histogram
-                            hist.fill(v, i, total_steps); // Pass the progress to the fill method
-                        }
-                    }
-
-                    log::info!("Completed filling histogram '{}'", name);
-
-                    // Optionally: Set progress to None or trigger any final updates here
-                    hist.lock().unwrap().plot_settings.progress = None;
-                } else {
-                    log::error!("Failed to collect LazyFrame for histogram '{}'", name);
+                    fill_errors
+                        .lock()
+                        .unwrap()
+                        .push(format!("Failed to fill '{}': {}", name, e));
                 }
-            });
+            };
 
-            // Store the thread handle in the vector
-            self.handles.push(handle);
+            self.spawn_fill(&fill_thread_name, cancel, vec![pane], job);
 
             return true;
         }
@@ -207,7 +1173,102 @@ impl Histogrammer {
         grid: Option<&str>,
     ) {
         self.add_hist1d(name, bins, range, grid); // Add the histogram.
-        self.fill_hist1d(name, lf, column_name); // Fill it with data.
+        self.fill_hist1d(name, lf, column_name, None, None); // Fill it with data.
+    }
+
+    // The range of a column from a min/max aggregation, widened by a small margin so the
+    // largest value isn't lost on the exclusive max edge
+    fn auto_range(&self, lf: &LazyFrame, column_name: &str) -> PolarsResult<(f64, f64)> {
+        let (min, max) = LazyFramer::column_min_max(lf, column_name)?;
+        let margin = if max > min {
+            (max - min) * self.defaults.auto_range_margin.max(0.0)
+        } else {
+            0.5 // a single value gets a unit wide range
+        };
+        Ok((min - margin, max + margin))
+    }
+
+    // Like add_fill_hist1d with the range taken from the data, false if the range couldn't be found
+    pub fn add_fill_hist1d_auto(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        column_name: &str,
+        bins: usize,
+        grid: Option<&str>,
+    ) -> bool {
+        match self.auto_range(lf, column_name) {
+            Ok(range) => {
+                log::info!(
+                    "Range of '{}' from column '{}': {:?}",
+                    name,
+                    column_name,
+                    range
+                );
+                self.add_fill_hist1d(name, lf, column_name, bins, range, grid);
+                true
+            }
+            Err(e) => {
+                self.report_fill_error(name, &e);
+                false
+            }
+        }
+    }
+
+    pub fn add_fill_hist2d_auto(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        x_column_name: &str,
+        y_column_name: &str,
+        bins: (usize, usize),
+        grid: Option<&str>,
+    ) -> bool {
+        let range = self
+            .auto_range(lf, x_column_name)
+            .and_then(|x_range| Ok((x_range, self.auto_range(lf, y_column_name)?)));
+
+        match range {
+            Ok(range) => {
+                log::info!(
+                    "Range of '{}' from columns '{}' and '{}': {:?}",
+                    name,
+                    x_column_name,
+                    y_column_name,
+                    range
+                );
+                self.add_fill_hist2d(name, lf, x_column_name, y_column_name, bins, range, grid);
+                true
+            }
+            Err(e) => {
+                self.report_fill_error(name, &e);
+                false
+            }
+        }
+    }
+
+    // Quick adds with the default bins and the range taken from the data
+    pub fn quick_add_hist1d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        column_name: &str,
+        grid: Option<&str>,
+    ) -> bool {
+        let bins = self.defaults.bins_1d;
+        self.add_fill_hist1d_auto(name, lf, column_name, bins, grid)
+    }
+
+    pub fn quick_add_hist2d(
+        &mut self,
+        name: &str,
+        lf: &LazyFrame,
+        x_column_name: &str,
+        y_column_name: &str,
+        grid: Option<&str>,
+    ) -> bool {
+        let bins = self.defaults.bins_2d;
+        self.add_fill_hist2d_auto(name, lf, x_column_name, y_column_name, bins, grid)
     }
 
     pub fn add_hist2d(
@@ -232,21 +1293,25 @@ impl Histogrammer {
 
         // If no existing histogram was found, create a new one
         if pane_id_to_update.is_none() {
-            let hist = Histogram2D::new(name, bins, range);
+            let grid_name = grid.unwrap_or(name);
+
+            let mut hist = Histogram2D::new(name, bins, range);
+            if self.global_colormap_applied {
+                hist.plot_settings.colormap = self.global_colormap.clone();
+                hist.plot_settings.colormap_options = self.global_colormap_options;
+            }
+            hist.plot_settings.custom_colormaps = Arc::new(self.custom_colormaps.clone());
+            if let Some(config) = self.grid_configs.get(grid_name) {
+                hist.plot_settings.view_aspect = config.aspect_2d;
+            }
             let pane = Pane::Histogram2D(Arc::new(Mutex::new(Box::new(hist))));
             let pane_id = self.tree.tiles.insert_pane(pane);
 
-            let grid_name = grid.unwrap_or(name);
-            let grid_id = if let Some((grid_id, _)) = self.grid_histogram_map.get(grid_name) {
-                *grid_id
-            } else {
-                self.create_grid(grid_name.to_string())
-            };
+            let grid_id = self.get_or_create_grid(grid_name);
 
-            if let Some(egui_tiles::Tile::Container(egui_tiles::Container::Grid(grid))) =
-                self.tree.tiles.get_mut(grid_id)
-            {
-                grid.add_child(pane_id);
+            // the grid may have been switched to tabs or a row/column of panes
+            if let Some(egui_tiles::Tile::Container(container)) = self.tree.tiles.get_mut(grid_id) {
+                container.add_child(pane_id);
                 self.grid_histogram_map
                     .entry(grid_name.to_string())
                     .or_insert((grid_id, Vec::new()))
@@ -264,6 +1329,8 @@ impl Histogrammer {
         lf: &LazyFrame,
         x_column_name: &str,
         y_column_name: &str,
+        weight_column: Option<&str>,
+        extra_filter: Option<Expr>,
     ) -> bool {
         if let Some((_id, egui_tiles::Tile::Pane(Pane::Histogram2D(hist)))) =
             self.tree.tiles.iter_mut().find(|(_id, tile)| {
@@ -273,81 +1340,31 @@ impl Histogrammer {
                     false
                 }
             })
-        {
-            let hist = Arc::clone(hist); // Clone the Arc to share ownership
-            let hist_range = hist.lock().unwrap().range.clone(); // Access the range safely
-            let filter_expr = col(x_column_name)
-                .gt(lit(hist_range.x.min))
-                .and(col(x_column_name).lt(lit(hist_range.x.max)))
-                .and(col(y_column_name).gt(lit(hist_range.y.min)))
-                .and(col(y_column_name).lt(lit(hist_range.y.max)));
-
-            let overflow_expr = col(x_column_name)
-                .gt(lit(hist_range.x.max))
-                .or(col(y_column_name).gt(lit(hist_range.y.max)));
-
-            let underflow_expr = col(x_column_name)
-                .lt(lit(hist_range.x.min))
-                .or(col(y_column_name).lt(lit(hist_range.y.min)));
-
-            let overflow_df = lf
-                .clone()
-                .select([col(x_column_name), col(y_column_name)])
-                .filter(overflow_expr)
-                .sum()
-                .collect()
-                .unwrap();
-
-            let underflow_df = lf
-                .clone()
-                .select([col(x_column_name), col(y_column_name)])
-                .filter(underflow_expr)
-                .sum()
-                .collect()
-                .unwrap();
-
-            let overflow_x_value = overflow_df.column(x_column_name).unwrap().get(0).unwrap();
-
-            let overflow_y_value = overflow_df.column(y_column_name).unwrap().get(0).unwrap();
-
-            let underflow_x_value = underflow_df.column(x_column_name).unwrap().get(0).unwrap();
-
-            let underflow_y_value = underflow_df.column(y_column_name).unwrap().get(0).unwrap();
-
-            let overflow_x_as_u64 = match overflow_x_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
-            };
-
-            let overflow_y_as_u64 = match overflow_y_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
-            };
-
-            let underflow_x_as_u64 = match underflow_x_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
-            };
-
-            let underflow_y_as_u64 = match underflow_y_value {
-                AnyValue::Int64(val) => val as u64,   // Cast if it's an Int64
-                AnyValue::Float64(val) => val as u64, // Cast if it's a Float64
-                _ => panic!("Unexpected value type!"),
-            };
+        {
+            let hist = Arc::clone(hist); // Clone the Arc to share ownership
 
-            hist.lock().unwrap().overflow = (overflow_x_as_u64, overflow_y_as_u64);
-            hist.lock().unwrap().underflow = (underflow_x_as_u64, underflow_y_as_u64);
+            let filter_expr =
+                match prepare_hist2d_fill(&hist, lf, x_column_name, y_column_name, weight_column) {
+                    Ok(filter_expr) => with_extra_filter(filter_expr, extra_filter),
+                    Err(e) => {
+                        self.report_fill_error(name, &e);
+                        return false;
+                    }
+                };
+
+            self.column_bindings.insert(
+                name.to_string(),
+                ColumnBinding {
+                    columns: vec![x_column_name.to_string(), y_column_name.to_string()],
+                    weight_column: weight_column.map(|weight_column| weight_column.to_string()),
+                },
+            );
 
             let lf = lf.clone();
             let name = name.to_string();
             let x_column_name = x_column_name.to_string();
             let y_column_name = y_column_name.to_string();
-
-            hist.lock().unwrap().plot_settings.cuts.x_column = x_column_name.clone();
-            hist.lock().unwrap().plot_settings.cuts.y_column = y_column_name.clone();
+            let weight_column = weight_column.map(|weight_column| weight_column.to_string());
 
             log::info!(
                 "Starting to fill 2D histogram '{}' with data from columns '{}' and '{}'",
@@ -356,48 +1373,40 @@ impl Histogrammer {
                 y_column_name
             );
 
-            // Spawn a new thread for the filling operation
-            let handle = std::thread::spawn(move || {
-                log::info!("Thread started for filling 2D histogram '{}'", name);
+            let cancel = Arc::new(AtomicBool::new(false));
+            let thread_cancel = Arc::clone(&cancel);
 
-                if let Ok(df) = lf
-                    .select([col(&x_column_name), col(&y_column_name)])
-                    .filter(filter_expr.clone()) // Clone for logging purposes
-                    .collect()
-                {
-                    log::info!("Data collected for 2D histogram '{}'", name);
+            let fill_errors = Arc::clone(&self.fill_errors);
 
-                    let x_values = df.column(&x_column_name).unwrap().f64().unwrap();
-                    let y_values = df.column(&y_column_name).unwrap().f64().unwrap();
-                    let total_steps = x_values.len();
+            let fill_thread_name = name.clone();
+            let pane = Pane::Histogram2D(Arc::clone(&hist));
 
-                    log::info!(
-                        "2D Histogram '{}' will be filled with {} value pairs from columns '{}' and '{}'",
+            // Fill on a new thread once a slot is free
+            let job = move || {
+                log::info!("Thread started for filling 2D histogram '{}'", name);
+
+                if let Err(e) = fill_hist2d_values(
+                    &hist,
+                    lf,
+                    filter_expr,
+                    &x_column_name,
+                    &y_column_name,
+                    weight_column.as_deref(),
+                    &thread_cancel,
+                ) {
+                    log::error!(
+                        "Failed to collect LazyFrame for 2D histogram '{}': {}",
                         name,
-                        total_steps,
-                        x_column_name,
-                        y_column_name
+                        e
                     );
-
-                    for (i, (x_value, y_value)) in x_values.iter().zip(y_values.iter()).enumerate()
-                    {
-                        if let (Some(x), Some(y)) = (x_value, y_value) {
-                            let mut hist = hist.lock().unwrap(); // Lock the mutex to access the correct Histogram2D
-                            hist.fill(x, y, i, total_steps); // Pass the progress to the fill method
-                        }
-                    }
-
-                    log::info!("Completed filling 2D histogram '{}'", name);
-
-                    // Optionally: Set progress to None or trigger any final updates here
-                    hist.lock().unwrap().plot_settings.progress = None;
-                } else {
-                    log::error!("Failed to collect LazyFrame for 2D histogram '{}'", name);
+                    fill_errors
+                        .lock()
+                        .unwrap()
+                        .push(format!("Failed to fill '{}': {}", name, e));
                 }
-            });
+            };
 
-            // Store the thread handle in the vector
-            self.handles.push(handle);
+            self.spawn_fill(&fill_thread_name, cancel, vec![pane], job);
 
             return true;
         }
@@ -406,6 +1415,181 @@ impl Histogrammer {
         false
     }
 
+    fn find_fill_pane(&self, request: &FillRequest) -> Option<Pane> {
+        self.tree
+            .tiles
+            .iter()
+            .find_map(|(_id, tile)| match (tile, request) {
+                (
+                    egui_tiles::Tile::Pane(Pane::Histogram(hist)),
+                    FillRequest::Hist1d { name, .. },
+                ) if hist.lock().unwrap().name == *name => Some(Pane::Histogram(Arc::clone(hist))),
+                (
+                    egui_tiles::Tile::Pane(Pane::Histogram2D(hist)),
+                    FillRequest::Hist2d { name, .. },
+                ) if hist.lock().unwrap().name == *name => {
+                    Some(Pane::Histogram2D(Arc::clone(hist)))
+                }
+                _ => None,
+            })
+    }
+
+    // Fill many histograms from one lazyframe. The columns they need are collected once and
+    // every histogram is binned from the in-memory data instead of re-reading the files.
+    pub fn fill_hists_batched(&mut self, lf: &LazyFrame, requests: Vec<FillRequest>) {
+        let mut fills = Vec::new();
+        let mut columns: Vec<String> = Vec::new();
+
+        for request in requests {
+            let Some(pane) = self.find_fill_pane(&request) else {
+                log::error!("Histogram '{}' not found in the tree", request.name());
+                continue;
+            };
+
+            // a bad column would fail the shared collect for every histogram
+            if let Err(e) = validate_fill_columns(lf, &request.columns()) {
+                self.report_fill_error(request.name(), &e);
+                continue;
+            }
+
+            for column in request.columns() {
+                if !columns.iter().any(|existing| existing == column) {
+                    columns.push(column.to_string());
+                }
+            }
+
+            self.column_bindings
+                .insert(request.name().to_string(), request.binding());
+
+            fills.push((request, pane));
+        }
+
+        if fills.is_empty() {
+            return;
+        }
+
+        log::info!(
+            "Starting to fill {} histograms from {} columns",
+            fills.len(),
+            columns.len()
+        );
+
+        let lf = lf.clone();
+        let panes: Vec<Pane> = fills.iter().map(|(_, pane)| pane.clone()).collect();
+        let fill_thread_name = format!("{} histograms", fills.len());
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let thread_cancel = Arc::clone(&cancel);
+
+        let fill_errors = Arc::clone(&self.fill_errors);
+
+        let job = move || {
+            let report = |name: &str, e: &PolarsError| {
+                log::error!("Failed to fill histogram '{}': {}", name, e);
+                fill_errors
+                    .lock()
+                    .unwrap()
+                    .push(format!("Failed to fill '{}': {}", name, e));
+            };
+
+            // the extra filters are evaluated on the full lazyframe, each into its own column
+            let selection: Vec<Expr> = columns
+                .iter()
+                .map(|column| col(column).cast(DataType::Float64))
+                .chain(
+                    fills
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, (request, _))| {
+                            request
+                                .extra_filter()
+                                .map(|filter| filter.clone().alias(&extra_filter_column(index)))
+                        }),
+                )
+                .collect();
+
+            let data = match lf.select(selection).collect() {
+                Ok(df) => df.lazy(),
+                Err(e) => {
+                    for (request, _) in &fills {
+                        report(request.name(), &e);
+                    }
+                    return;
+                }
+            };
+
+            log::info!("Data collected for {} histograms", fills.len());
+
+            fills
+                .par_iter()
+                .enumerate()
+                .for_each(|(index, (request, pane))| {
+                    if thread_cancel.load(Ordering::Relaxed) {
+                        return;
+                    }
+
+                    let extra_filter = request
+                        .extra_filter()
+                        .map(|_| col(&extra_filter_column(index)));
+
+                    let result = match (request, pane) {
+                        (
+                            FillRequest::Hist1d {
+                                column,
+                                weight_column,
+                                ..
+                            },
+                            Pane::Histogram(hist),
+                        ) => prepare_hist1d_fill(&data, column, weight_column.as_deref()).and_then(
+                            |filter_expr| {
+                                fill_hist1d_values(
+                                    hist,
+                                    data.clone(),
+                                    with_extra_filter(filter_expr, extra_filter),
+                                    column,
+                                    weight_column.as_deref(),
+                                    &thread_cancel,
+                                )
+                            },
+                        ),
+                        (
+                            FillRequest::Hist2d {
+                                x_column,
+                                y_column,
+                                weight_column,
+                                ..
+                            },
+                            Pane::Histogram2D(hist),
+                        ) => prepare_hist2d_fill(
+                            hist,
+                            &data,
+                            x_column,
+                            y_column,
+                            weight_column.as_deref(),
+                        )
+                        .and_then(|filter_expr| {
+                            fill_hist2d_values(
+                                hist,
+                                data.clone(),
+                                with_extra_filter(filter_expr, extra_filter),
+                                x_column,
+                                y_column,
+                                weight_column.as_deref(),
+                                &thread_cancel,
+                            )
+                        }),
+                        _ => Ok(()),
+                    };
+
+                    if let Err(e) = result {
+                        report(request.name(), &e);
+                    }
+                });
+        };
+
+        self.spawn_fill(&fill_thread_name, cancel, panes, job);
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn add_fill_hist2d(
         &mut self,
@@ -418,7 +1602,7 @@ impl Histogrammer {
         grid: Option<&str>,
     ) {
         self.add_hist2d(name, bins, range, grid); // Add the histogram.
-        self.fill_hist2d(name, lf, x_column_name, y_column_name); // Fill it with data.
+        self.fill_hist2d(name, lf, x_column_name, y_column_name, None, None); // Fill it with data.
     }
 
     pub fn add_hist1d_with_bin_values(
@@ -442,9 +1626,12 @@ impl Histogrammer {
                 }
             })
         {
-            hist.lock().unwrap().bins = bins;
-            hist.lock().unwrap().underflow = underflow;
-            hist.lock().unwrap().overflow = overflow;
+            let mut hist = hist.lock().unwrap();
+            hist.core.bins.clone_from(&bins);
+            hist.bins = bins;
+            hist.core.underflow = underflow;
+            hist.core.overflow = overflow;
+            hist.update_line_points();
         }
     }
 
@@ -506,36 +1693,648 @@ impl Histogrammer {
         }
     }
 
-    pub fn check_and_join_finished_threads(&mut self) {
-        // Only proceed if there are threads to check
-        if self.handles.is_empty() {
-            return;
+    // Get the fit settings and markers of every 1D histogram that has markers set
+    pub fn get_fit_templates(&self) -> HashMap<String, FitTemplate> {
+        let mut templates = HashMap::new();
+
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                let hist = hist.lock().unwrap();
+                if hist
+                    .plot_settings
+                    .markers
+                    .get_region_marker_positions()
+                    .is_empty()
+                {
+                    continue;
+                }
+
+                templates.insert(
+                    hist.name.clone(),
+                    FitTemplate {
+                        settings: hist.fits.settings.clone(),
+                        markers: hist.plot_settings.markers.clone(),
+                    },
+                );
+            }
+        }
+
+        templates
+    }
+
+    pub fn apply_fit_templates(&mut self, templates: &HashMap<String, FitTemplate>) {
+        for (_id, tile) in self.tree.tiles.iter_mut() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                if let Some(template) = templates.get(&hist.name) {
+                    hist.fits.settings = template.settings.clone();
+                    hist.plot_settings.markers = template.markers.clone();
+                }
+            }
+        }
+    }
+
+    // Fit every 1D histogram in the templates using its markers and store the fit
+    pub fn fit_from_templates(&mut self, templates: &HashMap<String, FitTemplate>) {
+        for (_id, tile) in self.tree.tiles.iter_mut() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                if templates.contains_key(&hist.name) {
+                    hist.fit_gaussians();
+                    hist.fits.store_temp_fit();
+                }
+            }
+        }
+    }
+
+    // The grids and histogram definitions in grid order, the counts are left out
+    pub fn to_template(&self, name: &str) -> AnalysisTemplate {
+        let grid_ids = self
+            .grid_histogram_map
+            .get(&self.name)
+            .map(|(_, grid_ids)| grid_ids.clone())
+            .unwrap_or_default();
+
+        let mut grids = Vec::new();
+        for grid_id in grid_ids {
+            let Some(grid_name) = self.behavior.get_tab_name(&grid_id) else {
+                continue;
+            };
+            let Some((_, histogram_ids)) = self.grid_histogram_map.get(grid_name) else {
+                continue;
+            };
+
+            let histograms: Vec<TemplateHistogram> = histogram_ids
+                .iter()
+                .filter_map(|id| self.tree.tiles.get(*id))
+                .filter_map(|tile| match tile {
+                    egui_tiles::Tile::Pane(Pane::Histogram(hist)) => {
+                        let hist = hist.lock().unwrap();
+                        Some((
+                            hist.name.clone(),
+                            TemplateBinning::Hist1d {
+                                bins: hist.core.bins.len(),
+                                range: hist.range,
+                                edges: hist.core.edges.clone(),
+                            },
+                        ))
+                    }
+                    egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) => {
+                        let hist = hist.lock().unwrap();
+                        // the backup holds the original binning of a rebinned histogram
+                        let bins = hist.backup_bins.as_ref().unwrap_or(&hist.bins);
+                        Some((
+                            hist.name.clone(),
+                            TemplateBinning::Hist2d {
+                                bins: (bins.x, bins.y),
+                                range: (
+                                    (hist.range.x.min, hist.range.x.max),
+                                    (hist.range.y.min, hist.range.y.max),
+                                ),
+                            },
+                        ))
+                    }
+                    _ => None,
+                })
+                .map(|(name, binning)| TemplateHistogram {
+                    binding: self.column_bindings.get(&name).cloned(),
+                    name,
+                    binning,
+                })
+                .collect();
+
+            if !histograms.is_empty() {
+                grids.push(TemplateGrid {
+                    name: grid_name.clone(),
+                    histograms,
+                });
+            }
+        }
+
+        AnalysisTemplate {
+            name: name.to_string(),
+            grids,
+        }
+    }
+
+    // Add the template histograms empty, existing histograms with the same name are reset
+    pub fn apply_template(&mut self, template: &AnalysisTemplate) {
+        for grid in &template.grids {
+            for histogram in &grid.histograms {
+                match &histogram.binning {
+                    TemplateBinning::Hist1d { bins, range, edges } => {
+                        self.add_hist1d(&histogram.name, *bins, *range, Some(&grid.name));
+
+                        if let Some(edges) = edges {
+                            match Histogram::with_edges(&histogram.name, edges.clone()) {
+                                Ok(variable) => {
+                                    if let Some(hist) = self.hist1d_panes().get(&histogram.name) {
+                                        **hist.lock().unwrap() = variable;
+                                    }
+                                }
+                                Err(e) => log::error!("{}", e),
+                            }
+                        }
+                    }
+                    TemplateBinning::Hist2d { bins, range } => {
+                        self.add_hist2d(&histogram.name, *bins, *range, Some(&grid.name));
+                    }
+                }
+
+                if let Some(binding) = &histogram.binding {
+                    self.column_bindings
+                        .insert(histogram.name.clone(), binding.clone());
+                }
+            }
+        }
+
+        log::info!(
+            "Added {} histograms from template '{}'",
+            template.histogram_count(),
+            template.name
+        );
+    }
+
+    // Fill the template histograms that have column bindings from a new lazyframe
+    pub fn fill_template(&mut self, template: &AnalysisTemplate, lf: &LazyFrame) {
+        let requests: Vec<FillRequest> = template
+            .grids
+            .iter()
+            .flat_map(|grid| &grid.histograms)
+            .filter_map(|histogram| {
+                let binding = histogram.binding.as_ref()?;
+                match (&histogram.binning, binding.columns.as_slice()) {
+                    (TemplateBinning::Hist1d { .. }, [column]) => Some(FillRequest::Hist1d {
+                        name: histogram.name.clone(),
+                        column: column.clone(),
+                        weight_column: binding.weight_column.clone(),
+                        extra_filter: None,
+                    }),
+                    (TemplateBinning::Hist2d { .. }, [x_column, y_column]) => {
+                        Some(FillRequest::Hist2d {
+                            name: histogram.name.clone(),
+                            x_column: x_column.clone(),
+                            y_column: y_column.clone(),
+                            weight_column: binding.weight_column.clone(),
+                            extra_filter: None,
+                        })
+                    }
+                    _ => {
+                        log::error!(
+                            "Template histogram '{}' has an invalid column binding",
+                            histogram.name
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        if requests.is_empty() {
+            log::error!(
+                "Template '{}' has no column bindings to fill",
+                template.name
+            );
+            return;
+        }
+
+        self.fill_hists_batched(lf, requests);
+    }
+
+    fn report_fill_error(&self, name: &str, error: &PolarsError) {
+        log::error!("Failed to fill histogram '{}': {}", name, error);
+        self.fill_errors
+            .lock()
+            .unwrap()
+            .push(format!("Failed to fill '{}': {}", name, error));
+    }
+
+    fn fill_errors_ui(&self, ui: &mut egui::Ui) {
+        let mut fill_errors = self.fill_errors.lock().unwrap();
+        if fill_errors.is_empty() {
+            return;
+        }
+
+        egui::Frame::group(ui.style())
+            .fill(ui.visuals().extreme_bg_color)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("{} histogram(s) failed to fill", fill_errors.len()),
+                    );
+                    if ui.small_button("Dismiss").clicked() {
+                        fill_errors.clear();
+                    }
+                });
+
+                for error in fill_errors.iter() {
+                    ui.colored_label(egui::Color32::RED, error);
+                }
+            });
+    }
+
+    // Start a fill thread if fewer than max_concurrent_fills are running, otherwise queue it
+    pub fn spawn_fill(
+        &mut self,
+        name: &str,
+        cancel: Arc<AtomicBool>,
+        panes: Vec<Pane>,
+        job: impl FnOnce() + Send + 'static,
+    ) {
+        let fill = QueuedFill {
+            name: name.to_string(),
+            job: Box::new(job),
+            cancel,
+            panes,
+        };
+
+        if self.handles.len() < self.max_concurrent_fills.max(1) {
+            self.start_fill(fill);
+        } else {
+            log::info!("Queued the fill of '{}'", name);
+            self.queued_fills.push_back(fill);
+        }
+    }
+
+    fn start_fill(&mut self, fill: QueuedFill) {
+        let handle = std::thread::spawn(fill.job);
+        self.handles
+            .push(FillThread::new(&fill.name, handle, fill.cancel).with_panes(fill.panes));
+    }
+
+    // Running or waiting for a slot
+    pub fn is_filling(&self) -> bool {
+        !self.handles.is_empty() || !self.queued_fills.is_empty()
+    }
+
+    pub fn check_and_join_finished_threads(&mut self) {
+        // Only proceed if there are threads to check
+        if !self.is_filling() {
+            return;
+        }
+
+        let mut finished_indices = Vec::new();
+
+        // First, identify all the threads that have finished
+        for (i, thread) in self.handles.iter().enumerate() {
+            if thread.handle.is_finished() {
+                finished_indices.push(i);
+            }
+        }
+
+        // Then, remove and join the finished threads
+        for &i in finished_indices.iter().rev() {
+            let thread = self.handles.swap_remove(i);
+            match thread.handle.join() {
+                Ok(_) => log::info!("A thread completed successfully."),
+                Err(e) => log::error!("A thread encountered an error: {:?}", e),
+            }
+
+            // histograms reset for the refill redo their fits with the new counts
+            if !thread.cancel.load(Ordering::Relaxed) {
+                for pane in &thread.panes {
+                    if let Pane::Histogram(hist) = pane {
+                        let mut hist = hist.lock().unwrap();
+                        if hist.pending_refill_refit {
+                            hist.refit_after_refill();
+                        }
+                    }
+                }
+            }
+        }
+
+        // Start the queued fills in the freed slots
+        while self.handles.len() < self.max_concurrent_fills.max(1) {
+            let Some(fill) = self.queued_fills.pop_front() else {
+                break;
+            };
+            self.start_fill(fill);
+        }
+    }
+
+    // Ask every fill thread to stop, the threads are joined once they exit
+    pub fn cancel_all_fills(&mut self) {
+        for thread in &self.handles {
+            thread.cancel.store(true, Ordering::Relaxed);
+        }
+
+        log::info!(
+            "Cancelling {} histogram fill(s) and {} queued",
+            self.handles.len(),
+            self.queued_fills.len()
+        );
+        self.queued_fills.clear();
+    }
+
+    // Cancel the fills that only fill the given panes, fills shared with other panes keep running
+    fn cancel_fills_of(&mut self, panes: &[Pane]) {
+        let only_fills_removed = |fill_panes: &[Pane]| {
+            !fill_panes.is_empty()
+                && fill_panes
+                    .iter()
+                    .all(|fill_pane| panes.iter().any(|pane| pane.is_same(fill_pane)))
+        };
+
+        let mut cancelled = 0;
+        for thread in &self.handles {
+            if only_fills_removed(&thread.panes) {
+                thread.cancel.store(true, Ordering::Relaxed);
+                cancelled += 1;
+            }
+        }
+
+        let queued = self.queued_fills.len();
+        self.queued_fills
+            .retain(|fill| !only_fills_removed(&fill.panes));
+        let dequeued = queued - self.queued_fills.len();
+
+        if cancelled + dequeued > 0 {
+            log::info!(
+                "Cancelling {} histogram fill(s) and {} queued",
+                cancelled,
+                dequeued
+            );
+        }
+    }
+
+    pub fn start_batch_export(&mut self) {
+        let Some(directory) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+
+        let mut used_names = HashSet::new();
+        let mut queue = Vec::new();
+
+        for (id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(pane) = tile {
+                if !self.tree.tiles.is_visible(*id) {
+                    continue;
+                }
+
+                // avoid overwriting files when two names sanitize to the same file name
+                let base_name = sanitize_file_name(&pane.name());
+                let mut file_name = base_name.clone();
+                let mut index = 1;
+                while !used_names.insert(file_name.clone()) {
+                    file_name = format!("{}_{}", base_name, index);
+                    index += 1;
+                }
+
+                queue.push((*id, directory.join(format!("{}.png", file_name))));
+            }
+        }
+
+        // the queue is popped from the back
+        queue.reverse();
+
+        log::info!("Exporting {} plots to {:?}", queue.len(), directory);
+
+        self.batch_export = BatchExport {
+            total: queue.len(),
+            queue,
+            ..Default::default()
+        };
+    }
+
+    fn update_batch_export(&mut self) {
+        if let Some((tile_id, path)) = self.batch_export.current.clone() {
+            let result = match self.tree.tiles.get(tile_id) {
+                Some(egui_tiles::Tile::Pane(pane)) => pane.export_result(),
+                _ => Some(Err("pane no longer exists".to_string())),
+            };
+
+            match result {
+                Some(Ok(_)) => {
+                    self.batch_export.exported += 1;
+                    self.batch_export.current = None;
+                }
+                Some(Err(e)) => {
+                    self.batch_export
+                        .failures
+                        .push(format!("{}: {}", path.display(), e));
+                    self.batch_export.current = None;
+                }
+                None => {
+                    self.batch_export.frames_waited += 1;
+
+                    // give up if the pane is never drawn
+                    if self.batch_export.frames_waited > 60 {
+                        if let Some(egui_tiles::Tile::Pane(pane)) = self.tree.tiles.get(tile_id) {
+                            pane.cancel_export();
+                        }
+                        self.batch_export
+                            .failures
+                            .push(format!("{}: plot was not drawn", path.display()));
+                        self.batch_export.current = None;
+                    }
+                }
+            }
+        }
+
+        if self.batch_export.current.is_none() {
+            if let Some((tile_id, path)) = self.batch_export.queue.pop() {
+                self.tree.make_active(|id, _| id == tile_id);
+
+                if let Some(egui_tiles::Tile::Pane(pane)) = self.tree.tiles.get(tile_id) {
+                    pane.request_export(path.clone());
+                }
+
+                self.batch_export.current = Some((tile_id, path));
+                self.batch_export.frames_waited = 0;
+            }
+        }
+    }
+
+    fn batch_export_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .button("Export All as PNG")
+            .on_hover_text("Save every visible histogram as a PNG in a folder")
+            .clicked()
+        {
+            self.start_batch_export();
+        }
+
+        let export = &self.batch_export;
+        if export.total == 0 {
+            return;
+        }
+
+        let done = export.exported + export.failures.len();
+        ui.add(
+            egui::ProgressBar::new(done as f32 / export.total as f32)
+                .text(format!("Exported {}/{}", export.exported, export.total)),
+        );
+
+        if !export.failures.is_empty() {
+            ui.collapsing(format!("{} Failed", export.failures.len()), |ui| {
+                for failure in &export.failures {
+                    ui.label(failure);
+                }
+            });
+        }
+
+        if !export.is_running() && ui.button("Clear").clicked() {
+            self.batch_export = BatchExport::default();
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        // Check and join finished threads
+        self.check_and_join_finished_threads();
+
+        if self.batch_export.is_running() {
+            self.update_batch_export();
+        }
+
+        self.add_pending_cut_projections();
+        self.add_pending_duplicates();
+        self.propagate_pending_cuts();
+        self.clone_pending_fits();
+
+        self.fill_errors_ui(ui);
+
+        if self.is_filling() {
+            ui.horizontal(|ui| {
+                ui.label("Filling histograms");
+                ui.add(egui::widgets::Spinner::default());
+
+                if ui
+                    .small_button("Cancel")
+                    .on_hover_text("Stop filling, the histograms keep the counts filled so far")
+                    .clicked()
+                {
+                    self.cancel_all_fills();
+                }
+            });
+        }
+
+        self.tree.ui(&mut self.behavior, ui);
+    }
+
+    pub fn apply_colormap_to_all(&mut self) {
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                hist.plot_settings.colormap = self.global_colormap.clone();
+                hist.plot_settings.colormap_options = self.global_colormap_options;
+                hist.plot_settings.recalculate_image = true;
+            }
+        }
+
+        self.global_colormap_applied = true;
+    }
+
+    // Give every 2D histogram the current custom colormaps for its colormap menu
+    pub fn sync_custom_colormaps(&self) {
+        let custom_colormaps = Arc::new(self.custom_colormaps.clone());
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                hist.lock().unwrap().plot_settings.custom_colormaps = Arc::clone(&custom_colormaps);
+            }
         }
+    }
 
-        let mut finished_indices = Vec::new();
+    fn custom_colormaps_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label("Custom Colormaps");
 
-        // First, identify all the threads that have finished
-        for (i, handle) in self.handles.iter().enumerate() {
-            if handle.is_finished() {
-                finished_indices.push(i);
+        let mut changed = false;
+        if ui
+            .button("Load Colormap")
+            .on_hover_text(
+                "CSV lines of scalar,r,g,b with scalars from 0 to 1, or a JSON with a name and stops",
+            )
+            .clicked()
+        {
+            if let Some(colormap) = CustomColormap::load_from_file() {
+                // reloading a map replaces the old stops
+                self.custom_colormaps
+                    .retain(|custom| custom.name != colormap.name);
+                self.custom_colormaps.push(colormap);
+                changed = true;
             }
         }
 
-        // Then, remove and join the finished threads
-        for &i in finished_indices.iter().rev() {
-            let handle = self.handles.swap_remove(i);
-            match handle.join() {
-                Ok(_) => log::info!("A thread completed successfully."),
-                Err(e) => log::error!("A thread encountered an error: {:?}", e),
-            }
+        let mut to_remove = None;
+        for (index, custom) in self.custom_colormaps.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(&custom.name);
+                if ui.small_button("X").clicked() {
+                    to_remove = Some(index);
+                }
+            });
+        }
+
+        // histograms already using a removed map keep their copy of it
+        if let Some(index) = to_remove {
+            self.custom_colormaps.remove(index);
+            changed = true;
+        }
+
+        if changed {
+            self.sync_custom_colormaps();
         }
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
-        // Check and join finished threads
-        self.check_and_join_finished_threads();
+    fn global_colormap_ui(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button("2D Colormap", |ui| {
+            // the images are only recalculated when the colormap is applied
+            let mut recalculate_image = false;
+            self.global_colormap_options
+                .ui(ui, &mut recalculate_image, u64::MAX);
+            ui.separator();
+            self.global_colormap
+                .color_maps_ui(ui, &mut recalculate_image, &self.custom_colormaps);
+            ui.separator();
+
+            self.custom_colormaps_ui(ui);
+            ui.separator();
+
+            if ui
+                .button("Apply to All 2D Histograms")
+                .on_hover_text("New 2D histograms will also use this colormap")
+                .clicked()
+            {
+                self.apply_colormap_to_all();
+                ui.close_menu();
+            }
+        });
+    }
 
-        self.tree.ui(&mut self.behavior, ui);
+    // Lists the running fills so stuck histograms stand out when filling many at once
+    fn fill_threads_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Max Concurrent Fills");
+            ui.add(egui::DragValue::new(&mut self.max_concurrent_fills).range(1..=256))
+                .on_hover_text("More fills wait in a queue until a running one finishes");
+        });
+
+        if !self.is_filling() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label(format!("Calculating {} Histograms", self.handles.len()));
+            ui.add(egui::widgets::Spinner::default());
+            if !self.queued_fills.is_empty() {
+                ui.label(format!("{} queued", self.queued_fills.len()));
+            }
+        });
+
+        egui::ScrollArea::vertical()
+            .id_salt("fill_threads")
+            .max_height(200.0)
+            .show(ui, |ui| {
+                for thread in &self.handles {
+                    if thread.panes.is_empty() {
+                        fill_progress_row(ui, &thread.name, None);
+                    }
+
+                    for pane in &thread.panes {
+                        fill_progress_row(ui, &pane.name(), pane.progress());
+                    }
+                }
+            });
     }
 
     pub fn side_panel_ui(&mut self, ui: &mut egui::Ui) {
@@ -553,28 +2352,150 @@ impl Histogrammer {
             //     }
             // });
 
-            if !self.handles.is_empty() {
+            self.fill_threads_ui(ui);
+
+            if let Some(root) = self.tree.root() {
+                ui.horizontal(|ui| {
+                    if ui.button("Reorganize").clicked() {
+                        self.reorganize();
+                    }
+
+                    if self.confirm_clear_all {
+                        ui.label("Remove every histogram and grid?");
+                        if ui.button("Confirm").clicked() {
+                            self.clear_all();
+                            self.confirm_clear_all = false;
+                        }
+                        if ui.button("Cancel").clicked() {
+                            self.confirm_clear_all = false;
+                        }
+                    } else if ui
+                        .button("Clear All")
+                        .on_hover_text("Remove every histogram and grid")
+                        .clicked()
+                    {
+                        self.confirm_clear_all = true;
+                    }
+                });
+
+                self.batch_export_ui(ui);
+                self.global_colormap_ui(ui);
+                self.arithmetic_ui(ui);
+                self.background_subtraction_ui(ui);
+                self.batch_fit_ui(ui);
+
                 ui.horizontal(|ui| {
-                    ui.label("Calculating Histograms");
-                    ui.add(egui::widgets::Spinner::default());
+                    ui.label("Filter:");
+                    ui.text_edit_singleline(&mut self.tree_filter)
+                        .on_hover_text("Search histograms by name or notes");
                 });
-            }
 
-            if let Some(root) = self.tree.root() {
-                if ui.button("Reorganize").clicked() {
-                    self.reorganize();
+                let mut rename_request = None;
+                let mut close_request = None;
+                let mut layout_request = None;
+                tree_ui(
+                    ui,
+                    &mut self.behavior,
+                    &mut self.tree.tiles,
+                    root,
+                    &self.tree_filter,
+                    &self.grid_configs,
+                    &mut self.renaming,
+                    &mut rename_request,
+                    &mut close_request,
+                    &mut layout_request,
+                );
+
+                if let Some((container_id, kind)) = layout_request {
+                    self.set_container_type(container_id, kind);
+                }
+
+                if let Some((grid_id, new_name)) = rename_request {
+                    self.rename_grid(grid_id, new_name);
                 }
 
-                tree_ui(ui, &mut self.behavior, &mut self.tree.tiles, root);
+                if let Some(grid_id) = close_request {
+                    self.close_grid(grid_id);
+                }
             }
         });
     }
 
+    // Grids are looked up by their current tab name, so this keeps working after renames
+    pub fn get_or_create_grid(&mut self, grid_name: &str) -> TileId {
+        if let Some((grid_id, _)) = self.grid_histogram_map.get(grid_name) {
+            *grid_id
+        } else {
+            self.create_grid(grid_name.to_string())
+        }
+    }
+
+    pub fn get_or_create_other_grid(&mut self) -> TileId {
+        self.get_or_create_grid("Other")
+    }
+
+    pub fn rename_grid(&mut self, grid_id: TileId, new_name: String) {
+        let new_name = new_name.trim().to_string();
+        if new_name.is_empty() {
+            log::error!("Grid name cannot be empty");
+            return;
+        }
+
+        let Some(old_name) = self.behavior.get_tab_name(&grid_id).cloned() else {
+            log::error!("No grid with ID {:?}", grid_id);
+            return;
+        };
+
+        if old_name == new_name {
+            return;
+        }
+
+        if self.grid_histogram_map.contains_key(&new_name) {
+            log::error!("A grid named '{}' already exists", new_name);
+            return;
+        }
+
+        self.behavior
+            .set_tile_tab_mapping(grid_id, new_name.clone());
+
+        if let Some(entry) = self.grid_histogram_map.remove(&old_name) {
+            self.grid_histogram_map.insert(new_name.clone(), entry);
+        }
+        if let Some(config) = self.grid_configs.remove(&old_name) {
+            self.grid_configs.insert(new_name.clone(), config);
+        }
+
+        // The main container is keyed by the Histogrammer's name
+        if self.tree.root == Some(grid_id) {
+            self.name = new_name;
+        }
+    }
+
     pub fn create_grid(&mut self, tab_name: String) -> egui_tiles::TileId {
-        // Create a new grid container
-        let grid = egui_tiles::Grid::new(vec![]);
-        let grid_container = egui_tiles::Container::Grid(grid);
-        let grid_id = self.tree.tiles.insert_new(grid_container.into());
+        // laid out with the config saved under its name
+        let config = self
+            .grid_configs
+            .get(&tab_name)
+            .copied()
+            .unwrap_or_default();
+        self.create_container(tab_name, ContainerType::Grid(config))
+    }
+
+    // Add a named container for histograms as a new tab of the main container. Histograms are
+    // added to it by name like any grid.
+    pub fn create_container(&mut self, tab_name: String, kind: ContainerType) -> TileId {
+        let container = match kind {
+            ContainerType::Grid(config) => {
+                let mut grid = egui_tiles::Grid::new(vec![]);
+                grid.layout = grid_layout(&config);
+                self.grid_configs.insert(tab_name.clone(), config);
+                egui_tiles::Container::Grid(grid)
+            }
+            ContainerType::Tabs => egui_tiles::Container::new_tabs(vec![]),
+            ContainerType::Vertical => egui_tiles::Container::new_vertical(vec![]),
+            ContainerType::Horizontal => egui_tiles::Container::new_horizontal(vec![]),
+        };
+        let grid_id = self.tree.tiles.insert_new(container.into());
 
         // Create a new tab and place the grid inside it
         let tab = egui_tiles::Tabs::new(vec![grid_id]);
@@ -625,6 +2546,189 @@ impl Histogrammer {
         grid_id
     }
 
+    // Switch a named container between a grid, tabs, and a column or row of panes
+    pub fn set_container_type(&mut self, container_id: TileId, kind: ContainerType) {
+        if self.tree.root == Some(container_id) {
+            log::error!("The layout of the main container can't be changed");
+            return;
+        }
+
+        let Some(name) = self.behavior.get_tab_name(&container_id).cloned() else {
+            log::error!("No container with ID {:?}", container_id);
+            return;
+        };
+
+        match self.tree.tiles.get_mut(container_id) {
+            Some(egui_tiles::Tile::Container(container)) => {
+                if container.kind() != kind.kind() {
+                    container.set_kind(kind.kind());
+                }
+            }
+            _ => {
+                log::error!("'{}' is not a container", name);
+                return;
+            }
+        }
+
+        if let ContainerType::Grid(config) = kind {
+            self.set_grid_config(&name, config);
+        }
+    }
+
+    // Lay out an existing grid and its 2D plots, the config is kept for panes added later
+    pub fn set_grid_config(&mut self, grid_name: &str, config: GridConfig) {
+        self.grid_configs.insert(grid_name.to_string(), config);
+
+        let Some((grid_id, histogram_ids)) = self.grid_histogram_map.get(grid_name) else {
+            return;
+        };
+
+        if let Some(egui_tiles::Tile::Container(egui_tiles::Container::Grid(grid))) =
+            self.tree.tiles.get_mut(*grid_id)
+        {
+            let layout = grid_layout(&config);
+            if grid.layout != layout {
+                // shares from the old column count no longer line up
+                grid.layout = layout;
+                grid.col_shares.clear();
+                grid.row_shares.clear();
+            }
+        }
+
+        for histogram_id in histogram_ids {
+            if let Some(egui_tiles::Tile::Pane(Pane::Histogram2D(hist))) =
+                self.tree.tiles.get(*histogram_id)
+            {
+                hist.lock().unwrap().plot_settings.view_aspect = config.aspect_2d;
+            }
+        }
+    }
+
+    // Revalidate the grid map against the tiles after deserialization, egui_tiles may have
+    // pruned or replaced containers so drop the dangling IDs and re-associate the panes by tab name
+    pub fn rebuild_after_load(&mut self) {
+        let tiles = &self.tree.tiles;
+
+        // Forget tab names of tiles that no longer exist
+        self.behavior
+            .tile_map
+            .retain(|tile_id, _| tiles.get(*tile_id).is_some());
+
+        let old_map = std::mem::take(&mut self.grid_histogram_map);
+        let mut claimed: HashSet<TileId> = HashSet::new();
+        let mut new_map: HashMap<String, (TileId, Vec<TileId>)> = HashMap::new();
+
+        // Every named container is a grid, its panes are the histograms that belong to it
+        for (tile_id, tab_name) in &self.behavior.tile_map {
+            if Some(*tile_id) == self.tree.root || tab_name == &self.name {
+                continue;
+            }
+
+            if let Some(egui_tiles::Tile::Container(container)) = tiles.get(*tile_id) {
+                let pane_ids: Vec<TileId> = container
+                    .children()
+                    .filter(|child| matches!(tiles.get(**child), Some(egui_tiles::Tile::Pane(_))))
+                    .copied()
+                    .collect();
+                claimed.extend(pane_ids.iter().copied());
+                new_map.insert(tab_name.clone(), (*tile_id, pane_ids));
+            }
+        }
+
+        // Panes the user dragged out of their grid keep their old association
+        for (grid_name, (_grid_id, histogram_ids)) in &old_map {
+            if let Some((_, pane_ids)) = new_map.get_mut(grid_name) {
+                for histogram_id in histogram_ids {
+                    if !claimed.contains(histogram_id)
+                        && matches!(tiles.get(*histogram_id), Some(egui_tiles::Tile::Pane(_)))
+                    {
+                        claimed.insert(*histogram_id);
+                        pane_ids.push(*histogram_id);
+                    }
+                }
+            }
+        }
+
+        // The main container lists the grids
+        if let Some(root_id) = self.tree.root {
+            if matches!(tiles.get(root_id), Some(egui_tiles::Tile::Container(_))) {
+                let grid_ids = new_map.values().map(|(grid_id, _)| *grid_id).collect();
+                new_map.insert(self.name.clone(), (root_id, grid_ids));
+            }
+        }
+
+        let dropped = old_map
+            .keys()
+            .filter(|grid_name| !new_map.contains_key(*grid_name))
+            .count();
+        if dropped > 0 {
+            log::info!("Dropped {} grids that no longer exist in the tree", dropped);
+        }
+
+        self.grid_histogram_map = new_map;
+
+        // the 2D histograms don't save the shared list of custom colormaps
+        self.sync_custom_colormaps();
+    }
+
+    // Remove every histogram and grid, running fills are cancelled since their panes are gone
+    pub fn clear_all(&mut self) {
+        self.cancel_all_fills();
+        self.tree = egui_tiles::Tree::empty("Empty tree");
+        self.behavior.tile_map.clear();
+        self.grid_histogram_map.clear();
+        self.renaming = None;
+    }
+
+    // Remove a grid and the histograms in it
+    pub fn close_grid(&mut self, grid_id: TileId) {
+        // the main container holds every grid
+        if self.tree.root == Some(grid_id) {
+            self.clear_all();
+            return;
+        }
+
+        let grid_name = self.behavior.get_tab_name(&grid_id).cloned();
+        let removed_tiles = self.tree.remove_recursively(grid_id);
+        let removed = removed_tiles.len();
+        let removed_panes: Vec<Pane> = removed_tiles
+            .into_iter()
+            .filter_map(|tile| match tile {
+                egui_tiles::Tile::Pane(pane) => Some(pane),
+                egui_tiles::Tile::Container(_) => None,
+            })
+            .collect();
+        self.cancel_fills_of(&removed_panes);
+
+        let tiles = &self.tree.tiles;
+        self.behavior
+            .tile_map
+            .retain(|tile_id, _| tiles.get(*tile_id).is_some());
+        if let Some(grid_name) = &grid_name {
+            self.grid_histogram_map.remove(grid_name);
+            self.grid_configs.remove(grid_name);
+        }
+        for (_grid_id, histogram_ids) in self.grid_histogram_map.values_mut() {
+            histogram_ids.retain(|id| tiles.get(*id).is_some());
+        }
+
+        log::info!(
+            "Closed grid '{}' with {} tiles",
+            grid_name.as_deref().unwrap_or("Unnamed"),
+            removed
+        );
+
+        // Nothing left to show, start over so the root doesn't point at a removed container
+        let root_is_empty = match self.tree.root.and_then(|root| self.tree.tiles.get(root)) {
+            Some(egui_tiles::Tile::Container(container)) => container.is_empty(),
+            Some(egui_tiles::Tile::Pane(_)) => false,
+            None => true,
+        };
+        if root_is_empty {
+            self.clear_all();
+        }
+    }
+
     pub fn reorganize(&mut self) {
         // Iterate over each entry in the grid_histogram_map
         for (grid_name, (grid_id, histogram_ids)) in &self.grid_histogram_map {
@@ -672,25 +2776,60 @@ impl Histogrammer {
                     // Set the correct column names in the Cut struct
                     new_cut.x_column = hist.plot_settings.cuts.x_column.clone();
                     new_cut.y_column = hist.plot_settings.cuts.y_column.clone();
+                    new_cut.range_edges = hist.plot_settings.cuts.range_edges;
 
                     cut_handler.cuts.push(new_cut);
                 }
             }
         }
     }
+
+    // Move the 1D gates made from 2D cuts into the cut handler
+    pub fn retrieve_range_cuts(&self, cut_handler: &mut CutHandler) {
+        for (_id, tile) in self.tree.tiles.iter() {
+            if let egui_tiles::Tile::Pane(Pane::Histogram2D(hist)) = tile {
+                let mut hist = hist.lock().unwrap();
+                for cut in hist.plot_settings.cuts.pending_range_cuts.drain(..) {
+                    log::info!(
+                        "Added 1D gate '{}' on {} [{}, {}]",
+                        cut.name,
+                        cut.column,
+                        cut.min,
+                        cut.max
+                    );
+                    cut_handler.range_cuts.push(cut);
+                }
+            }
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
+#[allow(clippy::too_many_arguments)]
 fn tree_ui(
     ui: &mut egui::Ui,
-    behavior: &mut dyn egui_tiles::Behavior<Pane>,
+    behavior: &mut TreeBehavior,
     tiles: &mut egui_tiles::Tiles<Pane>,
     tile_id: egui_tiles::TileId,
+    filter: &str,
+    grid_configs: &HashMap<String, GridConfig>,
+    renaming: &mut Option<(TileId, String)>,
+    rename_request: &mut Option<(TileId, String)>,
+    close_request: &mut Option<TileId>,
+    layout_request: &mut Option<(TileId, ContainerType)>,
 ) {
+    // Skip tiles that don't contain a histogram matching the filter
+    if !filter.is_empty() && !tile_matches_filter(tiles, tile_id, filter) {
+        return;
+    }
+
     // Get the name BEFORE we remove the tile below!
     let text = format!(
         "{} - {tile_id:?}",
-        behavior.tab_title_for_tile(tiles, tile_id).text()
+        egui_tiles::Behavior::tab_title_for_tile(behavior, tiles, tile_id).text()
     );
+    let tab_name = behavior.get_tab_name(&tile_id).cloned();
+    let is_root = tiles.is_root(tile_id);
 
     // Temporarily remove the tile to circumvent the borrowchecker
     let Some(mut tile) = tiles.remove(tile_id) else {
@@ -704,16 +2843,77 @@ fn tree_ui(
         false,
     )
     .show_header(ui, |ui| {
-        ui.label(text);
+        match renaming {
+            Some((renaming_id, new_name)) if *renaming_id == tile_id => {
+                let response = ui.text_edit_singleline(new_name);
+                response.request_focus();
+
+                if ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    *rename_request = Some((tile_id, new_name.clone()));
+                    *renaming = None;
+                } else if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                    *renaming = None;
+                }
+            }
+            _ => {
+                let response = ui.add(egui::Label::new(text).sense(egui::Sense::click()));
+
+                // Only named grids and tabs can be renamed
+                if let Some(tab_name) = &tab_name {
+                    if response
+                        .on_hover_text("Double-click to rename")
+                        .double_clicked()
+                    {
+                        *renaming = Some((tile_id, tab_name.clone()));
+                    }
+                }
+            }
+        }
+
         let mut visible = tiles.is_visible(tile_id);
         ui.checkbox(&mut visible, "Visible");
         tiles.set_visible(tile_id, visible);
+
+        if let (Some(tab_name), egui_tiles::Tile::Container(container), false) =
+            (&tab_name, &tile, is_root)
+        {
+            ui.menu_button("Layout", |ui| {
+                let config = grid_configs.get(tab_name).copied().unwrap_or_default();
+                let mut kind = ContainerType::from_container(container, config);
+                if kind.ui(ui) {
+                    *layout_request = Some((tile_id, kind));
+                }
+            });
+        }
+
+        if tab_name.is_some()
+            && ui
+                .small_button("Close tab")
+                .on_hover_text("Remove this grid and its histograms")
+                .clicked()
+        {
+            *close_request = Some(tile_id);
+        }
     })
     .body(|ui| match &mut tile {
-        egui_tiles::Tile::Pane(_) => {}
+        egui_tiles::Tile::Pane(pane) => {
+            ui.label("Notes:");
+            pane.notes_ui(ui);
+        }
         egui_tiles::Tile::Container(container) => {
             for &child in container.children() {
-                tree_ui(ui, behavior, tiles, child);
+                tree_ui(
+                    ui,
+                    behavior,
+                    tiles,
+                    child,
+                    filter,
+                    grid_configs,
+                    renaming,
+                    rename_request,
+                    close_request,
+                    layout_request,
+                );
             }
         }
     });
@@ -721,3 +2921,255 @@ fn tree_ui(
     // Put the tile back
     tiles.insert(tile_id, tile);
 }
+
+fn grid_layout(config: &GridConfig) -> egui_tiles::GridLayout {
+    match config.columns {
+        Some(columns) => egui_tiles::GridLayout::Columns(columns.max(1)),
+        None => egui_tiles::GridLayout::Auto,
+    }
+}
+
+fn tile_matches_filter(
+    tiles: &egui_tiles::Tiles<Pane>,
+    tile_id: egui_tiles::TileId,
+    filter: &str,
+) -> bool {
+    match tiles.get(tile_id) {
+        Some(egui_tiles::Tile::Pane(pane)) => pane.matches_filter(filter),
+        Some(egui_tiles::Tile::Container(container)) => container
+            .children()
+            .any(|&child| tile_matches_filter(tiles, child, filter)),
+        None => false,
+    }
+}
+
+// A progress bar while filling, a spinner while the data is still being collected
+fn fill_progress_row(ui: &mut egui::Ui, name: &str, progress: Option<f32>) {
+    ui.horizontal(|ui| {
+        match progress {
+            Some(progress) => {
+                ui.add(
+                    egui::ProgressBar::new(progress)
+                        .desired_width(80.0)
+                        .show_percentage(),
+                );
+            }
+            None => {
+                ui.add(egui::widgets::Spinner::default());
+            }
+        }
+        ui.label(name);
+    });
+}
+
+fn with_extra_filter(filter_expr: Expr, extra_filter: Option<Expr>) -> Expr {
+    match extra_filter {
+        Some(extra_filter) => filter_expr.and(extra_filter),
+        None => filter_expr,
+    }
+}
+
+// Name of the column holding the extra filter of a batched fill
+fn extra_filter_column(index: usize) -> String {
+    format!("__extra_filter_{}", index)
+}
+
+// Validates the columns, returns the filter for the values that are filled. Values outside the
+// range are kept so the fill counts them in the overflow and underflow.
+fn prepare_hist1d_fill(
+    lf: &LazyFrame,
+    column_name: &str,
+    weight_column: Option<&str>,
+) -> PolarsResult<Expr> {
+    let mut columns = vec![column_name];
+    columns.extend(weight_column);
+    validate_fill_columns(lf, &columns)?;
+
+    // nulls and NaNs are not entries
+    Ok(col(column_name).cast(DataType::Float64).is_not_nan())
+}
+
+// Collects the values and fills them in batches so the mutex is only locked once per batch
+fn fill_hist1d_values(
+    hist: &Mutex<Box<Histogram>>,
+    lf: LazyFrame,
+    filter_expr: Expr,
+    column_name: &str,
+    weight_column: Option<&str>,
+    cancel: &AtomicBool,
+) -> PolarsResult<()> {
+    let name = hist.lock().unwrap().name.clone();
+
+    let mut columns = vec![col(column_name).cast(DataType::Float64)];
+    if let Some(weight_column) = weight_column {
+        columns.push(col(weight_column).cast(DataType::Float64));
+    }
+
+    let df = lf.filter(filter_expr).select(columns).collect()?;
+
+    log::info!("Data collected for histogram '{}'", name);
+
+    let series = df.column(column_name)?;
+    let (values, weights): (Vec<f64>, Vec<f64>) = match weight_column {
+        Some(weight_column) => series
+            .f64()?
+            .into_iter()
+            .zip(df.column(weight_column)?.f64()?)
+            .filter_map(|(value, weight)| Some((value?, weight?)))
+            .unzip(),
+        None => (series.f64()?.into_iter().flatten().collect(), Vec::new()),
+    };
+    let total_steps = values.len();
+
+    log::info!(
+        "Histogram '{}' will be filled with {} values from column '{}'",
+        name,
+        total_steps,
+        column_name
+    );
+
+    let mut filled = 0;
+    for batch in values.chunks(FILL_BATCH_SIZE) {
+        if cancel.load(Ordering::Relaxed) {
+            log::info!("Cancelled filling histogram '{}'", name);
+            break;
+        }
+
+        let mut hist = hist.lock().unwrap();
+        if weight_column.is_some() {
+            hist.fill_batch_weighted(batch, &weights[filled..filled + batch.len()]);
+        } else {
+            hist.fill_batch(batch);
+        }
+
+        filled += batch.len();
+        hist.plot_settings.progress = Some(filled as f32 / total_steps as f32);
+    }
+
+    log::info!("Completed filling histogram '{}'", name);
+
+    hist.lock().unwrap().plot_settings.progress = None;
+
+    Ok(())
+}
+
+// Validates the columns, clears the overflow and underflow and sets the cut columns, returns the
+// filter for the values that are filled. Values outside the range are kept so the fill counts
+// them in the overflow and underflow.
+fn prepare_hist2d_fill(
+    hist: &Mutex<Box<Histogram2D>>,
+    lf: &LazyFrame,
+    x_column_name: &str,
+    y_column_name: &str,
+    weight_column: Option<&str>,
+) -> PolarsResult<Expr> {
+    let mut columns = vec![x_column_name, y_column_name];
+    columns.extend(weight_column);
+    validate_fill_columns(lf, &columns)?;
+
+    let mut hist = hist.lock().unwrap();
+    hist.overflow = (0, 0);
+    hist.underflow = (0, 0);
+
+    let range_edges = hist.range_edges;
+    hist.plot_settings.cuts.x_column = x_column_name.to_string();
+    hist.plot_settings.cuts.y_column = y_column_name.to_string();
+    hist.plot_settings.cuts.range_edges = range_edges;
+
+    // nulls and NaNs are not entries
+    Ok(col(x_column_name)
+        .cast(DataType::Float64)
+        .is_not_nan()
+        .and(col(y_column_name).cast(DataType::Float64).is_not_nan()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fill_hist2d_values(
+    hist: &Mutex<Box<Histogram2D>>,
+    lf: LazyFrame,
+    filter_expr: Expr,
+    x_column_name: &str,
+    y_column_name: &str,
+    weight_column: Option<&str>,
+    cancel: &AtomicBool,
+) -> PolarsResult<()> {
+    let name = hist.lock().unwrap().name.clone();
+
+    let mut columns = vec![
+        col(x_column_name).cast(DataType::Float64),
+        col(y_column_name).cast(DataType::Float64),
+    ];
+    if let Some(weight_column) = weight_column {
+        columns.push(col(weight_column).cast(DataType::Float64));
+    }
+
+    let df = lf.filter(filter_expr).select(columns).collect()?;
+
+    log::info!("Data collected for 2D histogram '{}'", name);
+
+    let x_values = df.column(x_column_name)?.f64()?;
+    let y_values = df.column(y_column_name)?.f64()?;
+    let weights = match weight_column {
+        Some(weight_column) => Some(df.column(weight_column)?.f64()?),
+        None => None,
+    };
+    let total_steps = x_values.len();
+
+    log::info!(
+        "2D Histogram '{}' will be filled with {} value pairs from columns '{}' and '{}'",
+        name,
+        total_steps,
+        x_column_name,
+        y_column_name
+    );
+
+    for (i, (x_value, y_value)) in x_values.iter().zip(y_values.iter()).enumerate() {
+        // Checking the flag every value is too slow
+        if i % 100_000 == 0 && cancel.load(Ordering::Relaxed) {
+            log::info!("Cancelled filling 2D histogram '{}'", name);
+            break;
+        }
+
+        if let (Some(x), Some(y)) = (x_value, y_value) {
+            let mut hist = hist.lock().unwrap(); // Lock the mutex to access the correct Histogram2D
+            match weights {
+                Some(weights) => {
+                    if let Some(weight) = weights.get(i) {
+                        hist.fill_weighted(x, y, weight, i, total_steps);
+                    }
+                }
+                None => hist.fill(x, y, i, total_steps), // Pass the progress to the fill method
+            }
+        }
+    }
+
+    log::info!("Completed filling 2D histogram '{}'", name);
+
+    let mut hist = hist.lock().unwrap();
+    // the mean weights can go down during the fill, so the running limits are only bounds
+    if hist.bins.average_weights {
+        hist.bins.update_min_max();
+    }
+    hist.plot_settings.progress = None;
+
+    Ok(())
+}
+
+// Integer columns are fine, the fill threads cast every column to f64
+fn validate_fill_columns(lf: &LazyFrame, columns: &[&str]) -> PolarsResult<()> {
+    let schema = lf.clone().schema()?;
+
+    for column in columns {
+        match schema.get(column) {
+            Some(dtype) if dtype.is_numeric() => {}
+            Some(dtype) => polars_bail!(
+                SchemaMismatch: "column '{}' has type {} and cannot be cast to f64",
+                column,
+                dtype
+            ),
+            None => polars_bail!(ColumnNotFound: "column '{}' does not exist", column),
+        }
+    }
+
+    Ok(())
+}
@@ -1,5 +1,9 @@
+pub mod batch_fit;
+pub mod core;
 pub mod histo1d;
 pub mod histo2d;
 pub mod histogrammer;
 pub mod pane;
+pub mod root_export;
+pub mod template;
 pub mod tree;
@@ -9,7 +9,11 @@ pub enum Pane {
 }
 
 impl Pane {
-    pub fn ui(&mut self, ui: &mut egui::Ui) -> egui_tiles::UiResponse {
+    pub fn ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        placeholder_while_filling: bool,
+    ) -> egui_tiles::UiResponse {
         let hist_name = match self {
             Pane::Histogram(hist) => hist.lock().unwrap().name.clone(),
             Pane::Histogram2D(hist) => hist.lock().unwrap().name.clone(),
@@ -21,29 +25,135 @@ impl Pane {
             .frame(false);
 
         if ui.add(button.sense(egui::Sense::drag())).drag_started() {
-            match self {
-                Pane::Histogram(hist) => {
-                    hist.lock().unwrap().render(ui);
-                }
-
-                Pane::Histogram2D(hist) => {
-                    hist.lock().unwrap().render(ui);
-                }
-            }
+            self.render(ui, placeholder_while_filling);
 
             egui_tiles::UiResponse::DragStarted
         } else {
-            match self {
-                Pane::Histogram(hist) => {
-                    hist.lock().unwrap().render(ui);
+            self.render(ui, placeholder_while_filling);
+
+            egui_tiles::UiResponse::None
+        }
+    }
+
+    // While a histogram is being filled, only the progress bar is drawn when the placeholder is enabled
+    fn render(&mut self, ui: &mut egui::Ui, placeholder_while_filling: bool) {
+        match self {
+            Pane::Histogram(hist) => {
+                let mut hist = hist.lock().unwrap();
+                if placeholder_while_filling && hist.plot_settings.progress.is_some() {
+                    hist.plot_settings.progress_ui(ui);
+                    filling_placeholder_ui(ui);
+                } else {
+                    hist.render(ui);
                 }
+            }
 
-                Pane::Histogram2D(hist) => {
-                    hist.lock().unwrap().render(ui);
+            Pane::Histogram2D(hist) => {
+                let mut hist = hist.lock().unwrap();
+                if placeholder_while_filling && hist.plot_settings.progress.is_some() {
+                    hist.plot_settings.progress_ui(ui);
+                    filling_placeholder_ui(ui);
+                } else {
+                    hist.render(ui);
                 }
             }
+        }
+    }
 
-            egui_tiles::UiResponse::None
+    // Both panes point at the same histogram
+    pub fn is_same(&self, other: &Pane) -> bool {
+        match (self, other) {
+            (Pane::Histogram(a), Pane::Histogram(b)) => Arc::ptr_eq(a, b),
+            (Pane::Histogram2D(a), Pane::Histogram2D(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    // The fill progress of the histogram, None when it is not being filled
+    pub fn progress(&self) -> Option<f32> {
+        match self {
+            Pane::Histogram(hist) => hist.lock().unwrap().plot_settings.progress,
+            Pane::Histogram2D(hist) => hist.lock().unwrap().plot_settings.progress,
+        }
+    }
+
+    // Edit the notes attached to the histogram
+    pub fn notes_ui(&mut self, ui: &mut egui::Ui) {
+        match self {
+            Pane::Histogram(hist) => {
+                ui.text_edit_multiline(&mut hist.lock().unwrap().notes);
+            }
+            Pane::Histogram2D(hist) => {
+                ui.text_edit_multiline(&mut hist.lock().unwrap().notes);
+            }
         }
     }
+
+    // Check if the histogram name, its notes, or the notes of its stored fits contain the filter
+    // text (case insensitive)
+    pub fn matches_filter(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        let (name, notes) = match self {
+            Pane::Histogram(hist) => {
+                let hist = hist.lock().unwrap();
+                let mut notes = vec![hist.notes.clone()];
+                notes.extend(hist.fits.stored_fits.iter().map(|fit| fit.notes.clone()));
+                (hist.name.clone(), notes)
+            }
+            Pane::Histogram2D(hist) => {
+                let hist = hist.lock().unwrap();
+                (hist.name.clone(), vec![hist.notes.clone()])
+            }
+        };
+
+        name.to_lowercase().contains(&filter)
+            || notes
+                .iter()
+                .any(|notes| notes.to_lowercase().contains(&filter))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Pane::Histogram(hist) => hist.lock().unwrap().name.clone(),
+            Pane::Histogram2D(hist) => hist.lock().unwrap().name.clone(),
+        }
+    }
+
+    pub fn request_export(&self, path: std::path::PathBuf) {
+        match self {
+            Pane::Histogram(hist) => hist.lock().unwrap().plot_settings.export.request(path),
+            Pane::Histogram2D(hist) => hist.lock().unwrap().plot_settings.export.request(path),
+        }
+    }
+
+    pub fn cancel_export(&self) {
+        match self {
+            Pane::Histogram(hist) => hist.lock().unwrap().plot_settings.export.cancel(),
+            Pane::Histogram2D(hist) => hist.lock().unwrap().plot_settings.export.cancel(),
+        }
+    }
+
+    // Returns the export result once the pane has been saved
+    pub fn export_result(&self) -> Option<Result<std::path::PathBuf, String>> {
+        let export = match self {
+            Pane::Histogram(hist) => hist.lock().unwrap().plot_settings.export.clone(),
+            Pane::Histogram2D(hist) => hist.lock().unwrap().plot_settings.export.clone(),
+        };
+
+        if export.is_pending() {
+            None
+        } else {
+            export.result
+        }
+    }
+}
+
+fn filling_placeholder_ui(ui: &mut egui::Ui) {
+    ui.centered_and_justified(|ui| {
+        ui.label("Filling histogram...");
+    });
+
+    // keep the progress bar moving without redrawing the plot every frame
+    ui.ctx()
+        .request_repaint_after(std::time::Duration::from_millis(250));
 }
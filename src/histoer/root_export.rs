@@ -0,0 +1,195 @@
+use super::histo1d::histogram1d::Histogram;
+use super::histo2d::histogram2d::Histogram2D;
+use crate::egui_plot_stuff::egui_plot_export::sanitize_file_name;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+// Writing real ROOT files needs the ROOT streamers, so the histograms are written in a small
+// binary format that converts to a TH1D/TH2D with uproot. Everything is little-endian:
+//
+//   magic        8 bytes   b"SPXHIST\0"
+//   version      u32       2
+//   ndim         u32       1 or 2
+//   name_length  u32
+//   name         name_length bytes of utf-8
+//   fingerprint  16 bytes of ascii hex, the content fingerprint shown in the stats box
+//   ndim axes    u64 bins, f64 min, f64 max, u64 underflow, u64 overflow
+//   counts       f64 * (product of the axis bins), x varies fastest
+//   sumw2        f64 * (product of the axis bins), same order as the counts
+//
+// The counts are weighted if the histogram was filled with weights, and sumw2 equals the
+// counts otherwise. Version 1 files have no fingerprint. Convert with python:
+//
+//   import struct, numpy as np, hist, uproot
+//
+//   def read_spxhist(path):
+//       with open(path, "rb") as f:
+//           assert f.read(8) == b"SPXHIST\0"
+//           version, ndim, name_length = struct.unpack("<III", f.read(12))
+//           name = f.read(name_length).decode()
+//           fingerprint = f.read(16).decode() if version >= 2 else None
+//           axes = [struct.unpack("<QddQQ", f.read(40)) for _ in range(ndim)]
+//           shape = [int(axis[0]) for axis in axes][::-1]
+//           size = int(np.prod(shape))
+//           counts = np.frombuffer(f.read(8 * size), "<f8").reshape(shape).T
+//           sumw2 = np.frombuffer(f.read(8 * size), "<f8").reshape(shape).T
+//       h = hist.Hist(
+//           *[hist.axis.Regular(int(n), lo, hi) for n, lo, hi, _, _ in axes],
+//           storage=hist.storage.Weight(),
+//       )
+//       h.view().value[...] = counts
+//       h.view().variance[...] = sumw2
+//       return name, fingerprint, h
+//
+//   name, fingerprint, h = read_spxhist("histogram.spxhist")
+//   with uproot.recreate("histogram.root") as f:
+//       f[name] = h
+const MAGIC: &[u8; 8] = b"SPXHIST\0";
+const VERSION: u32 = 2;
+
+pub const EXTENSION: &str = "spxhist";
+
+struct Axis {
+    bins: usize,
+    min: f64,
+    max: f64,
+    underflow: u64,
+    overflow: u64,
+}
+
+fn write_histogram(
+    path: &Path,
+    name: &str,
+    fingerprint: &str,
+    axes: &[Axis],
+    counts: &[f64],
+    sumw2: &[f64],
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(axes.len() as u32).to_le_bytes())?;
+    writer.write_all(&(name.len() as u32).to_le_bytes())?;
+    writer.write_all(name.as_bytes())?;
+    writer.write_all(fingerprint.as_bytes())?;
+
+    for axis in axes {
+        writer.write_all(&(axis.bins as u64).to_le_bytes())?;
+        writer.write_all(&axis.min.to_le_bytes())?;
+        writer.write_all(&axis.max.to_le_bytes())?;
+        writer.write_all(&axis.underflow.to_le_bytes())?;
+        writer.write_all(&axis.overflow.to_le_bytes())?;
+    }
+
+    for value in counts.iter().chain(sumw2) {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    log::info!("Exported '{}' to {:?}", name, path);
+
+    Ok(())
+}
+
+fn export_root_button(
+    ui: &mut egui::Ui,
+    name: &str,
+    export: impl FnOnce(&Path) -> std::io::Result<()>,
+) {
+    if ui
+        .button("Export for ROOT")
+        .on_hover_text(
+            "Save the bin edges, counts and summed squared weights in a binary file that converts to a ROOT histogram with uproot",
+        )
+        .clicked()
+    {
+        if let Some(path) = rfd::FileDialog::new()
+            .set_file_name(format!("{}.{}", sanitize_file_name(name), EXTENSION))
+            .add_filter("Spectrix histogram", &[EXTENSION])
+            .save_file()
+        {
+            if let Err(e) = export(&path) {
+                log::error!("Failed to export '{}' to {:?}: {:?}", name, path, e);
+            }
+        }
+
+        ui.close_menu();
+    }
+}
+
+impl Histogram {
+    // The current bins are written, so the rebin factor is already applied
+    pub fn export_root(&self, path: &Path) -> std::io::Result<()> {
+        // the format only describes uniform axes
+        if self.is_variable_binning() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "variable bin edges can not be exported for ROOT",
+            ));
+        }
+
+        let axis = Axis {
+            bins: self.bins.len(),
+            min: self.range.0,
+            max: self.range.1,
+            underflow: self.core.underflow,
+            overflow: self.core.overflow,
+        };
+
+        write_histogram(
+            path,
+            &self.name,
+            &self.fingerprint(),
+            &[axis],
+            &self.counts(),
+            &self.sumw2(),
+        )
+    }
+
+    pub fn export_root_button(&self, ui: &mut egui::Ui) {
+        export_root_button(ui, &self.name, |path| self.export_root(path));
+    }
+}
+
+impl Histogram2D {
+    // The current bins are written, so the rebin factors are already applied
+    pub fn export_root(&self, path: &Path) -> std::io::Result<()> {
+        let axes = [
+            Axis {
+                bins: self.bins.x,
+                min: self.range.x.min,
+                max: self.range.x.max,
+                underflow: self.underflow.0,
+                overflow: self.overflow.0,
+            },
+            Axis {
+                bins: self.bins.y,
+                min: self.range.y.min,
+                max: self.range.y.max,
+                underflow: self.underflow.1,
+                overflow: self.overflow.1,
+            },
+        ];
+
+        let indices: Vec<(usize, usize)> = (0..self.bins.y)
+            .flat_map(|y| (0..self.bins.x).map(move |x| (x, y)))
+            .collect();
+        let counts: Vec<f64> = indices.iter().map(|index| self.bins.count(index)).collect();
+        let sumw2: Vec<f64> = indices.iter().map(|index| self.bins.sumw2(index)).collect();
+
+        write_histogram(
+            path,
+            &self.name,
+            &self.fingerprint(),
+            &axes,
+            &counts,
+            &sumw2,
+        )
+    }
+
+    pub fn export_root_button(&self, ui: &mut egui::Ui) {
+        export_root_button(ui, &self.name, |path| self.export_root(path));
+    }
+}
@@ -0,0 +1,101 @@
+use rfd::FileDialog;
+
+use std::fs::File;
+use std::io::{Read, Write};
+
+// The columns a histogram was filled from, so a template can be filled against new data
+#[derive(Debug, Clone, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ColumnBinding {
+    pub columns: Vec<String>, // x for 1D histograms, x and y for 2D histograms
+    #[serde(default)]
+    pub weight_column: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub enum TemplateBinning {
+    Hist1d {
+        bins: usize,
+        range: (f64, f64),
+        #[serde(default)]
+        edges: Option<Vec<f64>>, // variable binning
+    },
+    Hist2d {
+        bins: (usize, usize),
+        range: ((f64, f64), (f64, f64)),
+    },
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct TemplateHistogram {
+    pub name: String,
+    pub binning: TemplateBinning,
+    #[serde(default)]
+    pub binding: Option<ColumnBinding>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct TemplateGrid {
+    pub name: String,
+    pub histograms: Vec<TemplateHistogram>,
+}
+
+// The grids and histogram definitions of the histogrammer without any counts
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct AnalysisTemplate {
+    pub name: String,
+    pub grids: Vec<TemplateGrid>,
+}
+
+impl AnalysisTemplate {
+    pub fn histogram_count(&self) -> usize {
+        self.grids.iter().map(|grid| grid.histograms.len()).sum()
+    }
+
+    pub fn save_to_file(&self) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name(format!("{}.json", self.name))
+            .add_filter("JSON", &["json"])
+            .save_file()
+        {
+            match File::create(path) {
+                Ok(mut file) => match serde_json::to_string_pretty(self) {
+                    Ok(json) => {
+                        if let Err(e) = file.write_all(json.as_bytes()) {
+                            log::error!("Error writing template: {:?}", e);
+                        }
+                    }
+                    Err(e) => log::error!("Error serializing template: {:?}", e),
+                },
+                Err(e) => log::error!("Error creating file: {:?}", e),
+            }
+        }
+    }
+
+    pub fn load_from_file() -> Option<Self> {
+        let path = FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()?;
+
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                if let Err(e) = file.read_to_string(&mut contents) {
+                    log::error!("Error reading template: {:?}", e);
+                    return None;
+                }
+            }
+            Err(e) => {
+                log::error!("Error opening file: {:?}", e);
+                return None;
+            }
+        }
+
+        match serde_json::from_str(&contents) {
+            Ok(template) => Some(template),
+            Err(e) => {
+                log::error!("Error deserializing template: {:?}", e);
+                None
+            }
+        }
+    }
+}
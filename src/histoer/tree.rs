@@ -14,6 +14,12 @@ pub struct TreeBehavior {
     #[serde(skip)]
     preview_dragged_panes: bool,
     pub tile_map: std::collections::HashMap<egui_tiles::TileId, String>,
+    #[serde(default = "default_placeholder_while_filling")]
+    pub placeholder_while_filling: bool, // skip drawing plots that are still being filled
+}
+
+fn default_placeholder_while_filling() -> bool {
+    true
 }
 
 impl Default for TreeBehavior {
@@ -32,6 +38,7 @@ impl Default for TreeBehavior {
             min_size: 50.0,
             preview_dragged_panes: true,
             tile_map: std::collections::HashMap::new(),
+            placeholder_while_filling: default_placeholder_while_filling(),
         }
     }
 }
@@ -83,6 +90,11 @@ impl TreeBehavior {
                     ui.label("Preview dragged panes:");
                     ui.checkbox(&mut self.preview_dragged_panes, "");
                     ui.end_row();
+
+                    ui.label("Placeholder while filling:");
+                    ui.checkbox(&mut self.placeholder_while_filling, "")
+                        .on_hover_text("Only show the progress bar for histograms that are being filled to keep the app responsive");
+                    ui.end_row();
                 });
         });
     }
@@ -103,7 +115,7 @@ impl egui_tiles::Behavior<Pane> for TreeBehavior {
         _tile_id: egui_tiles::TileId,
         pane: &mut Pane,
     ) -> egui_tiles::UiResponse {
-        pane.ui(ui)
+        pane.ui(ui, self.placeholder_while_filling)
     }
 
     fn tab_title_for_pane(&mut self, pane: &Pane) -> egui::WidgetText {
@@ -3,7 +3,8 @@ use super::configure_lazyframes::{LazyFrameInfo, LazyFrames};
 use super::histogram_ui_elements::{AddHisto1d, AddHisto2d, FillHisto1d, FillHisto2d, HistoConfig};
 use super::manual_histogram_script::manual_add_histograms;
 
-use crate::histoer::histogrammer::Histogrammer;
+use crate::histoer::core::{Histogram1DCore, Histogram2DCore};
+use crate::histoer::histogrammer::{FillRequest, HistogramDefaults, Histogrammer};
 use polars::prelude::*;
 
 #[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
@@ -70,7 +71,7 @@ impl HistogramScript {
         self.fill_histograms.push(HistoConfig::FillHisto2d(config));
     }
 
-    pub fn ui(&mut self, ui: &mut egui::Ui) {
+    pub fn ui(&mut self, ui: &mut egui::Ui, defaults: &HistogramDefaults) {
         ui.checkbox(&mut self.manual_histogram_script, "Manual Histogram Script");
         if self.manual_histogram_script {
             ui.label("Manual Histogram Script Enabled");
@@ -134,10 +135,10 @@ impl HistogramScript {
             ui.horizontal(|ui| {
                 ui.heading("Add Histograms");
                 if ui.button("1d").clicked() {
-                    self.add_histogram1d(AddHisto1d::new(self.add_histograms.len()));
+                    self.add_histogram1d(AddHisto1d::new(self.add_histograms.len(), defaults));
                 }
                 if ui.button("2d").clicked() {
-                    self.add_histogram2d(AddHisto2d::new(self.add_histograms.len()));
+                    self.add_histogram2d(AddHisto2d::new(self.add_histograms.len(), defaults));
                 }
             });
 
@@ -187,13 +188,14 @@ impl HistogramScript {
 
             egui::Grid::new("Histogram Config")
                 .striped(true)
-                .num_columns(5)
+                .num_columns(6)
                 .show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.label("Histogram");
                     });
                     ui.label("LazyFrame");
                     ui.label("Column");
+                    ui.label("Filter");
                     ui.label("Calculate");
                     ui.label("Remove");
                     ui.end_row();
@@ -268,27 +270,163 @@ impl HistogramScript {
                 }
             }
 
-            // fill histograms
-            for hist in self.fill_histograms.iter_mut() {
-                match hist {
-                    HistoConfig::FillHisto1d(config) => {
-                        if let Some(lf) = lazyframes.get_lf(&config.lazyframe) {
-                            let name = config.name.clone();
-                            let column = config.column.clone();
-                            h.fill_hist1d(&name, lf, &column);
+            // fill histograms, grouped by lazyframe so each lazyframe is only collected once
+            let mut requests: Vec<(String, Vec<FillRequest>)> = Vec::new();
+            for hist in self.fill_histograms.iter() {
+                let (lazyframe, request) = match hist {
+                    HistoConfig::FillHisto1d(config) => (
+                        &config.lazyframe,
+                        FillRequest::Hist1d {
+                            name: config.name.clone(),
+                            column: config.column.clone(),
+                            weight_column: None,
+                            extra_filter: config.filter.to_expr(),
+                        },
+                    ),
+                    HistoConfig::FillHisto2d(config) => (
+                        &config.lazyframe,
+                        FillRequest::Hist2d {
+                            name: config.name.clone(),
+                            x_column: config.x_column.clone(),
+                            y_column: config.y_column.clone(),
+                            weight_column: None,
+                            extra_filter: config.filter.to_expr(),
+                        },
+                    ),
+                    _ => continue,
+                };
+
+                match requests.iter_mut().find(|(name, _)| name == lazyframe) {
+                    Some((_, group)) => group.push(request),
+                    None => requests.push((lazyframe.clone(), vec![request])),
+                }
+            }
+
+            for (lazyframe, group) in requests {
+                if let Some(lf) = lazyframes.get_lf(&lazyframe) {
+                    h.fill_hists_batched(lf, group);
+                }
+            }
+        }
+    }
+
+    // Fill the scripted histograms without a Histogrammer so they can be exported from a batch script
+    pub fn fill_histograms_headless(
+        &self,
+        lf: LazyFrame,
+    ) -> (Vec<Histogram1DCore>, Vec<Histogram2DCore>) {
+        let mut hists_1d: Vec<Histogram1DCore> = Vec::new();
+        let mut hists_2d: Vec<Histogram2DCore> = Vec::new();
+
+        if self.manual_histogram_script {
+            log::error!(
+                "The manual histogram script needs the Histogrammer and can't run headless"
+            );
+            return (hists_1d, hists_2d);
+        }
+
+        let mut lazyframes = LazyFrames::new();
+        let lf = lazyframes.add_columns_to_lazyframe(&lf);
+        lazyframes.lfs = lazyframes.filtered_lfs(lf);
+
+        for hist in &self.add_histograms {
+            match hist {
+                HistoConfig::AddHisto1d(config) => {
+                    hists_1d.push(Histogram1DCore::new(
+                        &config.name,
+                        config.bins,
+                        config.range,
+                    ));
+                }
+                HistoConfig::AddHisto2d(config) => {
+                    hists_2d.push(Histogram2DCore::new(
+                        &config.name,
+                        config.bins,
+                        config.range,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        for hist in &self.fill_histograms {
+            match hist {
+                HistoConfig::FillHisto1d(config) => {
+                    let Some(lf) = lazyframes.get_lf(&config.lazyframe) else {
+                        log::error!("LazyFrame '{}' not found", config.lazyframe);
+                        continue;
+                    };
+                    let Some(core) = hists_1d.iter_mut().find(|h| h.name == config.name) else {
+                        log::error!("Histogram '{}' was not added", config.name);
+                        continue;
+                    };
+
+                    let mut lf = lf.clone();
+                    if let Some(filter) = config.filter.to_expr() {
+                        lf = lf.filter(filter);
+                    }
+
+                    let column = col(&config.column).cast(DataType::Float64);
+                    match lf.select([column]).collect() {
+                        Ok(df) => {
+                            let values: Vec<f64> = match df
+                                .column(&config.column)
+                                .and_then(|s| s.f64())
+                            {
+                                Ok(values) => values.into_iter().flatten().collect(),
+                                Err(e) => {
+                                    log::error!("Failed to read column '{}': {}", config.column, e);
+                                    continue;
+                                }
+                            };
+                            core.fill_batch(&values);
                         }
+                        Err(e) => log::error!("Failed to collect '{}': {}", config.name, e),
+                    }
+                }
+                HistoConfig::FillHisto2d(config) => {
+                    let Some(lf) = lazyframes.get_lf(&config.lazyframe) else {
+                        log::error!("LazyFrame '{}' not found", config.lazyframe);
+                        continue;
+                    };
+                    let Some(core) = hists_2d.iter_mut().find(|h| h.name == config.name) else {
+                        log::error!("Histogram '{}' was not added", config.name);
+                        continue;
+                    };
+
+                    let mut lf = lf.clone();
+                    if let Some(filter) = config.filter.to_expr() {
+                        lf = lf.filter(filter);
                     }
-                    HistoConfig::FillHisto2d(config) => {
-                        if let Some(lf) = lazyframes.get_lf(&config.lazyframe) {
-                            let name = config.name.clone();
-                            let x_column = config.x_column.clone();
-                            let y_column = config.y_column.clone();
-                            h.fill_hist2d(&name, lf, &x_column, &y_column);
+
+                    let columns = [
+                        col(&config.x_column).cast(DataType::Float64),
+                        col(&config.y_column).cast(DataType::Float64),
+                    ];
+                    match lf.select(columns).drop_nulls(None).collect() {
+                        Ok(df) => {
+                            let x_values = df.column(&config.x_column).and_then(|s| s.f64());
+                            let y_values = df.column(&config.y_column).and_then(|s| s.f64());
+                            match (x_values, y_values) {
+                                (Ok(x_values), Ok(y_values)) => {
+                                    let x_values: Vec<f64> = x_values.into_no_null_iter().collect();
+                                    let y_values: Vec<f64> = y_values.into_no_null_iter().collect();
+                                    core.fill_batch(&x_values, &y_values);
+                                }
+                                _ => log::error!(
+                                    "Failed to read columns '{}' and '{}'",
+                                    config.x_column,
+                                    config.y_column
+                                ),
+                            }
                         }
+                        Err(e) => log::error!("Failed to collect '{}': {}", config.name, e),
                     }
-                    _ => {}
                 }
+                _ => {}
             }
         }
+
+        (hists_1d, hists_2d)
     }
 }
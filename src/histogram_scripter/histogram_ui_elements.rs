@@ -1,4 +1,6 @@
 use super::configure_lazyframes::LazyFrameInfo;
+use crate::histoer::histogrammer::HistogramDefaults;
+use polars::prelude::{col, lit, DataType, Expr};
 
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum HistoConfig {
@@ -79,11 +81,11 @@ impl Default for AddHisto1d {
 }
 
 impl AddHisto1d {
-    pub fn new(id: usize) -> Self {
+    pub fn new(id: usize, defaults: &HistogramDefaults) -> Self {
         Self {
             name: format!("Histogram {}", id),
-            bins: 512,
-            range: (0.0, 4096.0),
+            bins: defaults.bins_1d,
+            range: defaults.range_1d,
             grid: None,
             id,
         }
@@ -161,11 +163,11 @@ impl Default for AddHisto2d {
 }
 
 impl AddHisto2d {
-    pub fn new(id: usize) -> Self {
+    pub fn new(id: usize, defaults: &HistogramDefaults) -> Self {
         Self {
             name: format!("Histogram {}", id),
-            bins: (512, 512),
-            range: ((0.0, 4096.0), (0.0, 4096.0)),
+            bins: defaults.bins_2d,
+            range: defaults.range_2d,
             grid: None,
             id,
         }
@@ -245,11 +247,101 @@ impl AddHisto2d {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Default, serde::Deserialize, serde::Serialize)]
+pub enum FilterOperator {
+    #[default]
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl FilterOperator {
+    const ALL: [FilterOperator; 6] = [
+        FilterOperator::Equal,
+        FilterOperator::NotEqual,
+        FilterOperator::Less,
+        FilterOperator::LessEqual,
+        FilterOperator::Greater,
+        FilterOperator::GreaterEqual,
+    ];
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            FilterOperator::Equal => "==",
+            FilterOperator::NotEqual => "!=",
+            FilterOperator::Less => "<",
+            FilterOperator::LessEqual => "<=",
+            FilterOperator::Greater => ">",
+            FilterOperator::GreaterEqual => ">=",
+        }
+    }
+}
+
+// Extra condition on the rows that fill a histogram, on top of the histogram range
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct FillFilter {
+    pub enabled: bool,
+    pub column: String,
+    pub operator: FilterOperator,
+    pub value: f64,
+}
+
+impl FillFilter {
+    pub fn to_expr(&self) -> Option<Expr> {
+        if !self.enabled || self.column.is_empty() {
+            return None;
+        }
+
+        let column = col(&self.column).cast(DataType::Float64);
+        let value = lit(self.value);
+        Some(match self.operator {
+            FilterOperator::Equal => column.eq(value),
+            FilterOperator::NotEqual => column.neq(value),
+            FilterOperator::Less => column.lt(value),
+            FilterOperator::LessEqual => column.lt_eq(value),
+            FilterOperator::Greater => column.gt(value),
+            FilterOperator::GreaterEqual => column.gt_eq(value),
+        })
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui, columns: &[String], id: usize) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.enabled, "");
+
+            ui.add_enabled_ui(self.enabled, |ui| {
+                egui::ComboBox::from_id_salt(format!("Fill filter column {}", id))
+                    .selected_text(&self.column)
+                    .show_ui(ui, |ui| {
+                        for column in columns {
+                            ui.selectable_value(&mut self.column, column.clone(), column.clone());
+                        }
+                    });
+
+                egui::ComboBox::from_id_salt(format!("Fill filter operator {}", id))
+                    .selected_text(self.operator.symbol())
+                    .width(40.0)
+                    .show_ui(ui, |ui| {
+                        for operator in FilterOperator::ALL {
+                            ui.selectable_value(&mut self.operator, operator, operator.symbol());
+                        }
+                    });
+
+                ui.add(egui::DragValue::new(&mut self.value).speed(1.0));
+            });
+        });
+    }
+}
+
 #[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct FillHisto1d {
     pub name: String,
     pub lazyframe: String,
     pub column: String,
+    #[serde(default)]
+    pub filter: FillFilter,
     pub calculate: bool,
     pub id: usize,
 }
@@ -260,6 +352,7 @@ impl Default for FillHisto1d {
             name: "Xavg".to_string(),
             lazyframe: "Raw".to_string(),
             column: "Xavg".to_string(),
+            filter: FillFilter::default(),
             calculate: true,
             id: 0,
         }
@@ -272,6 +365,7 @@ impl FillHisto1d {
             name: format!("Histogram {}", id),
             lazyframe: "Raw".to_string(),
             column: "".to_string(),
+            filter: FillFilter::default(),
             calculate: true,
             id,
         }
@@ -310,6 +404,8 @@ impl FillHisto1d {
                 });
         });
 
+        self.filter.ui(ui, &lazyframe_info.columns, self.id);
+
         ui.checkbox(&mut self.calculate, "");
     }
 }
@@ -320,6 +416,8 @@ pub struct FillHisto2d {
     pub lazyframe: String,
     pub x_column: String,
     pub y_column: String,
+    #[serde(default)]
+    pub filter: FillFilter,
     pub calculate: bool,
     pub id: usize,
 }
@@ -331,6 +429,7 @@ impl Default for FillHisto2d {
             lazyframe: "Raw".to_string(),
             x_column: "ScintLeftEnergy".to_string(),
             y_column: "AnodeBackEnergy".to_string(),
+            filter: FillFilter::default(),
             calculate: true,
             id: 0,
         }
@@ -344,6 +443,7 @@ impl FillHisto2d {
             lazyframe: "Raw".to_string(),
             x_column: "".to_string(),
             y_column: "".to_string(),
+            filter: FillFilter::default(),
             calculate: true,
             id,
         }
@@ -395,6 +495,8 @@ impl FillHisto2d {
             });
         });
 
+        self.filter.ui(ui, &lazyframe_info.columns, self.id);
+
         ui.checkbox(&mut self.calculate, "");
     }
 }
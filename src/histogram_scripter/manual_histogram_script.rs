@@ -290,10 +290,10 @@ pub fn manual_add_histograms(h: &mut Histogrammer, lf: LazyFrame) {
         h.tabs.insert(format!("CeBr3 Detector {}", i).to_string(), cebra_det_panes);
 
         // fill the combined histograms
-        h.fill_hist1d(&format!("CeBrA Gain Matched"), &det_lfs[i], &format!("Cebra{}EnergyGM", i));
-        h.fill_hist1d(&format!("CeBrA Time to Scint Shifted with TCut"), &det_time_cut_lfs[i], &format!("Cebra{}Time_ScintLeftTime_Shifted", i));
-        h.fill_hist2d(&format!("CeBrA Gain Matched vs Xavg with TCut"), &det_time_cut_lfs[i], "Xavg", &format!("Cebra{}EnergyGM", i));
-        h.fill_hist2d(&format!("CeBrA vs Xavg: Energy Calibrated"), &det_time_cut_lfs[i], "XavgEnergyCalibrated", &format!("Cebra{}EnergyECal", i));
+        h.fill_hist1d(&format!("CeBrA Gain Matched"), &det_lfs[i], &format!("Cebra{}EnergyGM", i), None, None);
+        h.fill_hist1d(&format!("CeBrA Time to Scint Shifted with TCut"), &det_time_cut_lfs[i], &format!("Cebra{}Time_ScintLeftTime_Shifted", i), None, None);
+        h.fill_hist2d(&format!("CeBrA Gain Matched vs Xavg with TCut"), &det_time_cut_lfs[i], "Xavg", &format!("Cebra{}EnergyGM", i), None, None);
+        h.fill_hist2d(&format!("CeBrA vs Xavg: Energy Calibrated"), &det_time_cut_lfs[i], "XavgEnergyCalibrated", &format!("Cebra{}EnergyECal", i), None, None);
     }
 
     //....oooOO0OOooo........oooOO0OOooo........oooOO0OOooo........oooOO0OOooo....
@@ -2,22 +2,37 @@
 // use super::tree::TreeBehavior;
 use crate::util::processer::Processer;
 
+// Bump when a change to the saved state needs a step in `migrate`
+const STATE_VERSION: u32 = 1;
+
+// Prefix of the storage keys that keep a saved state this version couldn't read
+const UNREADABLE_STATE_KEY: &str = "spectrix_unreadable_state";
+
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct Spectrix {
+    #[serde(default)] // states saved before the version existed read as 0
+    version: u32,
     // tree: egui_tiles::Tree<Pane>,
     processer: Processer,
     // behavior: TreeBehavior,
     left_side_panel_open: bool,
     right_side_panel_open: bool,
+    #[serde(skip)]
+    unreadable_state: Option<String>, // the saved state that failed to load, kept in the storage on save
+    #[serde(skip)]
+    load_error: Option<String>, // shown in the top panel until dismissed
 }
 
 impl Default for Spectrix {
     fn default() -> Self {
         Self {
+            version: STATE_VERSION,
             processer: Processer::new(),
             left_side_panel_open: true,
             right_side_panel_open: true,
+            unreadable_state: None,
+            load_error: None,
         }
     }
 }
@@ -27,16 +42,81 @@ impl Spectrix {
         // Load previous app state (if any).
         // Note that you must enable the `persistence` feature for this to work.
         if let Some(storage) = cc.storage {
-            return eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+            if let Some(mut app) = eframe::get_value::<Self>(storage, eframe::APP_KEY) {
+                app.migrate();
+                app.processer.histogrammer.rebuild_after_load();
+                return app;
+            }
+
+            // eframe drops a state it can't decode, keep it so the next save doesn't lose it
+            if let Some(state) = storage.get_string(eframe::APP_KEY) {
+                let message = format!(
+                    "The saved state could not be read, it may be from an incompatible version of spectrix. It is kept in the app storage under a key starting with '{}' and a new state was started",
+                    UNREADABLE_STATE_KEY
+                );
+                log::error!("{}", message);
+
+                return Self {
+                    unreadable_state: Some(state),
+                    load_error: Some(message),
+                    ..Default::default()
+                };
+            }
         }
 
         Default::default()
     }
+
+    // Upgrade a state saved by an older version, serde has already filled in the new fields
+    fn migrate(&mut self) {
+        if self.version > STATE_VERSION {
+            log::warn!(
+                "The saved state is from a newer version of spectrix (state version {}, this version reads {}), settings this version doesn't know about are dropped",
+                self.version,
+                STATE_VERSION
+            );
+        }
+
+        // 0 -> 1: the version field was added, nothing else changed
+        if self.version < STATE_VERSION {
+            log::info!(
+                "Upgraded the saved state from version {} to {}",
+                self.version,
+                STATE_VERSION
+            );
+        }
+
+        self.version = STATE_VERSION;
+    }
+
+    fn load_error_ui(&mut self, ui: &mut egui::Ui) {
+        let Some(message) = &self.load_error else {
+            return;
+        };
+
+        let mut dismissed = false;
+        ui.horizontal_wrapped(|ui| {
+            ui.colored_label(egui::Color32::RED, message);
+            dismissed = ui.button("Dismiss").clicked();
+        });
+
+        if dismissed {
+            self.load_error = None;
+        }
+    }
 }
 
 impl eframe::App for Spectrix {
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // one key per failed load so an older unreadable state is never overwritten
+        if let Some(state) = self.unreadable_state.take() {
+            let seconds = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs());
+            storage.set_string(&format!("{}_{}", UNREADABLE_STATE_KEY, seconds), state);
+        }
+
         eframe::set_value(storage, eframe::APP_KEY, self);
     }
 
@@ -46,7 +126,15 @@ impl eframe::App for Spectrix {
                 ui.label("Show: ");
                 ui.checkbox(&mut self.left_side_panel_open, "Info Panel");
                 ui.checkbox(&mut self.right_side_panel_open, "Histogram Script");
+
+                ui.separator();
+
+                ui.menu_button("Settings", |ui| {
+                    self.processer.histogrammer.defaults.ui(ui);
+                });
             });
+
+            self.load_error_ui(ui);
         });
 
         egui::SidePanel::left("spectrix_left_panel").show_animated(
@@ -0,0 +1,335 @@
+use polars::prelude::*;
+
+// Parses arithmetic over column names into a polars expression, e.g. "(energy1 + energy2) * 0.5".
+// Supports + - * /, parentheses, unary minus, and numeric literals.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Column(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LeftParen,
+    RightParen,
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            ' ' | '\t' => {
+                i += 1;
+            }
+            '+' => {
+                tokens.push(Token::Plus);
+                i += 1;
+            }
+            '-' => {
+                tokens.push(Token::Minus);
+                i += 1;
+            }
+            '*' => {
+                tokens.push(Token::Star);
+                i += 1;
+            }
+            '/' => {
+                tokens.push(Token::Slash);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LeftParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RightParen);
+                i += 1;
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+
+                // scientific notation, e.g. 1e-3
+                if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                    let mut j = i + 1;
+                    if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                        j += 1;
+                    }
+                    if j < chars.len() && chars[j].is_ascii_digit() {
+                        i = j;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+
+                let text: String = chars[start..i].iter().collect();
+                let value = text
+                    .parse::<f64>()
+                    .map_err(|_| format!("Invalid number '{}'", text))?;
+                tokens.push(Token::Number(value));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Column(chars[start..i].iter().collect()));
+            }
+            c => return Err(format!("Unexpected character '{}' at position {}", c, i)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    columns: &'a [String],
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    // expression := term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Plus => {
+                    self.next();
+                    expr = expr + self.term()?;
+                }
+                Token::Minus => {
+                    self.next();
+                    expr = expr - self.term()?;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // term := factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Star => {
+                    self.next();
+                    expr = expr * self.factor()?;
+                }
+                Token::Slash => {
+                    self.next();
+                    expr = expr / self.factor()?;
+                }
+                _ => break,
+            }
+        }
+
+        Ok(expr)
+    }
+
+    // factor := '-' factor | number | column | '(' expression ')'
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Minus) => Ok(lit(0.0) - self.factor()?),
+            Some(Token::Number(value)) => Ok(lit(value)),
+            Some(Token::Column(name)) => {
+                if self.columns.contains(&name) {
+                    Ok(col(&name).cast(DataType::Float64))
+                } else {
+                    Err(format!("Unknown column '{}'", name))
+                }
+            }
+            Some(Token::LeftParen) => {
+                let expr = self.expression()?;
+                match self.next() {
+                    Some(Token::RightParen) => Ok(expr),
+                    _ => Err("Missing closing parenthesis".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected {:?}", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+}
+
+pub fn parse_column_expression(input: &str, columns: &[String]) -> Result<Expr, String> {
+    let tokens = tokenize(input)?;
+    if tokens.is_empty() {
+        return Err("Expression is empty".to_string());
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        columns,
+    };
+
+    let expr = parser.expression()?;
+
+    if let Some(token) = parser.peek() {
+        return Err(format!("Unexpected {:?} after the expression", token));
+    }
+
+    Ok(expr)
+}
+
+// Text fields for defining a new column, the parse error is shown below the fields
+#[derive(Default)]
+pub struct ColumnExpressionEditor {
+    pub name: String,
+    pub expression: String,
+    pub error: Option<String>,
+}
+
+impl ColumnExpressionEditor {
+    // Returns the column name and expression when "Add Column" is clicked and the expression is valid
+    pub fn ui(&mut self, ui: &mut egui::Ui, columns: &[String]) -> Option<(String, Expr)> {
+        let mut new_column = None;
+
+        ui.horizontal(|ui| {
+            ui.label("Name:");
+            ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(100.0));
+
+            ui.label("=");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.expression)
+                    .hint_text("(energy1 + energy2) * 0.5"),
+            )
+            .on_hover_text("Arithmetic over the column names with + - * / and parentheses");
+
+            if ui.button("Add Column").clicked() {
+                self.error = None;
+
+                if self.name.trim().is_empty() {
+                    self.error = Some("The column needs a name".to_string());
+                } else if columns.contains(&self.name.trim().to_string()) {
+                    self.error = Some(format!("Column '{}' already exists", self.name.trim()));
+                } else {
+                    match parse_column_expression(&self.expression, columns) {
+                        Ok(expr) => new_column = Some((self.name.trim().to_string(), expr)),
+                        Err(e) => self.error = Some(e),
+                    }
+                }
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.colored_label(egui::Color32::RED, error);
+        }
+
+        new_column
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<String> {
+        ["a", "b", "c"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    // Evaluates the expression on a single row with a = 2, b = 3 and the integer c = 4
+    fn evaluate(input: &str) -> f64 {
+        let df = df!("a" => [2.0], "b" => [3.0], "c" => [4i32]).unwrap();
+        let expr = parse_column_expression(input, &columns()).unwrap();
+
+        let result = df.lazy().select([expr.alias("result")]).collect().unwrap();
+        result
+            .column("result")
+            .unwrap()
+            .f64()
+            .unwrap()
+            .get(0)
+            .unwrap()
+    }
+
+    fn error(input: &str) -> String {
+        parse_column_expression(input, &columns()).unwrap_err()
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(evaluate("a + b * c"), 14.0);
+        assert_eq!(evaluate("a * b + c"), 10.0);
+        assert_eq!(evaluate("c - a / a"), 3.0);
+    }
+
+    #[test]
+    fn operators_of_equal_precedence_are_left_associative() {
+        assert_eq!(evaluate("a - b - c"), -5.0);
+        assert_eq!(evaluate("a - b + c"), 3.0);
+        assert_eq!(evaluate("c / a / a"), 1.0);
+        assert_eq!(evaluate("c / a * b"), 6.0);
+    }
+
+    #[test]
+    fn unary_minus_applies_to_the_next_factor() {
+        assert_eq!(evaluate("-a * b"), -6.0);
+        assert_eq!(evaluate("a * -b"), -6.0);
+        assert_eq!(evaluate("--a"), 2.0);
+        assert_eq!(evaluate("-(a + b)"), -5.0);
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(evaluate("(a + b) * c"), 20.0);
+        assert_eq!(evaluate("c / (a / a)"), 4.0);
+        assert_eq!(evaluate("((a))"), 2.0);
+        assert_eq!(evaluate("1e1 * (a - 1.5)"), 5.0);
+    }
+
+    #[test]
+    fn unknown_names_are_errors() {
+        assert_eq!(error("a + d"), "Unknown column 'd'");
+        // there are no functions, a call reads as an unknown column
+        assert_eq!(error("sqrt(a)"), "Unknown column 'sqrt'");
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        assert_eq!(error(""), "Expression is empty");
+        assert_eq!(error("a +"), "Unexpected end of expression");
+        assert_eq!(error("(a + b"), "Missing closing parenthesis");
+        assert_eq!(error("a * / b"), "Unexpected Slash");
+        assert_eq!(error("a % b"), "Unexpected character '%' at position 2");
+    }
+
+    #[test]
+    fn tokens_after_the_expression_are_errors() {
+        assert_eq!(
+            error("a b"),
+            "Unexpected Column(\"b\") after the expression"
+        );
+        assert_eq!(
+            error("(a + b))"),
+            "Unexpected RightParen after the expression"
+        );
+        assert_eq!(error("a 2"), "Unexpected Number(2.0) after the expression");
+    }
+}
@@ -0,0 +1,62 @@
+use super::lazyframer::LazyFramer;
+
+use std::path::PathBuf;
+
+// Colors given to new datasets so overlays are distinguishable without picking one
+const DATASET_COLORS: &[egui::Color32] = &[
+    egui::Color32::from_rgb(31, 119, 180),
+    egui::Color32::from_rgb(255, 127, 14),
+    egui::Color32::from_rgb(44, 160, 44),
+    egui::Color32::from_rgb(214, 39, 40),
+    egui::Color32::from_rgb(148, 103, 189),
+    egui::Color32::from_rgb(23, 190, 207),
+];
+
+// A named set of files, e.g. one run, whose 1D histograms are drawn over the main ones
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct Dataset {
+    pub name: String,
+    pub files: Vec<PathBuf>,
+    pub color: egui::Color32,
+    pub enabled: bool,
+    #[serde(skip)]
+    pub lazyframer: Option<LazyFramer>,
+}
+
+impl Dataset {
+    pub fn new(name: &str, files: Vec<PathBuf>, index: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            files,
+            color: DATASET_COLORS[index % DATASET_COLORS.len()],
+            enabled: true,
+            lazyframer: None,
+        }
+    }
+
+    pub fn create_lazyframe(&mut self, csv_delimiter: u8) {
+        self.lazyframer = Some(LazyFramer::new(self.files.clone(), csv_delimiter));
+    }
+
+    // Returns true if the dataset should be removed
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        ui.checkbox(&mut self.enabled, "");
+        ui.add(egui::TextEdit::singleline(&mut self.name).desired_width(100.0));
+        egui::color_picker::color_edit_button_srgba(
+            ui,
+            &mut self.color,
+            egui::color_picker::Alpha::Opaque,
+        );
+
+        let files = self
+            .files
+            .iter()
+            .map(|file| file.display().to_string())
+            .collect::<Vec<String>>()
+            .join("\n");
+        ui.label(format!("{} files", self.files.len()))
+            .on_hover_text(files);
+
+        ui.button("X").clicked()
+    }
+}
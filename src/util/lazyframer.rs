@@ -1,47 +1,190 @@
+use super::column_expression::ColumnExpressionEditor;
 use polars::prelude::*;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub struct LazyFramer {
     pub lazyframe: Option<LazyFrame>,
     pub columns: Vec<String>,
+    pub computed_columns: Vec<(String, Expr)>, // derived columns added on top of the files
+    pub column_editor: ColumnExpressionEditor,
+    pub schema: Vec<(String, String)>, // column name and data type, refreshed when columns are added
+    pub schema_filter: String,
+}
+
+// The file types that can be scanned into a LazyFrame
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FileKind {
+    Parquet,
+    Csv,
+}
+
+impl FileKind {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .as_deref()
+        {
+            Some("parquet") => Some(FileKind::Parquet),
+            Some("csv") | Some("txt") => Some(FileKind::Csv),
+            _ => None,
+        }
+    }
 }
 
 impl LazyFramer {
-    pub fn new(files: Vec<PathBuf>) -> Self {
-        let files_arc: Arc<[PathBuf]> = Arc::from(files);
-        let args = ScanArgsParquet::default();
-        log::info!("Files {:?}", files_arc);
+    pub fn new(files: Vec<PathBuf>, csv_delimiter: u8) -> Self {
+        log::info!("Files {:?}", files);
 
-        match LazyFrame::scan_parquet_files(files_arc, args) {
+        match Self::scan_files(files, csv_delimiter) {
             Ok(lf) => {
-                log::info!("Loaded Parquet files");
+                log::info!("Loaded files");
                 let column_names = Self::get_column_names_from_lazyframe(&lf);
 
-                Self {
+                let mut lazyframer = Self {
                     lazyframe: Some(lf),
                     columns: column_names,
-                }
+                    computed_columns: Vec::new(),
+                    column_editor: ColumnExpressionEditor::default(),
+                    schema: Vec::new(),
+                    schema_filter: String::new(),
+                };
+                lazyframer.refresh_schema();
+                lazyframer
             }
             Err(e) => {
-                log::error!("Failed to load Parquet files: {}", e);
+                log::error!("Failed to load files: {}", e);
                 Self {
                     lazyframe: None, // Indicates that loading failed
                     columns: Vec::new(),
+                    computed_columns: Vec::new(),
+                    column_editor: ColumnExpressionEditor::default(),
+                    schema: Vec::new(),
+                    schema_filter: String::new(),
                 }
             }
         }
     }
 
+    // Scan parquet or csv files, all the files must be the same type with the same columns
+    fn scan_files(files: Vec<PathBuf>, csv_delimiter: u8) -> PolarsResult<LazyFrame> {
+        let Some(first_file) = files.first() else {
+            polars_bail!(NoData: "no files selected");
+        };
+
+        let Some(kind) = FileKind::from_path(first_file) else {
+            polars_bail!(ComputeError: "unsupported file type: {}", first_file.display());
+        };
+
+        if let Some(other) = files
+            .iter()
+            .find(|file| FileKind::from_path(file) != Some(kind))
+        {
+            polars_bail!(
+                ComputeError: "cannot load {} together with {:?} files, select files of one type",
+                other.display(),
+                kind
+            );
+        }
+
+        // check every file has the same columns as the first one
+        let scan_one = |file: &PathBuf| match kind {
+            FileKind::Parquet => LazyFrame::scan_parquet(file, ScanArgsParquet::default()),
+            FileKind::Csv => LazyCsvReader::new(file)
+                .with_separator(csv_delimiter)
+                .with_has_header(true)
+                .finish(),
+        };
+
+        let first_columns = Self::schema_column_names(scan_one(first_file)?)?;
+        for file in files.iter().skip(1) {
+            let columns = Self::schema_column_names(scan_one(file)?)?;
+            if columns != first_columns {
+                polars_bail!(
+                    SchemaMismatch: "{} has columns {:?} but {} has columns {:?}",
+                    first_file.display(),
+                    first_columns,
+                    file.display(),
+                    columns
+                );
+            }
+        }
+
+        let files_arc: Arc<[PathBuf]> = Arc::from(files);
+        match kind {
+            FileKind::Parquet => {
+                LazyFrame::scan_parquet_files(files_arc, ScanArgsParquet::default())
+            }
+            // the schema is inferred from the first file and used for all of them
+            FileKind::Csv => LazyCsvReader::new_paths(files_arc)
+                .with_separator(csv_delimiter)
+                .with_has_header(true)
+                .with_schema_modify(Ok)?
+                .finish(),
+        }
+    }
+
+    fn schema_column_names(mut lf: LazyFrame) -> PolarsResult<Vec<String>> {
+        Ok(lf
+            .schema()?
+            .iter_names()
+            .map(|name| name.to_string())
+            .collect())
+    }
+
     pub fn set_lazyframe(&mut self, lazyframe: LazyFrame) {
         self.lazyframe = Some(lazyframe);
+        self.refresh_schema();
+    }
+
+    // Resolve the column names and types without reading any rows
+    pub fn refresh_schema(&mut self) {
+        let Some(lf) = &self.lazyframe else {
+            self.schema.clear();
+            return;
+        };
+
+        match lf.clone().schema() {
+            Ok(schema) => {
+                self.schema = schema
+                    .iter()
+                    .map(|(name, dtype)| (name.to_string(), dtype.to_string()))
+                    .collect();
+            }
+            Err(e) => {
+                log::error!("Failed to get the LazyFrame schema: {}", e);
+                self.schema.clear();
+            }
+        }
     }
 
     pub fn get_column_names(&self) -> Vec<String> {
         self.columns.clone()
     }
 
+    // Smallest and largest value of a numeric column, nulls are ignored
+    pub fn column_min_max(lf: &LazyFrame, column: &str) -> PolarsResult<(f64, f64)> {
+        let df = lf
+            .clone()
+            .select([
+                col(column).cast(DataType::Float64).min().alias("min"),
+                col(column).cast(DataType::Float64).max().alias("max"),
+            ])
+            .collect()?;
+
+        let value = |name: &str| -> PolarsResult<f64> {
+            match df.column(name)?.f64()?.get(0) {
+                Some(value) => Ok(value),
+                None => polars_bail!(NoData: "column '{}' has no values", column),
+            }
+        };
+
+        Ok((value("min")?, value("max")?))
+    }
+
     pub fn get_column_names_from_lazyframe(lazyframe: &LazyFrame) -> Vec<String> {
         let lf: LazyFrame = lazyframe.clone().limit(1);
         let df: DataFrame = lf.collect().unwrap();
@@ -57,6 +200,25 @@ impl LazyFramer {
     pub fn add_column(&mut self, expr: Expr) {
         let lf = self.lazyframe.clone().unwrap().with_column(expr);
         self.lazyframe = Some(lf);
+        self.refresh_schema();
+    }
+
+    pub fn add_computed_column(&mut self, new_name: &str, expr: Expr) {
+        if self.lazyframe.is_none() {
+            log::error!("LazyFrame is not loaded");
+            return;
+        }
+
+        self.add_column(expr.clone().alias(new_name));
+        self.columns.push(new_name.to_string());
+        self.computed_columns.push((new_name.to_string(), expr));
+    }
+
+    // Add the derived columns from a previous LazyFramer, used when the files are reloaded
+    pub fn restore_computed_columns(&mut self, computed_columns: Vec<(String, Expr)>) {
+        for (name, expr) in computed_columns {
+            self.add_computed_column(&name, expr);
+        }
     }
 
     pub fn save_lazyframe(&mut self, output_path: &PathBuf, scan: bool) -> Result<(), PolarsError> {
@@ -110,19 +272,69 @@ impl LazyFramer {
                 }
             }
 
-            ui.label("Columns:");
-            if self.columns.is_empty() {
-                ui.label("No columns");
+            ui.label("Computed Column:");
+            if let Some((name, expr)) = self.column_editor.ui(ui, &self.columns) {
+                self.add_computed_column(&name, expr);
+            }
+
+            ui.separator();
+
+            self.schema_ui(ui);
+        });
+    }
+
+    // Column names and types, clicking a name copies it for the histogram script or expressions
+    fn schema_ui(&mut self, ui: &mut egui::Ui) {
+        ui.label(format!("Columns ({}):", self.schema.len()));
+        if self.schema.is_empty() {
+            ui.label("No columns");
+            if self.lazyframe.is_some() && ui.button("Get Columns").clicked() {
+                self.refresh_schema();
                 if let Some(lf) = &self.lazyframe {
-                    if ui.button("Get Columns").clicked() {
-                        self.columns = Self::get_column_names_from_lazyframe(lf);
-                    }
-                }
-            } else {
-                for column in &self.columns {
-                    ui.label(column);
+                    self.columns = Self::get_column_names_from_lazyframe(lf);
                 }
             }
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.schema_filter)
+                    .hint_text("column name")
+                    .desired_width(150.0),
+            );
+            if !self.schema_filter.is_empty() && ui.small_button("🗙").clicked() {
+                self.schema_filter.clear();
+            }
         });
+
+        let filter = self.schema_filter.to_lowercase();
+        egui::ScrollArea::vertical()
+            .id_salt("lazyframe_schema_scroll")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("lazyframe_schema_grid")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        for (name, dtype) in self
+                            .schema
+                            .iter()
+                            .filter(|(name, _)| name.to_lowercase().contains(&filter))
+                        {
+                            if ui
+                                .add(egui::Label::new(name).sense(egui::Sense::click()))
+                                .on_hover_text("Click to copy the column name")
+                                .clicked()
+                            {
+                                ui.output_mut(|o| o.copied_text = name.clone());
+                                log::info!("Copied column name '{}'", name);
+                            }
+                            ui.weak(dtype);
+                            ui.end_row();
+                        }
+                    });
+            });
     }
 }
@@ -1,6 +1,12 @@
 #[cfg(not(target_arch = "wasm32"))]
+pub mod column_expression;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dataset;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod lazyframer;
 #[cfg(not(target_arch = "wasm32"))]
+pub mod preset;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod processer;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod workspacer;
@@ -0,0 +1,79 @@
+use rfd::FileDialog;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+
+use crate::cutter::cuts::Cut;
+use crate::fitter::fit_settings::FitSettings;
+use crate::histoer::histo1d::markers::FitMarkers;
+use crate::histogram_scripter::histogram_script::HistogramScript;
+
+// The fit settings and markers of a 1D histogram so the same fit can be redone on new data
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FitTemplate {
+    pub settings: FitSettings,
+    pub markers: FitMarkers,
+}
+
+// Bundles the histogram definitions, cuts, and fit templates of an analysis
+#[derive(Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct AnalysisPreset {
+    pub name: String,
+    pub histogram_script: HistogramScript,
+    pub cuts: Vec<Cut>,
+    pub fit_templates: HashMap<String, FitTemplate>, // histogram name -> fit template
+}
+
+impl AnalysisPreset {
+    pub fn save_to_file(&self) {
+        if let Some(path) = FileDialog::new()
+            .set_file_name(format!("{}.json", self.name))
+            .add_filter("JSON", &["json"])
+            .save_file()
+        {
+            let file = File::create(path);
+            match file {
+                Ok(mut file) => match serde_json::to_string_pretty(self) {
+                    Ok(json) => {
+                        if let Err(e) = file.write_all(json.as_bytes()) {
+                            log::error!("Error writing preset: {:?}", e);
+                        }
+                    }
+                    Err(e) => log::error!("Error serializing preset: {:?}", e),
+                },
+                Err(e) => {
+                    log::error!("Error creating file: {:?}", e);
+                }
+            }
+        }
+    }
+
+    pub fn load_from_file() -> Option<Self> {
+        let path = FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()?;
+
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut file) => {
+                if let Err(e) = file.read_to_string(&mut contents) {
+                    log::error!("Error reading preset: {:?}", e);
+                    return None;
+                }
+            }
+            Err(e) => {
+                log::error!("Error opening file: {:?}", e);
+                return None;
+            }
+        }
+
+        match serde_json::from_str(&contents) {
+            Ok(preset) => Some(preset),
+            Err(e) => {
+                log::error!("Error deserializing preset: {:?}", e);
+                None
+            }
+        }
+    }
+}
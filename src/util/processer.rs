@@ -1,10 +1,44 @@
+use super::dataset::Dataset;
 use super::lazyframer::LazyFramer;
+use super::preset::AnalysisPreset;
 use super::workspacer::Workspacer;
-use crate::cutter::cut_handler::CutHandler;
+use crate::cutter::cut_handler::{row_count, CutHandler};
+use crate::egui_plot_stuff::egui_plot_export::sanitize_file_name;
+use crate::histoer::core::ExportFormat;
+use crate::histoer::histo1d::histogram1d::Histogram;
 use crate::histoer::histogrammer::Histogrammer;
+use crate::histoer::template::AnalysisTemplate;
 use crate::histogram_scripter::histogram_script::HistogramScript;
 use pyo3::{prelude::*, types::PyModule};
 
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+// Counts per time bin of a timestamp column, the range comes from the column's min and max
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct RateHistogramSettings {
+    pub column: String,
+    pub bin_width: f64,        // seconds
+    pub units_per_second: f64, // 1e9 for timestamps in ns
+}
+
+impl Default for RateHistogramSettings {
+    fn default() -> Self {
+        Self {
+            column: String::new(),
+            bin_width: 1.0,
+            units_per_second: 1.0,
+        }
+    }
+}
+
+// More bins than this is almost certainly a wrong unit
+const MAX_RATE_BINS: usize = 10_000_000;
+
+// Integer columns spanning more values than this are refused instead of getting one bin per value
+const MAX_INTEGER_BINS: usize = 65_536;
+
 #[derive(Default, serde::Deserialize, serde::Serialize)]
 pub struct Processer {
     pub workspacer: Workspacer,
@@ -15,6 +49,22 @@ pub struct Processer {
     pub histogram_script: HistogramScript,
     pub save_with_scanning: bool,
     pub suffix: String,
+    #[serde(default)]
+    pub preset_name: String,
+    #[serde(default)]
+    pub fit_preset_after_fill: bool,
+    #[serde(skip)]
+    pub pending_preset: Option<AnalysisPreset>, // preset waiting for the histograms to fill before fitting
+    #[serde(default)]
+    pub datasets: Vec<Dataset>,
+    #[serde(skip)]
+    pub filter_preview: Option<(usize, usize)>, // rows passing the selected cuts and the total rows
+    #[serde(default)]
+    pub rate_histogram: RateHistogramSettings,
+    #[serde(default)]
+    pub integer_histogram_column: String, // e.g. a detector or channel ID
+    #[serde(default)]
+    pub template: Option<AnalysisTemplate>, // last imported template, filled against the selected files
 }
 
 impl Processer {
@@ -27,6 +77,14 @@ impl Processer {
             histogram_script: HistogramScript::new(),
             save_with_scanning: false,
             suffix: "filtered".to_string(),
+            preset_name: "Preset".to_string(),
+            fit_preset_after_fill: false,
+            pending_preset: None,
+            datasets: Vec::new(),
+            filter_preview: None,
+            rate_histogram: RateHistogramSettings::default(),
+            integer_histogram_column: String::new(),
+            template: None,
         }
     }
 
@@ -186,7 +244,29 @@ def get_2d_histograms(file_name):
     }
 
     fn create_lazyframe(&mut self) {
-        self.lazyframer = Some(LazyFramer::new(self.workspacer.selected_files.clone()));
+        let csv_delimiter = match self.workspacer.options.csv_delimiter_byte() {
+            Ok(csv_delimiter) => csv_delimiter,
+            Err(e) => {
+                log::error!("{}", e);
+                // drop the stale data but keep the computed columns for the next load
+                if let Some(lazyframer) = &mut self.lazyframer {
+                    lazyframer.lazyframe = None;
+                }
+                return;
+            }
+        };
+
+        // keep the computed columns when the files are reloaded
+        let computed_columns = self
+            .lazyframer
+            .take()
+            .map(|lazyframer| lazyframer.computed_columns)
+            .unwrap_or_default();
+
+        let mut lazyframer = LazyFramer::new(self.workspacer.selected_files.clone(), csv_delimiter);
+        lazyframer.restore_computed_columns(computed_columns);
+
+        self.lazyframer = Some(lazyframer);
     }
 
     fn perform_histogrammer_from_lazyframe(&mut self) {
@@ -207,6 +287,165 @@ def get_2d_histograms(file_name):
         self.perform_histogrammer_from_lazyframe();
     }
 
+    // Fill the scripted histograms for the selected files and write one file per histogram,
+    // nothing is added to the Histogrammer so this can run without the UI
+    pub fn export_histograms_headless(
+        &mut self,
+        output_dir: &Path,
+        format: ExportFormat,
+    ) -> polars::prelude::PolarsResult<()> {
+        self.create_lazyframe();
+
+        let Some(lf) = self
+            .lazyframer
+            .as_ref()
+            .and_then(|lazyframer| lazyframer.lazyframe.clone())
+        else {
+            return Err(polars::prelude::PolarsError::NoData(
+                "no LazyFrame could be loaded from the selected files".into(),
+            ));
+        };
+
+        let (hists_1d, hists_2d) = self.histogram_script.fill_histograms_headless(lf);
+
+        for hist in &hists_1d {
+            let path = output_dir
+                .join(sanitize_file_name(&hist.name))
+                .with_extension(format.extension());
+            hist.export(&path, format)?;
+        }
+
+        for hist in &hists_2d {
+            let path = output_dir
+                .join(sanitize_file_name(&hist.name))
+                .with_extension(format.extension());
+            hist.export(&path, format)?;
+        }
+
+        log::info!(
+            "Exported {} histograms to {}",
+            hists_1d.len() + hists_2d.len(),
+            output_dir.display()
+        );
+
+        Ok(())
+    }
+
+    pub fn add_dataset_from_selected_files(&mut self) {
+        let index = self.datasets.len();
+        self.datasets.push(Dataset::new(
+            &format!("Dataset {}", index),
+            self.workspacer.selected_files.clone(),
+            index,
+        ));
+    }
+
+    // Fill the scripted 1D histograms for every enabled dataset and draw them over the
+    // histograms of the same name
+    pub fn calculate_dataset_overlays(&mut self) {
+        let panes = self.histogrammer.hist1d_panes();
+        if panes.is_empty() {
+            log::error!("Calculate the histograms before overlaying datasets");
+            return;
+        }
+
+        let csv_delimiter = match self.workspacer.options.csv_delimiter_byte() {
+            Ok(csv_delimiter) => csv_delimiter,
+            Err(e) => {
+                log::error!("{}", e);
+                return;
+            }
+        };
+
+        for dataset in self.datasets.iter_mut().filter(|dataset| dataset.enabled) {
+            dataset.create_lazyframe(csv_delimiter);
+
+            let Some(lf) = dataset
+                .lazyframer
+                .as_ref()
+                .and_then(|lazyframer| lazyframer.lazyframe.clone())
+            else {
+                log::error!("Failed to load the files of dataset '{}'", dataset.name);
+                continue;
+            };
+
+            let script = self.histogram_script.clone();
+            let panes = panes.clone();
+            let name = dataset.name.clone();
+            let color = dataset.color;
+
+            let cancel = Arc::new(AtomicBool::new(false));
+            let job = move || {
+                let (hists_1d, _) = script.fill_histograms_headless(lf);
+
+                for core in &hists_1d {
+                    if let Some(hist) = panes.get(&core.name) {
+                        let mut overlay = Histogram::from_core(core);
+                        overlay.name.clone_from(&name);
+                        hist.lock().unwrap().set_overlay(overlay, color);
+                    }
+                }
+
+                log::info!(
+                    "Overlaid {} histograms from dataset '{}'",
+                    hists_1d.len(),
+                    name
+                );
+            };
+
+            self.histogrammer.spawn_fill(
+                &format!("Dataset overlay '{}'", dataset.name),
+                cancel,
+                Vec::new(),
+                job,
+            );
+        }
+    }
+
+    pub fn datasets_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Datasets", |ui| {
+            ui.label("Overlay the 1D histograms of other file sets on the current histograms");
+
+            if ui
+                .add_enabled(
+                    !self.workspacer.selected_files.is_empty(),
+                    egui::Button::new("Add Selected Files as Dataset"),
+                )
+                .on_disabled_hover_text("No files selected.")
+                .clicked()
+            {
+                self.add_dataset_from_selected_files();
+            }
+
+            let mut index_to_remove = None;
+            egui::Grid::new("datasets_grid")
+                .striped(true)
+                .show(ui, |ui| {
+                    for (i, dataset) in self.datasets.iter_mut().enumerate() {
+                        if dataset.ui(ui) {
+                            index_to_remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+
+            if let Some(index) = index_to_remove {
+                self.datasets.remove(index);
+            }
+
+            if ui
+                .add_enabled(
+                    self.datasets.iter().any(|dataset| dataset.enabled),
+                    egui::Button::new("Overlay Datasets"),
+                )
+                .on_hover_text("Fill the script histograms for each enabled dataset")
+                .clicked()
+            {
+                self.calculate_dataset_overlays();
+            }
+        });
+    }
+
     pub fn calculate_histograms_with_cuts(&mut self) {
         self.create_lazyframe();
         if let Some(ref mut lazyframer) = self.lazyframer {
@@ -224,6 +463,415 @@ def get_2d_histograms(file_name):
         }
     }
 
+    // Count the rows that pass the selected cuts before filling anything
+    pub fn preview_filtered_rows(&mut self) {
+        self.create_lazyframe();
+
+        let Some(lf) = self
+            .lazyframer
+            .as_ref()
+            .and_then(|lazyframer| lazyframer.lazyframe.clone())
+        else {
+            log::error!("LazyFrame is not loaded");
+            return;
+        };
+
+        let counts = self
+            .cut_handler
+            .preview_row_count(&lf)
+            .and_then(|filtered| Ok((filtered, row_count(&lf)?)));
+
+        match counts {
+            Ok(counts) => self.filter_preview = Some(counts),
+            Err(e) => {
+                log::error!("Failed to count the rows passing the cuts: {}", e);
+                self.filter_preview = None;
+            }
+        }
+    }
+
+    // Histogram the event rate over a timestamp column in the "Rate" grid
+    pub fn add_rate_histogram(&mut self) {
+        let settings = self.rate_histogram.clone();
+        let width = settings.bin_width * settings.units_per_second;
+        if !width.is_finite() || width <= 0.0 {
+            log::error!("The rate bin width and units per second must be positive");
+            return;
+        }
+
+        self.create_lazyframe();
+        let Some(lf) = self
+            .lazyframer
+            .as_ref()
+            .and_then(|lazyframer| lazyframer.lazyframe.clone())
+        else {
+            log::error!("LazyFrame is not loaded");
+            return;
+        };
+
+        let (min, max) = match LazyFramer::column_min_max(&lf, &settings.column) {
+            Ok(range) => range,
+            Err(e) => {
+                log::error!(
+                    "Failed to get the range of column '{}': {}",
+                    settings.column,
+                    e
+                );
+                return;
+            }
+        };
+
+        // the range is [min, max) so one extra bin keeps the last timestamp
+        let bins = ((max - min) / width).floor() as usize + 1;
+        if bins > MAX_RATE_BINS {
+            log::error!(
+                "{} bins of {} s would be needed for '{}', use a larger bin width or check the units per second",
+                bins,
+                settings.bin_width,
+                settings.column
+            );
+            return;
+        }
+        let range = (min, min + bins as f64 * width);
+
+        self.histogrammer.add_fill_hist1d(
+            &format!("{} Rate", settings.column),
+            &lf,
+            &settings.column,
+            bins,
+            range,
+            Some("Rate"),
+        );
+    }
+
+    pub fn rate_histogram_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Rate vs Time", |ui| {
+            ui.label("Histogram the counts per time bin of a timestamp column");
+
+            egui::Grid::new("rate_histogram_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Timestamp Column");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.rate_histogram.column)
+                            .hint_text("Timestamp"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Bin Width");
+                    ui.add(
+                        egui::DragValue::new(&mut self.rate_histogram.bin_width)
+                            .speed(0.1)
+                            .range(1e-9..=f64::INFINITY)
+                            .suffix(" s"),
+                    );
+                    ui.end_row();
+
+                    ui.label("Units per Second")
+                        .on_hover_text("1e9 for timestamps in ns, 1e12 for ps");
+                    ui.add(
+                        egui::DragValue::new(&mut self.rate_histogram.units_per_second)
+                            .speed(1.0)
+                            .range(1e-9..=f64::INFINITY),
+                    );
+                    ui.end_row();
+                });
+
+            if ui
+                .add_enabled(
+                    !self.workspacer.selected_files.is_empty()
+                        && !self.rate_histogram.column.trim().is_empty(),
+                    egui::Button::new("Add Rate Histogram"),
+                )
+                .on_disabled_hover_text("No files or column selected.")
+                .clicked()
+            {
+                self.add_rate_histogram();
+            }
+        });
+    }
+
+    // One bin centered on every integer between the column's min and max, so channel IDs don't
+    // land on bin edges
+    pub fn add_integer_histogram(&mut self) {
+        let column = self.integer_histogram_column.trim().to_string();
+
+        self.create_lazyframe();
+        let Some(lf) = self
+            .lazyframer
+            .as_ref()
+            .and_then(|lazyframer| lazyframer.lazyframe.clone())
+        else {
+            self.report_integer_histogram_error(&column, "the LazyFrame is not loaded".to_string());
+            return;
+        };
+
+        match lf
+            .clone()
+            .schema()
+            .map(|schema| schema.get(&column).cloned())
+        {
+            Ok(Some(dtype)) if dtype.is_integer() => {}
+            Ok(Some(dtype)) => {
+                self.report_integer_histogram_error(
+                    &column,
+                    format!(
+                        "the column has type {}, one bin per value needs an integer column",
+                        dtype
+                    ),
+                );
+                return;
+            }
+            Ok(None) => {
+                self.report_integer_histogram_error(
+                    &column,
+                    "the column does not exist".to_string(),
+                );
+                return;
+            }
+            Err(e) => {
+                self.report_integer_histogram_error(
+                    &column,
+                    format!("failed to get the schema: {}", e),
+                );
+                return;
+            }
+        }
+
+        let (min, max) = match LazyFramer::column_min_max(&lf, &column) {
+            Ok(range) => range,
+            Err(e) => {
+                self.report_integer_histogram_error(
+                    &column,
+                    format!("failed to get the range: {}", e),
+                );
+                return;
+            }
+        };
+
+        // checked as a float, a huge span would saturate the cast and overflow the + 1
+        let span = max - min;
+        if !(span < MAX_INTEGER_BINS as f64) {
+            self.report_integer_histogram_error(
+                &column,
+                format!(
+                    "the column spans {} values, more than the {} allowed for one bin per value",
+                    span + 1.0,
+                    MAX_INTEGER_BINS
+                ),
+            );
+            return;
+        }
+        let bins = span as usize + 1;
+
+        self.histogrammer.add_fill_hist1d(
+            &column,
+            &lf,
+            &column,
+            bins,
+            (min - 0.5, max + 0.5),
+            Some("Integer"),
+        );
+    }
+
+    // Logged and shown in the fill error banner, the histogram is never created
+    fn report_integer_histogram_error(&self, column: &str, reason: String) {
+        log::error!("Cannot add the integer histogram '{}': {}", column, reason);
+        self.histogrammer
+            .fill_errors
+            .lock()
+            .unwrap()
+            .push(format!("Cannot add '{}': {}", column, reason));
+    }
+
+    pub fn integer_histogram_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("One Bin per Integer", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Integer Column");
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.integer_histogram_column)
+                        .hint_text("DetectorID"),
+                );
+            });
+
+            if ui
+                .add_enabled(
+                    !self.workspacer.selected_files.is_empty()
+                        && !self.integer_histogram_column.trim().is_empty(),
+                    egui::Button::new("Add Integer Histogram"),
+                )
+                .on_hover_text(format!(
+                    "The range is the column's min to max, up to {} values",
+                    MAX_INTEGER_BINS
+                ))
+                .on_disabled_hover_text("No files or column selected.")
+                .clicked()
+            {
+                self.add_integer_histogram();
+            }
+        });
+    }
+
+    pub fn create_preset(&self) -> AnalysisPreset {
+        // include the cuts drawn on the 2D histograms
+        let mut cut_handler = CutHandler {
+            cuts: self.cut_handler.cuts.clone(),
+            ..Default::default()
+        };
+        self.histogrammer.retrieve_active_cuts(&mut cut_handler);
+
+        AnalysisPreset {
+            name: self.preset_name.clone(),
+            histogram_script: self.histogram_script.clone(),
+            cuts: cut_handler.cuts,
+            fit_templates: self.histogrammer.get_fit_templates(),
+        }
+    }
+
+    // Load the histogram definitions and cuts, fill the histograms, and set up the fits
+    pub fn apply_preset(&mut self, preset: AnalysisPreset) {
+        log::info!("Applying analysis preset '{}'", preset.name);
+
+        self.preset_name.clone_from(&preset.name);
+        self.histogram_script = preset.histogram_script.clone();
+        self.cut_handler.add_cuts(preset.cuts.clone());
+
+        if self.workspacer.selected_files.is_empty() {
+            log::error!("No files selected, the preset histograms will not be filled");
+            return;
+        }
+
+        self.calculate_histograms();
+        self.histogrammer.apply_fit_templates(&preset.fit_templates);
+
+        if self.fit_preset_after_fill {
+            self.pending_preset = Some(preset);
+        }
+    }
+
+    fn check_pending_preset(&mut self) {
+        if self.pending_preset.is_none() || self.histogrammer.is_filling() {
+            return;
+        }
+
+        if let Some(preset) = self.pending_preset.take() {
+            self.histogrammer.fit_from_templates(&preset.fit_templates);
+        }
+    }
+
+    pub fn preset_ui(&mut self, ui: &mut egui::Ui) {
+        self.check_pending_preset();
+
+        ui.collapsing("Analysis Preset", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Name:");
+                ui.text_edit_singleline(&mut self.preset_name);
+            });
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Save Preset")
+                    .on_hover_text("Save the histogram script, cuts, and fit markers/settings")
+                    .clicked()
+                {
+                    self.create_preset().save_to_file();
+                }
+
+                if ui
+                    .button("Load and Apply Preset")
+                    .on_hover_text(
+                        "Load a preset and calculate the histograms for the selected files",
+                    )
+                    .clicked()
+                {
+                    if let Some(preset) = AnalysisPreset::load_from_file() {
+                        self.apply_preset(preset);
+                    }
+                }
+            });
+
+            ui.checkbox(&mut self.fit_preset_after_fill, "Fit after filling")
+                .on_hover_text("Fit the histograms with the preset markers once they are filled");
+
+            if self.pending_preset.is_some() {
+                ui.horizontal(|ui| {
+                    ui.label("Waiting for histograms to fill");
+                    ui.add(egui::widgets::Spinner::default());
+                });
+            }
+        });
+    }
+
+    pub fn fill_template(&mut self) {
+        let Some(template) = &self.template else {
+            return;
+        };
+
+        self.create_lazyframe();
+        let Some(lf) = self
+            .lazyframer
+            .as_ref()
+            .and_then(|lazyframer| lazyframer.lazyframe.clone())
+        else {
+            log::error!("LazyFrame is not loaded");
+            return;
+        };
+
+        self.histogrammer.fill_template(template, &lf);
+    }
+
+    pub fn template_ui(&mut self, ui: &mut egui::Ui) {
+        ui.collapsing("Analysis Template", |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Export Template")
+                    .on_hover_text(
+                        "Save the grids, histogram binning, and filled columns without the counts",
+                    )
+                    .clicked()
+                {
+                    self.histogrammer
+                        .to_template(&self.preset_name)
+                        .save_to_file();
+                }
+
+                if ui
+                    .button("Import Template")
+                    .on_hover_text("Add the template histograms empty")
+                    .clicked()
+                {
+                    if let Some(template) = AnalysisTemplate::load_from_file() {
+                        self.histogrammer.apply_template(&template);
+                        self.template = Some(template);
+                    }
+                }
+            });
+
+            if let Some(template) = &self.template {
+                ui.label(format!(
+                    "'{}': {} histograms in {} grids",
+                    template.name,
+                    template.histogram_count(),
+                    template.grids.len()
+                ));
+            }
+
+            if ui
+                .add_enabled(
+                    self.template.is_some() && !self.workspacer.selected_files.is_empty(),
+                    egui::Button::new("Fill Template"),
+                )
+                .on_hover_text(
+                    "Fill the template histograms from their columns in the selected files",
+                )
+                .on_disabled_hover_text("No template imported or no files selected.")
+                .clicked()
+            {
+                self.fill_template();
+            }
+        });
+    }
+
     pub fn save_selected_files_to_single_file(&mut self) {
         let scan = self.save_with_scanning;
         if let Some(output_path) = rfd::FileDialog::new()
@@ -374,8 +1022,47 @@ def get_2d_histograms(file_name):
                 {
                     self.calculate_histograms_with_cuts();
                 }
+
+                if ui
+                    .add_enabled(
+                        !self.workspacer.selected_files.is_empty()
+                            && self.cut_handler.cuts_are_selected(),
+                        egui::Button::new("Preview filtered rows"),
+                    )
+                    .on_hover_text("Count the rows that pass the selected cuts")
+                    .on_disabled_hover_text("No files selected or cuts selected.")
+                    .clicked()
+                {
+                    self.preview_filtered_rows();
+                }
             });
 
+            if let Some((filtered, total)) = self.filter_preview {
+                let fraction = if total > 0 {
+                    filtered as f64 / total as f64
+                } else {
+                    0.0
+                };
+
+                ui.horizontal(|ui| {
+                    let text = format!(
+                        "{} of {} rows pass the cuts ({:.2}%)",
+                        filtered,
+                        total,
+                        100.0 * fraction
+                    );
+                    if filtered == 0 {
+                        ui.colored_label(egui::Color32::RED, text);
+                    } else {
+                        ui.label(text);
+                    }
+
+                    if ui.small_button("🗙").clicked() {
+                        self.filter_preview = None;
+                    }
+                });
+            }
+
             ui.separator();
         } else if ui
             .add_enabled(
@@ -401,6 +1088,26 @@ def get_2d_histograms(file_name):
 
             ui.separator();
 
+            self.preset_ui(ui);
+
+            ui.separator();
+
+            self.template_ui(ui);
+
+            ui.separator();
+
+            self.datasets_ui(ui);
+
+            ui.separator();
+
+            self.rate_histogram_ui(ui);
+
+            ui.separator();
+
+            self.integer_histogram_ui(ui);
+
+            ui.separator();
+
             if let Some(lazyframer) = &mut self.lazyframer {
                 lazyframer.ui(ui);
 
@@ -412,6 +1119,6 @@ def get_2d_histograms(file_name):
     }
 
     pub fn histogram_script_ui(&mut self, ui: &mut egui::Ui) {
-        self.histogram_script.ui(ui);
+        self.histogram_script.ui(ui, &self.histogrammer.defaults);
     }
 }
@@ -1,6 +1,7 @@
 use polars::prelude::*;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
 use super::lazyframer::LazyFramer;
@@ -25,6 +26,27 @@ pub struct WorkspacerOptions {
     pub save_with_scanning: bool,
     pub suffix: String,
     pub root: bool,
+    #[serde(default)]
+    pub csv_delimiter: String, // empty uses a comma, "\t" for tabs
+    #[serde(default)]
+    pub scan_pattern: String, // file names matched when adding a directory, empty uses *.parquet
+}
+
+impl WorkspacerOptions {
+    // Polars takes a single byte, so anything but one ASCII character is refused
+    pub fn csv_delimiter_byte(&self) -> Result<u8, String> {
+        match self.csv_delimiter.as_str() {
+            "" => Ok(b','),
+            "\\t" => Ok(b'\t'),
+            delimiter => match delimiter.as_bytes() {
+                &[byte] if byte.is_ascii() => Ok(byte),
+                _ => Err(format!(
+                    "CSV delimiter '{}' is not a single ASCII character or \\t",
+                    delimiter
+                )),
+            },
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug, serde::Deserialize, serde::Serialize)]
@@ -33,8 +55,116 @@ pub struct Workspacer {
     pub files: Vec<PathBuf>,
     pub selected_files: Vec<PathBuf>,
     pub options: WorkspacerOptions,
+    #[serde(skip)]
+    directory_scan: Option<Arc<Mutex<DirectoryScan>>>,
+    #[serde(skip)]
+    scan_message: Option<String>,
+    #[serde(default)]
+    pub recent_directories: Vec<PathBuf>, // most recent first
+    #[serde(default)]
+    pub recent_files: Vec<PathBuf>, // most recent first
+}
+
+const MAX_RECENT: usize = 10;
+
+// Move the path to the front of the list, dropping the oldest past MAX_RECENT
+fn push_recent(recent: &mut Vec<PathBuf>, path: &Path) {
+    recent.retain(|recent_path| recent_path != path);
+    recent.insert(0, path.to_path_buf());
+    recent.truncate(MAX_RECENT);
+}
+
+// Show the file selected in the OS file browser, Linux file managers only open the folder
+fn reveal_in_file_browser(path: &Path) {
+    let result = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+            .arg("-R")
+            .arg(path)
+            .spawn()
+    } else if cfg!(target_os = "windows") {
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        std::process::Command::new("explorer").arg(select).spawn()
+    } else {
+        let folder = if path.is_dir() {
+            path
+        } else {
+            path.parent().unwrap_or(path)
+        };
+        std::process::Command::new("xdg-open").arg(folder).spawn()
+    };
+
+    if let Err(e) = result {
+        log::error!("Failed to open the folder of {:?}: {}", path, e);
+    }
+}
+
+// A recursive directory scan running on a background thread, found is set when it finishes
+#[derive(Debug)]
+struct DirectoryScan {
+    directory: PathBuf,
+    found: Option<Vec<PathBuf>>,
 }
 
+// Match a file name against a pattern where * is any run of characters and ? is one character
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None; // position after the last * and the name position it matched up to
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p + 1, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            // let the last * take one more character
+            p = star_p;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Symlinked directories are not followed so links back up the tree can't loop forever
+fn scan_directory(directory: &Path, pattern: &str, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::error!("Failed to read directory {:?}: {}", directory, e);
+            return;
+        }
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            scan_directory(&path, pattern, found);
+        } else if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| wildcard_match(pattern, name))
+        {
+            found.push(path);
+        }
+    }
+}
+
+// Drag and drop payload, the index of the file in the selected files
+struct SelectedFileDrag(usize);
+
 impl SortingOption {
     fn display_name(&self) -> &str {
         match self {
@@ -59,7 +189,11 @@ impl Workspacer {
     ) -> Result<(), PolarsError> {
         let selected_files = &self.selected_files;
         // create a lazyframe from the selected files
-        let mut lazyframer = LazyFramer::new(selected_files.clone());
+        let csv_delimiter = self
+            .options
+            .csv_delimiter_byte()
+            .map_err(|e| PolarsError::ComputeError(e.into()))?;
+        let mut lazyframer = LazyFramer::new(selected_files.clone(), csv_delimiter);
 
         // save the lazyframe to a single file
         lazyframer.save_lazyframe(output_path, scan)
@@ -73,7 +207,11 @@ impl Workspacer {
     ) -> Result<(), PolarsError> {
         let selected_files = &self.selected_files;
         // create a lazyframe from the selected files
-        let mut lazyframer = LazyFramer::new(selected_files.clone());
+        let csv_delimiter = self
+            .options
+            .csv_delimiter_byte()
+            .map_err(|e| PolarsError::ComputeError(e.into()))?;
+        let mut lazyframer = LazyFramer::new(selected_files.clone(), csv_delimiter);
 
         if let Some(ref mut lazyframe) = lazyframer.lazyframe {
             match cut_handler.filter_lf_with_selected_cuts(lazyframe) {
@@ -98,19 +236,21 @@ impl Workspacer {
         suffix: &str,
         scan: bool,
     ) -> Result<(), PolarsError> {
+        let csv_delimiter = self
+            .options
+            .csv_delimiter_byte()
+            .map_err(|e| PolarsError::ComputeError(e.into()))?;
         for file in &self.selected_files {
             // Create a LazyFramer for the current file
-            let mut lazyframer = LazyFramer::new(vec![file.clone()]);
+            let mut lazyframer = LazyFramer::new(vec![file.clone()], csv_delimiter);
 
             if let Some(ref mut lazyframe) = lazyframer.lazyframe {
                 match cut_handler.filter_lf_with_selected_cuts(lazyframe) {
                     Ok(filtered_lf) => {
                         lazyframer.lazyframe = Some(filtered_lf);
 
-                        // need to put suffix before .parquet
-                        let file_name = file.file_name().unwrap().to_string_lossy();
-                        // strip the .parquet extension
-                        let file_name = &file_name[..file_name.len() - 8];
+                        // need to put suffix before the extension, the output is always parquet
+                        let file_name = file.file_stem().unwrap().to_string_lossy();
 
                         let new_file_name = format!("{}_{}.parquet", file_name, suffix);
 
@@ -147,10 +287,89 @@ impl Workspacer {
     fn select_directory(&mut self) {
         let directory = rfd::FileDialog::new().pick_folder();
         if let Some(dir) = directory {
-            self.directory = Some(dir.clone());
-            // After directory selection, automatically load .parquet files
-            self.get_files_in_directory(&dir);
-            self.validate_selected_files(); // Ensure selected_files are still valid
+            self.open_directory(&dir);
+        }
+    }
+
+    fn open_directory(&mut self, dir: &Path) {
+        if !dir.is_dir() {
+            log::error!("Directory {:?} no longer exists", dir);
+            self.recent_directories.retain(|recent| recent != dir);
+            return;
+        }
+
+        self.directory = Some(dir.to_path_buf());
+        push_recent(&mut self.recent_directories, dir);
+        // After directory selection, automatically load .parquet files
+        self.get_files_in_directory(dir);
+        self.validate_selected_files(); // Ensure selected_files are still valid
+    }
+
+    fn select_recent_file(&mut self, file: &Path) {
+        if !file.is_file() {
+            log::error!("File {:?} no longer exists", file);
+            self.recent_files.retain(|recent| recent != file);
+            return;
+        }
+
+        push_recent(&mut self.recent_files, file);
+        if !self.selected_files.iter().any(|selected| selected == file) {
+            self.selected_files.push(file.to_path_buf());
+        }
+    }
+
+    fn recent_menu_button(&mut self, ui: &mut egui::Ui) {
+        if self.recent_directories.is_empty() && self.recent_files.is_empty() {
+            return;
+        }
+
+        let mut open_directory = None;
+        let mut select_file = None;
+
+        ui.menu_button("Recent", |ui| {
+            if !self.recent_directories.is_empty() {
+                ui.label("Directories");
+                for dir in &self.recent_directories {
+                    if ui
+                        .button(dir.display().to_string())
+                        .on_hover_text("Open this directory")
+                        .clicked()
+                    {
+                        open_directory = Some(dir.clone());
+                        ui.close_menu();
+                    }
+                }
+            }
+
+            if !self.recent_files.is_empty() {
+                ui.separator();
+                ui.label("Files");
+                for file in &self.recent_files {
+                    let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+                    if ui
+                        .button(file_name)
+                        .on_hover_text(format!("Select {}", file.display()))
+                        .clicked()
+                    {
+                        select_file = Some(file.clone());
+                        ui.close_menu();
+                    }
+                }
+            }
+
+            ui.separator();
+            if ui.button("Clear").clicked() {
+                self.recent_directories.clear();
+                self.recent_files.clear();
+                ui.close_menu();
+            }
+        });
+
+        if let Some(dir) = open_directory {
+            self.open_directory(&dir);
+        }
+        if let Some(file) = select_file {
+            self.select_recent_file(&file);
         }
     }
 
@@ -166,7 +385,10 @@ impl Workspacer {
                         files.push(path);
                     }
                 } else if path.is_file()
-                    && path.extension().and_then(|s| s.to_str()) == Some("parquet")
+                    && matches!(
+                        path.extension().and_then(|s| s.to_str()),
+                        Some("parquet") | Some("csv") | Some("txt")
+                    )
                 {
                     files.push(path);
                 }
@@ -181,19 +403,28 @@ impl Workspacer {
         }
     }
 
+    // Files added from other folders with Add Directory are kept while they exist
     fn validate_selected_files(&mut self) {
         let files = &mut self.files;
+        let directory = self.directory.as_deref();
         let selected_files = &mut self.selected_files;
-        selected_files.retain(|selected_file| files.contains(selected_file));
+        selected_files.retain(|selected_file| {
+            files.contains(selected_file)
+                || (selected_file.parent() != directory && selected_file.exists())
+        });
     }
 
     fn clear_selected_files(&mut self) {
         self.selected_files.clear();
     }
 
+    // Keep the order of the files that were already selected and add the rest after them
     fn select_all_files(&mut self) {
-        let files = self.files.clone();
-        self.selected_files = files;
+        for file in &self.files {
+            if !self.selected_files.contains(file) {
+                self.selected_files.push(file.clone());
+            }
+        }
     }
 
     fn sort_files(&mut self) {
@@ -253,6 +484,108 @@ impl Workspacer {
         });
     }
 
+    // Scan a folder and its subfolders on a background thread, the matches are added when it finishes
+    fn add_directory(&mut self) {
+        let Some(directory) = rfd::FileDialog::new().pick_folder() else {
+            return;
+        };
+        push_recent(&mut self.recent_directories, &directory);
+
+        let pattern = match self.options.scan_pattern.trim() {
+            "" => "*.parquet".to_string(),
+            pattern => pattern.to_string(),
+        };
+
+        let scan = Arc::new(Mutex::new(DirectoryScan {
+            directory: directory.clone(),
+            found: None,
+        }));
+        self.directory_scan = Some(Arc::clone(&scan));
+        self.scan_message = None;
+
+        std::thread::spawn(move || {
+            let mut found = Vec::new();
+            scan_directory(&directory, &pattern, &mut found);
+            found.sort();
+            scan.lock().unwrap().found = Some(found);
+        });
+    }
+
+    // Pick up the files of a finished scan, skipping the ones already selected
+    fn poll_directory_scan(&mut self) {
+        let Some(scan) = &self.directory_scan else {
+            return;
+        };
+
+        let Some((directory, found)) = ({
+            let mut scan = scan.lock().unwrap();
+            scan.found
+                .take()
+                .map(|found| (scan.directory.clone(), found))
+        }) else {
+            return;
+        };
+        self.directory_scan = None;
+
+        let total = found.len();
+        let mut added = 0;
+        for file in found {
+            if !self.selected_files.contains(&file) {
+                self.selected_files.push(file);
+                added += 1;
+            }
+        }
+
+        let message = format!(
+            "Added {} files from {:?} ({} already selected)",
+            added,
+            directory,
+            total - added
+        );
+        log::info!("{}", message);
+        self.scan_message = Some(message);
+    }
+
+    fn add_directory_ui(&mut self, ui: &mut egui::Ui) {
+        self.poll_directory_scan();
+
+        ui.horizontal(|ui| {
+            let scanning = self.directory_scan.is_some();
+            if ui
+                .add_enabled(!scanning, egui::Button::new("Add Directory"))
+                .on_hover_text(
+                    "Select every file in a folder and its subfolders whose name matches the pattern",
+                )
+                .clicked()
+            {
+                self.add_directory();
+            }
+
+            ui.add(
+                egui::TextEdit::singleline(&mut self.options.scan_pattern)
+                    .hint_text("*.parquet")
+                    .desired_width(100.0),
+            )
+            .on_hover_text("File name pattern, * matches any characters and ? a single one");
+
+            if scanning {
+                ui.spinner();
+                ui.ctx().request_repaint();
+            }
+        });
+
+        if let Some(message) = &self.scan_message {
+            let mut dismiss = false;
+            ui.horizontal(|ui| {
+                ui.label(message);
+                dismiss = ui.small_button("🗙").clicked();
+            });
+            if dismiss {
+                self.scan_message = None;
+            }
+        }
+    }
+
     fn get_directory(&self) -> Option<&PathBuf> {
         self.directory.as_ref()
     }
@@ -274,7 +607,15 @@ impl Workspacer {
                 self.select_directory();
             }
 
-            if let Some(_dir) = self.get_directory() {
+            if let Some(dir) = self.get_directory() {
+                if ui
+                    .button("📂")
+                    .on_hover_text("Open the directory in the file browser")
+                    .clicked()
+                {
+                    reveal_in_file_browser(dir);
+                }
+
                 if ui
                     .button("↻")
                     .on_hover_text("Refresh the directory")
@@ -283,25 +624,28 @@ impl Workspacer {
                     self.refresh_files();
                 }
             }
+
+            self.recent_menu_button(ui);
         });
     }
 
     fn file_selection_settings_ui(&mut self, ui: &mut egui::Ui) {
         ui.horizontal(|ui| {
+            let mut all_selected = !self.files.is_empty()
+                && self
+                    .files
+                    .iter()
+                    .all(|file| self.selected_files.contains(file));
             if ui
-                .small_button("Select All")
-                .on_hover_text("Select all files in the directory")
-                .clicked()
+                .checkbox(&mut all_selected, "Select All")
+                .on_hover_text("Select all or none of the files in the directory")
+                .changed()
             {
-                self.select_all_files();
-            }
-
-            if ui
-                .small_button("Clear")
-                .on_hover_text("Clear all selected files")
-                .clicked()
-            {
-                self.clear_selected_files();
+                if all_selected {
+                    self.select_all_files();
+                } else {
+                    self.clear_selected_files();
+                }
             }
         });
 
@@ -398,11 +742,12 @@ impl Workspacer {
         if self.options.root {
             ui.label(".root Files");
         } else {
-            ui.label(".parquet Files");
+            ui.label(".parquet/.csv Files");
         }
 
         let files = &mut self.files;
         let selected_files = &mut self.selected_files;
+        let recent_files = &mut self.recent_files;
 
         ui.horizontal_wrapped(|ui| {
             for file in files.iter() {
@@ -415,20 +760,117 @@ impl Workspacer {
                         selected_files.retain(|f| f != file);
                     } else {
                         selected_files.push(file.clone());
+                        push_recent(recent_files, file);
                     }
                 }
             }
         });
     }
 
+    // The selected files in the order they are passed to the LazyFramer, drag a row to move it
+    fn selected_files_ui(&mut self, ui: &mut egui::Ui) {
+        if self.selected_files.is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Selected Files")
+                .on_hover_text("The files are combined in this order, drag a file to move it");
+
+            if ui
+                .small_button("Clear All")
+                .on_hover_text("Clear all selected files")
+                .clicked()
+            {
+                self.clear_selected_files();
+            }
+        });
+
+        let mut index_to_remove = None;
+        let mut moved = None;
+
+        for (index, file) in self.selected_files.iter().enumerate() {
+            let file_stem = file.file_stem().unwrap_or_default().to_string_lossy();
+
+            let row = ui
+                .horizontal(|ui| {
+                    ui.dnd_drag_source(
+                        egui::Id::new("workspacer_selected_file").with(index),
+                        SelectedFileDrag(index),
+                        |ui| {
+                            ui.label(format!("☰ {}. {}", index + 1, file_stem));
+                        },
+                    )
+                    .response
+                    .on_hover_text(file.display().to_string());
+
+                    if ui
+                        .small_button("📂")
+                        .on_hover_text("Show in the file browser")
+                        .clicked()
+                    {
+                        reveal_in_file_browser(file);
+                    }
+
+                    if ui
+                        .small_button("🗙")
+                        .on_hover_text("Remove from the selected files")
+                        .clicked()
+                    {
+                        index_to_remove = Some(index);
+                    }
+                })
+                .response;
+
+            // the dragged file is inserted in place of the row it is dropped on
+            if row.dnd_hover_payload::<SelectedFileDrag>().is_some() {
+                ui.painter().hline(
+                    row.rect.x_range(),
+                    row.rect.top(),
+                    ui.visuals().selection.stroke,
+                );
+            }
+            if let Some(payload) = row.dnd_release_payload::<SelectedFileDrag>() {
+                moved = Some((payload.0, index));
+            }
+        }
+
+        if let Some(index) = index_to_remove {
+            self.selected_files.remove(index);
+        } else if let Some((from, to)) = moved {
+            if from != to && from < self.selected_files.len() {
+                let file = self.selected_files.remove(from);
+                self.selected_files
+                    .insert(to.min(self.selected_files.len()), file);
+            }
+        }
+    }
+
     pub fn workspace_ui(&mut self, ui: &mut egui::Ui) {
         ui.collapsing("Workspace", |ui| {
             ui.horizontal(|ui| {
                 ui.checkbox(&mut self.options.root, "Root Files");
+
+                if !self.options.root {
+                    ui.separator();
+                    ui.label("CSV Delimiter:");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.options.csv_delimiter)
+                            .hint_text(",")
+                            .desired_width(30.0),
+                    )
+                    .on_hover_text("Column separator for .csv/.txt files, use \\t for tabs");
+
+                    if let Err(e) = self.options.csv_delimiter_byte() {
+                        ui.colored_label(egui::Color32::RED, e);
+                    }
+                }
             });
             self.select_directory_ui(ui);
+            self.add_directory_ui(ui);
             self.file_selection_settings_ui(ui);
             self.file_selection_ui(ui);
+            self.selected_files_ui(ui);
         });
     }
 }